@@ -0,0 +1,110 @@
+//! Latency of small requests while large bodies are deserialized or serialized on the same worker thread,
+//! inline and on the thread pool for blocking tasks with `PayloadConfig::blocking_threshold` and
+//! `Payload::into_response_blocking`. The difference depends on the number of cores available to the blocking threads.
+//!
+//! Run with `cargo bench --bench blocking`.
+
+mod common;
+
+use actix_multiresponse::{Payload, PayloadConfig};
+use actix_web::test::{call_service, init_service, TestRequest};
+use actix_web::{web, App, HttpRequest, HttpResponse};
+use std::time::Instant;
+
+#[derive(Clone, serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+struct Large {
+    #[prost(uint64, repeated, tag = "1")]
+    values: Vec<u64>,
+}
+
+#[derive(serde_derive::Deserialize, prost_derive::Message)]
+struct Small {
+    #[prost(string, tag = "1")]
+    name: String,
+}
+
+const CONCURRENT_LARGE: usize = 4;
+const ROUNDS: usize = 50;
+const VALUES: usize = 200_000;
+
+fn large() -> Large {
+    Large {
+        values: vec![1_234_567_890; VALUES],
+    }
+}
+
+async fn small(_: Payload<Small>) -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+async fn deserialize_large(_: Payload<Large>) -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+async fn serialize_large(_: HttpRequest) -> Payload<Large> {
+    Payload::new(large())
+}
+
+async fn serialize_large_blocking(req: HttpRequest) -> HttpResponse {
+    Payload::new(large()).into_response_blocking(&req).await
+}
+
+/// Measure the latency of a small request sent together with [CONCURRENT_LARGE] requests to `large_uri`
+fn measure(name: &str, config: PayloadConfig, large_uri: &str) {
+    let large_body = serde_json::to_vec(&large()).unwrap();
+    let samples = actix_rt::System::new().block_on(async {
+        let app = init_service(
+            App::new()
+                .app_data(config.limit(16 * 1024 * 1024))
+                .route("/small", web::post().to(small))
+                .route("/deserialize", web::post().to(deserialize_large))
+                .route("/serialize", web::post().to(serialize_large))
+                .route("/serialize-blocking", web::post().to(serialize_large_blocking)),
+        )
+        .await;
+
+        let mut samples = Vec::with_capacity(ROUNDS);
+        for _ in 0..ROUNDS {
+            let large: Vec<_> = (0..CONCURRENT_LARGE)
+                .map(|_| {
+                    TestRequest::post()
+                        .uri(large_uri)
+                        .insert_header(("Content-Type", "application/json"))
+                        .set_payload(large_body.clone())
+                        .to_request()
+                })
+                .collect();
+            let small = TestRequest::post()
+                .uri("/small")
+                .insert_header(("Content-Type", "application/json"))
+                .set_payload(r#"{"name":"foo"}"#)
+                .to_request();
+
+            let start = Instant::now();
+            let small = async {
+                call_service(&app, small).await;
+                start.elapsed()
+            };
+            let large = futures_util::future::join_all(large.into_iter().map(|req| call_service(&app, req)));
+            let (_, latency) = futures_util::future::join(large, small).await;
+            samples.push(latency);
+        }
+
+        samples
+    });
+
+    common::report(name, samples);
+}
+
+fn main() {
+    println!("{CONCURRENT_LARGE} concurrent large bodies of {VALUES} integers, {ROUNDS} rounds");
+
+    measure("deserializing, inline", PayloadConfig::default(), "/deserialize");
+    measure(
+        "deserializing, blocking_threshold(64 KiB)",
+        PayloadConfig::default().blocking_threshold(64 * 1024),
+        "/deserialize",
+    );
+    measure("serializing, inline", PayloadConfig::default(), "/serialize");
+    measure("serializing, into_response_blocking", PayloadConfig::default(), "/serialize-blocking");
+}
@@ -0,0 +1,74 @@
+//! A minimal benchmark harness, the benchmarks are plain binaries run with `cargo bench`.
+
+// Every benchmark includes this module, not every benchmark uses every helper
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Run `f` `iterations` times after warming up, and print the duration of an iteration
+pub fn bench<F: FnMut()>(name: &str, iterations: usize, mut f: F) {
+    for _ in 0..iterations.div_ceil(10) {
+        f();
+    }
+
+    let samples = (0..iterations)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+    report(name, samples);
+}
+
+/// Print the mean, median and 99th percentile of `samples`
+pub fn report(name: &str, mut samples: Vec<Duration>) {
+    samples.sort();
+    let mean = samples.iter().sum::<Duration>() / samples.len() as u32;
+    let percentile = |p: usize| samples[(samples.len() * p / 100).min(samples.len() - 1)];
+
+    println!(
+        "{name:<52} mean {mean:>12?}  p50 {:>12?}  p99 {:>12?}  ({} samples)",
+        percentile(50),
+        percentile(99),
+        samples.len()
+    );
+}
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting allocations. Installed by benchmarks measuring allocations with
+/// `#[global_allocator] static ALLOCATOR: common::Counting = common::Counting;`
+pub struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Run `f` once and print the number of allocations and reallocations it made, and the bytes they requested.
+/// Only counts with [Counting] installed as the global allocator
+pub fn allocations<F: FnOnce()>(name: &str, f: F) {
+    let (count, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed));
+    f();
+    let count = ALLOCATIONS.load(Ordering::Relaxed) - count;
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+
+    println!("{name:<52} {count:>8} allocations  {:>10.1} KiB", bytes as f64 / 1024.0);
+}
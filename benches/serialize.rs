@@ -0,0 +1,85 @@
+//! Time and allocations of serializing large responses, for bodies of 1 MiB and up.
+//!
+//! JSON and XML are written into the body buffer directly, compared with serializing into a `String` first.
+//! Bodies above `PayloadConfig::stream_threshold` are written into a chain of chunks instead of one growing buffer,
+//! and `PayloadConfig::buffer_pool` reuses the buffer of the previous response.
+//!
+//! Run with `cargo bench --bench serialize`.
+
+mod common;
+
+use actix_multiresponse::{ContentType, Payload, PayloadConfig};
+use actix_web::test::TestRequest;
+
+#[global_allocator]
+static ALLOCATOR: common::Counting = common::Counting;
+
+#[derive(serde_derive::Serialize, prost_derive::Message)]
+struct Record {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(string, tag = "2")]
+    name: String,
+    #[prost(string, repeated, tag = "3")]
+    tags: Vec<String>,
+}
+
+#[derive(serde_derive::Serialize, prost_derive::Message)]
+struct Records {
+    #[prost(message, repeated, tag = "1")]
+    records: Vec<Record>,
+}
+
+/// About 100 bytes of JSON per record
+fn records(count: usize) -> Payload<Records> {
+    let records = (0..count as u64)
+        .map(|id| Record {
+            id,
+            name: format!("record number {id}"),
+            tags: vec!["first".to_string(), "second".to_string(), "third".to_string()],
+        })
+        .collect();
+    Payload::new(Records { records })
+}
+
+/// Measure serializing `payload` in `content_type`, through this crate and through a `String` first
+fn measure(payload: &Payload<Records>, content_type: ContentType, to_string: fn(&Records) -> String) {
+    let size = payload.serialize(content_type.clone()).unwrap().len();
+    let label = |what: &str| format!("{content_type} {:.1} MiB, {what}", size as f64 / (1024.0 * 1024.0));
+    let accept = content_type.to_string();
+    let response = |config: PayloadConfig| {
+        let req = TestRequest::default()
+            .insert_header(("Accept", accept.as_str()))
+            .app_data(config)
+            .to_http_request();
+        move || drop(payload.to_response(&req))
+    };
+
+    let via_string = || drop(to_string(payload).into_bytes());
+    let serialize = || drop(payload.serialize(content_type.clone()).unwrap());
+    let streamed = response(PayloadConfig::default().stream_threshold(64 * 1024));
+    let pooled = response(PayloadConfig::default().buffer_pool(1, 16 * 1024 * 1024));
+
+    common::bench(&label("String intermediate"), 20, via_string);
+    common::bench(&label("Payload::serialize"), 20, serialize);
+    let plain = response(PayloadConfig::default());
+    common::bench(&label("to_response"), 20, &plain);
+    common::bench(&label("to_response, stream_threshold"), 20, &streamed);
+    common::bench(&label("to_response, buffer_pool"), 20, &pooled);
+
+    common::allocations(&label("String intermediate"), via_string);
+    common::allocations(&label("Payload::serialize"), serialize);
+    common::allocations(&label("to_response"), &plain);
+    common::allocations(&label("to_response, stream_threshold"), &streamed);
+    // The first response fills the pool
+    pooled();
+    common::allocations(&label("to_response, buffer_pool"), &pooled);
+}
+
+fn main() {
+    for count in [10_000, 50_000] {
+        let payload = records(count);
+        measure(&payload, ContentType::Json, |value| serde_json::to_string_pretty(value).unwrap());
+        measure(&payload, ContentType::Xml, |value| quick_xml::se::to_string(value).unwrap());
+    }
+}
@@ -0,0 +1,29 @@
+#![no_main]
+
+use actix_multiresponse::ContentType;
+use actix_web::http::header::HeaderValue;
+use actix_web::test::TestRequest;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|value: &[u8]| {
+    if let Ok(value) = std::str::from_utf8(value) {
+        let _ = ContentType::from_header_value(value);
+        let _ = ContentType::from_accept_value(value, ContentType::enabled());
+        let _ = value.parse::<ContentType>();
+    }
+
+    // Header values may be any visible bytes, not only UTF-8
+    let header_value = match HeaderValue::from_bytes(value) {
+        Ok(header_value) => header_value,
+        Err(_) => return,
+    };
+
+    let req = TestRequest::default()
+        .insert_header(("Content-Type", header_value.clone()))
+        .append_header(("Accept", header_value))
+        .to_http_request();
+
+    let _ = ContentType::from_request_header(&req, "Content-Type");
+    let _ = ContentType::try_from_request_content_type(&req);
+    let _ = ContentType::from_request_accepts(&req);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use actix_multiresponse::ContentType;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|body: &[u8]| {
+    actix_multiresponse_fuzz::deserialize(body, ContentType::GrpcWeb);
+});
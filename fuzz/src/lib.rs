@@ -0,0 +1,34 @@
+//! Shared types of the fuzz targets.
+
+use actix_multiresponse::{ContentType, Payload, PayloadConfig};
+
+use std::collections::HashMap;
+
+/// A recursive message covering the common field types of all formats
+#[derive(serde_derive::Deserialize, prost_derive::Message)]
+pub struct FuzzPayload {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(int64, tag = "2")]
+    pub value: i64,
+    #[prost(double, tag = "3")]
+    pub ratio: f64,
+    #[prost(bytes = "vec", tag = "4")]
+    pub data: Vec<u8>,
+    #[prost(map = "string, string", tag = "5")]
+    pub labels: HashMap<String, String>,
+    #[prost(message, repeated, tag = "6")]
+    pub children: Vec<FuzzPayload>,
+    #[prost(message, optional, boxed, tag = "7")]
+    pub next: Option<Box<FuzzPayload>>,
+}
+
+/// Deserialize `body` in `content_type`, with the default configuration and with unknown fields denied.
+/// Errors are expected, only panics are failures
+pub fn deserialize(body: &[u8], content_type: ContentType) {
+    let _ = Payload::<FuzzPayload>::deserialize(body, content_type.clone());
+    let _ = Payload::<FuzzPayload>::deserialize_bytes(body.to_vec().into(), content_type.clone());
+
+    let strict = PayloadConfig::default().deny_unknown_fields(true);
+    let _ = Payload::<FuzzPayload>::deserialize_with(body, content_type, &strict);
+}
@@ -0,0 +1,154 @@
+use crate::headers::merged_header_value;
+use crate::{ContentType, Negotiation, PayloadConfig};
+
+use actix_web::dev::Payload as DevPayload;
+use actix_web::{FromRequest, HttpRequest};
+
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+
+/// The formats accepted by the `Accept` header of a request, with their quality values.
+///
+/// This lets handlers branch on the accepted formats themselves, e.g. to return a different representation
+/// of a resource, using the same parsing as the negotiation of [crate::Payload].
+/// Only formats supported with the [PayloadConfig] of the request are included, formats with a quality value of `0`
+/// are excluded. Formats with the same quality value are ordered by the preference of the configuration.
+///
+/// Without an `Accept` header, every supported format is accepted with a quality value of `1`.
+/// Malformed media ranges are skipped, with [PayloadConfig::strict_accept] a malformed `Accept` header accepts no format.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "json")]
+/// # fn main() {
+///     use actix_multiresponse::{AcceptedFormats, ContentType};
+///
+///     async fn handler(accepted: AcceptedFormats) -> String {
+///         match accepted.best() {
+///             Some(ContentType::Json) => "json".to_string(),
+///             _ => "something else".to_string(),
+///         }
+///     }
+/// # }
+/// # #[cfg(not(feature = "json"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct AcceptedFormats(Vec<AcceptedFormat>);
+
+/// A format in [AcceptedFormats]
+#[derive(Debug, PartialEq, Clone)]
+pub struct AcceptedFormat {
+    /// The accepted format
+    pub format: ContentType,
+    /// The quality value of the format, between `0.0` exclusive and `1.0`
+    pub q: f32,
+}
+
+impl AcceptedFormats {
+    /// The formats accepted by the request, usually done by extracting [AcceptedFormats] instead
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let config = PayloadConfig::from_req(req);
+        let preference = config.preference_order(&Negotiation::from_request(req).content_type);
+
+        let accepted = match merged_header_value(req.headers(), "Accept") {
+            Some(value) => ContentType::accepted_formats(&value, &preference, config.strict_accept).unwrap_or_default(),
+            None => preference.into_iter().map(|format| (format, 1.0)).collect(),
+        };
+
+        Self(accepted.into_iter().map(|(format, q)| AcceptedFormat { format, q }).collect())
+    }
+
+    /// The accepted formats, the highest quality value first
+    pub fn formats(&self) -> &[AcceptedFormat] {
+        &self.0
+    }
+
+    /// The most preferred accepted format, if any
+    pub fn best(&self) -> Option<&ContentType> {
+        self.0.first().map(|accepted| &accepted.format)
+    }
+
+    /// The quality value of `format`, `None` if it is not accepted
+    pub fn quality(&self, format: &ContentType) -> Option<f32> {
+        self.0.iter().find(|accepted| accepted.format.eq(format)).map(|accepted| accepted.q)
+    }
+
+    /// Whether `format` is accepted
+    pub fn accepts(&self, format: &ContentType) -> bool {
+        self.quality(format).is_some()
+    }
+
+    /// Whether no format is accepted
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl IntoIterator for AcceptedFormats {
+    type Item = AcceptedFormat;
+    type IntoIter = std::vec::IntoIter<AcceptedFormat>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a AcceptedFormats {
+    type Item = &'a AcceptedFormat;
+    type IntoIter = std::slice::Iter<'a, AcceptedFormat>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl FromRequest for AcceptedFormats {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut DevPayload) -> Self::Future {
+        ready(Ok(AcceptedFormats::from_request(req)))
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_quality_order() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json;q=0.5, application/protobuf, text/html"))
+            .to_http_request();
+        let accepted = AcceptedFormats::from_request(&req);
+
+        let formats: Vec<&ContentType> = accepted.formats().iter().map(|accepted| &accepted.format).collect();
+        assert_eq!(vec![&ContentType::Protobuf, &ContentType::Json], formats);
+        assert_eq!(Some(0.5), accepted.quality(&ContentType::Json));
+    }
+
+    #[test]
+    fn test_ties_and_exclusions() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "*/*;q=0.8, application/json;q=0"))
+            .app_data(PayloadConfig::default().preference([ContentType::Protobuf]))
+            .to_http_request();
+        let accepted = AcceptedFormats::from_request(&req);
+
+        assert_eq!(Some(&ContentType::Protobuf), accepted.best());
+        assert!(!accepted.accepts(&ContentType::Json));
+        assert!(!accepted.accepts(&ContentType::GrpcWeb));
+    }
+
+    #[test]
+    fn test_missing_and_malformed() {
+        let req = TestRequest::get().to_http_request();
+        let accepted = AcceptedFormats::from_request(&req);
+        assert!(accepted.into_iter().all(|accepted| accepted.q == 1.0));
+
+        let req = TestRequest::get().insert_header(("Accept", "json")).to_http_request();
+        assert!(AcceptedFormats::from_request(&req).is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+//! Batch requests, with a `multipart/mixed` or `multipart/batch` body where every part is a payload.
+
+use crate::negotiated_error::negotiated_extract;
+use crate::{body, ContentType, Payload, PayloadConfig, PayloadError, ProtobufSupport, SerdeSupportDeserialize};
+
+use actix_web::dev::Payload as DevPayload;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest};
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// A batch of payloads, extracted from a `multipart/mixed` or `multipart/batch` body.
+///
+/// Every part is deserialized independently, in the format indicated by the `Content-Type` header of the part.
+/// A part which fails to deserialize does not reject the request, its error is returned in its place instead.
+/// The request is rejected with a HTTP 400 error if its `Content-Type` is not multipart, or if the body is not valid multipart.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::BatchPayload;
+///
+///     async fn bulk(batch: BatchPayload<String>) -> String {
+///         let failed = batch.0.iter().filter(|part| part.is_err()).count();
+///         format!("{failed} parts failed")
+///     }
+/// ```
+#[derive(Debug)]
+pub struct BatchPayload<T>(pub Vec<Result<Payload<T>, PayloadError>>);
+
+impl<T: 'static + SerdeSupportDeserialize + ProtobufSupport> FromRequest for BatchPayload<T> {
+    type Error = PayloadError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut DevPayload) -> Self::Future {
+        let req = req.clone();
+        let payload = payload.take();
+
+        negotiated_extract(req.clone(), async move {
+            let boundary = req
+                .headers()
+                .get("Content-Type")
+                .and_then(|header_value| header_value.to_str().ok())
+                .and_then(boundary)
+                .ok_or_else(|| PayloadError::InvalidContentType(ContentType::from_request_content_type(&req)))?;
+
+            let config = PayloadConfig::from_req(&req);
+            let payload_bytes = body::read_body_with(&req, payload, config).await?;
+
+            let parts = split_parts(&payload_bytes, &boundary)
+                .map_err(|e| PayloadError::InvalidMultipart(e.to_string()))?
+                .into_iter()
+                .map(|part| deserialize_part(config, part))
+                .collect();
+
+            Ok(Self(parts))
+        })
+    }
+}
+
+/// Deserialize one part, consisting of its headers and body
+fn deserialize_part<T: SerdeSupportDeserialize + ProtobufSupport>(
+    config: &PayloadConfig,
+    part: Bytes,
+) -> Result<Payload<T>, PayloadError> {
+    let (headers, body) = match find(&part, b"\r\n\r\n") {
+        Some(idx) => (&part[..idx], part.slice(idx + 4..)),
+        // A part starting with an empty line has no headers
+        None if part.starts_with(b"\r\n") => (&part[..0], part.slice(2..)),
+        None => return Err(PayloadError::InvalidMultipart("Part has no header section".to_string())),
+    };
+
+    let content_type = headers
+        .split(|b| *b == b'\n')
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Content-Type"))
+        .map(|(_, value)| ContentType::from_header_value(value.trim()))
+        .unwrap_or(ContentType::Other(String::new()));
+
+    if !config.supports(&content_type) {
+        return Err(PayloadError::InvalidContentType(content_type));
+    }
+
+    body::prepare_body(config, body.clone(), &content_type)
+        .and_then(|prepared| Payload::deserialize_bytes_with(prepared, content_type.clone(), config))
+        .map_err(|error| {
+            config.report_error(PayloadError::Deserialize {
+                error,
+                body,
+                content_type,
+            })
+        })
+}
+
+/// The boundary of a `multipart/mixed` or `multipart/batch` content type
+fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    let essence = params.next()?.trim().to_lowercase();
+    if essence.ne("multipart/mixed") && essence.ne("multipart/batch") {
+        return None;
+    }
+
+    params
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Split a multipart body into its parts, each consisting of the headers and the body of the part.
+/// The preamble and epilogue are ignored.
+fn split_parts(body: &Bytes, boundary: &str) -> Result<Vec<Bytes>, &'static str> {
+    let delimiter = format!("--{boundary}");
+    let delimiter = delimiter.as_bytes();
+
+    let mut pos = find(body, delimiter).ok_or("Missing boundary")? + delimiter.len();
+    let mut parts = Vec::new();
+    loop {
+        if body[pos..].starts_with(b"--") {
+            return Ok(parts);
+        }
+
+        // Skip the transport padding and the line break after the delimiter
+        let start = pos + find(&body[pos..], b"\r\n").ok_or("Missing line break after boundary")? + 2;
+        let end = start
+            + find(&body[start..], &[b"\r\n", delimiter].concat()).ok_or("Missing closing boundary")?;
+
+        parts.push(body.slice(start..end));
+        pos = end + 2 + delimiter.len();
+    }
+}
+
+/// The position of the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window.eq(needle))
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[derive(serde_derive::Deserialize, prost_derive::Message)]
+    struct TestPayload {
+        #[prost(string, tag = "1")]
+        foo: String,
+    }
+
+    #[test]
+    fn test_boundary() {
+        assert_eq!(Some("abc".to_string()), boundary(r#"multipart/mixed; boundary="abc""#));
+        assert_eq!(Some("abc".to_string()), boundary("Multipart/Batch;boundary=abc"));
+        assert_eq!(None, boundary("multipart/form-data; boundary=abc"));
+        assert_eq!(None, boundary("multipart/mixed"));
+    }
+
+    #[actix_macros::test]
+    async fn test_batch() {
+        let protobuf = prost::Message::encode_to_vec(&TestPayload { foo: "baz".to_string() });
+        let body = [
+            &b"preamble\r\n--abc\r\nContent-Type: application/json\r\n\r\n{\"foo\":\"bar\"}\r\n"[..],
+            &b"--abc\r\nContent-Type: application/protobuf\r\n\r\n"[..],
+            &protobuf,
+            &b"\r\n--abc\r\nContent-Type: application/json\r\n\r\nnot json\r\n"[..],
+            &b"--abc\r\nContent-Type: text/plain\r\n\r\nfoo\r\n--abc--\r\n"[..],
+        ]
+        .concat();
+
+        let (req, mut payload) = TestRequest::post()
+            .insert_header(("Content-Type", "multipart/mixed; boundary=abc"))
+            .set_payload(body)
+            .to_http_parts();
+        let batch = BatchPayload::<TestPayload>::from_request(&req, &mut payload).await.unwrap();
+
+        assert_eq!(4, batch.0.len());
+        assert_eq!("bar", batch.0[0].as_ref().unwrap().foo);
+        assert_eq!("baz", batch.0[1].as_ref().unwrap().foo);
+        assert!(matches!(batch.0[2], Err(PayloadError::Deserialize { .. })));
+        assert!(matches!(batch.0[3], Err(PayloadError::InvalidContentType(_))));
+    }
+
+    #[actix_macros::test]
+    async fn test_missing_closing_boundary() {
+        let (req, mut payload) = TestRequest::post()
+            .insert_header(("Content-Type", "multipart/mixed; boundary=abc"))
+            .set_payload("--abc\r\nContent-Type: application/json\r\n\r\n{}")
+            .to_http_parts();
+        let result = BatchPayload::<TestPayload>::from_request(&req, &mut payload).await;
+
+        assert!(matches!(result.unwrap_err().detailed(), PayloadError::InvalidMultipart(_)));
+    }
+}
@@ -0,0 +1,435 @@
+use crate::{checksum, idempotency, ContentType, DeserializeError, PayloadConfig, PayloadError};
+#[cfg(feature = "protobuf")]
+use crate::codec;
+
+use actix_web::dev::Payload as DevPayload;
+use actix_web::error::PayloadError as ActixPayloadError;
+use actix_web::http::header::{HeaderMap, CONTENT_LENGTH};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{HttpMessage, HttpRequest};
+
+use futures_util::StreamExt;
+
+#[cfg(feature = "xml")]
+use std::fmt;
+#[cfg(feature = "json")]
+use std::io;
+
+/// The size of the chunks of streamed response bodies, 64 KiB
+pub(crate) const CHUNK_SIZE: usize = 65_536;
+
+/// The most of a body announced with `Content-Length` which is allocated before it is received, 256 KiB
+const PREALLOCATE_LIMIT: usize = 262_144;
+
+/// Aggregate the request body into a single buffer.
+/// A body consisting of a single chunk is returned without copying it.
+///
+/// # Errors
+///
+/// If the payload stream yields an error, or if the body is larger than `limit` bytes,
+/// in which case [ActixPayloadError::Overflow] is returned. If the `Content-Length` header of the request
+/// already exceeds the limit, the body is rejected before any of it is read.
+/// Bodies without a `Content-Length`, e.g. chunked bodies, are rejected as soon as the limit is crossed.
+pub(crate) async fn read_body(req: &HttpRequest, mut payload: DevPayload, limit: usize) -> Result<Bytes, ActixPayloadError> {
+    let mut aggregator = Aggregator::new(req, limit)?;
+    while let Some(b) = payload.next().await {
+        aggregator.push(b?)?;
+    }
+
+    Ok(aggregator.finish())
+}
+
+/// The chunks of a request body read so far, see [read_body]
+pub(crate) struct Aggregator {
+    first: Option<Bytes>,
+    aggregated: Option<BytesMut>,
+    len: usize,
+    limit: usize,
+    /// The `Content-Length` of the request, if any
+    expected: Option<usize>,
+}
+
+impl Aggregator {
+    /// Start reading the body of `req`, rejecting it if its `Content-Length` exceeds `limit` already
+    pub(crate) fn new(req: &HttpRequest, limit: usize) -> Result<Self, ActixPayloadError> {
+        let expected = content_length(req.headers());
+        if expected.is_some_and(|len| len > limit) {
+            return Err(ActixPayloadError::Overflow);
+        }
+
+        Ok(Self {
+            first: None,
+            aggregated: None,
+            len: 0,
+            limit,
+            expected,
+        })
+    }
+
+    /// Add a chunk, failing if the body becomes larger than the limit
+    pub(crate) fn push(&mut self, b: Bytes) -> Result<(), ActixPayloadError> {
+        self.len += b.len();
+        if self.len > self.limit {
+            return Err(ActixPayloadError::Overflow);
+        }
+
+        let capacity = self.capacity();
+        match (&mut self.aggregated, self.first.take()) {
+            (Some(aggregated), _) => {
+                if aggregated.capacity() < self.len {
+                    aggregated.reserve(capacity - aggregated.len());
+                }
+                aggregated.extend_from_slice(&b);
+            }
+            (None, Some(first)) => {
+                let mut buf = BytesMut::with_capacity(capacity);
+                buf.extend_from_slice(&first);
+                buf.extend_from_slice(&b);
+                self.aggregated = Some(buf);
+            }
+            (None, None) => self.first = Some(b),
+        }
+
+        Ok(())
+    }
+
+    /// The capacity for the body once `len` bytes were received. The `Content-Length` is allocated at once if
+    /// it is known, but at most [PREALLOCATE_LIMIT] or twice the bytes received, so a client announcing
+    /// a large body and sending only a few bytes of it does not make each request allocate the limit
+    fn capacity(&self) -> usize {
+        self.expected
+            .unwrap_or_default()
+            .min(self.len.saturating_mul(2).max(PREALLOCATE_LIMIT))
+            .max(self.len)
+    }
+
+    /// The number of bytes read so far
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The complete body
+    pub(crate) fn finish(self) -> Bytes {
+        match self.aggregated {
+            Some(aggregated) => aggregated.freeze(),
+            None => self.first.unwrap_or_default(),
+        }
+    }
+}
+
+/// The aggregated request body, stored in the request extensions by [read_body_with]
+#[derive(Clone)]
+pub(crate) struct CachedBody {
+    body: Bytes,
+    /// Whether the body matches the checksum headers of the request, see [PayloadConfig::verify_checksums]
+    verified: bool,
+}
+
+impl CachedBody {
+    /// Cache `body`, of which `verified` tells whether it matches the checksum headers of the request
+    pub(crate) fn new(body: Bytes, verified: bool) -> Self {
+        Self { body, verified }
+    }
+}
+
+/// Like [read_body], with the limit and the read timeout of `config`.
+/// If the body is not received within the timeout, [PayloadError::Timeout] is returned.
+/// With [PayloadConfig::verify_checksums], a body not matching its checksum headers is rejected.
+/// The [crate::IdempotencyKey] of the request is captured here, see [PayloadConfig::idempotency_key].
+///
+/// The body is cached in the request extensions, so the extractors of this crate can be used more than once
+/// for a request, e.g. in middleware and in the handler, even though the payload stream can only be read once.
+/// A cached body is checked against the limit and checksums of `config` again, see [cached_body].
+pub(crate) async fn read_body_with(
+    req: &HttpRequest,
+    payload: DevPayload,
+    config: &PayloadConfig,
+) -> Result<Bytes, PayloadError> {
+    if let Some(body) = cached_body(req, config) {
+        return body;
+    }
+
+    let read = read_body(req, payload, config.limit);
+    let body = match config.read_timeout {
+        Some(timeout) => actix_rt::time::timeout(timeout, read)
+            .await
+            .map_err(|_| PayloadError::Timeout)??,
+        None => read.await?,
+    };
+
+    complete_body(req, body, config)
+}
+
+/// The body cached in the request extensions by [read_body_with], if it was read already.
+///
+/// The body may have been read with a more lenient configuration, e.g. by middleware using the configuration of the app,
+/// so it is rejected if it exceeds the limit of `config`, and verified if `config` requires checksums
+/// and it was not verified before.
+pub(crate) fn cached_body(req: &HttpRequest, config: &PayloadConfig) -> Option<Result<Bytes, PayloadError>> {
+    let CachedBody { body, verified } = req.extensions().get::<CachedBody>()?.clone();
+    if body.len() > config.limit {
+        return Some(Err(ActixPayloadError::Overflow.into()));
+    }
+
+    if config.verify_checksums && !verified {
+        if let Err(e) = checksum::verify(req.headers(), &body) {
+            return Some(Err(e));
+        }
+        req.extensions_mut().insert(CachedBody::new(body.clone(), true));
+    }
+
+    Some(Ok(body))
+}
+
+/// Verify and cache a body which was read completely, as [read_body_with] does
+pub(crate) fn complete_body(req: &HttpRequest, body: Bytes, config: &PayloadConfig) -> Result<Bytes, PayloadError> {
+    if config.verify_checksums {
+        checksum::verify(req.headers(), &body)?;
+    }
+    idempotency::capture(req, &body, config)?;
+
+    req.extensions_mut().insert(CachedBody::new(body.clone(), config.verify_checksums));
+    Ok(body)
+}
+
+/// The length of the request body according to the `Content-Length` header, if it is present and valid
+pub(crate) fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|hv_str| hv_str.trim().parse().ok())
+}
+
+/// A writer collecting the written data in chunks of [CHUNK_SIZE] bytes
+#[cfg(any(feature = "json", feature = "xml"))]
+#[derive(Default)]
+pub(crate) struct ChunkWriter {
+    chunks: Vec<Bytes>,
+    current: BytesMut,
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl ChunkWriter {
+    /// The written data, in chunks
+    pub(crate) fn into_chunks(mut self) -> Vec<Bytes> {
+        if !self.current.is_empty() {
+            self.chunks.push(self.current.freeze());
+        }
+
+        self.chunks
+    }
+
+    fn write_bytes(&mut self, buf: &[u8]) {
+        self.current.extend_from_slice(buf);
+        while self.current.len() >= CHUNK_SIZE {
+            self.chunks.push(self.current.split_to(CHUNK_SIZE).freeze());
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl io::Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Allows serializers producing text, like the XML serializer, to write into chunks directly.
+/// Chunks are split at byte boundaries, so a chunk may end in the middle of a character.
+#[cfg(feature = "xml")]
+impl fmt::Write for ChunkWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Split a buffer into chunks of [CHUNK_SIZE] bytes, without copying
+pub(crate) fn split_chunks(buf: Vec<u8>) -> Vec<Bytes> {
+    let mut buf = Bytes::from(buf);
+    let mut chunks = Vec::with_capacity(buf.len() / CHUNK_SIZE + 1);
+    while buf.len() > CHUNK_SIZE {
+        chunks.push(buf.split_to(CHUNK_SIZE));
+    }
+
+    if !buf.is_empty() {
+        chunks.push(buf);
+    }
+
+    chunks
+}
+
+/// Prepare a request body in `content_type` for deserialization, according to the configuration.
+/// This strips the length delimiter of protobuf bodies, if configured, and applies the [PayloadConfig::transform].
+pub(crate) fn prepare_body(
+    config: &PayloadConfig,
+    body: Bytes,
+    content_type: &ContentType,
+) -> Result<Bytes, DeserializeError> {
+    #[cfg(feature = "protobuf")]
+    let body = if content_type.eq(&ContentType::Protobuf) && config.protobuf_length_delimited {
+        codec::strip_length_delimiter(body)?
+    } else {
+        body
+    };
+
+    match &config.transform {
+        Some(transform) => transform.apply(content_type, body).map_err(DeserializeError::Transform),
+        None => Ok(body),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn stream_payload(chunks: Vec<&'static [u8]>) -> DevPayload {
+        let stream = futures_util::stream::iter(
+            chunks.into_iter().map(|chunk| Ok(Bytes::from_static(chunk))),
+        );
+        let stream: std::pin::Pin<Box<dyn futures_util::Stream<Item = _>>> = Box::pin(stream);
+        DevPayload::from(stream)
+    }
+
+    #[actix_macros::test]
+    async fn test_single_chunk() {
+        let body = read_body(&TestRequest::default().to_http_request(), stream_payload(vec![b"foo"]), 10).await.unwrap();
+        assert_eq!(&b"foo"[..], &body[..]);
+    }
+
+    #[actix_macros::test]
+    async fn test_multiple_chunks() {
+        let body = read_body(&TestRequest::default().to_http_request(), stream_payload(vec![b"foo", b"bar", b"baz"]), 10).await.unwrap();
+        assert_eq!(&b"foobarbaz"[..], &body[..]);
+    }
+
+    #[actix_macros::test]
+    async fn test_overflow() {
+        let req = TestRequest::default().to_http_request();
+        let result = read_body(&req, stream_payload(vec![b"foo", b"bar"]), 5).await;
+        assert!(matches!(result, Err(ActixPayloadError::Overflow)));
+    }
+
+    #[actix_macros::test]
+    async fn test_content_length_overflow() {
+        // The body itself fits, but is never read
+        let req = TestRequest::default().insert_header(("Content-Length", "11")).to_http_request();
+        let result = read_body(&req, stream_payload(vec![b"foo"]), 10).await;
+        assert!(matches!(result, Err(ActixPayloadError::Overflow)));
+    }
+
+    #[test]
+    fn test_preallocation_bounded() {
+        let req = TestRequest::default().insert_header(("Content-Length", "2097152")).to_http_request();
+        let mut aggregator = Aggregator::new(&req, 2 * 1024 * 1024).unwrap();
+        aggregator.push(Bytes::from_static(b"foo")).unwrap();
+        aggregator.push(Bytes::from_static(b"bar")).unwrap();
+        assert!(aggregator.aggregated.as_ref().unwrap().capacity() <= PREALLOCATE_LIMIT);
+
+        let chunk = Bytes::from(vec![0; PREALLOCATE_LIMIT]);
+        aggregator.push(chunk.clone()).unwrap();
+        aggregator.push(chunk).unwrap();
+        assert!(aggregator.aggregated.as_ref().unwrap().capacity() <= 4 * PREALLOCATE_LIMIT);
+        assert_eq!(2 * PREALLOCATE_LIMIT + 6, aggregator.finish().len());
+
+        // Small bodies are still allocated at once
+        let req = TestRequest::default().insert_header(("Content-Length", "1024")).to_http_request();
+        let mut aggregator = Aggregator::new(&req, 2 * 1024 * 1024).unwrap();
+        aggregator.push(Bytes::from_static(b"foo")).unwrap();
+        aggregator.push(Bytes::from_static(b"bar")).unwrap();
+        assert_eq!(1024, aggregator.aggregated.as_ref().unwrap().capacity());
+    }
+
+    #[actix_macros::test]
+    async fn test_empty() {
+        let body = read_body(&TestRequest::default().to_http_request(), stream_payload(vec![]), 10).await.unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[actix_macros::test]
+    async fn test_cached_body() {
+        let req = TestRequest::default().to_http_request();
+        let config = PayloadConfig::default();
+
+        let first = read_body_with(&req, stream_payload(vec![b"foo"]), &config).await.unwrap();
+        // The payload stream has been consumed at this point
+        let second = read_body_with(&req, stream_payload(vec![]), &config).await.unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[actix_macros::test]
+    async fn test_cached_body_stricter_config() {
+        let req = TestRequest::default()
+            .insert_header(("Content-MD5", "rL0Y20zC+Fzt72VPzMSk2A=="))
+            .to_http_request();
+        let lenient = PayloadConfig::default();
+        read_body_with(&req, stream_payload(vec![b"bar"]), &lenient).await.unwrap();
+
+        // A route with a lower limit or checksums rejects the body read with the configuration of the app
+        let limited = PayloadConfig::default().limit(2);
+        let result = read_body_with(&req, stream_payload(vec![]), &limited).await;
+        assert!(matches!(result, Err(PayloadError::ActixPayload(ActixPayloadError::Overflow))));
+        let verified = PayloadConfig::default().verify_checksums(true);
+        let result = read_body_with(&req, stream_payload(vec![]), &verified).await;
+        assert!(matches!(result, Err(PayloadError::ChecksumMismatch(_))));
+        assert!(read_body_with(&req, stream_payload(vec![]), &lenient).await.is_ok());
+
+        let req = TestRequest::default()
+            .insert_header(("Content-MD5", "rL0Y20zC+Fzt72VPzMSk2A=="))
+            .to_http_request();
+        read_body_with(&req, stream_payload(vec![b"foo"]), &lenient).await.unwrap();
+        assert_eq!(&b"foo"[..], &read_body_with(&req, stream_payload(vec![]), &verified).await.unwrap()[..]);
+        assert!(req.extensions().get::<CachedBody>().unwrap().verified);
+    }
+
+    #[actix_macros::test]
+    async fn test_read_timeout() {
+        let stream = futures_util::stream::pending::<Result<Bytes, ActixPayloadError>>();
+        let stream: std::pin::Pin<Box<dyn futures_util::Stream<Item = _>>> = Box::pin(stream);
+        let config = PayloadConfig::default().read_timeout(std::time::Duration::from_millis(10));
+
+        let result = read_body_with(&TestRequest::default().to_http_request(), DevPayload::from(stream), &config).await;
+        assert!(matches!(result, Err(PayloadError::Timeout)));
+    }
+
+    #[actix_macros::test]
+    async fn test_verify_checksums() {
+        let req = TestRequest::default()
+            .insert_header(("Content-MD5", "rL0Y20zC+Fzt72VPzMSk2A=="))
+            .to_http_request();
+        let config = PayloadConfig::default().verify_checksums(true);
+
+        let result = read_body_with(&req, stream_payload(vec![b"bar"]), &config).await;
+        assert!(matches!(result, Err(PayloadError::ChecksumMismatch(_))));
+        let body = read_body_with(&req, stream_payload(vec![b"foo"]), &config).await.unwrap();
+        assert_eq!(&b"foo"[..], &body[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_chunk_writer() {
+        use std::io::Write;
+
+        let mut writer = ChunkWriter::default();
+        writer.write_all(&vec![1; CHUNK_SIZE + 1]).unwrap();
+        writer.write_all(&[2]).unwrap();
+
+        let chunks = writer.into_chunks();
+        assert_eq!(vec![CHUNK_SIZE, 2], chunks.iter().map(Bytes::len).collect::<Vec<_>>());
+        assert_eq!(&[1, 2], &chunks[1][..]);
+    }
+
+    #[test]
+    fn test_split_chunks() {
+        let chunks = split_chunks(vec![0; 2 * CHUNK_SIZE + 1]);
+        assert_eq!(vec![CHUNK_SIZE, CHUNK_SIZE, 1], chunks.iter().map(Bytes::len).collect::<Vec<_>>());
+
+        assert!(split_chunks(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+//! Deserializing request bodies into types borrowing from the body, for JSON and XML.
+
+use crate::negotiated_error::negotiated_extract;
+use crate::{
+    body, codec, ContentType, DeserializeError, Negotiation, Payload, PayloadConfig, PayloadError, ProtobufSupport,
+    SerdeSupportDeserialize,
+};
+
+use actix_web::dev::Payload as DevPayload;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest};
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// The request body, retained so it can be deserialized into types borrowing from it,
+/// e.g. structs with `&str` or `#[serde(borrow)] Cow<'a, str>` fields.
+///
+/// This avoids allocating every string of large, text heavy bodies. The body is read like [Payload] reads it,
+/// including the [PayloadConfig::transform], but only deserialized by [BorrowedPayload::deserialize].
+/// Protobuf messages can't borrow from the body, use [BorrowedPayload::deserialize_owned] to accept them as well.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::BorrowedPayload;
+///     use actix_web::HttpResponse;
+///     use serde_derive::Deserialize;
+///     use std::borrow::Cow;
+///
+///     #[derive(Deserialize)]
+///     struct Article<'a> {
+///         #[serde(borrow)]
+///         title: Cow<'a, str>,
+///         #[serde(borrow)]
+///         text: Cow<'a, str>,
+///     }
+///
+///     async fn publish(payload: BorrowedPayload) -> Result<HttpResponse, actix_web::Error> {
+///         let article: Article = payload.deserialize()?;
+///         Ok(HttpResponse::Ok().body(format!("{} has {} bytes", article.title, article.text.len())))
+///     }
+/// ```
+#[derive(Debug)]
+pub struct BorrowedPayload {
+    body: Bytes,
+    content_type: ContentType,
+    req: HttpRequest,
+}
+
+impl BorrowedPayload {
+    /// The request body, after the [PayloadConfig::transform]
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The format of the request body
+    pub fn content_type(&self) -> &ContentType {
+        &self.content_type
+    }
+
+    /// Deserialize the body into a type borrowing from it, using the deserializer settings of the [PayloadConfig].
+    /// Protobuf bodies are rejected with [PayloadError::InvalidContentType]. Fields are only borrowed if the format
+    /// allows it, e.g. JSON strings containing escape sequences are copied into a `Cow`, while a `&str` fails
+    pub fn deserialize<'de, T: serde::Deserialize<'de>>(&'de self) -> Result<T, PayloadError> {
+        let config = PayloadConfig::from_req(&self.req);
+        let deserialized = match self.content_type {
+            #[cfg(feature = "json")]
+            ContentType::Json => codec::deserialize_json(&self.body, config),
+            #[cfg(feature = "xml")]
+            ContentType::Xml => codec::deserialize_xml_borrowed(&self.body, config),
+            _ => return Err(PayloadError::InvalidContentType(self.content_type.clone())),
+        };
+
+        deserialized.map_err(|error| self.report(config, error))
+    }
+
+    /// Deserialize the body into an owned type, from any format, exactly like [Payload] does
+    pub fn deserialize_owned<T: ProtobufSupport + SerdeSupportDeserialize>(&self) -> Result<T, PayloadError> {
+        let config = PayloadConfig::from_req(&self.req);
+        Payload::<T>::deserialize_bytes_with(self.body.clone(), self.content_type.clone(), config)
+            .map(|payload| payload.0)
+            .map_err(|error| self.report(config, error))
+    }
+
+    fn report(&self, config: &PayloadConfig, error: DeserializeError) -> PayloadError {
+        config.report_error(PayloadError::Deserialize {
+            error,
+            body: self.body.clone(),
+            content_type: self.content_type.clone(),
+        })
+    }
+}
+
+impl FromRequest for BorrowedPayload {
+    type Error = PayloadError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut DevPayload) -> Self::Future {
+        let req = req.clone();
+        let payload = payload.take();
+
+        negotiated_extract(req.clone(), async move {
+            let content_type = Negotiation::try_from_request(&req)?.content_type;
+            if content_type.is_other() {
+                return Err(PayloadError::InvalidContentType(content_type));
+            }
+
+            let config = PayloadConfig::from_req(&req);
+            let payload_bytes = body::read_body_with(&req, payload, config).await?;
+            let body = body::prepare_body(config, payload_bytes.clone(), &content_type).map_err(|error| {
+                config.report_error(PayloadError::Deserialize {
+                    error,
+                    body: payload_bytes,
+                    content_type: content_type.clone(),
+                })
+            })?;
+
+            Ok(Self {
+                body,
+                content_type,
+                req,
+            })
+        })
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::borrow::Cow;
+
+    #[derive(serde_derive::Deserialize)]
+    struct Article<'a> {
+        title: &'a str,
+        #[serde(borrow)]
+        text: Cow<'a, str>,
+    }
+
+    async fn extract(body: &'static str) -> BorrowedPayload {
+        let (req, mut payload) = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(body)
+            .to_http_parts();
+        BorrowedPayload::from_request(&req, &mut payload).await.unwrap()
+    }
+
+    #[actix_macros::test]
+    async fn test_borrowed() {
+        let payload = extract(r#"{"title":"foo","text":"bar"}"#).await;
+        let article: Article = payload.deserialize().unwrap();
+
+        assert_eq!("foo", article.title);
+        assert!(matches!(article.text, Cow::Borrowed("bar")));
+        assert!(std::ptr::eq(article.title.as_ptr(), payload.body()[10..].as_ptr()));
+    }
+
+    #[actix_macros::test]
+    async fn test_escaped() {
+        let payload = extract(r#"{"title":"foo","text":"b\"ar"}"#).await;
+        let article: Article = payload.deserialize().unwrap();
+        assert!(matches!(article.text, Cow::Owned(text) if text == "b\"ar"));
+
+        let payload = extract(r#"{"title":"f\"oo","text":"bar"}"#).await;
+        assert!(payload.deserialize::<Article>().is_err());
+    }
+
+    #[actix_macros::test]
+    async fn test_deserialize_owned() {
+        let payload = extract(r#""foo""#).await;
+        assert_eq!("foo", payload.deserialize_owned::<String>().unwrap());
+    }
+}
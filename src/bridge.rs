@@ -0,0 +1,281 @@
+//! Middleware re-encoding request bodies into the format the handlers expect.
+
+use crate::{body, checksum, supports_format, ContentType, Negotiation, Payload, PayloadConfig, PayloadError, ProtobufSupport};
+use crate::{SerdeSupportDeserialize, SerdeSupportSerialize};
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use actix_web::web::Bytes;
+use actix_web::HttpMessage;
+
+use futures_util::future::LocalBoxFuture;
+
+use std::future::{ready, Ready};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// Middleware which re-encodes request bodies of type `T` into one format, so handlers only have to deal with that format.
+/// E.g. accept XML and protobuf from clients, while handing JSON to handlers using other extractors, or to a proxied service.
+///
+/// Bodies in a supported format other than the target format are deserialized as `T`, serialized in the target format,
+/// and replace the request body, along with the `Content-Type` and `Content-Length` headers.
+/// Requests without a body in a supported format are passed on as is, as are all requests if the target format
+/// is not supported, e.g. grpc-web without [PayloadConfig::grpc_web]. Bodies which fail to deserialize
+/// are rejected with a HTTP 400 error, and bodies above [PayloadConfig::limit] with a HTTP 413 error.
+///
+/// The response is still negotiated against the original request, so a client sending XML without
+/// an `Accept` header still receives XML from [crate::Payload] responders.
+///
+/// # Example
+/// ```
+/// # #[cfg(all(feature = "json", feature = "protobuf"))]
+/// # fn main() {
+///     use actix_multiresponse::{ContentType, FormatBridge};
+///     use actix_web::{web, HttpResponse};
+///
+///     #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+///     struct Order {
+///         #[prost(string, tag = "1")]
+///         id: String,
+///     }
+///
+///     async fn create(order: web::Json<Order>) -> HttpResponse {
+///         HttpResponse::Created().body(order.id.clone())
+///     }
+///
+///     let app = actix_web::App::new().service(
+///         web::resource("/orders")
+///             .wrap(FormatBridge::<Order>::new(ContentType::Json))
+///             .route(web::post().to(create)),
+///     );
+/// # }
+/// # #[cfg(not(all(feature = "json", feature = "protobuf")))]
+/// # fn main() {}
+/// ```
+pub struct FormatBridge<T> {
+    target: ContentType,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T> FormatBridge<T> {
+    /// Re-encode request bodies into `target`
+    pub fn new(target: ContentType) -> Self {
+        Self {
+            target,
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<S, B, T> Transform<S, ServiceRequest> for FormatBridge<T>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    T: 'static + SerdeSupportDeserialize + SerdeSupportSerialize + ProtobufSupport,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = FormatBridgeMiddleware<S, T>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(FormatBridgeMiddleware {
+            service: Rc::new(service),
+            target: self.target.clone(),
+            _payload: PhantomData,
+        }))
+    }
+}
+
+/// The service created by [FormatBridge]
+pub struct FormatBridgeMiddleware<S, T> {
+    service: Rc<S>,
+    target: ContentType,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<S, B, T> Service<ServiceRequest> for FormatBridgeMiddleware<S, T>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    T: 'static + SerdeSupportDeserialize + SerdeSupportSerialize + ProtobufSupport,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let target = self.target.clone();
+
+        Box::pin(async move {
+            let negotiation = Negotiation::try_from_request(req.request())?;
+            let source = negotiation.content_type.clone();
+            let bridged = {
+                let http_req = req.request().clone();
+                let config = PayloadConfig::from_req(&http_req);
+                // Formats which are disabled, or which T does not support, are left to the normal negotiation
+                let bridged = |format: &ContentType| config.supports(format) && supports_format::<T>(format);
+                if source.eq(&target) || !bridged(&source) || !bridged(&target) {
+                    None
+                } else {
+                    let raw = body::read_body_with(&http_req, req.take_payload(), config).await?;
+                    // The checksum headers are of the original body, so whether it matches them is kept
+                    let verified = checksum::verify(http_req.headers(), &raw).is_ok();
+                    Some((reencode::<T>(config, raw, &source, &target)?, verified))
+                }
+            };
+            let Some((bridged, verified)) = bridged else {
+                return service.call(req).await;
+            };
+
+            let headers = req.headers_mut();
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(bridged.len()));
+            if let Ok(content_type) = HeaderValue::from_str(&target.to_string()) {
+                headers.insert(CONTENT_TYPE, content_type);
+            }
+
+            // Extractors see the target format, responders still negotiate against the original request
+            req.extensions_mut().insert(Negotiation {
+                content_type: target,
+                accept: negotiation.response_format(),
+            });
+            // Later extractors read the re-encoded body rather than the cached original
+            req.extensions_mut().insert(body::CachedBody::new(bridged.clone(), verified));
+            req.set_payload(bridged.into());
+
+            service.call(req).await
+        })
+    }
+}
+
+/// Deserialize a body in `source` as `T`, and serialize it in `target`
+fn reencode<T: SerdeSupportDeserialize + SerdeSupportSerialize + ProtobufSupport>(
+    config: &PayloadConfig,
+    raw: Bytes,
+    source: &ContentType,
+    target: &ContentType,
+) -> Result<Bytes, actix_web::Error> {
+    let payload = body::prepare_body(config, raw.clone(), source)
+        .and_then(|body| Payload::<T>::deserialize_bytes_with(body, source.clone(), config))
+        .map_err(|error| {
+            config.report_error(PayloadError::Deserialize {
+                error,
+                body: raw,
+                content_type: source.clone(),
+            })
+        })?;
+
+    let serialized = payload
+        .serialize_with(target.clone(), config)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(serialized.into())
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, try_call_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+
+    #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+    struct TestPayload {
+        #[prost(string, tag = "1")]
+        foo: String,
+    }
+
+    async fn json_handler(payload: web::Json<TestPayload>) -> HttpResponse {
+        HttpResponse::Ok().body(payload.foo.clone())
+    }
+
+    #[actix_macros::test]
+    async fn test_bridge_protobuf_to_json() {
+        let app = init_service(
+            App::new().service(
+                web::resource("/")
+                    .wrap(FormatBridge::<TestPayload>::new(ContentType::Json))
+                    .route(web::post().to(json_handler)),
+            ),
+        )
+        .await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .set_payload(prost::Message::encode_to_vec(&TestPayload { foo: "bar".to_string() }))
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!(&b"bar"[..], &actix_web::body::to_bytes(resp.into_body()).await.unwrap()[..]);
+    }
+
+    #[actix_macros::test]
+    async fn test_bridge_invalid_body() {
+        let app = init_service(
+            App::new().service(
+                web::resource("/")
+                    .wrap(FormatBridge::<TestPayload>::new(ContentType::Json))
+                    .route(web::post().to(json_handler)),
+            ),
+        )
+        .await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .set_payload(vec![0x0a, 0xff])
+            .to_request();
+        let err = try_call_service(&app, req).await.unwrap_err();
+
+        assert_eq!(StatusCode::BAD_REQUEST, err.as_response_error().status_code());
+    }
+
+    #[actix_macros::test]
+    async fn test_response_format_preserved() {
+        async fn responder(payload: Payload<TestPayload>) -> Payload<TestPayload> {
+            payload
+        }
+
+        let app = init_service(
+            App::new().service(
+                web::resource("/")
+                    .wrap(FormatBridge::<TestPayload>::new(ContentType::Json))
+                    .route(web::post().to(responder)),
+            ),
+        )
+        .await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .set_payload(prost::Message::encode_to_vec(&TestPayload { foo: "bar".to_string() }))
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+    }
+
+    #[actix_macros::test]
+    async fn test_unsupported_target() {
+        async fn responder(payload: Payload<TestPayload>) -> HttpResponse {
+            HttpResponse::Ok().body(payload.foo.clone())
+        }
+
+        // grpc-web is not enabled, so the body is left in JSON rather than re-encoded into a format which is rejected
+        let app = init_service(
+            App::new().service(
+                web::resource("/")
+                    .wrap(FormatBridge::<TestPayload>::new(ContentType::GrpcWeb))
+                    .route(web::post().to(responder)),
+            ),
+        )
+        .await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#"{"foo":"bar"}"#)
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!(&b"bar"[..], &actix_web::body::to_bytes(resp.into_body()).await.unwrap()[..]);
+    }
+}
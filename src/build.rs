@@ -0,0 +1,193 @@
+//! Attributes for the types generated by `prost-build`, so they are usable with [crate::Payload] in all enabled formats.
+//! Requires the `build-helper` feature.
+//!
+//! The attributes derive `Serialize` and `Deserialize` for all messages, enums and oneofs, and accept missing fields in
+//! JSON and XML like protobuf does. The generated code refers to the `serde` crate, which must be a dependency.
+//! Include the generated code with [crate::include_protos].
+//!
+//! # Example
+//! In `build.rs`, with `actix-multiresponse` and `prost-build` as build dependencies:
+//! ```ignore
+//!     use actix_multiresponse::build::ProstAttributes;
+//!
+//!     fn main() -> std::io::Result<()> {
+//!         let attributes = ProstAttributes::new()
+//!             .camel_case(true)
+//!             .enum_field(".shop.Product.kind", "crate::proto::Kind");
+//!
+//!         let mut config = prost_build::Config::new();
+//!         for (path, attribute) in attributes.message_attributes() {
+//!             config.message_attribute(path, attribute);
+//!         }
+//!         for (path, attribute) in attributes.enum_attributes() {
+//!             config.enum_attribute(path, attribute);
+//!         }
+//!         for (path, attribute) in attributes.field_attributes() {
+//!             config.field_attribute(path, attribute);
+//!         }
+//!
+//!         config.compile_protos(&["proto/shop.proto"], &["proto/"])
+//!     }
+//! ```
+
+/// Derive the serde traits for both messages and enums, including oneofs
+const DERIVE: &str = "#[derive(::serde::Serialize, ::serde::Deserialize)]";
+
+/// The attributes for the types generated by `prost-build`, see the [module documentation](self)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ProstAttributes {
+    paths: Vec<String>,
+    camel_case: bool,
+    default_fields: bool,
+    enum_fields: Vec<(String, String)>,
+    optional_fields: Vec<String>,
+}
+
+impl Default for ProstAttributes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProstAttributes {
+    /// Attributes for all generated types, accepting missing fields
+    pub fn new() -> Self {
+        Self {
+            paths: Vec::new(),
+            camel_case: false,
+            default_fields: true,
+            enum_fields: Vec::new(),
+            optional_fields: Vec::new(),
+        }
+    }
+
+    /// Only add the attributes to the types matching `path`, e.g. `.shop` for all types of the `shop` package.
+    /// Can be called multiple times. Without paths, the attributes are added to all types.
+    pub fn path<S: Into<String>>(mut self, path: S) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Rename fields and oneof variants to camelCase, like the protobuf JSON mapping does.
+    /// By default, fields keep the snake_case names of the proto file.
+    ///
+    /// Alternatively, rename JSON fields at runtime with [crate::PayloadConfig::json_naming], which does not apply to XML.
+    pub fn camel_case(mut self, camel_case: bool) -> Self {
+        self.camel_case = camel_case;
+        self
+    }
+
+    /// Accept JSON and XML bodies with missing fields, using the default value of the field as protobuf does. Enabled by default.
+    pub fn default_fields(mut self, default_fields: bool) -> Self {
+        self.default_fields = default_fields;
+        self
+    }
+
+    /// Represent the enum field `path`, e.g. `.shop.Product.kind`, by the names of the variants of `enum_type`,
+    /// using [crate::serialize_proto_enum] and [crate::deserialize_proto_enum]. The Rust path of the generated enum
+    /// must implement [crate::ProtobufEnum], see [crate::protobuf_enum].
+    pub fn enum_field<P: Into<String>, E: Into<String>>(mut self, path: P, enum_type: E) -> Self {
+        self.enum_fields.push((path.into(), enum_type.into()));
+        self
+    }
+
+    /// Keep the presence of the `proto3 optional` field `path`, e.g. `.shop.Product.discount`,
+    /// using [crate::serialize_proto_optional] and [crate::deserialize_proto_optional]
+    pub fn optional_field<P: Into<String>>(mut self, path: P) -> Self {
+        self.optional_fields.push(path.into());
+        self
+    }
+
+    /// The attributes for `prost_build::Config::message_attribute`, as pairs of the path and the attribute
+    pub fn message_attributes(&self) -> Vec<(String, String)> {
+        let mut attributes = vec![DERIVE.to_string()];
+        if self.camel_case {
+            attributes.push(r#"#[serde(rename_all = "camelCase")]"#.to_string());
+        }
+        if self.default_fields {
+            attributes.push("#[serde(default)]".to_string());
+        }
+
+        self.for_paths(attributes)
+    }
+
+    /// The attributes for `prost_build::Config::enum_attribute`, as pairs of the path and the attribute.
+    /// The variants of oneofs are renamed to the names of their fields
+    pub fn enum_attributes(&self) -> Vec<(String, String)> {
+        let rename = if self.camel_case { "camelCase" } else { "snake_case" };
+        self.for_paths(vec![DERIVE.to_string(), format!(r#"#[serde(rename_all = "{rename}")]"#)])
+    }
+
+    /// The attributes for `prost_build::Config::field_attribute`, as pairs of the path and the attribute
+    pub fn field_attributes(&self) -> Vec<(String, String)> {
+        let enums = self.enum_fields.iter().map(|(path, enum_type)| {
+            let attribute = format!(
+                r#"#[serde(serialize_with = "::actix_multiresponse::serialize_proto_enum::<{enum_type}, _>", deserialize_with = "::actix_multiresponse::deserialize_proto_enum::<{enum_type}, _>")]"#
+            );
+            (path.clone(), attribute)
+        });
+        let optionals = self.optional_fields.iter().map(|path| {
+            let attribute = r#"#[serde(default, serialize_with = "::actix_multiresponse::serialize_proto_optional", deserialize_with = "::actix_multiresponse::deserialize_proto_optional")]"#;
+            (path.clone(), attribute.to_string())
+        });
+
+        enums.chain(optionals).collect()
+    }
+
+    /// Each of the `attributes` for each of the paths
+    fn for_paths(&self, attributes: Vec<String>) -> Vec<(String, String)> {
+        let all = [".".to_string()];
+        let paths = if self.paths.is_empty() { &all[..] } else { &self.paths[..] };
+
+        paths
+            .iter()
+            .flat_map(|path| attributes.iter().map(move |attribute| (path.clone(), attribute.clone())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_attributes() {
+        let attributes = ProstAttributes::new();
+        assert_eq!(
+            vec![
+                (".".to_string(), DERIVE.to_string()),
+                (".".to_string(), "#[serde(default)]".to_string()),
+            ],
+            attributes.message_attributes()
+        );
+        assert_eq!(
+            vec![
+                (".".to_string(), DERIVE.to_string()),
+                (".".to_string(), r#"#[serde(rename_all = "snake_case")]"#.to_string()),
+            ],
+            attributes.enum_attributes()
+        );
+        assert!(attributes.field_attributes().is_empty());
+    }
+
+    #[test]
+    fn test_attributes() {
+        let attributes = ProstAttributes::new()
+            .path(".shop")
+            .path(".billing")
+            .camel_case(true)
+            .default_fields(false)
+            .enum_field(".shop.Product.kind", "crate::proto::Kind")
+            .optional_field(".shop.Product.discount");
+
+        let messages = attributes.message_attributes();
+        assert_eq!(4, messages.len());
+        assert!(messages.contains(&(".billing".to_string(), r#"#[serde(rename_all = "camelCase")]"#.to_string())));
+
+        let fields = attributes.field_attributes();
+        assert_eq!(".shop.Product.kind", fields[0].0);
+        assert!(fields[0].1.contains("deserialize_proto_enum::<crate::proto::Kind, _>"));
+        assert_eq!(".shop.Product.discount", fields[1].0);
+        assert!(fields[1].1.starts_with("#[serde(default, "));
+    }
+}
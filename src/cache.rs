@@ -0,0 +1,264 @@
+//! Middleware caching serialized responses per negotiated format.
+
+use crate::{negotiate, ContentType};
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::HeaderMap;
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+
+use futures_util::future::LocalBoxFuture;
+
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Middleware caching successful responses to `GET` requests for a fixed time, so identical requests are not
+/// handled and serialized again.
+///
+/// Responses are cached per path, query string and negotiated response format,
+/// so clients negotiating JSON and protobuf each receive the representation in their format.
+/// Only responses with status `200 OK` are cached. Entries are invalidated through a [CacheHandle],
+/// which can be shared with handlers through the app data.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{CacheHandle, Payload, ResponseCache};
+///     use actix_web::web;
+///     use std::time::Duration;
+///
+///     async fn list() -> Payload<String> {
+///         Payload::new("foo".to_string())
+///     }
+///
+///     async fn create(cache: web::Data<CacheHandle>) -> &'static str {
+///         cache.invalidate("/items");
+///         "Created"
+///     }
+///
+///     let cache = ResponseCache::new(Duration::from_secs(30));
+///     let app = actix_web::App::new()
+///         .app_data(web::Data::new(cache.handle()))
+///         .wrap(cache)
+///         .route("/items", web::get().to(list))
+///         .route("/items", web::post().to(create));
+/// ```
+pub struct ResponseCache {
+    ttl: Duration,
+    handle: CacheHandle,
+}
+
+impl ResponseCache {
+    /// Cache responses for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            handle: CacheHandle::default(),
+        }
+    }
+
+    /// A handle to invalidate the entries of this cache
+    pub fn handle(&self) -> CacheHandle {
+        self.handle.clone()
+    }
+}
+
+/// A handle to invalidate the entries of a [ResponseCache]. Clones share the same cache
+#[derive(Debug, Default, Clone)]
+pub struct CacheHandle {
+    entries: Arc<Mutex<HashMap<CacheKey, CacheEntry>>>,
+}
+
+impl CacheHandle {
+    /// Remove the cached responses for `path`, for any query string and format
+    pub fn invalidate(&self, path: &str) {
+        self.lock().retain(|key, _| key.path.ne(path));
+    }
+
+    /// Remove all cached responses
+    pub fn invalidate_all(&self) {
+        self.lock().clear();
+    }
+
+    /// The number of cached responses, including expired responses which have not been removed yet
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no responses are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<CacheKey, CacheEntry>> {
+        // The map is consistent after any operation, so a poisoned lock can be used as is
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The cached response for `key`, removing it if it expired
+    fn get(&self, key: &CacheKey) -> Option<CacheEntry> {
+        let mut entries = self.lock();
+        match entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: CacheKey, entry: CacheEntry) {
+        let mut entries = self.lock();
+        let now = Instant::now();
+        entries.retain(|_, entry| entry.expires > now);
+        entries.insert(key, entry);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+struct CacheKey {
+    path: String,
+    query: String,
+    format: ContentType,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    headers: HeaderMap,
+    body: Bytes,
+    expires: Instant,
+}
+
+impl CacheEntry {
+    fn to_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::Ok().body(self.body.clone());
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ResponseCache
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Transform = ResponseCacheMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ResponseCacheMiddleware {
+            service: Rc::new(service),
+            ttl: self.ttl,
+            handle: self.handle.clone(),
+        }))
+    }
+}
+
+/// The service created by [ResponseCache]
+pub struct ResponseCacheMiddleware<S> {
+    service: Rc<S>,
+    ttl: Duration,
+    handle: CacheHandle,
+}
+
+impl<S, B> Service<ServiceRequest> for ResponseCacheMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let ttl = self.ttl;
+        let handle = self.handle.clone();
+
+        Box::pin(async move {
+            if req.method().ne(&Method::GET) {
+                return Ok(service.call(req).await?.map_into_boxed_body());
+            }
+
+            let key = CacheKey {
+                path: req.path().to_string(),
+                query: req.query_string().to_string(),
+                format: negotiate::response_format(req.request()),
+            };
+
+            if let Some(entry) = handle.get(&key) {
+                return Ok(req.into_response(entry.to_response()));
+            }
+
+            let response = service.call(req).await?;
+            if response.status().ne(&StatusCode::OK) {
+                return Ok(response.map_into_boxed_body());
+            }
+
+            let (http_req, response) = response.into_parts();
+            let headers = response.headers().clone();
+            let body = actix_web::body::to_bytes(response.into_body())
+                .await
+                .map_err(|e| actix_web::error::ErrorInternalServerError(e.into()))?;
+
+            let entry = CacheEntry {
+                headers,
+                body,
+                expires: Instant::now() + ttl,
+            };
+            let response = entry.to_response();
+            handle.insert(key, entry);
+
+            Ok(ServiceResponse::new(http_req, response))
+        })
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use crate::Payload;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[actix_macros::test]
+    async fn test_cache_per_format() {
+        let calls = web::Data::new(AtomicUsize::new(0));
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        let handle = cache.handle();
+
+        let app = init_service(App::new().app_data(calls.clone()).wrap(cache).route(
+            "/",
+            web::get().to(|calls: web::Data<AtomicUsize>| async move {
+                Payload::new(format!("call {}", calls.fetch_add(1, Ordering::SeqCst)))
+            }),
+        ))
+        .await;
+
+        for accept in ["application/json", "application/json", "application/protobuf"] {
+            let req = TestRequest::get().uri("/").insert_header(("Accept", accept)).to_request();
+            let resp = call_service(&app, req).await;
+            assert_eq!(accept, resp.headers().get("Content-Type").unwrap());
+        }
+
+        // One call per format
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+        assert_eq!(2, handle.len());
+
+        handle.invalidate("/");
+        let req = TestRequest::get().uri("/").insert_header(("Accept", "application/json")).to_request();
+        let body = actix_web::body::to_bytes(call_service(&app, req).await.into_body()).await.unwrap();
+        assert_eq!(&b"\"call 2\""[..], &body[..]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Capturing request bodies which failed to deserialize, for error reporting.
+
+use crate::{ContentType, PayloadError};
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A truncated copy of a request body which failed to deserialize, passed to the hook configured
+/// with [crate::PayloadConfig::error_hook], or created with [PayloadError::capture_body].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BodyCapture {
+    /// The format the body was deserialized from
+    pub content_type: ContentType,
+    /// The error message
+    pub error: String,
+    /// The captured body, as text for JSON and XML bodies, otherwise base64 encoded
+    pub body: String,
+    /// Whether [BodyCapture::body] is base64 encoded
+    pub base64: bool,
+    /// The length of the complete body in bytes
+    pub len: usize,
+    /// Whether the body was longer than the capture limit, and [BodyCapture::body] is truncated
+    pub truncated: bool,
+}
+
+impl BodyCapture {
+    fn new(content_type: &ContentType, error: String, body: &[u8], max_len: usize) -> Self {
+        let truncated = body.len() > max_len;
+        let captured = &body[..body.len().min(max_len)];
+
+        let text = match std::str::from_utf8(captured) {
+            Ok(text) => Some(text),
+            // The limit may split a character, in which case the incomplete character is dropped
+            Err(e) if truncated && e.error_len().is_none() => std::str::from_utf8(&captured[..e.valid_up_to()]).ok(),
+            Err(_) => None,
+        }
+        .filter(|_| is_text(content_type));
+
+        let (captured_body, base64) = match text {
+            Some(text) => (text.to_string(), false),
+            None => (encode_base64(captured), true),
+        };
+
+        Self {
+            content_type: content_type.clone(),
+            error,
+            body: captured_body,
+            base64,
+            len: body.len(),
+            truncated,
+        }
+    }
+}
+
+impl PayloadError {
+    /// Capture at most `max_len` bytes of the request body, if the error occurred while deserializing it
+    pub fn capture_body(&self, max_len: usize) -> Option<BodyCapture> {
+        match self.detailed() {
+            Self::Deserialize {
+                error,
+                body,
+                content_type,
+            } => Some(BodyCapture::new(content_type, error.to_string(), body, max_len)),
+            _ => None,
+        }
+    }
+}
+
+/// Whether bodies in the format are text
+fn is_text(content_type: &ContentType) -> bool {
+    match content_type {
+        #[cfg(feature = "json")]
+        ContentType::Json => true,
+        #[cfg(feature = "xml")]
+        ContentType::Xml => true,
+        _ => false,
+    }
+}
+
+/// Encode as standard base64, with padding
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// The hook bodies which failed to deserialize are reported to, see [crate::PayloadConfig::error_hook]
+#[derive(Clone)]
+pub(crate) struct ErrorHook {
+    hook: Arc<HookFn>,
+    /// The number of errors seen, shared between clones of the configuration
+    seen: Arc<AtomicU64>,
+}
+
+type HookFn = dyn Fn(&BodyCapture) + Send + Sync;
+
+impl ErrorHook {
+    pub(crate) fn new(hook: Arc<HookFn>) -> Self {
+        Self {
+            hook,
+            seen: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Report the error to the hook, if it is a deserialize error which is sampled
+    pub(crate) fn report(&self, error: &PayloadError, max_len: usize, sample_rate: f64) {
+        if !matches!(error, PayloadError::Deserialize { .. }) || !self.sampled(sample_rate) {
+            return;
+        }
+
+        if let Some(capture) = error.capture_body(max_len) {
+            (self.hook)(&capture);
+        }
+    }
+
+    /// Whether the next error is sampled. Errors are sampled evenly, e.g. every fourth error with a rate of 0.25
+    fn sampled(&self, sample_rate: f64) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * sample_rate).floor() > (n * sample_rate).floor()
+    }
+}
+
+impl fmt::Debug for ErrorHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ErrorHook")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!("", encode_base64(b""));
+        assert_eq!("Zg==", encode_base64(b"f"));
+        assert_eq!("Zm8=", encode_base64(b"fo"));
+        assert_eq!("Zm9vYmFy", encode_base64(b"foobar"));
+        assert_eq!("/w==", encode_base64(&[0xff]));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_sampling() {
+        use std::sync::Mutex;
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let hook = {
+            let captured = Arc::clone(&captured);
+            ErrorHook::new(Arc::new(move |capture: &BodyCapture| captured.lock().unwrap().push(capture.clone())))
+        };
+
+        let error = PayloadError::Deserialize {
+            error: crate::DeserializeError::SerdeJson(serde_json::from_str::<String>("{}").unwrap_err()),
+            body: actix_web::web::Bytes::from_static(b"{\"foo\""),
+            content_type: ContentType::Json,
+        };
+        for _ in 0..4 {
+            hook.report(&error, 3, 0.5);
+        }
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(2, captured.len());
+        assert_eq!("{\"f", captured[0].body);
+        assert!(captured[0].truncated && !captured[0].base64);
+        assert_eq!(6, captured[0].len);
+    }
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_binary_body() {
+        let error = PayloadError::Deserialize {
+            error: crate::DeserializeError::Transform("invalid".to_string()),
+            body: actix_web::web::Bytes::from_static(&[0x0a, 0xff, 0x01]),
+            content_type: ContentType::Protobuf,
+        };
+
+        let capture = error.capture_body(2).unwrap();
+        assert_eq!("Cv8=", capture.body);
+        assert_eq!(3, capture.len);
+        assert!(capture.truncated && capture.base64);
+        assert!(PayloadError::InvalidContentType(ContentType::Other(String::new())).capture_body(2).is_none());
+    }
+}
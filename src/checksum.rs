@@ -0,0 +1,356 @@
+//! Verifying the integrity of request bodies, see [crate::PayloadConfig::verify_checksums].
+//!
+//! The digests are implemented here, as only the two fixed algorithms of the headers are required.
+//!
+//! Checksums in trailers are not supported. actix does not expose the trailers of chunked requests,
+//! so the checksums are only read from the headers.
+
+use crate::PayloadError;
+
+use actix_web::http::header::HeaderMap;
+
+/// The header carrying the base64 encoded MD5 digest of the body, as defined by RFC 1864
+pub(crate) const CONTENT_MD5: &str = "Content-MD5";
+/// The header carrying the hex or base64 encoded SHA-256 digest of the body
+pub(crate) const CHECKSUM_SHA256: &str = "x-checksum-sha256";
+
+/// Verify the body against the checksum headers present in `headers`.
+/// A header which is not a valid digest is treated as a mismatch, requests without checksum headers are accepted
+pub(crate) fn verify(headers: &HeaderMap, body: &[u8]) -> Result<(), PayloadError> {
+    if let Some(header_value) = headers.get(CONTENT_MD5) {
+        let expected = header_value.to_str().ok().and_then(decode_base64);
+        if expected.as_deref() != Some(&md5(body)[..]) {
+            return Err(PayloadError::ChecksumMismatch(CONTENT_MD5.to_string()));
+        }
+    }
+
+    if let Some(header_value) = headers.get(CHECKSUM_SHA256) {
+        let expected = header_value
+            .to_str()
+            .ok()
+            .and_then(|v| decode_hex(v).or_else(|| decode_base64(v)));
+        if expected.as_deref() != Some(&sha256(body)[..]) {
+            return Err(PayloadError::ChecksumMismatch(CHECKSUM_SHA256.to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode standard base64, with or without padding. Padding, if present, has to complete the last group
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    fn sextet(c: u8) -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+            b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+
+    let padded = encoded.trim();
+    let encoded = padded.trim_end_matches('=').as_bytes();
+    let padding = padded.len() - encoded.len();
+    if encoded.len() % 4 == 1 || (padding > 0 && (padding > 2 || padded.len() % 4 != 0)) {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(encoded.len() * 3 / 4);
+    for group in encoded.chunks(4) {
+        let bits = group.iter().try_fold(0u32, |bits, c| Some(bits << 6 | sextet(*c)?))? << (6 * (4 - group.len()));
+        decoded.extend_from_slice(&bits.to_be_bytes()[1..group.len()]);
+    }
+
+    Some(decoded)
+}
+
+/// Decode hexadecimal digits, in either case
+fn decode_hex(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.trim().as_bytes();
+    if encoded.len() % 2 != 0 || !encoded.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+
+    encoded
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Call `compress` with each 64 byte block of the message padded with the bit length, as required by MD5 and SHA-256.
+/// Only the last one or two blocks are copied to be padded, the others are borrowed from the message
+fn for_each_block(message: &[u8], length_bytes: impl Fn(u64) -> [u8; 8], mut compress: impl FnMut(&[u8])) {
+    let mut blocks = message.chunks_exact(64);
+    for block in &mut blocks {
+        compress(block);
+    }
+
+    let rest = blocks.remainder();
+    let mut padded = [0; 128];
+    padded[..rest.len()].copy_from_slice(rest);
+    padded[rest.len()] = 0x80;
+    let len = if rest.len() + 9 > 64 { 128 } else { 64 };
+    padded[len - 8..len].copy_from_slice(&length_bytes((message.len() as u64).wrapping_mul(8)));
+
+    for block in padded[..len].chunks_exact(64) {
+        compress(block);
+    }
+}
+
+/// The constants of MD5, the integer parts of `abs(sin(i + 1)) * 2^32`
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501, 0x698098d8,
+    0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821, 0xf61e2562, 0xc040b340,
+    0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8, 0x21e1cde6, 0xc33707d6, 0xf4d50d87,
+    0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a, 0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c,
+    0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70, 0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039,
+    0xe6db99e5, 0x1fa27cf8, 0xc4ac5665, 0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92,
+    0xffeff47d, 0x85845dd1, 0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb,
+    0xeb86d391,
+];
+
+/// The MD5 digest, as defined by RFC 1321
+fn md5(message: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for_each_block(message, u64::to_le_bytes, |block| {
+        let mut words = [0u32; 16];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = state;
+        for (i, constant) in MD5_K.iter().enumerate() {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(*constant)
+                .wrapping_add(words[g])
+                .rotate_left(SHIFTS[i / 16 * 4 + i % 4]);
+
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+
+        for (state, value) in state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    });
+
+    let mut digest = [0; 16];
+    for (bytes, word) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+/// The round constants of SHA-256
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98,
+    0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786,
+    0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8,
+    0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+    0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819,
+    0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a,
+    0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+    0xc67178f2,
+];
+
+/// The SHA-256 digest, as defined by FIPS 180-4
+pub(crate) fn sha256(message: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    for_each_block(message, u64::to_be_bytes, |block| {
+        let mut schedule = [0u32; 64];
+        for (word, bytes) in schedule.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = schedule[i - 15].rotate_right(7) ^ schedule[i - 15].rotate_right(18) ^ (schedule[i - 15] >> 3);
+            let s1 = schedule[i - 2].rotate_right(17) ^ schedule[i - 2].rotate_right(19) ^ (schedule[i - 2] >> 10);
+            schedule[i] = schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for (k, w) in SHA256_K.iter().zip(schedule) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(*k).wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+
+            (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+        }
+
+        for (state, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    });
+
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn test_md5_rfc1321() {
+        // The test suite of RFC 1321, appendix A.5
+        let digests = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            ("abcdefghijklmnopqrstuvwxyz", "c3fcd3d76192e4007dfb496cca67e13b"),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "d174ab98d277d9f5a5611c2c9f419d9f",
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+        for (message, digest) in digests {
+            assert_eq!(digest, hex(&md5(message.as_bytes())), "{message:?}");
+        }
+    }
+
+    #[test]
+    fn test_sha256_fips180_2() {
+        // The examples of FIPS 180-2, appendix B
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            hex(&sha256(b"abc"))
+        );
+        assert_eq!(
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"))
+        );
+        assert_eq!(
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+            hex(&sha256(&vec![b'a'; 1_000_000]))
+        );
+    }
+
+    #[test]
+    fn test_digests() {
+        assert_eq!("d41d8cd98f00b204e9800998ecf8427e", hex(&md5(b"")));
+        assert_eq!(
+            "9e107d9d372bb6826bd81d3542a419d6",
+            hex(&md5(b"The quick brown fox jumps over the lazy dog"))
+        );
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            hex(&sha256(b""))
+        );
+        assert_eq!(
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"))
+        );
+    }
+
+    #[test]
+    fn test_padding_boundaries() {
+        // The length fits in the last block up to 55 bytes, longer remainders need another block
+        let digests = [
+            (55, "ef1772b6dff9a122358552954ad0df65", "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318"),
+            (56, "3b0c8ac703f828b04c6c197006d17218", "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a"),
+            (64, "014842d480b571495a4a0363793f7367", "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb"),
+            (119, "8a7bd0732ed6a28ce75f6dabc90e1613", "31eba51c313a5c08226adf18d4a359cfdfd8d2e816b13f4af952f7ea6584dcfb"),
+        ];
+        for (len, md5_digest, sha256_digest) in digests {
+            let message = vec![b'a'; len];
+            assert_eq!(md5_digest, hex(&md5(&message)), "{len} bytes");
+            assert_eq!(sha256_digest, hex(&sha256(&message)), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Some(b"foobar".to_vec()), decode_base64("Zm9vYmFy"));
+        assert_eq!(Some(b"fo".to_vec()), decode_base64("Zm8="));
+        assert_eq!(None, decode_base64("Zm9v!"));
+    }
+
+    #[test]
+    fn test_decode_base64_padding() {
+        for (padded, unpadded, decoded) in [
+            ("", "", &b""[..]),
+            ("Zg==", "Zg", b"f"),
+            ("Zm8=", "Zm8", b"fo"),
+            ("Zm9v", "Zm9v", b"foo"),
+            ("Zm9vYg==", "Zm9vYg", b"foob"),
+            ("Zm9vYmE=", "Zm9vYmE", b"fooba"),
+        ] {
+            assert_eq!(Some(decoded.to_vec()), decode_base64(padded), "{padded}");
+            assert_eq!(Some(decoded.to_vec()), decode_base64(unpadded), "{unpadded}");
+        }
+
+        // Padding which does not complete the last group
+        assert_eq!(None, decode_base64("Zg="));
+        assert_eq!(None, decode_base64("Zm8=="));
+        assert_eq!(None, decode_base64("Zm9v="));
+        assert_eq!(None, decode_base64("Zg===="));
+        // A group of a single character, padded or not
+        assert_eq!(None, decode_base64("Zm9vY"));
+        assert_eq!(None, decode_base64("Z==="));
+        // Padding or invalid characters inside the encoded data
+        assert_eq!(None, decode_base64("Zg==Zg=="));
+        assert_eq!(None, decode_base64("Zm-v"));
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(Some(vec![0xab, 0x01]), decode_hex("AB01"));
+        assert_eq!(Some(vec![0xab, 0x01]), decode_hex("ab01"));
+        assert_eq!(None, decode_hex("abc"));
+        assert_eq!(None, decode_hex("ag01"));
+        assert_eq!(None, decode_hex("+a01"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut headers = HeaderMap::new();
+        assert!(verify(&headers, b"foo").is_ok());
+
+        headers.insert(
+            HeaderName::from_static("content-md5"),
+            HeaderValue::from_static("rL0Y20zC+Fzt72VPzMSk2A=="),
+        );
+        assert!(verify(&headers, b"foo").is_ok());
+        assert!(matches!(
+            verify(&headers, b"bar"),
+            Err(PayloadError::ChecksumMismatch(header)) if header == CONTENT_MD5
+        ));
+
+        headers.insert(
+            HeaderName::from_static(CHECKSUM_SHA256),
+            HeaderValue::from_static("2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"),
+        );
+        assert!(verify(&headers, b"foo").is_ok());
+    }
+}
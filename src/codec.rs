@@ -0,0 +1,384 @@
+//! Serialization and deserialization for the individual formats
+
+use crate::{DeserializeError, PayloadConfig, SerializeError};
+#[cfg(feature = "protobuf")]
+use crate::{ProtobufError, ProtobufSupport};
+#[cfg(any(feature = "json", feature = "xml"))]
+use crate::proto_json::mapped;
+
+#[cfg(feature = "json")]
+pub(crate) fn serialize_json<T: serde::Serialize>(value: &T, config: &PayloadConfig) -> Result<Vec<u8>, SerializeError> {
+    let mut json = Vec::new();
+    write_json(&mut json, value, config)?;
+    Ok(json)
+}
+
+/// Serialize to JSON directly into chunks, see [crate::PayloadConfig::stream_threshold]
+#[cfg(feature = "json")]
+pub(crate) fn serialize_json_chunked<T: serde::Serialize>(
+    value: &T,
+    config: &PayloadConfig,
+) -> Result<Vec<actix_web::web::Bytes>, SerializeError> {
+    let mut writer = crate::body::ChunkWriter::default();
+    write_json(std::io::BufWriter::with_capacity(8192, &mut writer), value, config)?;
+    Ok(writer.into_chunks())
+}
+
+#[cfg(feature = "json")]
+pub(crate) fn write_json<W: std::io::Write, T: serde::Serialize>(writer: W, value: &T, config: &PayloadConfig) -> Result<(), SerializeError> {
+    use crate::json_backend::{Backend, JsonBackend};
+
+    if config.sort_json_keys {
+        let mut json = to_json_value(value, config)?;
+        sort_keys(&mut json);
+        return Backend::to_writer(writer, &json, &config.json_format, crate::JsonNaming::Preserve);
+    }
+
+    if config.protobuf_mapping.is_default() {
+        Backend::to_writer(writer, value, &config.json_format, config.json_naming)
+    } else {
+        Backend::to_writer(writer, &mapped(value, config.protobuf_mapping), &config.json_format, config.json_naming)
+    }
+}
+
+/// Convert a value to a JSON value, applying the naming policy and the protobuf mapping of `config`
+#[cfg(feature = "json")]
+pub(crate) fn to_json_value<T: serde::Serialize + ?Sized>(value: &T, config: &PayloadConfig) -> serde_json::Result<serde_json::Value> {
+    if config.protobuf_mapping.is_default() {
+        crate::naming::to_value(value, config.json_naming)
+    } else {
+        crate::naming::to_value(&mapped(value, config.protobuf_mapping), config.json_naming)
+    }
+}
+
+/// Serialize a JSON value of which the naming policy and the protobuf mapping are applied already, see [to_json_value]
+#[cfg(feature = "json")]
+pub(crate) fn serialize_json_value(value: &serde_json::Value, config: &PayloadConfig) -> Result<Vec<u8>, SerializeError> {
+    use crate::json_backend::{Backend, JsonBackend};
+
+    let mut json = Vec::new();
+    if config.sort_json_keys {
+        let mut value = value.clone();
+        sort_keys(&mut value);
+        Backend::to_writer(&mut json, &value, &config.json_format, crate::JsonNaming::Preserve)?;
+    } else {
+        Backend::to_writer(&mut json, value, &config.json_format, crate::JsonNaming::Preserve)?;
+    }
+
+    Ok(json)
+}
+
+/// Sort the members of all objects in a JSON value by their keys, see [PayloadConfig::sort_json_keys].
+/// The map of `serde_json` is only sorted already if its `preserve_order` feature is not enabled by another crate
+#[cfg(feature = "json")]
+pub(crate) fn sort_keys(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            let mut members: Vec<_> = std::mem::take(object).into_iter().collect();
+            members.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (_, member) in &mut members {
+                sort_keys(member);
+            }
+            object.extend(members);
+        }
+        serde_json::Value::Array(elements) => elements.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+#[cfg(feature = "json")]
+pub(crate) fn deserialize_json<'de, T: serde::Deserialize<'de>>(body: &'de [u8], config: &PayloadConfig) -> Result<T, DeserializeError> {
+    use crate::json_backend::{Backend, JsonBackend};
+
+    Backend::from_slice(body, config.deny_unknown_fields, config.json_naming, config.protobuf_mapping.int64_as_string)
+}
+
+#[cfg(feature = "protobuf")]
+pub(crate) fn serialize_protobuf<T: prost::Message>(value: &T) -> Result<Vec<u8>, SerializeError> {
+    let mut protobuf = Vec::new();
+    value
+        .encode(&mut protobuf)
+        .map_err(|e| SerializeError::Prost(e.to_string()))?;
+    Ok(protobuf)
+}
+
+#[cfg(feature = "protobuf")]
+pub(crate) fn deserialize_protobuf<T: prost::Message + Default, B: prost::bytes::Buf>(body: B) -> Result<T, DeserializeError> {
+    Ok(T::decode(body)?)
+}
+
+/// Strip the varint length prefix of a length-delimited protobuf message.
+/// The body must contain exactly one message.
+#[cfg(feature = "protobuf")]
+pub(crate) fn strip_length_delimiter(mut body: actix_web::web::Bytes) -> Result<actix_web::web::Bytes, DeserializeError> {
+    let len = prost::encoding::decode_varint(&mut body)?;
+
+    if len != body.len() as u64 {
+        return Err(DeserializeError::Prost(ProtobufError::new(format!(
+            "Length delimiter of {len} bytes does not match the message of {} bytes",
+            body.len()
+        ))));
+    }
+
+    Ok(body)
+}
+
+/// Flag of a grpc-web frame containing a message
+#[cfg(feature = "protobuf")]
+const GRPC_WEB_MESSAGE: u8 = 0x00;
+/// Flag bit indicating the frame is compressed
+#[cfg(feature = "protobuf")]
+const GRPC_WEB_COMPRESSED: u8 = 0x01;
+/// Flag of a grpc-web frame containing trailers
+#[cfg(feature = "protobuf")]
+const GRPC_WEB_TRAILERS: u8 = 0x80;
+
+/// Write a grpc-web frame, consisting of the flag, the big endian length of the data and the data
+#[cfg(feature = "protobuf")]
+fn write_grpc_web_frame(buf: &mut Vec<u8>, flag: u8, data: &[u8]) {
+    buf.push(flag);
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Encode protobuf, deterministically if configured
+#[cfg(feature = "protobuf")]
+pub(crate) fn encode_protobuf<T: ProtobufSupport>(value: &T, config: &PayloadConfig) -> Result<Vec<u8>, SerializeError> {
+    if config.protobuf_deterministic {
+        value.encode_protobuf_deterministic()
+    } else {
+        value.encode_protobuf()
+    }
+}
+
+#[cfg(feature = "protobuf")]
+pub(crate) fn serialize_grpc_web<T: ProtobufSupport>(value: &T, config: &PayloadConfig) -> Result<Vec<u8>, SerializeError> {
+    Ok(frame_grpc_web(&encode_protobuf(value, config)?))
+}
+
+/// Frame an encoded message as a grpc-web body, followed by trailers indicating success
+#[cfg(feature = "protobuf")]
+pub(crate) fn frame_grpc_web(message: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(message.len() + 32);
+    write_grpc_web_frame(&mut framed, GRPC_WEB_MESSAGE, message);
+    write_grpc_web_frame(&mut framed, GRPC_WEB_TRAILERS, b"grpc-status:0\r\n");
+    framed
+}
+
+#[cfg(feature = "protobuf")]
+pub(crate) fn deserialize_grpc_web<T: ProtobufSupport>(body: &[u8]) -> Result<T, DeserializeError> {
+    if body.len() < 5 {
+        return Err(DeserializeError::GrpcWeb("Frame header is incomplete".to_string()));
+    }
+
+    let flag = body[0];
+    if flag & GRPC_WEB_TRAILERS != 0 {
+        return Err(DeserializeError::GrpcWeb("Expected a message frame".to_string()));
+    }
+
+    if flag & GRPC_WEB_COMPRESSED != 0 {
+        return Err(DeserializeError::GrpcWeb("Compressed frames are not supported".to_string()));
+    }
+
+    let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+    let message = body[5..]
+        .get(..len)
+        .ok_or_else(|| DeserializeError::GrpcWeb("Message frame is incomplete".to_string()))?;
+
+    // Anything after the message frame may only be a trailers frame
+    let rest = &body[5 + len..];
+    if !rest.is_empty() && rest[0] & GRPC_WEB_TRAILERS == 0 {
+        return Err(DeserializeError::GrpcWeb("Only a single message frame is supported".to_string()));
+    }
+
+    T::decode_protobuf(message)
+}
+
+#[cfg(feature = "xml")]
+pub(crate) fn serialize_xml<T: serde::Serialize>(value: &T, config: &PayloadConfig) -> Result<Vec<u8>, SerializeError> {
+    let mut xml = Vec::new();
+    write_xml(TextWriter(&mut xml), value, config)?;
+    Ok(xml)
+}
+
+/// Allows serializers producing text, like the XML serializer, to write into a byte buffer directly
+#[cfg(feature = "xml")]
+struct TextWriter<'a>(&'a mut Vec<u8>);
+
+#[cfg(feature = "xml")]
+impl std::fmt::Write for TextWriter<'_> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Serialize to XML directly into chunks, see [crate::PayloadConfig::stream_threshold]
+#[cfg(feature = "xml")]
+pub(crate) fn serialize_xml_chunked<T: serde::Serialize>(
+    value: &T,
+    config: &PayloadConfig,
+) -> Result<Vec<actix_web::web::Bytes>, SerializeError> {
+    let mut writer = crate::body::ChunkWriter::default();
+    write_xml(&mut writer, value, config)?;
+    Ok(writer.into_chunks())
+}
+
+#[cfg(feature = "xml")]
+pub(crate) fn write_xml<W: std::fmt::Write, T: serde::Serialize>(writer: W, value: &T, config: &PayloadConfig) -> Result<(), SerializeError> {
+    let mut serializer = quick_xml::se::Serializer::new(writer);
+    if let Some((indent_char, indent_size)) = config.xml_indent {
+        serializer.indent(indent_char, indent_size);
+    }
+
+    // Always wrapped, so enums annotated with `serialize_proto_enum` are serialized as text rather than as elements.
+    // XML has no null, absent fields annotated with `serialize_proto_optional` are always omitted
+    let mapping = crate::proto_json::Mapping {
+        omit_absent_fields: true,
+        ..config.protobuf_mapping
+    };
+    if config.xml_mapping.is_empty() {
+        serde::Serialize::serialize(&mapped(value, mapping), serializer)?;
+    } else {
+        let mapped = crate::xml_mapping::XmlMapped {
+            value: &mapped(value, mapping),
+            mapping: &config.xml_mapping,
+        };
+        serde::Serialize::serialize(&mapped, serializer)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "xml")]
+pub(crate) fn deserialize_xml<T: serde::de::DeserializeOwned>(body: &[u8], config: &PayloadConfig) -> Result<T, DeserializeError> {
+    let deserialized = if !config.deny_unknown_fields && config.xml_mapping.is_empty() {
+        quick_xml::de::from_reader(body).map_err(DeserializeError::from)
+    } else {
+        let mut deserializer = quick_xml::de::Deserializer::from_reader(body);
+        deserialize_xml_with(&mut deserializer, config)
+    };
+
+    deserialized.map_err(|e| e.in_field(xml_error_path::<T, _>(&mut quick_xml::de::Deserializer::from_reader(body), config)))
+}
+
+/// Like [deserialize_xml], borrowing from `body` where `T` allows it
+#[cfg(feature = "xml")]
+pub(crate) fn deserialize_xml_borrowed<'de, T: serde::Deserialize<'de>>(
+    body: &'de [u8],
+    config: &PayloadConfig,
+) -> Result<T, DeserializeError> {
+    let xml = std::str::from_utf8(body)
+        .map_err(|e| quick_xml::DeError::InvalidXml(quick_xml::Error::NonDecodable(Some(e))))?;
+    let mut deserializer = quick_xml::de::Deserializer::from_str(xml);
+    deserialize_xml_with(&mut deserializer, config)
+        .map_err(|e| e.in_field(xml_error_path::<T, _>(&mut quick_xml::de::Deserializer::from_str(xml), config)))
+}
+
+/// Deserialize with [crate::PayloadConfig::deny_unknown_fields] and [crate::PayloadConfig::xml_mapping] applied
+#[cfg(feature = "xml")]
+fn deserialize_xml_with<'de, T: serde::Deserialize<'de>, D: serde::Deserializer<'de, Error = quick_xml::DeError>>(
+    deserializer: D,
+    config: &PayloadConfig,
+) -> Result<T, DeserializeError> {
+    let deserialized = match (config.deny_unknown_fields, config.xml_mapping.is_empty()) {
+        (false, true) => T::deserialize(deserializer),
+        (false, false) => T::deserialize(crate::xml_mapping::mapped(deserializer, &config.xml_mapping)),
+        (true, true) => crate::strict::deserialize(deserializer),
+        (true, false) => crate::strict::deserialize(crate::xml_mapping::mapped(deserializer, &config.xml_mapping)),
+    };
+
+    Ok(deserialized?)
+}
+
+/// The path of the value deserializing with `deserializer` fails in, see [crate::DeserializeError::path]
+#[cfg(feature = "xml")]
+fn xml_error_path<'de, T: serde::Deserialize<'de>, D: serde::Deserializer<'de>>(
+    deserializer: D,
+    config: &PayloadConfig,
+) -> Option<String> {
+    if config.xml_mapping.is_empty() {
+        crate::strict::error_path::<T, _>(deserializer, config.deny_unknown_fields)
+    } else {
+        crate::strict::error_path::<T, _>(
+            crate::xml_mapping::mapped(deserializer, &config.xml_mapping),
+            config.deny_unknown_fields,
+        )
+    }
+}
+
+#[cfg(all(test, feature = "protobuf"))]
+mod test {
+    use super::*;
+    use prost_derive::Message;
+
+    #[derive(Message, PartialEq)]
+    struct TestPayload {
+        #[prost(string, tag = "1")]
+        foo: String,
+    }
+
+    #[test]
+    fn test_strip_length_delimiter() {
+        let payload = TestPayload { foo: "bar".to_string() };
+        let delimited = prost::Message::encode_length_delimited_to_vec(&payload);
+
+        let body = strip_length_delimiter(delimited.clone().into()).unwrap();
+        assert_eq!(prost::Message::encode_to_vec(&payload), body.to_vec());
+
+        assert!(strip_length_delimiter(delimited[..delimited.len() - 1].to_vec().into()).is_err());
+    }
+
+    #[test]
+    fn test_grpc_web_roundtrip() {
+        let payload = TestPayload { foo: "bar".to_string() };
+        let framed = serialize_grpc_web(&payload, &PayloadConfig::default()).unwrap();
+
+        assert_eq!(GRPC_WEB_MESSAGE, framed[0]);
+        assert_eq!(payload, deserialize_grpc_web(&framed).unwrap());
+    }
+
+    #[test]
+    fn test_grpc_web_incomplete() {
+        let payload = TestPayload { foo: "bar".to_string() };
+        let framed = serialize_grpc_web(&payload, &PayloadConfig::default()).unwrap();
+
+        assert!(deserialize_grpc_web::<TestPayload>(&framed[..3]).is_err());
+        assert!(deserialize_grpc_web::<TestPayload>(&framed[..8]).is_err());
+    }
+
+    #[test]
+    fn test_grpc_web_compressed() {
+        let mut framed = serialize_grpc_web(&TestPayload::default(), &PayloadConfig::default()).unwrap();
+        framed[0] = GRPC_WEB_COMPRESSED;
+
+        assert!(matches!(
+            deserialize_grpc_web::<TestPayload>(&framed),
+            Err(DeserializeError::GrpcWeb(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_protobuf_deterministic() {
+        struct Unordered;
+
+        impl ProtobufSupport for Unordered {
+            fn encode_protobuf(&self) -> Result<Vec<u8>, SerializeError> {
+                Ok(vec![2, 1])
+            }
+
+            fn encode_protobuf_deterministic(&self) -> Result<Vec<u8>, SerializeError> {
+                Ok(vec![1, 2])
+            }
+
+            fn decode_protobuf(_: &[u8]) -> Result<Self, DeserializeError> {
+                Ok(Self)
+            }
+        }
+
+        let config = PayloadConfig::default();
+        assert_eq!(vec![2, 1], encode_protobuf(&Unordered, &config).unwrap());
+
+        let config = config.protobuf_deterministic(true);
+        assert_eq!(vec![1, 2], encode_protobuf(&Unordered, &config).unwrap());
+    }
+}
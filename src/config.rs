@@ -0,0 +1,1361 @@
+use crate::capture::ErrorHook;
+use crate::extract::CancelHook;
+use crate::mirror::{Mirror, MirroredRequest};
+use crate::links::LinkProvider;
+use crate::negotiate::{UnsupportedAccept, DEFAULT_NEGOTIATOR};
+use crate::pool::BufferPool;
+use crate::error_mapping::{self, ErrorMapping};
+use crate::field_mask::FIELDS_HEADER;
+#[cfg(any(feature = "json", feature = "xml"))]
+use crate::proto_json::Mapping;
+#[cfg(feature = "jsonschema")]
+use crate::JsonSchema;
+#[cfg(feature = "xml")]
+use crate::XmlMapping;
+use crate::{error_id, BodyCapture, DeserializeErrorKind, ContentType, ErrorVerbosity, Link, Negotiator, PayloadError, SerializeError};
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE, COOKIE, VARY};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+#[cfg(feature = "jsonschema")]
+use std::any::TypeId;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configuration for [crate::Payload] extraction and responding.
+///
+/// Register it with [actix_web::App::app_data], either directly or wrapped in [actix_web::web::Data].
+/// If no configuration is registered, the default configuration is used.
+///
+/// # Example
+/// ```
+/// # #[cfg(all(feature = "json", feature = "protobuf"))]
+/// # fn main() {
+///     use actix_multiresponse::{ContentType, PayloadConfig};
+///
+///     let app = actix_web::App::new()
+///         .app_data(PayloadConfig::default().preference([ContentType::Protobuf, ContentType::Json]));
+/// # }
+/// # #[cfg(not(all(feature = "json", feature = "protobuf")))]
+/// # fn main() {}
+/// ```
+///
+/// A configuration registered on a [actix_web::Scope] or [actix_web::Resource] replaces the configuration of the app
+/// for its routes. This allows e.g. a deprecated `/v1` scope to map media types and transform bodies to a legacy
+/// representation, without duplicating the routes.
+///
+/// ```
+/// # #[cfg(feature = "json")]
+/// # fn main() {
+///     use actix_multiresponse::{ContentType, PayloadConfig};
+///
+///     let config = PayloadConfig::default().preference([ContentType::Json]);
+///     let legacy = config
+///         .clone()
+///         .alias("text/plain", ContentType::Json)
+///         .transform(|_, body| Ok(body))
+///         .response_transform(|_, body| Ok(body));
+///
+///     let app = actix_web::App::new()
+///         .app_data(config)
+///         .service(actix_web::web::scope("/v1").app_data(legacy));
+/// # }
+/// # #[cfg(not(feature = "json"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct PayloadConfig {
+    pub(crate) limit: usize,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) verify_checksums: bool,
+    pub(crate) idempotency_key: Option<HeaderName>,
+    pub(crate) envelope: bool,
+    pub(crate) stream_threshold: Option<usize>,
+    pub(crate) blocking_threshold: Option<usize>,
+    pub(crate) buffer_pool: Option<BufferPool>,
+    preference: Vec<ContentType>,
+    vary: Cow<'static, [HeaderName]>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    aliases: Vec<(String, ContentType)>,
+    pub(crate) max_format_attempts: usize,
+    assume_content_type: Option<ContentType>,
+    infer_content_type: bool,
+    pub(crate) format_cookie: Option<String>,
+    pub(crate) unsupported_accept: UnsupportedAccept,
+    pub(crate) strict_accept: bool,
+    pub(crate) field_masks: bool,
+    error_formats: Vec<(StatusClass, ContentType)>,
+    #[cfg(feature = "problem-details")]
+    pub(crate) problem_type_base: Option<String>,
+    #[cfg(any(feature = "json", feature = "xml"))]
+    pub(crate) deny_unknown_fields: bool,
+    #[cfg(feature = "jsonschema")]
+    json_schemas: Vec<(TypeId, JsonSchema)>,
+    #[cfg(feature = "protobuf")]
+    grpc_web: bool,
+    #[cfg(feature = "protobuf")]
+    pub(crate) protobuf_length_delimited: bool,
+    #[cfg(feature = "protobuf")]
+    pub(crate) protobuf_deterministic: bool,
+    pub(crate) transform: Option<BodyTransform>,
+    response_transform: Option<BodyTransform>,
+    pub(crate) negotiator: Negotiator,
+    error_hook: Option<ErrorHook>,
+    pub(crate) cancel_hook: Option<CancelHook>,
+    pub(crate) mirror: Option<Mirror>,
+    pub(crate) mirror_credentials: bool,
+    capture_limit: usize,
+    capture_sample_rate: f64,
+    error_verbosity: ErrorVerbosity,
+    error_mappings: Vec<ErrorMapping>,
+    pub(crate) links: Option<LinkProvider>,
+    #[cfg(feature = "protobuf")]
+    pub(crate) protobuf_links_field: Option<u32>,
+    #[cfg(feature = "json")]
+    pub(crate) json_format: JsonFormat,
+    #[cfg(feature = "json")]
+    pub(crate) json_naming: JsonNaming,
+    #[cfg(feature = "json")]
+    pub(crate) sort_json_keys: bool,
+    #[cfg(feature = "xml")]
+    pub(crate) xml_indent: Option<(char, usize)>,
+    #[cfg(feature = "xml")]
+    pub(crate) xml_mapping: XmlMapping,
+    #[cfg(any(feature = "json", feature = "xml"))]
+    pub(crate) protobuf_mapping: Mapping,
+}
+
+/// The layout of serialized JSON, see [PayloadConfig::json_format]
+#[cfg(feature = "json")]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub enum JsonFormat {
+    /// Pretty printed, indented with two spaces
+    #[default]
+    Pretty,
+    /// Without any whitespace
+    Compact,
+    /// Pretty printed, indented with the given number of spaces
+    Indent(usize),
+}
+
+/// The naming policy of the fields of JSON objects, see [crate::PayloadConfig::json_naming]
+#[cfg(feature = "json")]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub enum JsonNaming {
+    /// The field names of the types, e.g. `display_name`
+    #[default]
+    Preserve,
+    /// Field names in snake_case are converted to camelCase, e.g. `display_name` to `displayName`.
+    /// Leading underscores are kept
+    CamelCase,
+}
+
+/// A class of HTTP status codes, see [PayloadConfig::error_format]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StatusClass {
+    /// 4xx status codes
+    ClientError,
+    /// 5xx status codes
+    ServerError,
+}
+
+impl StatusClass {
+    /// The class of `status`, if it is an error status
+    pub fn of(status: StatusCode) -> Option<Self> {
+        if status.is_client_error() {
+            Some(Self::ClientError)
+        } else if status.is_server_error() {
+            Some(Self::ServerError)
+        } else {
+            None
+        }
+    }
+}
+
+/// A callback transforming request bodies before they are deserialized, see [PayloadConfig::transform],
+/// or serialized response bodies, see [PayloadConfig::response_transform]
+#[derive(Clone)]
+pub(crate) struct BodyTransform(Arc<TransformFn>);
+
+type TransformFn = dyn Fn(&ContentType, Bytes) -> Result<Bytes, String> + Send + Sync;
+
+impl BodyTransform {
+    pub(crate) fn apply(&self, content_type: &ContentType, body: Bytes) -> Result<Bytes, String> {
+        (self.0)(content_type, body)
+    }
+}
+
+impl fmt::Debug for BodyTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BodyTransform")
+    }
+}
+
+impl PayloadConfig {
+    /// Set the maximum size of a request body in bytes. Defaults to 2 MiB.
+    ///
+    /// Larger bodies are rejected with a HTTP 413 error. Requests with a larger `Content-Length` are rejected
+    /// before the body is read, other bodies as soon as the limit is crossed while reading.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Set the time the whole request body has to be received in. Disabled by default.
+    ///
+    /// Clients which send the body slower are rejected with a HTTP 408 error,
+    /// rather than keeping the extractor waiting indefinitely.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::PayloadConfig;
+    ///     use std::time::Duration;
+    ///
+    ///     let config = PayloadConfig::default().read_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Verify request bodies against the `Content-MD5` (base64) and `x-checksum-sha256` (hex or base64) headers
+    /// before deserializing them. Disabled by default.
+    ///
+    /// Bodies which do not match are rejected with a HTTP 422 error, requests without these headers are accepted.
+    /// Register the config on a resource to require integrity checks of only some routes.
+    /// actix does not expose the trailers of chunked requests, so the checksums are only read from the headers.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::PayloadConfig;
+    ///     use actix_web::{web, App};
+    ///
+    ///     let app = App::new().service(
+    ///         web::resource("/upload")
+    ///             .app_data(PayloadConfig::default().verify_checksums(true))
+    ///     );
+    /// ```
+    pub fn verify_checksums(mut self, verify_checksums: bool) -> Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    /// Store the value of the header `name`, usually [crate::IDEMPOTENCY_KEY], together with a digest of the raw body
+    /// in the request extensions as an [crate::IdempotencyKey] while reading the body. Disabled by default.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{PayloadConfig, IDEMPOTENCY_KEY};
+    ///
+    ///     let config = PayloadConfig::default().idempotency_key(IDEMPOTENCY_KEY);
+    /// ```
+    pub fn idempotency_key(mut self, name: HeaderName) -> Self {
+        self.idempotency_key = Some(name);
+        self
+    }
+
+    /// Wrap response bodies of [crate::Payload] in an envelope, containing the data and the
+    /// [crate::ResponseMeta] found in the request extensions. Disabled by default.
+    ///
+    /// For JSON this looks like `{ "data": ..., "meta": { ... } }`, XML uses the same structure.
+    /// For protobuf the envelope is a message with the data in field 1, and the metadata
+    /// as a `map<string, string>` in field 2. grpc-web responses are never enveloped.
+    pub fn envelope(mut self, envelope: bool) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    /// Stream response bodies of [crate::Payload] larger than `threshold` bytes in chunks,
+    /// instead of sending them as one contiguous buffer. Disabled by default.
+    ///
+    /// JSON and XML are serialized directly into a chain of chunks, avoiding the reallocation of one
+    /// large buffer while serializing. Other formats are serialized into one buffer, which is split
+    /// into chunks without copying. The `Content-Length` of streamed responses is still known upfront.
+    pub fn stream_threshold(mut self, threshold: usize) -> Self {
+        self.stream_threshold = Some(threshold);
+        self
+    }
+
+    /// Deserialize request bodies of [crate::Payload] larger than `threshold` bytes on the blocking thread pool,
+    /// using [actix_web::web::block], so large bodies do not block the worker thread. Disabled by default.
+    ///
+    /// The [actix_web::Responder] of [crate::Payload] is synchronous, so it serializes on the worker thread.
+    /// Handlers returning large responses can serialize on the blocking thread pool with [crate::Payload::into_response_blocking].
+    pub fn blocking_threshold(mut self, threshold: usize) -> Self {
+        self.blocking_threshold = Some(threshold);
+        self
+    }
+
+    /// Serialize JSON and XML response bodies of [crate::Payload] into buffers reused across requests,
+    /// rather than into a new buffer which grows while serializing, to avoid repeated large allocations. Disabled by default.
+    ///
+    /// Each worker thread keeps up to `buffers` buffers. A buffer is reused once the response serialized into it was sent,
+    /// buffers of bodies larger than `max_capacity` bytes are freed. Streamed, enveloped, masked and transformed responses
+    /// and responses with [PayloadConfig::links] do not use the pool.
+    ///
+    /// Only the body buffer is pooled: JSON is serialized without allocating once the pool is filled,
+    /// while the XML serializer still allocates for each element. See the `serialize` benchmark.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::PayloadConfig;
+    ///
+    ///     // Up to 8 buffers of at most 4 MiB per worker
+    ///     let config = PayloadConfig::default().buffer_pool(8, 4 * 1024 * 1024);
+    /// ```
+    pub fn buffer_pool(mut self, buffers: usize, max_capacity: usize) -> Self {
+        self.buffer_pool = Some(BufferPool { buffers, max_capacity });
+        self
+    }
+
+    /// Set the layout of serialized JSON. Defaults to [JsonFormat::Pretty].
+    ///
+    /// This applies to all responses, including those of [crate::Json].
+    #[cfg(feature = "json")]
+    pub fn json_format(mut self, format: JsonFormat) -> Self {
+        self.json_format = format;
+        self
+    }
+
+    /// Set the naming policy of the fields of JSON objects. Defaults to [JsonNaming::Preserve].
+    ///
+    /// With [JsonNaming::CamelCase], e.g. types generated by prost with snake_case fields are serialized
+    /// and deserialized with camelCase fields, following the protobuf JSON mapping. The keys of maps are not renamed,
+    /// nor are the fields of `#[serde(flatten)]` structs and of internally tagged or untagged enums.
+    /// This applies to all JSON request and response bodies, including those of [crate::Json].
+    #[cfg(feature = "json")]
+    pub fn json_naming(mut self, naming: JsonNaming) -> Self {
+        self.json_naming = naming;
+        self
+    }
+
+    /// Serialize the members of JSON objects sorted by their keys, rather than in the order of the fields. Disabled by default.
+    ///
+    /// Together with [JsonFormat::Compact], responses are in a canonical form, e.g. for signing them or for caching
+    /// them by their content. The value is converted to a JSON value first, at the cost of an additional copy.
+    #[cfg(feature = "json")]
+    pub fn sort_json_keys(mut self, sort_json_keys: bool) -> Self {
+        self.sort_json_keys = sort_json_keys;
+        self
+    }
+
+    /// Serialize 64 bit integers as strings in JSON, like the protobuf JSON mapping does, so JavaScript clients
+    /// do not lose precision on e.g. `int64` IDs. Disabled by default.
+    ///
+    /// JSON request bodies may then contain 64 bit integers as either numbers or strings.
+    /// This applies to all JSON and XML bodies of [crate::Payload], including those of the single format wrappers.
+    #[cfg(any(feature = "json", feature = "xml"))]
+    pub fn int64_as_string(mut self, int64_as_string: bool) -> Self {
+        self.protobuf_mapping.int64_as_string = int64_as_string;
+        self
+    }
+
+    /// Serialize maps as lists of entries with the `key` and `value` fields, rather than as objects. Disabled by default.
+    ///
+    /// This allows e.g. protobuf `map<int32, string>` fields in XML, where integer keys are not valid element names.
+    /// Fields of `#[serde(flatten)]` structs are serialized as maps by serde, and become entries as well.
+    /// Request bodies are still expected to contain maps as objects.
+    #[cfg(any(feature = "json", feature = "xml"))]
+    pub fn map_entries(mut self, map_entries: bool) -> Self {
+        self.protobuf_mapping.map_entries = map_entries;
+        self
+    }
+
+    /// Serialize the protobuf enums of fields annotated with [crate::serialize_proto_enum] by their number,
+    /// rather than by the name of their variant. Disabled by default.
+    ///
+    /// Request bodies may contain either names or numbers, regardless of this setting.
+    #[cfg(any(feature = "json", feature = "xml"))]
+    pub fn enum_numbers(mut self, enum_numbers: bool) -> Self {
+        self.protobuf_mapping.enum_numbers = enum_numbers;
+        self
+    }
+
+    /// Omit `proto3 optional` fields annotated with [crate::serialize_proto_optional] from serialized JSON if they are absent,
+    /// like the protobuf JSON mapping does, rather than serializing them as `null`. Disabled by default.
+    ///
+    /// Present fields are serialized even if they hold the default value of their type, so clients can distinguish
+    /// a field which was not provided from a zero value. XML never contains absent fields.
+    #[cfg(any(feature = "json", feature = "xml"))]
+    pub fn omit_absent_fields(mut self, omit_absent_fields: bool) -> Self {
+        self.protobuf_mapping.omit_absent_fields = omit_absent_fields;
+        self
+    }
+
+    /// Indent serialized XML with `indent_size` times `indent_char` per level. Not indented by default.
+    #[cfg(feature = "xml")]
+    pub fn xml_indent(mut self, indent_char: char, indent_size: usize) -> Self {
+        self.xml_indent = Some((indent_char, indent_size));
+        self
+    }
+
+    /// Map fields of the types to XML attributes, elements with other names and text content, so the XML representation
+    /// can match an existing schema without changing the JSON and protobuf representations. Applies to request and response bodies.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{PayloadConfig, XmlMapping};
+    ///
+    ///     let config = PayloadConfig::default().xml_mapping(
+    ///         XmlMapping::new()
+    ///             .scalar_attributes("Product")
+    ///             .element("Product", "name", "Title"),
+    ///     );
+    /// ```
+    #[cfg(feature = "xml")]
+    pub fn xml_mapping(mut self, mapping: XmlMapping) -> Self {
+        self.xml_mapping = mapping;
+        self
+    }
+
+    /// Set the server side format preference, the most preferred format first.
+    ///
+    /// The preference is used to break ties when the `Accept` header of the client
+    /// lists multiple formats with equal quality values, or uses wildcards like `*/*`.
+    /// Enabled formats which are not listed are least preferred, in their default order.
+    ///
+    /// If no preference is set, the format of the request body is preferred, followed by
+    /// the default order.
+    pub fn preference<I: IntoIterator<Item = ContentType>>(mut self, preference: I) -> Self {
+        self.preference = preference.into_iter().collect();
+        self
+    }
+
+    /// Set the request headers listed in the `Vary` header of negotiated responses, so HTTP caches
+    /// don't serve a response in one format to clients asking for another. Defaults to `Accept`.
+    /// An empty list disables the header.
+    ///
+    /// This applies to [crate::Payload], [crate::PayloadFile] and
+    /// [crate::render_negotiated_error]. Existing `Vary` headers are kept.
+    pub fn vary<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+        self.vary = headers.into_iter().collect::<Vec<_>>().into();
+        self
+    }
+
+    /// Add a header to all negotiated responses, replacing a header with the same name.
+    /// This applies to the same responses as [PayloadConfig::vary].
+    pub fn response_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Treat request bodies with the media type `media_type` as `format`, for example to accept JSON
+    /// from legacy clients sending `Content-Type: text/plain`. Media type parameters are ignored when matching,
+    /// and the media type is matched case insensitively.
+    ///
+    /// Aliases only apply to media types which do not indicate a supported format already.
+    /// Responses to such requests are in `format`, unless the `Accept` header indicates otherwise.
+    /// A media type can be an alias for multiple formats. Only the first one registered is used,
+    /// unless more attempts are allowed with [PayloadConfig::max_format_attempts].
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "json")]
+    /// # fn main() {
+    ///     use actix_multiresponse::{ContentType, PayloadConfig};
+    ///
+    ///     let config = PayloadConfig::default().alias("text/plain", ContentType::Json);
+    /// # }
+    /// # #[cfg(not(feature = "json"))]
+    /// # fn main() {}
+    /// ```
+    pub fn alias<S: AsRef<str>>(mut self, media_type: S, format: ContentType) -> Self {
+        self.aliases.push((media_type.as_ref().to_lowercase(), format));
+        self
+    }
+
+    /// Try at most `attempts` formats to deserialize a request body of which the media type is an alias for multiple formats,
+    /// see [PayloadConfig::alias]. If the body fails to deserialize in the first format, the next ones are tried in the order
+    /// they were registered, on the same thread as the first one, see [PayloadConfig::blocking_threshold].
+    /// Only the first format is tried by default.
+    ///
+    /// If all attempts fail, the request is rejected with the error of the first format,
+    /// wrapped in a [PayloadError::Attempts] recording the formats which were tried. Values below 1 are treated as 1.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(all(feature = "json", feature = "protobuf"))]
+    /// # fn main() {
+    ///     use actix_multiresponse::{ContentType, PayloadConfig};
+    ///
+    ///     let config = PayloadConfig::default()
+    ///         .alias("application/octet-stream", ContentType::Protobuf)
+    ///         .alias("application/octet-stream", ContentType::Json)
+    ///         .max_format_attempts(2);
+    /// # }
+    /// # #[cfg(not(all(feature = "json", feature = "protobuf")))]
+    /// # fn main() {}
+    /// ```
+    pub fn max_format_attempts(mut self, attempts: usize) -> Self {
+        self.max_format_attempts = attempts.max(1);
+        self
+    }
+
+    /// Assume request bodies are in `format` if the request has no `Content-Type` header at all,
+    /// instead of rejecting them with a HTTP 400 error. Requests with an unsupported `Content-Type` are still rejected.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "json")]
+    /// # fn main() {
+    ///     use actix_multiresponse::{ContentType, PayloadConfig};
+    ///
+    ///     let config = PayloadConfig::default().assume_content_type(ContentType::Json);
+    /// # }
+    /// # #[cfg(not(feature = "json"))]
+    /// # fn main() {}
+    /// ```
+    pub fn assume_content_type(mut self, format: ContentType) -> Self {
+        self.assume_content_type = Some(format);
+        self
+    }
+
+    /// Assume request bodies without a `Content-Type` header are in the format indicated by their `Accept` header,
+    /// for clients which only set `Accept`. Disabled by default.
+    ///
+    /// Wildcards like `*/*` do not indicate a format. If the `Accept` header does not indicate a supported format either,
+    /// [PayloadConfig::assume_content_type] applies.
+    pub fn infer_content_type_from_accept(mut self, infer: bool) -> Self {
+        self.infer_content_type = infer;
+        self
+    }
+
+    /// Remember the format a client asked for in the `Accept` header in the cookie `name`, and respond in that format
+    /// to later requests of the client which do not explicitly accept a supported format, e.g. downloads triggered by
+    /// links or images, for which browsers send their own `Accept` header. Disabled by default.
+    ///
+    /// The cookie holds the short name of the format, see [ContentType::as_str]. It is set on successful responses of
+    /// [crate::Payload] when the `Accept` header explicitly names the negotiated format without wildcards
+    /// and the cookie does not hold that format already. See [crate::NegotiationRule::Cookie].
+    /// `Cookie` is added to the [PayloadConfig::vary] headers.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::PayloadConfig;
+    ///
+    ///     let config = PayloadConfig::default().format_cookie("format");
+    /// ```
+    pub fn format_cookie<S: Into<String>>(mut self, name: S) -> Self {
+        self.format_cookie = Some(name.into());
+        self
+    }
+
+    /// Set how [crate::Payload] responds to requests of which the `Accept` header only lists unsupported formats,
+    /// e.g. `Accept: text/html`. Defaults to [UnsupportedAccept::Fallback], responding in the format of the request body,
+    /// or the default format if the request has no body in a supported format.
+    ///
+    /// With [UnsupportedAccept::Reject], such requests are answered with a HTTP 406 error listing the supported formats.
+    /// Requests without an `Accept` header, and requests for which a [crate::ForcedFormat] or the cookie of
+    /// [PayloadConfig::format_cookie] indicates a supported format, are always answered.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{PayloadConfig, UnsupportedAccept};
+    ///
+    ///     let config = PayloadConfig::default().unsupported_accept(UnsupportedAccept::Reject);
+    /// ```
+    pub fn unsupported_accept(mut self, policy: UnsupportedAccept) -> Self {
+        self.unsupported_accept = policy;
+        self
+    }
+
+    /// Treat `Accept` headers containing a malformed media range as if they indicate no supported format,
+    /// rather than negotiating among the valid media ranges. Disabled by default.
+    ///
+    /// Some HTTP clients emit malformed entries, e.g. `Accept: application/json, ;;;`, which by default is negotiated as JSON.
+    /// With strict parsing, the format of the request body is used instead, see [PayloadConfig::unsupported_accept].
+    pub fn strict_accept(mut self, strict: bool) -> Self {
+        self.strict_accept = strict;
+        self
+    }
+
+    /// Accept and emit grpc-web framed protobuf bodies, under the `application/grpc-web+proto` media type.
+    /// This allows browsers using grpc-web clients to use the endpoints directly. Disabled by default.
+    ///
+    /// Request bodies must consist of a single uncompressed message frame, optionally followed by a trailers frame.
+    /// Responses contain one message frame followed by a trailers frame indicating success.
+    #[cfg(feature = "protobuf")]
+    pub fn grpc_web(mut self, grpc_web: bool) -> Self {
+        self.grpc_web = grpc_web;
+        self
+    }
+
+    /// Expect protobuf request bodies to be length-delimited, i.e. prefixed with the length of the message as varint,
+    /// as produced by some streaming clients. Disabled by default.
+    ///
+    /// This does not affect grpc-web bodies or responses.
+    #[cfg(feature = "protobuf")]
+    pub fn protobuf_length_delimited(mut self, length_delimited: bool) -> Self {
+        self.protobuf_length_delimited = length_delimited;
+        self
+    }
+
+    /// Transform request bodies before they are deserialized, for example to accept lenient JSON
+    /// with comments or trailing commas from internal tooling. Responses are not affected.
+    ///
+    /// The callback receives the format of the request body. An error rejects the request with a HTTP 400 error.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "json")]
+    /// # fn main() {
+    ///     use actix_multiresponse::{ContentType, PayloadConfig};
+    ///
+    ///     let config = PayloadConfig::default().transform(|content_type, body| {
+    ///         if content_type.ne(&ContentType::Json) {
+    ///             return Ok(body);
+    ///         }
+    ///
+    ///         // Strip lines containing only a comment
+    ///         let body = std::str::from_utf8(&body).map_err(|e| e.to_string())?;
+    ///         let stripped: Vec<&str> = body.lines().filter(|l| !l.trim_start().starts_with("//")).collect();
+    ///         Ok(stripped.join("\n").into())
+    ///     });
+    /// # }
+    /// # #[cfg(not(feature = "json"))]
+    /// # fn main() {}
+    /// ```
+    pub fn transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&ContentType, Bytes) -> Result<Bytes, String> + Send + Sync + 'static,
+    {
+        self.transform = Some(BodyTransform(Arc::new(transform)));
+        self
+    }
+
+    /// Transform serialized response bodies of [crate::Payload] before they are sent, for example to rename fields
+    /// to a legacy representation for a deprecated scope. Request bodies are not affected, see [PayloadConfig::transform].
+    ///
+    /// The callback receives the negotiated format of the response. An error results in a HTTP 500 error.
+    /// Streamed responses, see [PayloadConfig::stream_threshold], are transformed as a whole before they are split again.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "json")]
+    /// # fn main() {
+    ///     use actix_multiresponse::{ContentType, PayloadConfig};
+    ///
+    ///     let config = PayloadConfig::default().response_transform(|content_type, body| {
+    ///         if content_type.ne(&ContentType::Json) {
+    ///             return Ok(body);
+    ///         }
+    ///
+    ///         let body = std::str::from_utf8(&body).map_err(|e| e.to_string())?;
+    ///         Ok(body.replace("\"display_name\"", "\"name\"").into())
+    ///     });
+    /// # }
+    /// # #[cfg(not(feature = "json"))]
+    /// # fn main() {}
+    /// ```
+    pub fn response_transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&ContentType, Bytes) -> Result<Bytes, String> + Send + Sync + 'static,
+    {
+        self.response_transform = Some(BodyTransform(Arc::new(transform)));
+        self
+    }
+
+    /// Apply the [PayloadConfig::response_transform] to a serialized response body, if configured
+    pub(crate) fn transform_response(&self, content_type: &ContentType, body: Vec<u8>) -> Result<Vec<u8>, SerializeError> {
+        match &self.response_transform {
+            Some(transform) => transform
+                .apply(content_type, body.into())
+                .map(Vec::from)
+                .map_err(SerializeError::Transform),
+            None => Ok(body),
+        }
+    }
+
+    /// Whether a [PayloadConfig::response_transform] is configured
+    pub(crate) fn transforms_responses(&self) -> bool {
+        self.response_transform.is_some()
+    }
+
+    /// Apply the response header policy to a negotiated response
+    pub(crate) fn apply_response_headers(&self, response: &mut HttpResponse) {
+        let headers = response.headers_mut();
+        if !self.vary.is_empty() {
+            let mut vary = self.vary.iter().map(HeaderName::as_str).collect::<Vec<_>>();
+            // The format may be picked from the cookie of format_cookie
+            if self.format_cookie.is_some() && !self.vary.contains(&COOKIE) {
+                vary.push(COOKIE.as_str());
+            }
+            // The fields of the response may be picked with the header of field_masks
+            if self.field_masks && !self.vary.iter().any(|name| name.as_str().eq(FIELDS_HEADER)) {
+                vary.push(FIELDS_HEADER);
+            }
+
+            let vary = vary.join(", ");
+            if let Ok(vary) = HeaderValue::from_str(&vary) {
+                headers.append(VARY, vary);
+            }
+        }
+
+        for (name, value) in &self.headers {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+
+    /// Encode protobuf responses deterministically, using [crate::ProtobufSupport::encode_protobuf_deterministic],
+    /// e.g. for response signing or hashing. Disabled by default.
+    ///
+    /// Messages derived with prost are deterministic as long as their map fields are `btree_map`s.
+    #[cfg(feature = "protobuf")]
+    pub fn protobuf_deterministic(mut self, deterministic: bool) -> Self {
+        self.protobuf_deterministic = deterministic;
+        self
+    }
+
+    /// Reject JSON and XML request bodies containing fields which the target type does not know,
+    /// as if it was annotated with `#[serde(deny_unknown_fields)]`. Disabled by default.
+    ///
+    /// The error names the path of the first unknown field, e.g. ``unknown field `authors[1].email` ``.
+    /// This relies on the type ignoring unknown fields through [serde::de::IgnoredAny], as types derived with serde do.
+    #[cfg(any(feature = "json", feature = "xml"))]
+    pub fn deny_unknown_fields(mut self, deny_unknown_fields: bool) -> Self {
+        self.deny_unknown_fields = deny_unknown_fields;
+        self
+    }
+
+    /// Validate JSON request bodies deserialized into `T` against `schema` before deserializing them,
+    /// replacing a schema registered for `T` before. Requires the `jsonschema` feature.
+    ///
+    /// Bodies which do not match the schema are rejected with a [DeserializeError::Schema](crate::DeserializeError::Schema),
+    /// a HTTP 422 error listing the violations with the JSON Pointers of the invalid values.
+    /// Register the configuration on a route or scope to use a different schema per route,
+    /// or as [web::Data] to share the compiled schemas between workers.
+    ///
+    /// A body is parsed twice when a schema is registered: into a [serde_json::Value] to validate it,
+    /// and again to deserialize it, so deserialization errors keep their position in the body.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{JsonSchema, PayloadConfig};
+    ///
+    ///     #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+    ///     struct User {
+    ///         #[prost(string, tag = "1")]
+    ///         name: String,
+    ///     }
+    ///
+    ///     let schema = JsonSchema::compile(&serde_json::json!({
+    ///         "type": "object",
+    ///         "properties": { "name": { "type": "string", "maxLength": 64 } },
+    ///     }))
+    ///     .unwrap();
+    ///     let config = PayloadConfig::default().json_schema::<User>(schema);
+    /// ```
+    #[cfg(feature = "jsonschema")]
+    pub fn json_schema<T: 'static>(mut self, schema: JsonSchema) -> Self {
+        let type_id = TypeId::of::<T>();
+        self.json_schemas.retain(|(registered, _)| *registered != type_id);
+        self.json_schemas.push((type_id, schema));
+        self
+    }
+
+    /// The schema registered for the type `type_id` with [PayloadConfig::json_schema]
+    #[cfg(feature = "jsonschema")]
+    pub(crate) fn json_schema_of(&self, type_id: TypeId) -> Option<&JsonSchema> {
+        self.json_schemas
+            .iter()
+            .find(|(registered, _)| *registered == type_id)
+            .map(|(_, schema)| schema)
+    }
+
+    /// Limit responses of [crate::Payload] to the fields requested with the `X-Fields` header,
+    /// or the `fields` query parameter, see [crate::FieldMask]. Disabled by default.
+    ///
+    /// JSON responses are pruned after serialization. Protobuf responses are encoded with
+    /// [crate::ProtobufSupport::encode_protobuf_masked], which fails with a HTTP 406 unless it is implemented.
+    /// XML and enveloped responses are never masked.
+    ///
+    /// `X-Fields` is added to the [PayloadConfig::vary] headers.
+    pub fn field_masks(mut self, field_masks: bool) -> Self {
+        self.field_masks = field_masks;
+        self
+    }
+
+    /// Render errors with a status in `class` in `format`, instead of the negotiated format.
+    /// By default, errors are rendered in the negotiated format.
+    ///
+    /// This applies to [crate::render_negotiated_error], e.g. to always render errors as JSON
+    /// for clients which can only display JSON errors, while successful responses are still negotiated.
+    /// Formats which are not supported with this configuration are ignored.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "json")]
+    /// # fn main() {
+    ///     use actix_multiresponse::{ContentType, PayloadConfig, StatusClass};
+    ///
+    ///     let config = PayloadConfig::default()
+    ///         .error_format(StatusClass::ClientError, ContentType::Json)
+    ///         .error_format(StatusClass::ServerError, ContentType::Json);
+    /// # }
+    /// # #[cfg(not(feature = "json"))]
+    /// # fn main() {}
+    /// ```
+    pub fn error_format(mut self, class: StatusClass, format: ContentType) -> Self {
+        self.error_formats.retain(|(existing, _)| existing.ne(&class));
+        self.error_formats.push((class, format));
+        self
+    }
+
+    /// The format errors with `status` are always rendered in, if configured and supported
+    pub(crate) fn error_format_for(&self, status: StatusCode) -> Option<ContentType> {
+        let class = StatusClass::of(status)?;
+        self.error_formats
+            .iter()
+            .find(|(existing, format)| existing.eq(&class) && self.supports(format))
+            .map(|(_, format)| format.clone())
+    }
+
+    /// Call `hook` with a truncated copy of request bodies which failed to deserialize, e.g. to log them.
+    /// JSON and XML bodies are captured as text, other bodies are base64 encoded.
+    /// The size of the copy and the share of errors which are reported are set with [PayloadConfig::body_capture].
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::PayloadConfig;
+    ///
+    ///     let config = PayloadConfig::default().error_hook(|capture| {
+    ///         log::warn!("Malformed {} body: {} ({})", capture.content_type, capture.error, capture.body);
+    ///     });
+    /// ```
+    pub fn error_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&BodyCapture) + Send + Sync + 'static,
+    {
+        self.error_hook = Some(ErrorHook::new(Arc::new(hook)));
+        self
+    }
+
+    /// Call `hook` when the extraction of a [crate::Payload] is cancelled while its body is read, e.g. because the client
+    /// disconnected or a timeout of actix-web fired, with the request and the number of bytes read so far. Useful for metrics.
+    ///
+    /// The body is no longer read after the extraction is cancelled, the chunks read so far are freed.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::PayloadConfig;
+    ///
+    ///     let config = PayloadConfig::default().cancel_hook(|req, read| {
+    ///         log::debug!("Reading the body of {} was cancelled after {read} bytes", req.path());
+    ///     });
+    /// ```
+    pub fn cancel_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&HttpRequest, usize) + Send + Sync + 'static,
+    {
+        self.cancel_hook = Some(CancelHook(Arc::new(hook)));
+        self
+    }
+
+    /// Mirror request bodies extracted with [crate::Mirrored] to `sender`, re-serialized in `format`, e.g. to dark launch
+    /// a service expecting another format. The future returned by `sender` is spawned without waiting for it,
+    /// so a slow or failing shadow service does not affect requests. Bodies which fail to serialize are not mirrored.
+    /// The credentials of the request are left out, see [PayloadConfig::mirror_credentials].
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "protobuf")]
+    /// # fn main() {
+    ///     use actix_multiresponse::{ContentType, PayloadConfig};
+    ///
+    ///     let config = PayloadConfig::default().mirror(ContentType::Protobuf, |request| async move {
+    ///         // Send the request to the shadow service, e.g. with awc
+    ///         log::debug!("Mirroring {} {} ({} bytes)", request.method, request.path, request.body.len());
+    ///     });
+    /// # }
+    /// # #[cfg(not(feature = "protobuf"))]
+    /// # fn main() {}
+    /// ```
+    pub fn mirror<F, Fut>(mut self, format: ContentType, sender: F) -> Self
+    where
+        F: Fn(MirroredRequest) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + 'static,
+    {
+        self.mirror = Some(Mirror::new(format, sender));
+        self
+    }
+
+    /// Keep the `Authorization`, `Proxy-Authorization` and `Cookie` headers of requests mirrored with [PayloadConfig::mirror].
+    /// Disabled by default, so the credentials of clients are not handed to a shadow service unless it is trusted with them.
+    pub fn mirror_credentials(mut self, forward: bool) -> Self {
+        self.mirror_credentials = forward;
+        self
+    }
+
+    /// Set the maximum number of bytes captured of bodies reported to [PayloadConfig::error_hook], 1 KiB by default,
+    /// and the share of errors which are reported, between `0.0` and `1.0`. Defaults to reporting every error.
+    /// Errors are sampled evenly, e.g. every tenth error is reported with a sample rate of `0.1`.
+    pub fn body_capture(mut self, max_len: usize, sample_rate: f64) -> Self {
+        self.capture_limit = max_len;
+        self.capture_sample_rate = sample_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Report a deserialize error to the [PayloadConfig::error_hook], if configured,
+    /// and redact it with [ErrorVerbosity::Redacted]
+    pub(crate) fn report_error(&self, error: PayloadError) -> PayloadError {
+        if let Some(hook) = &self.error_hook {
+            hook.report(&error, self.capture_limit, self.capture_sample_rate);
+        }
+
+        let error = match self.error_verbosity {
+            ErrorVerbosity::Detailed => error,
+            ErrorVerbosity::Redacted => error_id::redact(error),
+        };
+
+        error_mapping::apply(&self.error_mappings, error)
+    }
+
+    /// Set whether the causes of deserialize and serialize errors are included in responses,
+    /// [ErrorVerbosity::Detailed] by default. With [ErrorVerbosity::Redacted], responses only contain
+    /// a generic message and an [crate::ErrorId], which is logged together with the cause,
+    /// so messages of the deserializer do not reveal the schema of the types.
+    ///
+    /// Handlers extracting `Result<Payload<T>, PayloadError>` receive a [PayloadError::Redacted],
+    /// [PayloadError::detailed] returns the original error.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{ErrorVerbosity, PayloadConfig};
+    ///
+    ///     let config = PayloadConfig::default().error_verbosity(ErrorVerbosity::Redacted);
+    /// ```
+    pub fn error_verbosity(mut self, verbosity: ErrorVerbosity) -> Self {
+        self.error_verbosity = verbosity;
+        self
+    }
+
+    /// Respond to deserialize errors of `kind` with `status`, and include `code` in the response,
+    /// so the error contract of an API does not depend on the status codes and messages of this crate.
+    /// Mapping a kind again replaces the earlier mapping.
+    ///
+    /// The error is wrapped in a [PayloadError::Mapped], of which [PayloadError::error_code] returns the code.
+    /// Plain text and problem details responses prefix the message with the code,
+    /// the [crate::ErrorBody] of [crate::render_negotiated_error] has a separate `code` field.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{DeserializeErrorKind, PayloadConfig};
+    ///     use actix_web::http::StatusCode;
+    ///
+    ///     let config = PayloadConfig::default()
+    ///         .map_error(DeserializeErrorKind::UnknownField, StatusCode::UNPROCESSABLE_ENTITY, "UNKNOWN_FIELD")
+    ///         .map_error(DeserializeErrorKind::Syntax, StatusCode::BAD_REQUEST, "MALFORMED_BODY");
+    /// ```
+    pub fn map_error<S: Into<String>>(mut self, kind: DeserializeErrorKind, status: StatusCode, code: S) -> Self {
+        self.error_mappings.retain(|mapping| mapping.kind != kind);
+        self.error_mappings.push(ErrorMapping {
+            kind,
+            status,
+            code: code.into(),
+        });
+        self
+    }
+
+    /// Inject the hypermedia links returned by `links` for the request into [crate::Payload] responses,
+    /// in the representation of the negotiated format:
+    /// - JSON objects get a HAL `_links` member, e.g. `"_links": {"self": {"href": "/books/1"}}`.
+    ///   Relations with multiple links are arrays
+    /// - XML root elements get a `<link rel="self" href="/books/1"/>` element per link
+    /// - Protobuf messages get the links in the field set with [PayloadConfig::protobuf_links_field]
+    ///
+    /// Other JSON values, grpc-web and enveloped responses are left unchanged.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{Link, PayloadConfig};
+    ///
+    ///     let config = PayloadConfig::default().links(|req| {
+    ///         let mut links = vec![Link::new("self", req.path())];
+    ///         if req.match_pattern().as_deref() == Some("/books/{id}") {
+    ///             links.push(Link::new("collection", "/books"));
+    ///         }
+    ///         links
+    ///     });
+    /// ```
+    pub fn links<F>(mut self, links: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> Vec<Link> + Send + Sync + 'static,
+    {
+        self.links = Some(LinkProvider::new(Arc::new(links)));
+        self
+    }
+
+    /// The number of the field protobuf responses carry the [PayloadConfig::links] in.
+    /// Without a field number, links are not added to protobuf responses.
+    ///
+    /// The links are appended as a repeated field of messages equivalent to
+    /// `message Link { string rel = 1; string href = 2; }`, which the response messages should declare,
+    /// e.g. `repeated Link links = 15;`.
+    #[cfg(feature = "protobuf")]
+    pub fn protobuf_links_field(mut self, tag: u32) -> Self {
+        self.protobuf_links_field = Some(tag);
+        self
+    }
+
+    /// The links of the response to `req`, see [PayloadConfig::links]. Enveloped responses have no links
+    pub(crate) fn links_for(&self, req: &HttpRequest) -> Vec<Link> {
+        match &self.links {
+            Some(provider) if !self.envelope => provider.links(req),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The message of a response to a serialize error, redacted with [ErrorVerbosity::Redacted]
+    pub(crate) fn serialize_error_message(&self, error: &SerializeError) -> String {
+        match self.error_verbosity {
+            ErrorVerbosity::Detailed => error.to_string(),
+            ErrorVerbosity::Redacted => error_id::redact_serialize_error(error),
+        }
+    }
+
+    /// The base URI of the `type` of problem details, see [crate::ProblemDetails].
+    /// The type of a problem is the base URI followed by the slug of the reason phrase of its status,
+    /// e.g. `https://example.com/problems/not-found`. Without a base URI the type is `about:blank`.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::PayloadConfig;
+    ///
+    ///     let config = PayloadConfig::default().problem_type_base("https://example.com/problems/");
+    /// ```
+    #[cfg(feature = "problem-details")]
+    pub fn problem_type_base<S: Into<String>>(mut self, base: S) -> Self {
+        self.problem_type_base = Some(base.into());
+        self
+    }
+
+    /// Set the [Negotiator] picking the format of responses, e.g. to prefer the format of the request body over the `Accept` header
+    pub fn negotiator(mut self, negotiator: Negotiator) -> Self {
+        self.negotiator = negotiator;
+        self
+    }
+
+    /// Whether the format is supported with this configuration
+    pub(crate) fn supports(&self, content_type: &ContentType) -> bool {
+        match content_type {
+            #[cfg(feature = "protobuf")]
+            ContentType::GrpcWeb => self.grpc_web,
+            ContentType::Other(_) => false,
+            _ => true,
+        }
+    }
+
+    /// The media types configured with [PayloadConfig::alias] for `format`
+    pub(crate) fn aliases_of<'a>(&'a self, format: &'a ContentType) -> impl Iterator<Item = &'a str> {
+        self.aliases
+            .iter()
+            .filter(move |(_, aliased)| aliased.eq(format))
+            .map(|(media_type, _)| media_type.as_str())
+    }
+
+    /// The format a `Content-Type` header value is an alias for, if any
+    pub(crate) fn resolve_alias(&self, header_value: &str) -> Option<ContentType> {
+        let l = header_value.to_lowercase();
+        let essence = l.split(';').next().unwrap_or_default().trim();
+
+        self.aliases
+            .iter()
+            .find(|(media_type, _)| media_type.eq(essence))
+            .map(|(_, format)| format.clone())
+    }
+
+    /// The other formats to try for a request body in `content_type`, if `content_type` was resolved from the aliases
+    /// of its `Content-Type` header, in the order they were registered. Unsupported formats are left out
+    pub(crate) fn alias_fallbacks(&self, headers: &HeaderMap, content_type: &ContentType) -> Vec<ContentType> {
+        let Some(l) = headers.get(CONTENT_TYPE).and_then(|hv| hv.to_str().ok()).map(str::to_lowercase) else {
+            return Vec::new();
+        };
+        let essence = l.split(';').next().unwrap_or_default().trim();
+
+        let mut formats = self
+            .aliases
+            .iter()
+            .filter(|(media_type, _)| media_type.eq(essence))
+            .map(|(_, format)| format);
+        if formats.next() != Some(content_type) {
+            return Vec::new();
+        }
+
+        formats.filter(|format| self.supports(format)).cloned().collect()
+    }
+
+    /// The format of a request body with this configuration, given the format parsed from its `Content-Type` header.
+    /// This applies [PayloadConfig::infer_content_type_from_accept], [PayloadConfig::assume_content_type] and the aliases,
+    /// unsupported formats become [ContentType::Other].
+    pub(crate) fn request_format(&self, content_type: ContentType, headers: &HeaderMap) -> ContentType {
+        let content_type = match (content_type, headers.get(CONTENT_TYPE)) {
+            (ContentType::Other(media_type), None) => self
+                .inferred_format(headers)
+                .or_else(|| self.assume_content_type.clone())
+                .unwrap_or(ContentType::Other(media_type)),
+            (ContentType::Other(media_type), Some(header_value)) => header_value
+                .to_str()
+                .ok()
+                .and_then(|hv_str| self.resolve_alias(hv_str))
+                .unwrap_or(ContentType::Other(media_type)),
+            (content_type, _) => content_type,
+        };
+
+        if self.supports(&content_type) || content_type.is_other() {
+            content_type
+        } else {
+            ContentType::Other(content_type.to_string())
+        }
+    }
+
+    /// The format indicated by the `Accept` header without wildcards, if [PayloadConfig::infer_content_type_from_accept] is enabled
+    fn inferred_format(&self, headers: &HeaderMap) -> Option<ContentType> {
+        if !self.infer_content_type {
+            return None;
+        }
+
+        self.explicit_accept_format(headers)
+    }
+
+    /// The format indicated by the `Accept` header without wildcards, if any
+    pub(crate) fn explicit_accept_format(&self, headers: &HeaderMap) -> Option<ContentType> {
+        let accept = crate::headers::merged_header_value(headers, ACCEPT.as_str())?;
+        let explicit: Vec<&str> = accept.split(',').filter(|range| !range.contains('*')).collect();
+        let preference = self.preference_order(&ContentType::Other(String::new()));
+
+        Some(ContentType::from_accept_value_with(&explicit.join(","), &preference, self.strict_accept)).filter(|format| !format.is_other())
+    }
+
+    /// The order in which formats are preferred for a request with a body in `request_format`
+    pub(crate) fn preference_order(&self, request_format: &ContentType) -> Vec<ContentType> {
+        let preferred = if self.preference.is_empty() {
+            std::slice::from_ref(request_format)
+        } else {
+            self.preference.as_slice()
+        };
+
+        let mut order = Vec::new();
+        for content_type in preferred.iter().chain(ContentType::enabled()) {
+            if self.supports(content_type) && !order.contains(content_type) {
+                order.push(content_type.clone());
+            }
+        }
+
+        order
+    }
+
+    /// Get the configuration registered for the request, or the default configuration
+    pub(crate) fn from_req(req: &HttpRequest) -> &Self {
+        req.app_data::<Self>()
+            .or_else(|| req.app_data::<web::Data<Self>>().map(|d| d.as_ref()))
+            .unwrap_or(&DEFAULT_CONFIG)
+    }
+}
+
+/// The default request body size limit, 2 MiB
+const DEFAULT_LIMIT: usize = 2_097_152;
+
+/// The default size of captured request bodies, 1 KiB
+const DEFAULT_CAPTURE_LIMIT: usize = 1024;
+
+pub(crate) static DEFAULT_CONFIG: PayloadConfig = PayloadConfig {
+    limit: DEFAULT_LIMIT,
+    read_timeout: None,
+    verify_checksums: false,
+    idempotency_key: None,
+    envelope: false,
+    stream_threshold: None,
+    blocking_threshold: None,
+    buffer_pool: None,
+    preference: Vec::new(),
+    vary: Cow::Borrowed(&[ACCEPT]),
+    headers: Vec::new(),
+    aliases: Vec::new(),
+    max_format_attempts: 1,
+    assume_content_type: None,
+    infer_content_type: false,
+    format_cookie: None,
+    unsupported_accept: UnsupportedAccept::Fallback,
+    strict_accept: false,
+    field_masks: false,
+    error_formats: Vec::new(),
+    #[cfg(feature = "problem-details")]
+    problem_type_base: None,
+    #[cfg(any(feature = "json", feature = "xml"))]
+    deny_unknown_fields: false,
+    #[cfg(feature = "jsonschema")]
+    json_schemas: Vec::new(),
+    #[cfg(feature = "protobuf")]
+    grpc_web: false,
+    #[cfg(feature = "protobuf")]
+    protobuf_length_delimited: false,
+    #[cfg(feature = "protobuf")]
+    protobuf_deterministic: false,
+    transform: None,
+    response_transform: None,
+    negotiator: DEFAULT_NEGOTIATOR,
+    error_hook: None,
+    cancel_hook: None,
+    mirror: None,
+    mirror_credentials: false,
+    capture_limit: DEFAULT_CAPTURE_LIMIT,
+    capture_sample_rate: 1.0,
+    error_verbosity: ErrorVerbosity::Detailed,
+    error_mappings: Vec::new(),
+    links: None,
+    #[cfg(feature = "protobuf")]
+    protobuf_links_field: None,
+    #[cfg(feature = "json")]
+    json_format: JsonFormat::Pretty,
+    #[cfg(feature = "json")]
+    json_naming: JsonNaming::Preserve,
+    #[cfg(feature = "json")]
+    sort_json_keys: false,
+    #[cfg(feature = "xml")]
+    xml_indent: None,
+    #[cfg(feature = "xml")]
+    xml_mapping: XmlMapping::new(),
+    #[cfg(any(feature = "json", feature = "xml"))]
+    protobuf_mapping: Mapping::DEFAULT,
+};
+
+impl Default for PayloadConfig {
+    fn default() -> Self {
+        DEFAULT_CONFIG.clone()
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_preference_order_request_format() {
+        let order = PayloadConfig::default().preference_order(&ContentType::Protobuf);
+        assert_eq!(vec![ContentType::Protobuf, ContentType::Json], order[..2]);
+    }
+
+    #[test]
+    fn test_preference_order_configured() {
+        let order = PayloadConfig::default()
+            .preference([ContentType::Protobuf])
+            .preference_order(&ContentType::Json);
+        assert_eq!(vec![ContentType::Protobuf, ContentType::Json], order[..2]);
+    }
+
+    #[test]
+    fn test_from_req() {
+        let req = TestRequest::get()
+            .app_data(web::Data::new(PayloadConfig::default().preference([ContentType::Protobuf])))
+            .to_http_request();
+
+        assert_eq!(vec![ContentType::Protobuf], PayloadConfig::from_req(&req).preference);
+    }
+
+    #[test]
+    fn test_preference_order_grpc_web() {
+        let order = PayloadConfig::default().preference_order(&ContentType::GrpcWeb);
+        assert!(!order.contains(&ContentType::GrpcWeb));
+
+        let order = PayloadConfig::default()
+            .grpc_web(true)
+            .preference_order(&ContentType::GrpcWeb);
+        assert_eq!(ContentType::GrpcWeb, order[0]);
+    }
+
+    #[test]
+    fn test_infer_content_type_from_accept() {
+        let config = PayloadConfig::default().infer_content_type_from_accept(true);
+        let headers = |accept: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(ACCEPT, HeaderValue::from_static(accept));
+            headers
+        };
+
+        let none = || ContentType::Other(String::new());
+        assert_eq!(ContentType::Protobuf, config.request_format(none(), &headers("application/protobuf")));
+        assert_eq!(none(), config.request_format(none(), &headers("*/*")));
+        assert_eq!(none(), PayloadConfig::default().request_format(none(), &headers("application/protobuf")));
+    }
+
+    #[test]
+    fn test_resolve_alias() {
+        let config = PayloadConfig::default().alias("Text/Plain", ContentType::Json);
+
+        assert_eq!(Some(ContentType::Json), config.resolve_alias("text/plain; charset=utf-8"));
+        assert_eq!(None, config.resolve_alias("text/html"));
+    }
+
+    #[test]
+    fn test_response_headers() {
+        let config = PayloadConfig::default()
+            .vary([ACCEPT, actix_web::http::header::CONTENT_TYPE])
+            .response_header(
+                actix_web::http::header::CACHE_CONTROL,
+                HeaderValue::from_static("no-cache"),
+            );
+
+        let mut response = HttpResponse::Ok().finish();
+        config.apply_response_headers(&mut response);
+
+        assert_eq!("accept, content-type", response.headers().get(VARY).unwrap());
+        assert_eq!("no-cache", response.headers().get("Cache-Control").unwrap());
+
+        let mut response = HttpResponse::Ok().finish();
+        PayloadConfig::default().vary([]).apply_response_headers(&mut response);
+        assert!(response.headers().get(VARY).is_none());
+    }
+
+    #[actix_macros::test]
+    async fn test_scope_override() {
+        use actix_web::test::{call_service, init_service, read_body};
+
+        async fn handler(payload: crate::Payload<String>) -> crate::Payload<String> {
+            payload
+        }
+
+        let config = PayloadConfig::default().json_format(JsonFormat::Compact);
+        let legacy = config
+            .clone()
+            .alias("text/plain", ContentType::Json)
+            .transform(|_, body| Ok(format!("\"{}\"", String::from_utf8_lossy(&body)).into()))
+            .response_transform(|_, body| Ok(format!("{{\"value\":{}}}", String::from_utf8_lossy(&body)).into()));
+
+        let app = init_service(
+            actix_web::App::new()
+                .app_data(config)
+                .service(web::scope("/v1").app_data(legacy).route("", web::post().to(handler)))
+                .service(web::scope("/v2").route("", web::post().to(handler))),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/v1")
+            .insert_header(("Content-Type", "text/plain"))
+            .set_payload("foo")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+        assert_eq!(r#"{"value":"foo"}"#, read_body(resp).await);
+
+        let req = TestRequest::post()
+            .uri("/v2")
+            .insert_header(("Content-Type", "text/plain"))
+            .set_payload("foo")
+            .to_request();
+        assert_eq!(StatusCode::BAD_REQUEST, call_service(&app, req).await.status());
+
+        let req = TestRequest::post()
+            .uri("/v2")
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#""foo""#)
+            .to_request();
+        assert_eq!(r#""foo""#, read_body(call_service(&app, req).await).await);
+    }
+}
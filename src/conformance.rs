@@ -0,0 +1,273 @@
+//! Content negotiation test vectors, to verify a [PayloadConfig] still negotiates formats as RFC 9110 describes
+//! after changing its preference, aliases or [crate::Negotiator].
+//!
+//! ```
+//! # #[cfg(all(feature = "json", feature = "protobuf", feature = "testing"))]
+//! # fn main() {
+//!     use actix_multiresponse::conformance::verify_negotiation;
+//!     use actix_multiresponse::{ContentType, PayloadConfig};
+//!
+//!     let config = PayloadConfig::default()
+//!         .preference([ContentType::Protobuf])
+//!         .alias("text/plain", ContentType::Json);
+//!     assert!(verify_negotiation(&config).is_ok());
+//! # }
+//! # #[cfg(not(all(feature = "json", feature = "protobuf", feature = "testing")))]
+//! # fn main() {}
+//! ```
+
+use crate::negotiate::not_acceptable;
+use crate::{current_format, ContentType, PayloadConfig, UnsupportedAccept};
+
+use actix_web::test::TestRequest;
+
+use std::fmt;
+
+/// A request and the format its response is expected in
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NegotiationVector {
+    /// What the vector verifies
+    pub description: String,
+    /// The `Accept` header of the request, if any
+    pub accept: Option<String>,
+    /// The `Content-Type` header of the request, if any
+    pub content_type: Option<String>,
+    /// The expected response format
+    pub expected: Expected,
+}
+
+/// The expected response format of a [NegotiationVector]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expected {
+    /// Exactly this format
+    Format(ContentType),
+    /// Any format except this one
+    Not(ContentType),
+    /// A HTTP 406 error, see [PayloadConfig::unsupported_accept]
+    NotAcceptable,
+}
+
+/// A [NegotiationVector] for which a different format was negotiated
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VectorFailure {
+    pub vector: NegotiationVector,
+    /// The format which was negotiated, `None` if the request was answered with a HTTP 406 error
+    pub actual: Option<ContentType>,
+}
+
+impl Expected {
+    /// Whether `actual` is the expected format, `None` meaning a HTTP 406 error
+    pub fn matches(&self, actual: Option<&ContentType>) -> bool {
+        match (self, actual) {
+            (Self::Format(expected), Some(actual)) => expected.eq(actual),
+            (Self::Not(excluded), Some(actual)) => excluded.ne(actual),
+            (Self::NotAcceptable, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for VectorFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.vector.expected {
+            Expected::Format(expected) => write!(f, "{}: expected {expected}", self.vector.description)?,
+            Expected::Not(excluded) => write!(f, "{}: expected anything but {excluded}", self.vector.description)?,
+            Expected::NotAcceptable => write!(f, "{}: expected a HTTP 406 error", self.vector.description)?,
+        }
+
+        match &self.actual {
+            Some(actual) => write!(f, ", negotiated {actual}"),
+            None => f.write_str(", answered with a HTTP 406 error"),
+        }
+    }
+}
+
+impl NegotiationVector {
+    fn new(description: String, accept: Option<String>, content_type: Option<String>, expected: Expected) -> Self {
+        Self {
+            description,
+            accept,
+            content_type,
+            expected,
+        }
+    }
+
+    /// The format negotiated for the request of this vector with `config`,
+    /// `None` if the request is answered with a HTTP 406 error, see [PayloadConfig::unsupported_accept]
+    pub fn negotiate(&self, config: &PayloadConfig) -> Option<ContentType> {
+        let mut req = TestRequest::default().app_data(config.clone());
+        if let Some(accept) = &self.accept {
+            req = req.insert_header(("Accept", accept.as_str()));
+        }
+        if let Some(content_type) = &self.content_type {
+            req = req.insert_header(("Content-Type", content_type.as_str()));
+        }
+
+        let req = req.to_http_request();
+        match not_acceptable(&req) {
+            Some(_) => None,
+            None => Some(current_format(&req)),
+        }
+    }
+}
+
+/// The test vectors for the formats supported with `config`.
+///
+/// They cover exact media types, quality values, wildcards, excluded formats, case insensitivity, media type parameters,
+/// malformed `Accept` headers, and falling back to the format of the request body,
+/// or a HTTP 406 error with [UnsupportedAccept::Reject].
+pub fn negotiation_vectors(config: &PayloadConfig) -> Vec<NegotiationVector> {
+    // grpc-web is only negotiated for grpc-web request bodies, it is left out
+    let formats = config.preference_order(&ContentType::default());
+    let unsupported = |format: &ContentType| match config.unsupported_accept {
+        UnsupportedAccept::Fallback => Expected::Format(format.clone()),
+        UnsupportedAccept::Reject => Expected::NotAcceptable,
+    };
+
+    let media_type = |format: &ContentType| format.media_type().unwrap_or_default().to_string();
+    let mut vectors = Vec::new();
+    for format in &formats {
+        let mt = media_type(format);
+        vectors.push(NegotiationVector::new(
+            format!("Accept: {mt}"),
+            Some(mt.clone()),
+            None,
+            Expected::Format(format.clone()),
+        ));
+        vectors.push(NegotiationVector::new(
+            format!("Accept: {mt} is case insensitive"),
+            Some(mt.to_uppercase()),
+            None,
+            Expected::Format(format.clone()),
+        ));
+        vectors.push(NegotiationVector::new(
+            format!("Accept: {mt} with parameters"),
+            Some(format!("{mt}; charset=utf-8")),
+            None,
+            Expected::Format(format.clone()),
+        ));
+        vectors.push(NegotiationVector::new(
+            format!("Accept: {mt} over a wildcard with a lower quality"),
+            Some(format!("*/*;q=0.1, {mt};q=0.5")),
+            None,
+            Expected::Format(format.clone()),
+        ));
+        vectors.push(NegotiationVector::new(
+            format!("Content-Type: {mt} without Accept"),
+            None,
+            Some(mt.clone()),
+            Expected::Format(format.clone()),
+        ));
+        vectors.push(NegotiationVector::new(
+            format!("Content-Type: {mt} with an unsupported Accept"),
+            Some("text/html".to_string()),
+            Some(mt.clone()),
+            unsupported(format),
+        ));
+        vectors.push(NegotiationVector::new(
+            format!("Content-Type: {mt} with a malformed Accept"),
+            Some("application/*;q=2".to_string()),
+            Some(mt.clone()),
+            unsupported(format),
+        ));
+
+        if formats.len() > 1 {
+            vectors.push(NegotiationVector::new(
+                format!("Accept: {mt};q=0 excludes the format"),
+                Some(format!("{mt};q=0, */*")),
+                None,
+                Expected::Not(format.clone()),
+            ));
+        }
+    }
+
+    for (format, other) in formats.iter().zip(formats.iter().skip(1)) {
+        let (mt, other_mt) = (media_type(format), media_type(other));
+        vectors.push(NegotiationVector::new(
+            format!("Accept: {mt};q=0.4, {other_mt};q=0.8 prefers the higher quality"),
+            Some(format!("{mt};q=0.4, {other_mt};q=0.8")),
+            None,
+            Expected::Format(other.clone()),
+        ));
+        vectors.push(NegotiationVector::new(
+            format!("Accept: {other_mt} over Content-Type: {mt}"),
+            Some(other_mt.clone()),
+            Some(mt.clone()),
+            Expected::Format(other.clone()),
+        ));
+    }
+
+    vectors
+}
+
+/// Run the [negotiation_vectors] against `config`, returning the vectors for which a different format was negotiated
+///
+/// # Errors
+///
+/// If any vector fails
+pub fn verify_negotiation(config: &PayloadConfig) -> Result<(), Vec<VectorFailure>> {
+    let failures: Vec<VectorFailure> = negotiation_vectors(config)
+        .into_iter()
+        .filter_map(|vector| {
+            let actual = vector.negotiate(config);
+            if vector.expected.matches(actual.as_ref()) {
+                None
+            } else {
+                Some(VectorFailure { vector, actual })
+            }
+        })
+        .collect();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use crate::{NegotiationRule, Negotiator};
+
+    #[test]
+    fn test_default_config() {
+        assert!(negotiation_vectors(&PayloadConfig::default())
+            .iter()
+            .any(|vector| vector.expected.eq(&Expected::Not(ContentType::Json))));
+        assert_eq!(Ok(()), verify_negotiation(&PayloadConfig::default()));
+        assert_eq!(Ok(()), verify_negotiation(&PayloadConfig::default().grpc_web(true)));
+    }
+
+    #[test]
+    fn test_reject_unsupported_accept() {
+        let strict = PayloadConfig::default().unsupported_accept(UnsupportedAccept::Reject);
+        assert!(negotiation_vectors(&strict)
+            .iter()
+            .any(|vector| vector.expected.eq(&Expected::NotAcceptable)));
+        assert_eq!(Ok(()), verify_negotiation(&strict));
+
+        // The vectors of the lenient default fail against the strict configuration
+        let failures: Vec<String> = negotiation_vectors(&PayloadConfig::default())
+            .into_iter()
+            .filter(|vector| !vector.expected.matches(vector.negotiate(&strict).as_ref()))
+            .map(|vector| vector.description)
+            .collect();
+        assert!(!failures.is_empty());
+        assert!(failures.iter().all(|description| description.ends_with("Accept")), "{failures:?}");
+    }
+
+    #[test]
+    fn test_request_format_first() {
+        let config = PayloadConfig::default().negotiator(Negotiator::new([NegotiationRule::RequestFormat, NegotiationRule::Accept]));
+        let failures = verify_negotiation(&config).unwrap_err();
+
+        assert!(failures
+            .iter()
+            .all(|failure| failure.vector.description.contains("over Content-Type")));
+        assert_eq!(
+            "Accept: application/protobuf over Content-Type: application/json: expected application/protobuf, negotiated application/json",
+            failures[0].to_string()
+        );
+    }
+}
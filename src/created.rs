@@ -0,0 +1,86 @@
+//! `201 Created` responses with a negotiated body.
+
+use crate::{Payload, ProtobufSupport, SerdeSupportSerialize};
+
+use actix_web::body::BoxBody;
+use actix_web::http::header::{HeaderValue, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+
+/// A [Payload] served with `201 Created` and a `Location` header, created with [Payload::created]
+#[derive(Debug)]
+pub struct Created<T> {
+    payload: Payload<T>,
+    location: String,
+}
+
+impl<T> Payload<T> {
+    /// Respond with `201 Created`, a `Location` header pointing to the created resource, and the negotiated body.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{Created, Payload};
+    ///
+    ///     async fn create(payload: Payload<String>) -> Created<String> {
+    ///         let location = format!("/items/{}", payload.as_str());
+    ///         payload.created(location)
+    ///     }
+    /// ```
+    pub fn created<S: Into<String>>(self, location: S) -> Created<T> {
+        Created {
+            payload: self,
+            location: location.into(),
+        }
+    }
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize> Responder for Created<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let mut response = self.payload.to_response(req);
+        if response.status().ne(&StatusCode::OK) {
+            return response;
+        }
+
+        match HeaderValue::try_from(self.location) {
+            Ok(location) => {
+                *response.status_mut() = StatusCode::CREATED;
+                response.headers_mut().insert(LOCATION, location);
+                response
+            }
+            Err(e) => HttpResponse::InternalServerError().body(format!("Invalid location: {e}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    // A structure rather than a string, as XML has no representation of a bare string
+    #[derive(serde_derive::Serialize, prost_derive::Message)]
+    struct Item {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    #[test]
+    fn test_created() {
+        let req = TestRequest::post().to_http_request();
+        let item = Item { name: "foo".to_string() };
+        let resp = Payload::new(item).created("/items/foo").respond_to(&req);
+
+        assert_eq!(StatusCode::CREATED, resp.status());
+        assert_eq!("/items/foo", resp.headers().get(LOCATION).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_location() {
+        let req = TestRequest::post().to_http_request();
+        let resp = Payload::new("foo".to_string()).created("/items/\n").respond_to(&req);
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, resp.status());
+    }
+}
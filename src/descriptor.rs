@@ -0,0 +1,326 @@
+//! Serving the protobuf descriptors of the payloads, enabled with the `descriptor` feature.
+
+use crate::{build_response, negotiate, response_builder, ContentType, Payload, PayloadConfig};
+
+use actix_web::body::BoxBody;
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse, Resource, Responder};
+use prost::Message;
+
+/// A compiled `FileDescriptorSet`, served with content negotiation so clients can decode payloads dynamically,
+/// similar to gRPC reflection.
+///
+/// Clients negotiating protobuf receive the descriptor set as provided, e.g. as generated by `protoc --descriptor_set_out`
+/// or `prost_build::Config::file_descriptor_set_path`. Clients negotiating other formats receive a rendering of
+/// the files, with their messages, fields, enums and services. Options and source info are only included in the binary descriptor set.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::DescriptorSet;
+///
+///     // Usually `include_bytes!` of a file generated at build time
+///     let descriptor = DescriptorSet::new(Vec::new()).unwrap();
+///     let app = actix_web::App::new().service(descriptor.resource("/descriptor"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DescriptorSet {
+    raw: Bytes,
+    set: FileDescriptorSet,
+}
+
+impl DescriptorSet {
+    /// Use an encoded `FileDescriptorSet`.
+    ///
+    /// # Errors
+    ///
+    /// If the bytes are not a valid `FileDescriptorSet`
+    pub fn new<B: Into<Bytes>>(raw: B) -> Result<Self, prost::DecodeError> {
+        let raw = raw.into();
+        let set = FileDescriptorSet::decode(raw.clone())?;
+        Ok(Self { raw, set })
+    }
+
+    /// A resource serving the descriptor set at `path` for `GET` requests
+    pub fn resource(self, path: &str) -> Resource {
+        web::resource(path).route(web::get().to(move || {
+            let descriptor = self.clone();
+            async move { descriptor }
+        }))
+    }
+}
+
+impl Responder for DescriptorSet {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let config = PayloadConfig::from_req(req);
+        let mut response = match negotiate::response_format(req) {
+            ContentType::Protobuf | ContentType::GrpcWeb => {
+                response_builder(StatusCode::OK, ContentType::Protobuf).body(self.raw)
+            }
+            content_type => {
+                let serialized = Payload::new(self.set).serialize_with(content_type.clone(), config);
+                build_response(StatusCode::OK, content_type, serialized, config)
+            }
+        };
+
+        config.apply_response_headers(&mut response);
+        response
+    }
+}
+
+/// The subset of `google.protobuf.FileDescriptorSet` which is rendered in other formats than protobuf.
+/// The names of the fields follow the JSON mapping of protobuf
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+struct FileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    file: Vec<FileDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<String>,
+    #[prost(string, repeated, tag = "3")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dependency: Vec<String>,
+    #[prost(message, repeated, tag = "4")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    message_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "5")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, repeated, tag = "6")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    service: Vec<ServiceDescriptorProto>,
+    #[prost(string, optional, tag = "12")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    syntax: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    field: Vec<FieldDescriptorProto>,
+    #[prost(message, repeated, tag = "3")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nested_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "4")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, repeated, tag = "8")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    oneof_decl: Vec<OneofDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FieldDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[prost(int32, optional, tag = "3")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<i32>,
+    #[prost(int32, optional, tag = "4")]
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_label")]
+    label: Option<i32>,
+    #[prost(int32, optional, tag = "5")]
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none", serialize_with = "serialize_type")]
+    field_type: Option<i32>,
+    #[prost(string, optional, tag = "6")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    type_name: Option<String>,
+    #[prost(int32, optional, tag = "9")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    oneof_index: Option<i32>,
+    #[prost(string, optional, tag = "10")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+struct OneofDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+struct EnumDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    value: Vec<EnumValueDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+struct EnumValueDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[prost(int32, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+struct ServiceDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    method: Vec<MethodDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost_derive::Message, serde_derive::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MethodDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_type: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_type: Option<String>,
+    #[prost(bool, optional, tag = "5")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_streaming: Option<bool>,
+    #[prost(bool, optional, tag = "6")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_streaming: Option<bool>,
+}
+
+/// The names of `FieldDescriptorProto.Label`, indexed by their number
+const LABELS: &[&str] = &["", "LABEL_OPTIONAL", "LABEL_REQUIRED", "LABEL_REPEATED"];
+
+/// The names of `FieldDescriptorProto.Type`, indexed by their number
+const TYPES: &[&str] = &[
+    "",
+    "TYPE_DOUBLE",
+    "TYPE_FLOAT",
+    "TYPE_INT64",
+    "TYPE_UINT64",
+    "TYPE_INT32",
+    "TYPE_FIXED64",
+    "TYPE_FIXED32",
+    "TYPE_BOOL",
+    "TYPE_STRING",
+    "TYPE_GROUP",
+    "TYPE_MESSAGE",
+    "TYPE_BYTES",
+    "TYPE_UINT32",
+    "TYPE_ENUM",
+    "TYPE_SFIXED32",
+    "TYPE_SFIXED64",
+    "TYPE_SINT32",
+    "TYPE_SINT64",
+];
+
+/// Serialize an enum value by its name, or by its number if it is unknown
+fn serialize_enum<S: serde::Serializer>(names: &[&str], value: &Option<i32>, serializer: S) -> Result<S::Ok, S::Error> {
+    let value = value.unwrap_or_default();
+    match usize::try_from(value).ok().and_then(|index| names.get(index)).filter(|name| !name.is_empty()) {
+        Some(name) => serializer.serialize_str(name),
+        None => serializer.serialize_i32(value),
+    }
+}
+
+fn serialize_label<S: serde::Serializer>(value: &Option<i32>, serializer: S) -> Result<S::Ok, S::Error> {
+    serialize_enum(LABELS, value, serializer)
+}
+
+fn serialize_type<S: serde::Serializer>(value: &Option<i32>, serializer: S) -> Result<S::Ok, S::Error> {
+    serialize_enum(TYPES, value, serializer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::App;
+
+    fn descriptor() -> Vec<u8> {
+        FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("order.proto".to_string()),
+                package: Some("shop".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Order".to_string()),
+                    field: vec![FieldDescriptorProto {
+                        name: Some("id".to_string()),
+                        number: Some(1),
+                        label: Some(1),
+                        field_type: Some(9),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                syntax: Some("proto3".to_string()),
+                ..Default::default()
+            }],
+        }
+        .encode_to_vec()
+    }
+
+    #[actix_macros::test]
+    async fn test_binary() {
+        let app = init_service(App::new().service(DescriptorSet::new(descriptor()).unwrap().resource("/descriptor"))).await;
+        let req = TestRequest::get()
+            .uri("/descriptor")
+            .insert_header(("Accept", "application/protobuf"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+        assert_eq!(descriptor(), actix_web::body::to_bytes(resp.into_body()).await.unwrap());
+    }
+
+    #[actix_macros::test]
+    async fn test_json() {
+        let app = init_service(App::new().service(DescriptorSet::new(descriptor()).unwrap().resource("/descriptor"))).await;
+        let req = TestRequest::get()
+            .uri("/descriptor")
+            .insert_header(("Accept", "application/json"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "file": [{
+                    "name": "order.proto",
+                    "package": "shop",
+                    "messageType": [{
+                        "name": "Order",
+                        "field": [{ "name": "id", "number": 1, "label": "LABEL_OPTIONAL", "type": "TYPE_STRING" }],
+                    }],
+                    "syntax": "proto3",
+                }],
+            }),
+            value
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(DescriptorSet::new(vec![0x0a, 0xff]).is_err());
+    }
+}
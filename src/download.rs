@@ -0,0 +1,104 @@
+//! Responses served as downloads, with a file name matching the negotiated format.
+
+use crate::{negotiate, ContentType, Payload, ProtobufSupport, SerdeSupportSerialize};
+
+use actix_web::body::BoxBody;
+use actix_web::http::header::{
+    ContentDisposition, DispositionParam, DispositionType, TryIntoHeaderValue, CONTENT_DISPOSITION,
+};
+use actix_web::{HttpRequest, HttpResponse, Responder};
+
+/// A [Payload] served as a download, created with [Payload::downloadable].
+///
+/// The response has a `Content-Disposition: attachment` header, with the file name stem
+/// followed by the extension of the negotiated format, e.g. `export.json` or `export.bin`.
+#[derive(Debug)]
+pub struct Downloadable<T> {
+    payload: Payload<T>,
+    name_stem: String,
+}
+
+impl<T> Payload<T> {
+    /// Serve the payload as a download named `name_stem`, with the extension of the negotiated format.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{Downloadable, Payload};
+    ///
+    ///     async fn export() -> Downloadable<Vec<String>> {
+    ///         Payload::new(vec!["foo".to_string()]).downloadable("export")
+    ///     }
+    /// ```
+    pub fn downloadable<S: Into<String>>(self, name_stem: S) -> Downloadable<T> {
+        Downloadable {
+            payload: self,
+            name_stem: name_stem.into(),
+        }
+    }
+}
+
+impl ContentType {
+    /// The file extension of the format, without the leading dot, e.g. `json`.
+    /// Protobuf uses `bin`. Empty for [ContentType::Other]
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "json")]
+            Self::Json => "json",
+            #[cfg(feature = "protobuf")]
+            Self::Protobuf | Self::GrpcWeb => "bin",
+            #[cfg(feature = "xml")]
+            Self::Xml => "xml",
+            Self::Other(_) => "",
+        }
+    }
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize> Responder for Downloadable<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let mut response = self.payload.to_response(req);
+        if !response.status().is_success() {
+            return response;
+        }
+
+        let extension = negotiate::response_format(req).file_extension();
+        let disposition = ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("{}.{extension}", self.name_stem))],
+        };
+
+        if let Ok(header_value) = disposition.try_into_value() {
+            response.headers_mut().insert(CONTENT_DISPOSITION, header_value);
+        }
+
+        response
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_file_name() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+        let resp = Payload::new("foo".to_string()).downloadable("export").respond_to(&req);
+
+        assert_eq!(
+            "attachment; filename=\"export.bin\"",
+            resp.headers().get(CONTENT_DISPOSITION).unwrap()
+        );
+
+        let req = TestRequest::get().to_http_request();
+        let resp = Payload::new("foo".to_string()).downloadable("export").respond_to(&req);
+
+        assert_eq!(
+            "attachment; filename=\"export.json\"",
+            resp.headers().get(CONTENT_DISPOSITION).unwrap()
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{codec, ContentType, PayloadConfig, ProtobufSupport, SerdeSupportSerialize, SerializeError};
+
+use std::collections::BTreeMap;
+
+/// Metadata included in enveloped responses, see [crate::PayloadConfig::envelope].
+///
+/// Insert it into the request extensions to supply the metadata for a response:
+/// ```
+///     use actix_multiresponse::ResponseMeta;
+///     use actix_web::{HttpMessage, HttpRequest};
+///
+///     fn paginate(req: &HttpRequest, page: usize) {
+///         let mut meta = ResponseMeta::default();
+///         meta.insert("page", page.to_string());
+///         req.extensions_mut().insert(meta);
+///     }
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ResponseMeta(pub BTreeMap<String, String>);
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl serde::Serialize for ResponseMeta {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(&self.0)
+    }
+}
+
+impl ResponseMeta {
+    /// Insert a metadata entry, replacing an existing entry with the same key
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.0.insert(key.into(), value.into());
+    }
+}
+
+/// The envelope around the data and metadata for JSON and XML responses
+#[cfg(any(feature = "json", feature = "xml"))]
+struct Envelope<'a, T> {
+    data: &'a T,
+    meta: &'a ResponseMeta,
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl<T: serde::Serialize> serde::Serialize for Envelope<'_, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut envelope = serializer.serialize_struct("envelope", 2)?;
+        envelope.serialize_field("data", self.data)?;
+        envelope.serialize_field("meta", self.meta)?;
+        envelope.end()
+    }
+}
+
+/// Serialize the value wrapped in an envelope together with the metadata.
+///
+/// For JSON and XML the value is placed in the `data` field, the metadata in the `meta` field.
+/// For protobuf, the envelope is a message equivalent to
+/// ```proto
+/// message Envelope {
+///     T data = 1;
+///     map<string, string> meta = 2;
+/// }
+/// ```
+/// grpc-web responses are never enveloped.
+pub(crate) fn serialize<T: ProtobufSupport + SerdeSupportSerialize>(
+    value: &T,
+    meta: &ResponseMeta,
+    content_type: ContentType,
+    config: &PayloadConfig,
+) -> Result<Vec<u8>, SerializeError> {
+    match content_type {
+        #[cfg(feature = "json")]
+        ContentType::Json => codec::serialize_json(&Envelope { data: value, meta }, config),
+        #[cfg(feature = "protobuf")]
+        ContentType::Protobuf => {
+            let data = codec::encode_protobuf(value, config)?;
+            Ok(encode_protobuf_envelope(&data, meta))
+        }
+        #[cfg(feature = "protobuf")]
+        ContentType::GrpcWeb => codec::serialize_grpc_web(value, config),
+        #[cfg(feature = "xml")]
+        ContentType::Xml => codec::serialize_xml(&Envelope { data: value, meta }, config),
+        ContentType::Other(_) => Err(SerializeError::Unserializable),
+    }
+}
+
+/// Encode the protobuf envelope message, `data` is the already encoded message
+#[cfg(feature = "protobuf")]
+fn encode_protobuf_envelope(data: &[u8], meta: &ResponseMeta) -> Vec<u8> {
+    use prost::encoding::{encode_key, encode_varint, string, WireType};
+
+    let mut buf = Vec::with_capacity(data.len() + 16);
+    encode_key(1, WireType::LengthDelimited, &mut buf);
+    encode_varint(data.len() as u64, &mut buf);
+    buf.extend_from_slice(data);
+
+    // Map fields are encoded as repeated entry messages, with the key in field 1 and the value in field 2
+    for (key, value) in &meta.0 {
+        encode_key(2, WireType::LengthDelimited, &mut buf);
+        encode_varint((string::encoded_len(1, key) + string::encoded_len(2, value)) as u64, &mut buf);
+        string::encode(1, key, &mut buf);
+        string::encode(2, value, &mut buf);
+    }
+
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use prost_derive::Message;
+    use serde_derive::Serialize;
+
+    #[derive(Serialize, Message, PartialEq)]
+    struct TestPayload {
+        #[prost(string, tag = "1")]
+        foo: String,
+    }
+
+    fn meta() -> ResponseMeta {
+        let mut meta = ResponseMeta::default();
+        meta.insert("page", "1");
+        meta
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_json_envelope() {
+        let payload = TestPayload { foo: "bar".to_string() };
+        let serialized = serialize(&payload, &meta(), ContentType::Json, &PayloadConfig::default()).unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&serialized).unwrap();
+        assert_eq!(
+            serde_json::json!({ "data": { "foo": "bar" }, "meta": { "page": "1" } }),
+            value
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "xml")]
+    fn test_xml_envelope() {
+        let payload = TestPayload { foo: "bar".to_string() };
+        let serialized = serialize(&payload, &meta(), ContentType::Xml, &PayloadConfig::default()).unwrap();
+
+        assert_eq!(
+            "<envelope><data><foo>bar</foo></data><meta><page>1</page></meta></envelope>",
+            String::from_utf8(serialized).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_protobuf_envelope() {
+        #[derive(Message, PartialEq)]
+        struct TestEnvelope {
+            #[prost(message, optional, tag = "1")]
+            data: Option<TestPayload>,
+            #[prost(btree_map = "string, string", tag = "2")]
+            meta: BTreeMap<String, String>,
+        }
+
+        let payload = TestPayload { foo: "bar".to_string() };
+        let serialized = serialize(&payload, &meta(), ContentType::Protobuf, &PayloadConfig::default()).unwrap();
+
+        let envelope: TestEnvelope = prost::Message::decode(serialized.as_slice()).unwrap();
+        assert_eq!(Some(payload), envelope.data);
+        assert_eq!(meta().0, envelope.meta);
+    }
+}
@@ -0,0 +1,106 @@
+//! Type erased payloads, for handlers which return different types behind a trait object.
+
+use crate::{
+    build_response, links, negotiate, serialize_value, ContentType, PayloadConfig, ProtobufSupport,
+    SerdeSupportSerialize, SerializeError,
+};
+
+use actix_web::body::BoxBody;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+
+/// An object safe form of a payload, implemented for every type a [crate::Payload] can respond with.
+///
+/// `Box<dyn ErasedPayload>` is a [Responder] which negotiates the format like [crate::Payload],
+/// so handlers, e.g. those provided by plugins, can return different types without being generic.
+/// Envelopes, field masks and streaming are not applied to erased payloads.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::ErasedPayload;
+///
+///     async fn plugin_handler() -> Box<dyn ErasedPayload> {
+///         if cfg!(debug_assertions) {
+///             Box::new("debug".to_string())
+///         } else {
+///             Box::new(42u32)
+///         }
+///     }
+/// ```
+pub trait ErasedPayload {
+    /// Serialize the payload in `content_type`, using the serializer settings of `config`
+    fn serialize_erased(&self, content_type: ContentType, config: &PayloadConfig) -> Result<Vec<u8>, SerializeError>;
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize> ErasedPayload for T {
+    fn serialize_erased(&self, content_type: ContentType, config: &PayloadConfig) -> Result<Vec<u8>, SerializeError> {
+        serialize_value(self, content_type, config)
+    }
+}
+
+impl Responder for Box<dyn ErasedPayload> {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        if let Some(response) = negotiate::not_acceptable(req) {
+            return response;
+        }
+        let content_type = negotiate::response_format(req);
+        let config = PayloadConfig::from_req(req);
+
+        let links = config.links_for(req);
+        let serialized = self
+            .serialize_erased(content_type.clone(), config)
+            .and_then(|serialized| links::inject(serialized, &content_type, &links, config));
+
+        let mut response = build_response(StatusCode::OK, content_type, serialized, config);
+        config.apply_response_headers(&mut response);
+        response
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[derive(serde_derive::Serialize, prost_derive::Message)]
+    struct First {
+        #[prost(string, tag = "1")]
+        foo: String,
+    }
+
+    #[derive(serde_derive::Serialize, prost_derive::Message)]
+    struct Second {
+        #[prost(int64, tag = "1")]
+        bar: i64,
+    }
+
+    #[actix_macros::test]
+    async fn test_heterogeneous() {
+        let payloads: Vec<Box<dyn ErasedPayload>> =
+            vec![Box::new(First { foo: "foo".to_string() }), Box::new(Second { bar: 1 })];
+        let expected = [&br#"{"foo":"foo"}"#[..], &br#"{"bar":1}"#[..]];
+
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json"))
+            .app_data(PayloadConfig::default().json_format(crate::JsonFormat::Compact))
+            .to_http_request();
+        for (payload, expected) in payloads.into_iter().zip(expected) {
+            let resp = payload.respond_to(&req);
+            assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+
+            let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+            assert_eq!(expected, &body[..]);
+        }
+
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+        let payload: Box<dyn ErasedPayload> = Box::new(Second { bar: 1 });
+        let body = actix_web::body::to_bytes(payload.respond_to(&req).into_body())
+            .await
+            .unwrap();
+        assert_eq!(prost::Message::encode_to_vec(&Second { bar: 1 }), body);
+    }
+}
@@ -2,24 +2,509 @@ use actix_web::body::BoxBody;
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use thiserror::Error;
-use crate::DeserializeError;
+
+#[cfg(feature = "protobuf")]
+use std::fmt;
+use actix_web::web::Bytes;
+use crate::{ContentType, DeserializeError, ErrorId};
 
 #[derive(Debug, Error)]
 pub enum PayloadError {
     #[error("Payload error: {0}")]
     ActixPayload(#[from] actix_web::error::PayloadError),
-    #[error("Error: {0}")]
-    Deserialize(#[from] DeserializeError),
-    #[error("Invalid content type")]
-    InvalidContentType,
+    /// Deserializing the body failed. The raw body and the format it was deserialized from are included,
+    /// so handlers extracting `Result<Payload<T>, PayloadError>` can implement their own fallback.
+    #[error("Error: {error}")]
+    Deserialize {
+        error: DeserializeError,
+        body: Bytes,
+        content_type: ContentType,
+    },
+    /// The format of the body is not supported. [ContentType::Other] carries the media type which was sent
+    #[error("Invalid content type: {0}")]
+    InvalidContentType(ContentType),
+    /// The format of the body is supported, but not by the type it is deserialized into, see [crate::serde_only]
+    #[error("The {0} format is not supported for this request body")]
+    UnsupportedFormat(ContentType),
+    #[error("Multiple Content-Type headers provided")]
+    AmbiguousContentType,
+    #[error("Malformed header: {0}")]
+    MalformedHeader(String),
+    #[error("Invalid multipart body: {0}")]
+    InvalidMultipart(String),
+    #[error("Blocking error: {0}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+    /// The body was not received within [crate::PayloadConfig::read_timeout]
+    #[error("Timed out reading the request body")]
+    Timeout,
+    /// The body does not match the checksum in the named header, see [crate::PayloadConfig::verify_checksums]
+    #[error("The request body does not match the checksum of the {0} header")]
+    ChecksumMismatch(String),
+    /// An error of which the cause is left out of the response with [crate::ErrorVerbosity::Redacted].
+    /// The cause is logged together with the ID
+    #[error("Invalid request body (error id {id})")]
+    Redacted { id: ErrorId, source: Box<PayloadError> },
+    /// A deserialize error of which the [crate::DeserializeErrorKind] is mapped to a status and an error code
+    /// with [crate::PayloadConfig::map_error]
+    #[error("{source}")]
+    Mapped {
+        status: StatusCode,
+        code: String,
+        source: Box<PayloadError>,
+    },
+    /// Deserializing the body failed in each of the formats its media type is an alias for,
+    /// see [crate::PayloadConfig::max_format_attempts]. The source is the error of the first format
+    #[error("{source} (tried {})", formats(.attempted))]
+    Attempts {
+        attempted: Vec<ContentType>,
+        source: Box<PayloadError>,
+    },
+    /// An error rendered for the request it occurred in, like [crate::render_negotiated_error].
+    /// With the `problem-details` feature the extractors wrap their errors in this, so the problem details
+    /// are sent in the format the client accepts. Use [PayloadError::detailed] to inspect the error itself
+    #[cfg(feature = "problem-details")]
+    #[error("{source}")]
+    Negotiated {
+        /// The status of the response, which differs from that of the source if rendering it failed
+        status: StatusCode,
+        headers: actix_web::http::header::HeaderMap,
+        body: Bytes,
+        source: Box<PayloadError>,
+    },
+}
+
+/// The formats separated by commas
+fn formats(formats: &[ContentType]) -> String {
+    formats.iter().map(ContentType::to_string).collect::<Vec<_>>().join(", ")
+}
+
+impl PayloadError {
+    /// The raw request body, if the error occurred while deserializing it
+    pub fn body(&self) -> Option<&Bytes> {
+        match self.detailed() {
+            Self::Deserialize { body, .. } => Some(body),
+            _ => None,
+        }
+    }
+
+    /// The format the body was deserialized from, if the error occurred while deserializing it
+    pub fn content_type(&self) -> Option<&ContentType> {
+        match self.detailed() {
+            Self::Deserialize { content_type, .. } => Some(content_type),
+            _ => None,
+        }
+    }
+
+    /// The ID the cause of the error was logged with, if the error is [PayloadError::Redacted]
+    pub fn error_id(&self) -> Option<&ErrorId> {
+        match self {
+            Self::Redacted { id, .. } => Some(id),
+            Self::Mapped { source, .. } => source.error_id(),
+            #[cfg(feature = "problem-details")]
+            Self::Negotiated { source, .. } => source.error_id(),
+            _ => None,
+        }
+    }
+
+    /// The formats deserializing the body was attempted in, in order, if more than one format was tried.
+    /// See [crate::PayloadConfig::max_format_attempts]
+    pub fn attempted_formats(&self) -> &[ContentType] {
+        match self {
+            Self::Attempts { attempted, .. } => attempted,
+            Self::Redacted { source, .. } | Self::Mapped { source, .. } => source.attempted_formats(),
+            #[cfg(feature = "problem-details")]
+            Self::Negotiated { source, .. } => source.attempted_formats(),
+            _ => &[],
+        }
+    }
+
+    /// The error code configured with [crate::PayloadConfig::map_error], if the error is [PayloadError::Mapped]
+    pub fn error_code(&self) -> Option<&str> {
+        match self {
+            Self::Mapped { code, .. } => Some(code),
+            #[cfg(feature = "problem-details")]
+            Self::Negotiated { source, .. } => source.error_code(),
+            _ => None,
+        }
+    }
+
+    /// The error with its cause, unwrapping [PayloadError::Redacted], [PayloadError::Mapped], [PayloadError::Attempts]
+    /// and, with the `problem-details` feature, `PayloadError::Negotiated`
+    pub fn detailed(&self) -> &Self {
+        match self {
+            Self::Redacted { source, .. } | Self::Mapped { source, .. } | Self::Attempts { source, .. } => source.detailed(),
+            #[cfg(feature = "problem-details")]
+            Self::Negotiated { source, .. } => source.detailed(),
+            error => error,
+        }
+    }
+
+    /// The message of the error, prefixed with its error code if it has one
+    pub(crate) fn coded_message(&self) -> String {
+        match self.error_code() {
+            Some(code) => format!("{code}: {self}"),
+            None => self.to_string(),
+        }
+    }
+}
+
+/// Details of a protobuf decoding error
+#[cfg(feature = "protobuf")]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ProtobufError {
+    description: String,
+    path: Vec<(String, String)>,
+}
+
+#[cfg(feature = "protobuf")]
+impl ProtobufError {
+    /// An error without a path, e.g. when the body is not a protobuf message at all
+    pub fn new<S: Into<String>>(description: S) -> Self {
+        Self {
+            description: description.into(),
+            path: Vec::new(),
+        }
+    }
+
+    /// The description of the error, without the path
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The path to the field which failed to decode, as pairs of message and field names,
+    /// starting at the outermost message. Empty if the error did not occur in a field.
+    pub fn path(&self) -> &[(String, String)] {
+        &self.path
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl From<prost::DecodeError> for ProtobufError {
+    fn from(e: prost::DecodeError) -> Self {
+        // prost does not expose the path of the error, other than through the Display implementation:
+        // the prefix, followed by a `Message.field: ` entry per field, innermost first, followed by the description
+        let display = e.to_string();
+        let mut rest = display.strip_prefix("failed to decode Protobuf message: ").unwrap_or(&display);
+
+        let mut path = Vec::new();
+        while let Some((entry, remaining)) = rest.split_once(": ") {
+            match entry.split_once('.') {
+                Some((message, field)) if !entry.contains(' ') && !field.contains('.') => {
+                    path.push((message.to_string(), field.to_string()));
+                    rest = remaining;
+                }
+                _ => break,
+            }
+        }
+
+        path.reverse();
+        Self {
+            description: rest.to_string(),
+            path,
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl fmt::Display for ProtobufError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (message, field) in &self.path {
+            write!(f, "{message}.{field}: ")?;
+        }
+
+        f.write_str(&self.description)
+    }
+}
+
+/// The location of an error in a request body
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ErrorLocation {
+    /// The line of the error, starting at 1. 0 if only the path of the error is known
+    pub line: usize,
+    /// The column of the error in bytes, starting at 1. 0 if only the path of the error is known
+    pub column: usize,
+    /// The part of the line around the error
+    pub snippet: String,
+    /// The path of the value the error occurred in, see [DeserializeError::path]
+    pub path: Option<String>,
+}
+
+/// The number of bytes included in an [ErrorLocation] snippet on both sides of the error
+#[cfg(any(feature = "json", feature = "xml"))]
+const SNIPPET_CONTEXT: usize = 20;
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl ErrorLocation {
+    /// The location of the error at `line` and `column` in `body`
+    fn new(body: &[u8], line: usize, column: usize) -> Self {
+        let line_bytes = body.split(|b| *b == b'\n').nth(line.saturating_sub(1)).unwrap_or_default();
+        let start = column.saturating_sub(SNIPPET_CONTEXT + 1).min(line_bytes.len());
+        let end = (column + SNIPPET_CONTEXT).min(line_bytes.len());
+
+        Self {
+            line,
+            column,
+            snippet: String::from_utf8_lossy(&line_bytes[start..end]).into_owned(),
+            path: None,
+        }
+    }
+
+    /// The location of `error` in `body`, if anything about it is known
+    fn of(error: &DeserializeError, body: &[u8]) -> Option<Self> {
+        let location = match error.detailed() {
+            #[cfg(feature = "json")]
+            DeserializeError::SerdeJson(e) if e.line() > 0 => Self::new(body, e.line(), e.column()),
+            // quick-xml does not report positions, the element the path leads to is located instead
+            #[cfg(feature = "xml")]
+            DeserializeError::Xml(_) => error
+                .path()
+                .and_then(|path| xml_offset(body, path))
+                .map(|offset| {
+                    let line_start = body[..offset].iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+                    let line = body[..offset].iter().filter(|b| **b == b'\n').count() + 1;
+                    Self::new(body, line, offset - line_start + 1)
+                })
+                .unwrap_or_default(),
+            _ => Self::default(),
+        };
+
+        let location = Self {
+            path: error.path().map(str::to_string),
+            ..location
+        };
+        (location != Self::default()).then_some(location)
+    }
+}
+
+/// The byte offset of the element `path` leads to in an XML `body`, e.g. `authors[1].name` for the `name` element
+/// in the second `authors` element in the root element.
+/// If it can't be followed all the way, the offset of the deepest element found is returned
+#[cfg(feature = "xml")]
+fn xml_offset(body: &[u8], path: &str) -> Option<usize> {
+    use quick_xml::events::Event;
+
+    // Attributes and text are part of the element they are in
+    let segments = path
+        .split('.')
+        .take_while(|segment| !segment.starts_with(['@', '$']))
+        .map(|segment| match segment.split_once('[') {
+            Some((name, index)) => (name, index.trim_end_matches(']').parse().unwrap_or(0)),
+            None => (segment, 0),
+        })
+        .collect::<Vec<(&str, usize)>>();
+
+    let mut reader = quick_xml::Reader::from_reader(body);
+    // The number of open elements, the number of segments matched by the open elements and the number
+    // of elements named like the next segment which were skipped in the deepest matched element
+    let (mut depth, mut matched, mut skipped) = (0, 0, 0);
+    let mut offset = None;
+    loop {
+        let start = reader.buffer_position();
+        let (element, opened) = match reader.read_event() {
+            Ok(Event::Start(element)) => (element, true),
+            Ok(Event::Empty(element)) => (element, false),
+            Ok(Event::End(_)) => {
+                depth -= 1;
+                if depth == matched {
+                    return offset;
+                }
+                continue;
+            }
+            Ok(Event::Eof) | Err(_) => return offset,
+            Ok(_) => continue,
+        };
+
+        if depth == 0 {
+            offset = Some(start);
+        } else if depth == matched + 1 {
+            let (name, index) = segments[matched];
+            if element.name().as_ref() == name.as_bytes() {
+                if skipped == index {
+                    offset = Some(start);
+                    matched += 1;
+                    skipped = 0;
+                    if matched == segments.len() {
+                        return offset;
+                    }
+                } else {
+                    skipped += 1;
+                }
+            }
+        }
+
+        if opened {
+            depth += 1;
+        } else if depth == 0 {
+            return offset;
+        }
+    }
+}
+
+impl PayloadError {
+    /// The location of the error in the request body, if known.
+    ///
+    /// JSON errors carry the line and column, and the path of the value the error occurred in if it occurred in a field.
+    /// XML errors in a field carry the path, and the line and column of the element it leads to as far as it can be found.
+    /// Protobuf errors have no location, see [DeserializeError::protobuf_error] for the fields they occurred in.
+    /// [PayloadError::Redacted] errors have no location.
+    pub fn location(&self) -> Option<ErrorLocation> {
+        match self {
+            #[cfg(any(feature = "json", feature = "xml"))]
+            Self::Deserialize { error, body, .. } => ErrorLocation::of(error, body),
+            Self::Mapped { source, .. } | Self::Attempts { source, .. } => source.location(),
+            #[cfg(feature = "problem-details")]
+            Self::Negotiated { source, .. } => source.location(),
+            _ => None,
+        }
+    }
 }
 
 impl ResponseError for PayloadError {
     fn status_code(&self) -> StatusCode {
-        StatusCode::BAD_REQUEST
+        match self {
+            Self::ActixPayload(actix_web::error::PayloadError::Overflow) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Blocking(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Timeout => StatusCode::REQUEST_TIMEOUT,
+            Self::UnsupportedFormat(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::ChecksumMismatch(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            #[cfg(feature = "jsonschema")]
+            Self::Deserialize {
+                error: DeserializeError::Schema(_),
+                ..
+            } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Redacted { source, .. } | Self::Attempts { source, .. } => source.status_code(),
+            Self::Mapped { status, .. } => *status,
+            #[cfg(feature = "problem-details")]
+            Self::Negotiated { source, .. } => source.status_code(),
+            _ => StatusCode::BAD_REQUEST,
+        }
     }
 
     fn error_response(&self) -> HttpResponse<BoxBody> {
-        HttpResponse::build(self.status_code()).body(format!("{self}"))
+        #[cfg(feature = "problem-details")]
+        if let Self::Negotiated { status, headers, body, .. } = self {
+            let mut response = HttpResponse::with_body(*status, BoxBody::new(body.clone()));
+            *response.headers_mut() = headers.clone();
+            return response;
+        }
+
+        #[cfg(all(feature = "problem-details", feature = "json"))]
+        if let Ok(body) = serde_json::to_vec(&crate::ProblemDetails::new(self.status_code(), self.coded_message(), None)) {
+            return HttpResponse::build(self.status_code())
+                .insert_header(("Content-Type", "application/problem+json"))
+                .body(body);
+        }
+
+        HttpResponse::build(self.status_code()).body(self.coded_message())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_protobuf_error_path() {
+        #[derive(prost_derive::Message)]
+        struct Inner {
+            #[prost(int64, tag = "1")]
+            bar: i64,
+        }
+
+        #[derive(prost_derive::Message)]
+        struct Outer {
+            #[prost(message, optional, tag = "1")]
+            inner: Option<Inner>,
+        }
+
+        // Field 1 of the inner message with the length delimited wire type, instead of varint
+        let body = [0x0a, 0x02, 0x0a, 0x00];
+        let error = ProtobufError::from(<Outer as prost::Message>::decode(&body[..]).unwrap_err());
+
+        assert_eq!(
+            &[("Outer".to_string(), "inner".to_string()), ("Inner".to_string(), "bar".to_string())],
+            error.path()
+        );
+        assert!(error.description().starts_with("invalid wire type"));
+        assert!(error.to_string().starts_with("Outer.inner: Inner.bar: invalid wire type"));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_location() {
+        let body = Bytes::from_static(b"{\n  \"foo\": \"bar\",\n  \"bar\": true\n}");
+        let error = serde_json::from_slice::<std::collections::BTreeMap<String, String>>(&body).unwrap_err();
+        let error = PayloadError::Deserialize {
+            error: DeserializeError::SerdeJson(error),
+            body,
+            content_type: ContentType::Json,
+        };
+
+        let location = error.location().unwrap();
+        assert_eq!(3, location.line);
+        assert_eq!("  \"bar\": true", location.snippet);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_json_path() {
+        #[derive(Debug, serde_derive::Deserialize)]
+        struct Author {
+            name: String,
+        }
+
+        #[derive(Debug, serde_derive::Deserialize)]
+        struct Post {
+            authors: Vec<Author>,
+        }
+
+        let post = crate::codec::deserialize_json::<Post>(br#"{"authors":[{"name":"foo"}]}"#, &crate::PayloadConfig::default());
+        assert_eq!("foo", post.unwrap().authors[0].name);
+
+        let body = Bytes::from_static(b"{\"authors\":[{\"name\":\"foo\"},{\"name\":1}]}");
+        let error = crate::codec::deserialize_json::<Post>(&body, &crate::PayloadConfig::default()).unwrap_err();
+        assert_eq!(Some("authors[1].name"), error.path());
+        assert!(matches!(error.detailed(), DeserializeError::SerdeJson(_)));
+
+        let error = PayloadError::Deserialize {
+            error,
+            body,
+            content_type: ContentType::Json,
+        };
+        let location = error.location().unwrap();
+        assert_eq!(Some("authors[1].name"), location.path.as_deref());
+        assert_eq!(1, location.line);
+    }
+
+    #[test]
+    #[cfg(feature = "xml")]
+    fn test_xml_location() {
+        #[derive(Debug, serde_derive::Deserialize)]
+        struct Author {
+            age: u32,
+        }
+
+        #[derive(Debug, serde_derive::Deserialize)]
+        struct Post {
+            authors: Vec<Author>,
+        }
+
+        let post = crate::codec::deserialize_xml::<Post>(b"<post><authors><age>1</age></authors></post>", &crate::PayloadConfig::default());
+        assert_eq!(1, post.unwrap().authors[0].age);
+
+        let body = Bytes::from_static(b"<post>\n  <authors><age>1</age></authors>\n  <authors><age>foo</age></authors>\n</post>");
+        let error = crate::codec::deserialize_xml::<Post>(&body, &crate::PayloadConfig::default()).unwrap_err();
+        assert_eq!(Some("authors[1].age"), error.path());
+
+        let error = PayloadError::Deserialize {
+            error,
+            body,
+            content_type: ContentType::Xml,
+        };
+        let location = error.location().unwrap();
+        assert_eq!(3, location.line);
+        assert_eq!(12, location.column);
+        assert_eq!("  <authors><age>foo</age></autho", location.snippet);
     }
 }
@@ -0,0 +1,120 @@
+//! Correlation IDs, which replace the cause of an error in responses when [ErrorVerbosity::Redacted] is configured.
+
+use crate::{PayloadError, SerializeError};
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How much of the cause of an error is included in the response, see [crate::PayloadConfig::error_verbosity]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorVerbosity {
+    /// Include the cause of the error, e.g. the message of the deserializer. The default
+    #[default]
+    Detailed,
+    /// Include a generic message and an [ErrorId] only. The cause is logged at error level together with the ID,
+    /// using `tracing` with the `tracing` feature and `log` otherwise
+    Redacted,
+}
+
+/// A random ID correlating an error response with the logged cause, rendered as 16 hexadecimal digits
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ErrorId(u64);
+
+/// Ensures IDs differ even if the random keys are reused
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+impl ErrorId {
+    /// A new ID, which is unpredictable but not cryptographically secure
+    pub fn new() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        Self(hasher.finish())
+    }
+}
+
+impl Default for ErrorId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for ErrorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Log the cause of the error with `id` at error level
+fn log_cause(id: &ErrorId, cause: &dyn fmt::Display) {
+    #[cfg(feature = "tracing")]
+    tracing::error!(error_id = %id, "{cause}");
+    #[cfg(not(feature = "tracing"))]
+    log::error!("{cause} (error id {id})");
+}
+
+/// Log the error and replace it with [PayloadError::Redacted]
+pub(crate) fn redact(error: PayloadError) -> PayloadError {
+    let id = ErrorId::new();
+    log_cause(&id, &error);
+
+    PayloadError::Redacted {
+        id,
+        source: Box::new(error),
+    }
+}
+
+/// Log the serialize error, returning the message for the response
+pub(crate) fn redact_serialize_error(error: &SerializeError) -> String {
+    let id = ErrorId::new();
+    log_cause(&id, error);
+
+    format!("Internal server error (error id {id})")
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use crate::{Payload, PayloadConfig};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App};
+
+    #[derive(serde_derive::Deserialize, prost_derive::Message)]
+    struct TestPayload {
+        #[prost(string, tag = "1")]
+        secret_field: String,
+    }
+
+    async fn handler(_: Payload<TestPayload>) -> &'static str {
+        ""
+    }
+
+    #[actix_macros::test]
+    async fn test_redacted() {
+        let app = init_service(
+            App::new()
+                .app_data(PayloadConfig::default().error_verbosity(ErrorVerbosity::Redacted))
+                .route("/", web::post().to(handler)),
+        )
+        .await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#"{"other": 1}"#)
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("error id"));
+        assert!(!body.contains("secret_field"));
+    }
+
+    #[test]
+    fn test_unique_ids() {
+        assert_ne!(ErrorId::new(), ErrorId::new());
+        assert_eq!(16, ErrorId::new().to_string().len());
+    }
+}
@@ -0,0 +1,168 @@
+//! Mapping classes of deserialize errors to the status codes and error codes of an API,
+//! see [crate::PayloadConfig::map_error].
+
+use crate::{DeserializeError, PayloadError};
+
+use actix_web::http::StatusCode;
+
+/// The class of a [DeserializeError], independent of the format and of the messages of the deserializers
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[non_exhaustive]
+pub enum DeserializeErrorKind {
+    /// The body is not syntactically valid in its format, or ends prematurely
+    Syntax,
+    /// A value does not have the type of its field
+    Type,
+    /// A field is not known to the type, see [crate::PayloadConfig::deny_unknown_fields]
+    UnknownField,
+    /// A required field is missing
+    MissingField,
+    /// The body is not a valid protobuf message, e.g. because of an invalid wire type
+    ProtobufWireFormat,
+    /// The framing of a grpc-web body is invalid
+    GrpcWebFraming,
+    /// The [crate::PayloadConfig::transform] failed
+    Transform,
+    /// The body does not match the JSON Schema registered with `PayloadConfig::json_schema`
+    Schema,
+    /// Any other error
+    Other,
+}
+
+/// The class of a failed deserialization of a field or value, from the message of the serde error
+#[cfg(any(feature = "json", feature = "xml"))]
+fn data_kind(message: &str) -> DeserializeErrorKind {
+    if message.starts_with("unknown field") {
+        DeserializeErrorKind::UnknownField
+    } else if message.starts_with("missing field") {
+        DeserializeErrorKind::MissingField
+    } else {
+        DeserializeErrorKind::Type
+    }
+}
+
+impl DeserializeError {
+    /// The class of the error
+    pub fn kind(&self) -> DeserializeErrorKind {
+        match self {
+            #[cfg(feature = "json")]
+            Self::SerdeJson(e) => match e.classify() {
+                serde_json::error::Category::Syntax | serde_json::error::Category::Eof => DeserializeErrorKind::Syntax,
+                serde_json::error::Category::Data => data_kind(&e.to_string()),
+                serde_json::error::Category::Io => DeserializeErrorKind::Other,
+            },
+            #[cfg(feature = "protobuf")]
+            Self::Prost(_) => DeserializeErrorKind::ProtobufWireFormat,
+            #[cfg(feature = "protobuf")]
+            Self::GrpcWeb(_) => DeserializeErrorKind::GrpcWebFraming,
+            #[cfg(feature = "xml")]
+            Self::Xml(quick_xml::DeError::Custom(message)) => data_kind(message),
+            #[cfg(feature = "xml")]
+            Self::Xml(_) => DeserializeErrorKind::Syntax,
+            Self::Transform(_) => DeserializeErrorKind::Transform,
+            #[cfg(feature = "jsonschema")]
+            Self::Schema(_) => DeserializeErrorKind::Schema,
+            Self::Undeserializable => DeserializeErrorKind::Other,
+            #[cfg(any(feature = "json", feature = "xml"))]
+            Self::Field { source, .. } => source.kind(),
+        }
+    }
+}
+
+/// The status and error code configured for a [DeserializeErrorKind]
+#[derive(Debug, Clone)]
+pub(crate) struct ErrorMapping {
+    pub(crate) kind: DeserializeErrorKind,
+    pub(crate) status: StatusCode,
+    pub(crate) code: String,
+}
+
+/// Wrap a deserialize error of which the kind is mapped in [PayloadError::Mapped]. Other errors are returned unchanged
+pub(crate) fn apply(mappings: &[ErrorMapping], error: PayloadError) -> PayloadError {
+    let kind = match error.detailed() {
+        PayloadError::Deserialize { error, .. } => error.kind(),
+        _ => return error,
+    };
+
+    match mappings.iter().find(|mapping| mapping.kind == kind) {
+        Some(mapping) => PayloadError::Mapped {
+            status: mapping.status,
+            code: mapping.code.clone(),
+            source: Box::new(error),
+        },
+        None => error,
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use crate::json_backend::{Backend, JsonBackend};
+    use crate::JsonNaming;
+
+    #[derive(Debug, serde_derive::Deserialize)]
+    struct Article {
+        title: String,
+        pages: u32,
+    }
+
+    fn kind(body: &str, deny_unknown_fields: bool) -> DeserializeErrorKind {
+        Backend::from_slice::<Article>(body.as_bytes(), deny_unknown_fields, JsonNaming::Preserve, false)
+            .unwrap_err()
+            .kind()
+    }
+
+    #[test]
+    fn test_json_kind() {
+        assert_eq!(DeserializeErrorKind::Syntax, kind(r#"{"title":"#, false));
+        assert_eq!(DeserializeErrorKind::Syntax, kind(r#"{"title" "foo"}"#, false));
+        assert_eq!(
+            DeserializeErrorKind::Type,
+            kind(r#"{"title":"foo","pages":"1"}"#, false)
+        );
+        assert_eq!(DeserializeErrorKind::MissingField, kind(r#"{"title":"foo"}"#, false));
+        assert_eq!(
+            DeserializeErrorKind::UnknownField,
+            kind(r#"{"title":"foo","pages":1,"author":"bar"}"#, true)
+        );
+
+        let article = Backend::from_slice::<Article>(br#"{"title":"foo","pages":1}"#, true, JsonNaming::Preserve, false).unwrap();
+        assert_eq!(("foo", 1), (article.title.as_str(), article.pages));
+    }
+
+    #[actix_macros::test]
+    async fn test_map_error() {
+        use actix_web::{FromRequest, ResponseError};
+
+        let config = crate::PayloadConfig::default()
+            .deny_unknown_fields(true)
+            .map_error(DeserializeErrorKind::UnknownField, StatusCode::UNPROCESSABLE_ENTITY, "UNKNOWN_FIELD");
+        let extract = |body: &'static str| {
+            let (req, mut payload) = actix_web::test::TestRequest::post()
+                .insert_header(("Content-Type", "application/json"))
+                .app_data(config.clone())
+                .set_payload(body)
+                .to_http_parts();
+            async move {
+                let payload = crate::BorrowedPayload::from_request(&req, &mut payload).await.unwrap();
+                payload.deserialize::<Article>().unwrap_err()
+            }
+        };
+
+        let error = extract(r#"{"title":"foo","pages":1,"author":"bar"}"#).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, error.status_code());
+        assert_eq!(Some("UNKNOWN_FIELD"), error.error_code());
+        assert!(matches!(error.detailed(), PayloadError::Deserialize { .. }));
+
+        let error = extract(r#"{"title":"foo"}"#).await;
+        assert_eq!(StatusCode::BAD_REQUEST, error.status_code());
+        assert_eq!(None, error.error_code());
+    }
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_protobuf_kind() {
+        let error = crate::Payload::<String>::deserialize(&[0xff], crate::ContentType::Protobuf).unwrap_err();
+        assert_eq!(DeserializeErrorKind::ProtobufWireFormat, error.kind());
+    }
+}
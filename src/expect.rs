@@ -0,0 +1,132 @@
+//! Validation of requests with `Expect: 100-continue`, before their body is sent.
+
+use crate::{body, ContentType, PayloadConfig, PayloadError};
+
+use actix_http::Request;
+use actix_web::dev::{Service, ServiceFactory};
+use actix_web::error::PayloadError as ActixPayloadError;
+use actix_web::http::header::HeaderMap;
+use actix_web::HttpMessage;
+
+use std::future::{ready, Ready};
+use std::task::{Context, Poll};
+
+/// Validates requests with an `Expect: 100-continue` header against a [PayloadConfig],
+/// before the server responds with `100 Continue` and the client sends the body.
+///
+/// Requests with a `Content-Length` above [PayloadConfig::limit] are rejected with a HTTP 413,
+/// requests with a `Content-Type` which is not supported by the configuration with a HTTP 400.
+/// This saves clients from uploading bodies which would be rejected anyway.
+///
+/// actix-web sends `100 Continue` before the routing and extractors run, so this has to be registered
+/// as the expect handler of the [actix_http::HttpService], rather than on the [actix_web::App].
+/// Like the default expect handler, it applies to every request of the server which expects `100 Continue`,
+/// so only use it on servers where those requests are all meant for [crate::Payload].
+///
+/// # Example
+/// ```no_run
+///     use actix_multiresponse::{ExpectContinue, Payload, PayloadConfig};
+///     use actix_web::dev::AppConfig;
+///     use actix_web::{web, App};
+///
+///     async fn upload(payload: Payload<String>) -> Payload<String> {
+///         payload
+///     }
+///
+///     # async fn run() -> std::io::Result<()> {
+///     let config = PayloadConfig::default().limit(1_048_576);
+///     actix_server::Server::build()
+///         .bind("upload", ("127.0.0.1", 8080), move || {
+///             let app = App::new()
+///                 .app_data(config.clone())
+///                 .route("/", web::post().to(upload));
+///
+///             actix_http::HttpService::build()
+///                 .expect(ExpectContinue::new(config.clone()))
+///                 .h1(actix_service::map_config(app, |_| AppConfig::default()))
+///                 .tcp()
+///         })?
+///         .run()
+///         .await
+///     # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ExpectContinue {
+    config: PayloadConfig,
+}
+
+impl ExpectContinue {
+    pub fn new(config: PayloadConfig) -> Self {
+        Self { config }
+    }
+
+    /// Validate the headers of a request, as the extractor of [crate::Payload] would
+    fn validate(&self, headers: &HeaderMap) -> Result<(), PayloadError> {
+        if body::content_length(headers).is_some_and(|len| len > self.config.limit) {
+            return Err(ActixPayloadError::Overflow.into());
+        }
+
+        let content_type = ContentType::try_from_header_map(headers, "Content-Type")?;
+        let content_type = self.config.request_format(content_type, headers);
+        if content_type.is_other() {
+            return Err(PayloadError::InvalidContentType(content_type));
+        }
+
+        Ok(())
+    }
+}
+
+impl ServiceFactory<Request> for ExpectContinue {
+    type Response = Request;
+    type Error = actix_web::Error;
+    type Config = ();
+    type Service = Self;
+    type InitError = ();
+    type Future = Ready<Result<Self::Service, Self::InitError>>;
+
+    fn new_service(&self, _: Self::Config) -> Self::Future {
+        ready(Ok(self.clone()))
+    }
+}
+
+impl Service<Request> for ExpectContinue {
+    type Response = Request;
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&self, req: Request) -> Self::Future {
+        ready(self.validate(req.headers()).map(|_| req).map_err(Into::into))
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use actix_web::http::StatusCode;
+
+    async fn call(expect: &ExpectContinue, headers: &[(&'static str, &'static str)]) -> Result<Request, actix_web::Error> {
+        let mut req = actix_http::test::TestRequest::default();
+        for header in headers {
+            req.insert_header(*header);
+        }
+
+        expect.call(req.finish()).await
+    }
+
+    #[actix_macros::test]
+    async fn test_expect_continue() {
+        let expect = ExpectContinue::new(PayloadConfig::default().limit(10));
+
+        assert!(call(&expect, &[("Content-Type", "application/json"), ("Content-Length", "10")]).await.is_ok());
+
+        let error = call(&expect, &[("Content-Type", "application/json"), ("Content-Length", "11")]).await.unwrap_err();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, error.as_response_error().status_code());
+
+        let error = call(&expect, &[("Content-Type", "text/plain"), ("Content-Length", "1")]).await.unwrap_err();
+        assert_eq!(StatusCode::BAD_REQUEST, error.as_response_error().status_code());
+    }
+}
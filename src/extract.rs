@@ -0,0 +1,538 @@
+//! The future of extracting a [Payload], see [PayloadFuture].
+
+use crate::body::{self, Aggregator};
+use crate::negotiated_error::negotiated;
+use crate::{
+    supports_format, AnyFormat, ContentType, DeserializeError, FormatSet, Negotiation, Payload, PayloadConfig,
+    PayloadError, ProtobufSupport, SerdeSupportDeserialize,
+};
+
+use actix_rt::time::Sleep;
+use actix_web::dev::Payload as DevPayload;
+use actix_web::error::BlockingError;
+use actix_web::web::{self, Bytes};
+use actix_web::HttpRequest;
+use futures_util::Stream;
+
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// The future of the [actix_web::FromRequest] implementation of [Payload].
+///
+/// This is a state machine rather than a boxed `async` block, so extracting a payload does not allocate
+/// besides the body itself. Only a timeout configured with [PayloadConfig::read_timeout] and deserializing
+/// on the blocking thread pool, see [PayloadConfig::blocking_threshold], allocate.
+pub struct PayloadFuture<T, F = AnyFormat> {
+    state: State<T>,
+    /// Whether the future was polled, extractors wrapping it may drop it without polling it when rejecting a request
+    polled: bool,
+    /// The request an error is rendered for, see [crate::negotiated_error::negotiated]
+    req: HttpRequest,
+    formats: PhantomData<F>,
+}
+
+type BlockingFuture<T> = Pin<Box<dyn Future<Output = Result<Attempted<T>, BlockingError>>>>;
+
+/// A deserialized payload, or the error of the first format with the formats which were tried, see [deserialize_attempts]
+type Attempted<T> = Result<Payload<T>, (DeserializeError, Vec<ContentType>)>;
+
+enum State<T> {
+    /// Reading the body from the payload stream
+    Reading {
+        req: HttpRequest,
+        payload: DevPayload,
+        content_type: ContentType,
+        aggregator: Aggregator,
+        timeout: Option<Pin<Box<Sleep>>>,
+    },
+    /// The body was read completely, or was already cached in the request extensions
+    Read {
+        req: HttpRequest,
+        body: Bytes,
+        content_type: ContentType,
+    },
+    /// Deserializing on the blocking thread pool, see [PayloadConfig::blocking_threshold]
+    Blocking {
+        req: HttpRequest,
+        body: Bytes,
+        content_type: ContentType,
+        handle: BlockingFuture<T>,
+    },
+    Ready(Result<Payload<T>, PayloadError>),
+    Done,
+}
+
+// The state is never pinned, only the timeout, which is boxed
+impl<T, F> Unpin for PayloadFuture<T, F> {}
+
+/// The hook extractions cancelled while reading the body are reported to, see [PayloadConfig::cancel_hook]
+#[derive(Clone)]
+pub(crate) struct CancelHook(pub(crate) Arc<CancelFn>);
+
+type CancelFn = dyn Fn(&HttpRequest, usize) + Send + Sync;
+
+impl fmt::Debug for CancelHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CancelHook")
+    }
+}
+
+impl<T, F> Drop for PayloadFuture<T, F> {
+    /// Dropping the future before it completed, e.g. because the client disconnected, stops reading the body.
+    /// The payload stream and the chunks read so far are dropped with the state.
+    fn drop(&mut self) {
+        if !self.polled {
+            return;
+        }
+
+        if let State::Reading { req, aggregator, .. } = &self.state {
+            if let Some(hook) = &PayloadConfig::from_req(req).cancel_hook {
+                (hook.0)(req, aggregator.len());
+            }
+        }
+    }
+}
+
+impl<T: ProtobufSupport, F: FormatSet> PayloadFuture<T, F> {
+    pub(crate) fn new(req: &HttpRequest, payload: DevPayload) -> Self {
+        Self {
+            state: State::start(req, payload, F::allows),
+            polled: false,
+            req: req.clone(),
+            formats: PhantomData,
+        }
+    }
+}
+
+impl<T: ProtobufSupport> State<T> {
+    /// Start extracting a payload, in one of the formats `allows` returns true for
+    fn start(req: &HttpRequest, payload: DevPayload, allows: fn(&ContentType) -> bool) -> Self {
+        // Reject unsupported formats before consuming the body
+        let content_type = match Negotiation::try_from_request(req) {
+            Ok(negotiation) if negotiation.content_type.is_other() => {
+                return Self::Ready(Err(PayloadError::InvalidContentType(negotiation.content_type)))
+            }
+            Ok(negotiation) if !supports_format::<T>(&negotiation.content_type) || !allows(&negotiation.content_type) => {
+                return Self::Ready(Err(PayloadError::UnsupportedFormat(negotiation.content_type)))
+            }
+            Ok(negotiation) => negotiation.content_type,
+            Err(e) => return Self::Ready(Err(e)),
+        };
+
+        let req = req.clone();
+        let config = PayloadConfig::from_req(&req);
+        match body::cached_body(&req, config) {
+            Some(Ok(body)) => return Self::Read { req, body, content_type },
+            Some(Err(e)) => return Self::Ready(Err(e)),
+            None => {}
+        }
+
+        let aggregator = match Aggregator::new(&req, config.limit) {
+            Ok(aggregator) => aggregator,
+            Err(e) => return Self::Ready(Err(e.into())),
+        };
+        let timeout = config.read_timeout.map(|timeout| Box::pin(actix_rt::time::sleep(timeout)));
+
+        Self::Reading {
+            req,
+            payload,
+            content_type,
+            aggregator,
+            timeout,
+        }
+    }
+}
+
+impl<T: 'static + Send + SerdeSupportDeserialize + ProtobufSupport> State<T> {
+    /// Deserialize a body which was read completely,
+    /// offloading large bodies so deserializing, including the fallback attempts, does not block the worker thread
+    fn deserialize(req: HttpRequest, payload_bytes: Bytes, content_type: ContentType, allows: fn(&ContentType) -> bool) -> Self {
+        let config = PayloadConfig::from_req(&req);
+        let deserialized = match body::prepare_body(config, payload_bytes.clone(), &content_type) {
+            Ok(body) if config.blocking_threshold.is_some_and(|threshold| body.len() > threshold) => {
+                let fallbacks = fallback_formats::<T>(&req, &content_type, allows);
+                let (blocking_body, blocking_content_type) = (payload_bytes.clone(), content_type.clone());
+                let blocking_config = config.clone();
+                let handle = Box::pin(web::block(move || {
+                    deserialize_attempts(body, &blocking_body, blocking_content_type, || fallbacks, &blocking_config)
+                }));
+
+                return Self::Blocking {
+                    req,
+                    body: payload_bytes,
+                    content_type,
+                    handle,
+                };
+            }
+            Ok(body) => deserialize_attempts(
+                body,
+                &payload_bytes,
+                content_type.clone(),
+                || fallback_formats::<T>(&req, &content_type, allows),
+                config,
+            ),
+            Err(e) => Err((e, Vec::new())),
+        };
+
+        Self::Ready(report(&req, deserialized, payload_bytes, content_type))
+    }
+}
+
+/// Deserialize a prepared body, validating JSON against the schema registered with `PayloadConfig::json_schema` first
+fn deserialize_body<T: 'static + SerdeSupportDeserialize + ProtobufSupport>(
+    body: Bytes,
+    content_type: ContentType,
+    config: &PayloadConfig,
+) -> Result<Payload<T>, DeserializeError> {
+    #[cfg(feature = "jsonschema")]
+    crate::schema::validate::<T>(&body, &content_type, config)?;
+
+    Payload::deserialize_bytes_with(body, content_type, config)
+}
+
+/// The other formats the media type of the request body is an alias for, which are tried if the body fails to deserialize
+/// in `content_type`, see [PayloadConfig::max_format_attempts]
+fn fallback_formats<T: ProtobufSupport>(
+    req: &HttpRequest,
+    content_type: &ContentType,
+    allows: fn(&ContentType) -> bool,
+) -> Vec<ContentType> {
+    let config = PayloadConfig::from_req(req);
+    config
+        .alias_fallbacks(req.headers(), content_type)
+        .into_iter()
+        .filter(|format| supports_format::<T>(format) && allows(format))
+        .take(config.max_format_attempts - 1)
+        .collect()
+}
+
+/// Deserialize the `prepared` body in `content_type`. If that fails, `body` is deserialized in the formats
+/// returned by `fallbacks` in order, until one succeeds.
+///
+/// If all attempts fail, the error of `content_type` is returned with the formats which were tried, `content_type` first
+fn deserialize_attempts<T: 'static + SerdeSupportDeserialize + ProtobufSupport>(
+    prepared: Bytes,
+    body: &Bytes,
+    content_type: ContentType,
+    fallbacks: impl FnOnce() -> Vec<ContentType>,
+    config: &PayloadConfig,
+) -> Attempted<T> {
+    let error = match deserialize_body(prepared, content_type.clone(), config) {
+        Ok(payload) => return Ok(payload),
+        Err(error) => error,
+    };
+
+    let mut attempted = vec![content_type];
+    for format in fallbacks() {
+        let deserialized = body::prepare_body(config, body.clone(), &format)
+            .and_then(|prepared| deserialize_body(prepared, format.clone(), config));
+        if let Ok(payload) = deserialized {
+            return Ok(payload);
+        }
+        attempted.push(format);
+    }
+
+    Err((error, attempted))
+}
+
+/// Report an error while deserializing `body`, see [PayloadConfig::error_hook]
+fn report<T>(
+    req: &HttpRequest,
+    deserialized: Attempted<T>,
+    body: Bytes,
+    content_type: ContentType,
+) -> Result<Payload<T>, PayloadError> {
+    let (error, attempted) = match deserialized {
+        Ok(payload) => return Ok(payload),
+        Err(error) => error,
+    };
+
+    let config = PayloadConfig::from_req(req);
+    let error = PayloadError::Deserialize {
+        error,
+        body,
+        content_type,
+    };
+    let error = if attempted.len() > 1 {
+        PayloadError::Attempts {
+            attempted,
+            source: Box::new(error),
+        }
+    } else {
+        error
+    };
+
+    Err(config.report_error(error))
+}
+
+impl<T: 'static + Send + SerdeSupportDeserialize + ProtobufSupport, F: FormatSet> Future for PayloadFuture<T, F> {
+    type Output = Result<Payload<T, F>, PayloadError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.polled = true;
+        loop {
+            this.state = match std::mem::replace(&mut this.state, State::Done) {
+                State::Reading {
+                    req,
+                    mut payload,
+                    content_type,
+                    mut aggregator,
+                    mut timeout,
+                } => match Pin::new(&mut payload).poll_next(cx) {
+                    Poll::Ready(Some(Ok(chunk))) => match aggregator.push(chunk) {
+                        Ok(()) => State::Reading {
+                            req,
+                            payload,
+                            content_type,
+                            aggregator,
+                            timeout,
+                        },
+                        Err(e) => State::Ready(Err(e.into())),
+                    },
+                    Poll::Ready(Some(Err(e))) => State::Ready(Err(e.into())),
+                    Poll::Ready(None) => {
+                        match body::complete_body(&req, aggregator.finish(), PayloadConfig::from_req(&req)) {
+                            Ok(body) => State::Read { req, body, content_type },
+                            Err(e) => State::Ready(Err(e)),
+                        }
+                    }
+                    Poll::Pending if timeout.as_mut().is_some_and(|timeout| timeout.as_mut().poll(cx).is_ready()) => {
+                        State::Ready(Err(PayloadError::Timeout))
+                    }
+                    Poll::Pending => {
+                        this.state = State::Reading {
+                            req,
+                            payload,
+                            content_type,
+                            aggregator,
+                            timeout,
+                        };
+                        return Poll::Pending;
+                    }
+                },
+                State::Read { req, body, content_type } => State::deserialize(req, body, content_type, F::allows),
+                State::Blocking {
+                    req,
+                    body,
+                    content_type,
+                    mut handle,
+                } => match handle.as_mut().poll(cx) {
+                    Poll::Ready(Ok(deserialized)) => State::Ready(report(&req, deserialized, body, content_type)),
+                    Poll::Ready(Err(e)) => State::Ready(Err(e.into())),
+                    Poll::Pending => {
+                        this.state = State::Blocking {
+                            req,
+                            body,
+                            content_type,
+                            handle,
+                        };
+                        return Poll::Pending;
+                    }
+                },
+                State::Ready(result) => {
+                    return Poll::Ready(
+                        result
+                            .map(|payload| Payload::from(payload.0))
+                            .map_err(|error| negotiated(&this.req, error)),
+                    )
+                }
+                State::Done => panic!("PayloadFuture polled after completion"),
+            };
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use actix_web::error::PayloadError as ActixPayloadError;
+    use actix_web::test::TestRequest;
+    use actix_web::FromRequest;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[actix_macros::test]
+    async fn test_read_timeout() {
+        let stream = futures_util::stream::pending::<Result<Bytes, ActixPayloadError>>();
+        let stream: Pin<Box<dyn Stream<Item = _>>> = Box::pin(stream);
+        let req = TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .app_data(PayloadConfig::default().read_timeout(std::time::Duration::from_millis(10)))
+            .to_http_request();
+
+        let result = Payload::<String>::from_request(&req, &mut DevPayload::from(stream)).await;
+        assert!(matches!(result.unwrap_err().detailed(), PayloadError::Timeout));
+    }
+
+    #[actix_macros::test]
+    async fn test_json_like_content_type() {
+        for media_type in ["application/json-seq", "application/jsonx"] {
+            let (req, mut payload) = TestRequest::default()
+                .insert_header(("Content-Type", media_type))
+                .set_payload(r#""foo""#)
+                .to_http_parts();
+
+            let error = Payload::<String>::from_request(&req, &mut payload).await.unwrap_err();
+            assert!(matches!(error.detailed(), PayloadError::InvalidContentType(ContentType::Other(_))));
+        }
+    }
+
+    #[actix_macros::test]
+    async fn test_cached_body() {
+        let req = TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#""foo""#)
+            .to_http_parts();
+        let (req, mut payload) = req;
+
+        let first = Payload::<String>::from_request(&req, &mut payload).await.unwrap();
+        let second = Payload::<String>::from_request(&req, &mut DevPayload::None).await.unwrap();
+        assert_eq!("foo", first.0);
+        assert_eq!(first.0, second.0);
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "protobuf")]
+    async fn test_max_format_attempts() {
+        let config = PayloadConfig::default()
+            .alias("application/octet-stream", ContentType::Protobuf)
+            .alias("application/octet-stream", ContentType::Json);
+        let extract = |config: &PayloadConfig, body: &'static str| {
+            let (req, mut payload) = TestRequest::default()
+                .insert_header(("Content-Type", "application/octet-stream"))
+                .app_data(config.clone())
+                .set_payload(body)
+                .to_http_parts();
+            async move { Payload::<String>::from_request(&req, &mut payload).await }
+        };
+
+        let error = extract(&config, r#""foo""#).await.unwrap_err();
+        assert!(error.attempted_formats().is_empty());
+        assert_eq!(Some(&ContentType::Protobuf), error.content_type());
+
+        let config = config.max_format_attempts(2);
+        assert_eq!("foo", extract(&config, r#""foo""#).await.unwrap().0);
+        assert_eq!("foo", extract(&config, "\n\x03foo").await.unwrap().0);
+
+        let error = extract(&config, "{").await.unwrap_err();
+        assert_eq!(&[ContentType::Protobuf, ContentType::Json], error.attempted_formats());
+        assert_eq!(Some(&ContentType::Protobuf), error.content_type());
+        assert!(error.to_string().ends_with("(tried application/protobuf, application/json)"));
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "protobuf")]
+    async fn test_blocking_format_attempts() {
+        /// Records the thread it was deserialized on, fails to decode from protobuf
+        struct ThreadPayload(std::thread::ThreadId);
+
+        impl<'de> serde::Deserialize<'de> for ThreadPayload {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                serde::de::IgnoredAny::deserialize(deserializer)?;
+                Ok(Self(std::thread::current().id()))
+            }
+        }
+
+        impl ProtobufSupport for ThreadPayload {
+            fn encode_protobuf(&self) -> Result<Vec<u8>, crate::SerializeError> {
+                Err(crate::SerializeError::Unserializable)
+            }
+
+            fn decode_protobuf(_: &[u8]) -> Result<Self, DeserializeError> {
+                Err(DeserializeError::Undeserializable)
+            }
+        }
+
+        let (req, mut payload) = TestRequest::default()
+            .insert_header(("Content-Type", "application/octet-stream"))
+            .app_data(
+                PayloadConfig::default()
+                    .alias("application/octet-stream", ContentType::Protobuf)
+                    .alias("application/octet-stream", ContentType::Json)
+                    .max_format_attempts(2)
+                    .blocking_threshold(0),
+            )
+            .set_payload(r#""foo""#)
+            .to_http_parts();
+
+        // The JSON fallback runs on the blocking thread pool as well
+        let payload = Payload::<ThreadPayload>::from_request(&req, &mut payload).await.unwrap();
+        assert_ne!(std::thread::current().id(), payload.0 .0);
+    }
+
+    /// A stream yielding one chunk and then nothing, recording when it is dropped
+    struct Stalled {
+        chunk: Option<Bytes>,
+        dropped: Arc<AtomicBool>,
+    }
+
+    impl Stream for Stalled {
+        type Item = Result<Bytes, ActixPayloadError>;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            match self.chunk.take() {
+                Some(chunk) => Poll::Ready(Some(Ok(chunk))),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    impl Drop for Stalled {
+        fn drop(&mut self) {
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[actix_macros::test]
+    async fn test_cancelled() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let stream: Pin<Box<dyn Stream<Item = _>>> = Box::pin(Stalled {
+            chunk: Some(Bytes::from_static(br#""foo"#)),
+            dropped: dropped.clone(),
+        });
+
+        let cancelled = Arc::new(AtomicUsize::new(usize::MAX));
+        let hook_cancelled = cancelled.clone();
+        let req = TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .app_data(PayloadConfig::default().cancel_hook(move |_, read| hook_cancelled.store(read, Ordering::SeqCst)))
+            .to_http_request();
+
+        let future = Payload::<String>::from_request(&req, &mut DevPayload::from(stream));
+        assert!(futures_util::FutureExt::now_or_never(future).is_none());
+        assert!(dropped.load(Ordering::SeqCst));
+        assert_eq!(4, cancelled.load(Ordering::SeqCst));
+    }
+
+    #[actix_macros::test]
+    async fn test_unpolled_not_cancelled() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let hook_cancelled = cancelled.clone();
+        let (req, mut payload) = TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .app_data(PayloadConfig::default().cancel_hook(move |_, _| hook_cancelled.store(true, Ordering::SeqCst)))
+            .to_http_parts();
+
+        drop(Payload::<String>::from_request(&req, &mut payload));
+        assert!(!cancelled.load(Ordering::SeqCst));
+    }
+
+    #[actix_macros::test]
+    async fn test_completed_not_cancelled() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let hook_cancelled = cancelled.clone();
+        let (req, mut payload) = TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .app_data(PayloadConfig::default().cancel_hook(move |_, _| hook_cancelled.store(true, Ordering::SeqCst)))
+            .set_payload(r#""foo""#)
+            .to_http_parts();
+
+        let payload = Payload::<String>::from_request(&req, &mut payload).await.unwrap();
+        assert_eq!("foo", payload.0);
+        assert!(!cancelled.load(Ordering::SeqCst));
+    }
+}
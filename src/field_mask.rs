@@ -0,0 +1,180 @@
+//! Field masks, limiting responses to the fields requested by the client.
+
+use actix_web::HttpRequest;
+
+/// The header containing the field mask of a request, `X-Fields`
+pub(crate) const FIELDS_HEADER: &str = "x-fields";
+/// The query parameter containing the field mask of a request, used if the header is missing
+const FIELDS_PARAM: &str = "fields";
+
+/// The fields to include in a response, see [crate::PayloadConfig::field_masks].
+///
+/// Paths follow the semantics of `google.protobuf.FieldMask`: field names separated by dots,
+/// where a path selects the named field and everything below it, e.g. `author.name`.
+/// Paths into repeated fields apply to every element.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct FieldMask {
+    paths: Vec<String>,
+}
+
+impl FieldMask {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(paths: I) -> Self {
+        Self {
+            paths: paths.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// The field mask of the request, from the `X-Fields` header or the `fields` query parameter.
+    /// Both contain a comma separated list of paths. `None` if neither is present or the list is empty.
+    pub fn from_request(req: &HttpRequest) -> Option<Self> {
+        let fields = match req.headers().get(FIELDS_HEADER) {
+            Some(header_value) => header_value.to_str().ok()?.to_string(),
+            None => req
+                .query_string()
+                .split('&')
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.eq(&FIELDS_PARAM))
+                .map(|(_, value)| percent_decode(value))?,
+        };
+
+        let mask = Self::new(fields.split(',').map(str::trim).filter(|path| !path.is_empty()));
+        (!mask.paths.is_empty()).then_some(mask)
+    }
+
+    /// The paths in the mask
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /// Whether the field at `path` is selected, either directly or because one of its parents is
+    pub fn contains(&self, path: &str) -> bool {
+        self.paths.iter().any(|masked| {
+            path.strip_prefix(masked.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+    }
+
+    /// The mask relative to the field `name`, i.e. the paths below it with the field name stripped.
+    /// Empty if the field itself is selected, as that selects everything below it.
+    pub fn child(&self, name: &str) -> Self {
+        if self.paths.iter().any(|path| path.eq(name)) {
+            return Self::default();
+        }
+
+        Self::new(
+            self.paths
+                .iter()
+                .filter_map(|path| path.strip_prefix(name)?.strip_prefix('.')),
+        )
+    }
+
+    /// Remove all fields from a JSON value which are not selected.
+    /// Only objects, and objects in arrays, are pruned, other values are left as is.
+    #[cfg(feature = "json")]
+    pub fn apply_json(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(object) => {
+                object.retain(|name, _| self.paths.iter().any(|path| first_segment(path).eq(name)));
+                for (name, field) in object.iter_mut() {
+                    let child = self.child(name);
+                    if !child.paths.is_empty() {
+                        child.apply_json(field);
+                    }
+                }
+            }
+            serde_json::Value::Array(elements) => elements.iter_mut().for_each(|element| self.apply_json(element)),
+            _ => {}
+        }
+    }
+}
+
+/// The first field name of a path
+#[cfg(feature = "json")]
+fn first_segment(path: &str) -> &str {
+    path.split('.').next().unwrap_or_default()
+}
+
+/// Decode a percent encoded query parameter value, where `+` is a space.
+/// Invalid escapes are kept as is.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (None, b'+') => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (None, byte) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_from_request() {
+        let req = TestRequest::get()
+            .insert_header(("X-Fields", "id, author.name"))
+            .to_http_request();
+        assert_eq!(Some(FieldMask::new(["id", "author.name"])), FieldMask::from_request(&req));
+
+        let req = TestRequest::get().uri("/?page=1&fields=id%2Cauthor.name").to_http_request();
+        assert_eq!(Some(FieldMask::new(["id", "author.name"])), FieldMask::from_request(&req));
+
+        let req = TestRequest::get().uri("/?fields=").to_http_request();
+        assert_eq!(None, FieldMask::from_request(&req));
+    }
+
+    #[test]
+    fn test_contains() {
+        let mask = FieldMask::new(["id", "author.name"]);
+
+        assert!(mask.contains("id"));
+        assert!(mask.contains("author.name"));
+        assert!(mask.contains("author.name.first"));
+        assert!(!mask.contains("author"));
+        assert!(!mask.contains("identifier"));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_apply_json() {
+        let mut value = serde_json::json!({
+            "id": 1,
+            "title": "foo",
+            "author": { "name": "bar", "email": "baz" },
+            "comments": [{ "id": 2, "body": "qux" }],
+        });
+
+        FieldMask::new(["id", "author.name", "comments.body"]).apply_json(&mut value);
+        assert_eq!(
+            serde_json::json!({
+                "id": 1,
+                "author": { "name": "bar" },
+                "comments": [{ "body": "qux" }],
+            }),
+            value
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Advertising the supported formats, so clients and gateways can discover them.
+
+use crate::{ContentType, Payload, PayloadConfig};
+
+use actix_web::HttpRequest;
+
+/// The formats supported with a [PayloadConfig], returned by [formats_handler].
+///
+/// In JSON and XML this is a structure with the `formats` field, a list of structures with the
+/// `name`, `media_types` and `aliases` fields. In protobuf it is a message equivalent to
+/// ```proto
+/// message Formats {
+///     repeated Format formats = 1;
+/// }
+///
+/// message Format {
+///     string name = 1;
+///     repeated string media_types = 2;
+///     repeated string aliases = 3;
+/// }
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Formats {
+    /// The supported formats, in the order the server prefers them
+    pub formats: Vec<FormatInfo>,
+}
+
+/// A format in [Formats]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct FormatInfo {
+    /// The short name of the format, see [ContentType::as_str]
+    pub name: String,
+    /// The media types indicating the format, the canonical media type first
+    pub media_types: Vec<String>,
+    /// The media types configured with [PayloadConfig::alias] for the format
+    pub aliases: Vec<String>,
+}
+
+impl Formats {
+    /// The formats supported with `config`, including grpc-web if enabled with [PayloadConfig::grpc_web]
+    pub fn from_config(config: &PayloadConfig) -> Self {
+        #[cfg_attr(not(feature = "protobuf"), allow(unused_mut))]
+        let mut order = config.preference_order(&ContentType::default());
+        #[cfg(feature = "protobuf")]
+        if config.supports(&ContentType::GrpcWeb) && !order.contains(&ContentType::GrpcWeb) {
+            order.push(ContentType::GrpcWeb);
+        }
+
+        let formats = order
+            .iter()
+            .map(|format| FormatInfo {
+                name: format.as_str().to_string(),
+                media_types: format.media_types().iter().map(|media_type| media_type.to_string()).collect(),
+                aliases: config.aliases_of(format).map(str::to_string).collect(),
+            })
+            .collect();
+
+        Self { formats }
+    }
+}
+
+/// A handler returning the [Formats] supported with the [PayloadConfig] of the request, in the negotiated format.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::formats_handler;
+///     use actix_web::web;
+///
+///     let app = actix_web::App::new().route("/formats", web::get().to(formats_handler));
+/// ```
+pub async fn formats_handler(req: HttpRequest) -> Payload<Formats> {
+    Payload::new(Formats::from_config(PayloadConfig::from_req(&req)))
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl serde::Serialize for Formats {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut formats = serializer.serialize_struct("formats", 1)?;
+        formats.serialize_field("formats", &self.formats)?;
+        formats.end()
+    }
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl serde::Serialize for FormatInfo {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut format = serializer.serialize_struct("format", 3)?;
+        format.serialize_field("name", &self.name)?;
+        format.serialize_field("media_types", &self.media_types)?;
+        format.serialize_field("aliases", &self.aliases)?;
+        format.end()
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl prost::Message for Formats {
+    fn encode_raw<B: prost::bytes::BufMut>(&self, buf: &mut B) {
+        prost::encoding::message::encode_repeated(1, &self.formats, buf);
+    }
+
+    fn merge_field<B: prost::bytes::Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: prost::encoding::WireType,
+        buf: &mut B,
+        ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        match tag {
+            1 => prost::encoding::message::merge_repeated(wire_type, &mut self.formats, buf, ctx),
+            _ => prost::encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        prost::encoding::message::encoded_len_repeated(1, &self.formats)
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl prost::Message for FormatInfo {
+    fn encode_raw<B: prost::bytes::BufMut>(&self, buf: &mut B) {
+        if !self.name.is_empty() {
+            prost::encoding::string::encode(1, &self.name, buf);
+        }
+
+        prost::encoding::string::encode_repeated(2, &self.media_types, buf);
+        prost::encoding::string::encode_repeated(3, &self.aliases, buf);
+    }
+
+    fn merge_field<B: prost::bytes::Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: prost::encoding::WireType,
+        buf: &mut B,
+        ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        match tag {
+            1 => prost::encoding::string::merge(wire_type, &mut self.name, buf, ctx),
+            2 => prost::encoding::string::merge_repeated(wire_type, &mut self.media_types, buf, ctx),
+            3 => prost::encoding::string::merge_repeated(wire_type, &mut self.aliases, buf, ctx),
+            _ => prost::encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if !self.name.is_empty() {
+            len += prost::encoding::string::encoded_len(1, &self.name);
+        }
+
+        len + prost::encoding::string::encoded_len_repeated(2, &self.media_types)
+            + prost::encoding::string::encoded_len_repeated(3, &self.aliases)
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use actix_web::Responder;
+
+    #[actix_macros::test]
+    async fn test_json_formats() {
+        let config = PayloadConfig::default()
+            .grpc_web(true)
+            .alias("text/plain", ContentType::Json);
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json"))
+            .app_data(config)
+            .to_http_request();
+        let resp = formats_handler(req.clone()).await.respond_to(&req);
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            serde_json::json!({ "name": "json", "media_types": ["application/json"], "aliases": ["text/plain"] }),
+            value["formats"][0]
+        );
+        assert!(value["formats"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|format| format["name"] == "grpc-web"));
+    }
+
+    #[actix_macros::test]
+    async fn test_protobuf_formats() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+        let resp = formats_handler(req.clone()).await.respond_to(&req);
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let formats: Formats = prost::Message::decode(body).unwrap();
+        assert_eq!(Formats::from_config(&PayloadConfig::default()), formats);
+        assert!(!formats.formats.iter().any(|format| format.name == "grpc-web"));
+    }
+}
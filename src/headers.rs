@@ -1,16 +1,32 @@
+use crate::error::PayloadError;
+use actix_web::http::header::HeaderMap;
 use actix_web::HttpRequest;
+use mime::Mime;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// A format of request and response bodies.
+///
+/// The variants depend on the enabled features, and more formats may be added in the future.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[non_exhaustive]
 pub enum ContentType {
     #[cfg(feature = "json")]
     Json,
     #[cfg(feature = "protobuf")]
     Protobuf,
+    /// Protobuf with grpc-web framing, only supported if enabled with [crate::PayloadConfig::grpc_web]
+    #[cfg(feature = "protobuf")]
+    GrpcWeb,
     #[cfg(feature = "xml")]
     Xml,
-    Other,
+    /// An unsupported format, with the media type or `Accept` header value as it was sent, e.g. `text/html`.
+    /// Empty if the header was not sent
+    Other(String),
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for ContentType {
     fn default() -> Self {
         cfg_if::cfg_if! {
@@ -19,56 +35,392 @@ impl Default for ContentType {
             } else if #[cfg(feature = "protobuf")] {
                 Self::Protobuf
             } else if #[cfg(feature = "xml")] {
-                Self::Xml,
+                Self::Xml
             } else {
-                Self::Other
+                Self::Other(String::new())
             }
         }
     }
 }
 
 impl ContentType {
+    /// Whether this is [ContentType::Other], regardless of the media type it carries
+    pub fn is_other(&self) -> bool {
+        matches!(self, Self::Other(_))
+    }
+
     #[inline]
     pub fn from_request_content_type(req: &HttpRequest) -> Self {
         Self::from_request_header(req, "Content-Type")
     }
 
+    /// Get the format from the `Accept` header, using the default
+    /// format order to break ties.
     #[inline]
     pub fn from_request_accepts(req: &HttpRequest) -> Self {
-        Self::from_request_header(req, "Accept")
+        merged_header_value(req.headers(), "Accept")
+            .map(|hv_str| Self::from_accept_value(hv_str, Self::enabled()))
+            .unwrap_or(ContentType::Other(String::new()))
+    }
+
+    /// Get the format from an `Accept` header value, e.g. `application/json;q=0.5, application/protobuf`.
+    ///
+    /// The enabled format with the highest quality value is returned.
+    /// If multiple formats have the same quality value, the one which comes first in `preference` is returned.
+    /// Formats not contained in `preference` are never returned.
+    /// Malformed media ranges are skipped, see [crate::PayloadConfig::strict_accept].
+    /// If the header value contains no supported formats, [ContentType::Other] with the value is returned.
+    pub fn from_accept_value<S: AsRef<str>>(value: S, preference: &[ContentType]) -> Self {
+        Self::from_accept_value_with(value.as_ref(), preference, false)
+    }
+
+    /// Like [ContentType::from_accept_value], returning [ContentType::Other] if `strict` and the header value
+    /// contains a malformed media range
+    pub(crate) fn from_accept_value_with(value: &str, preference: &[ContentType], strict: bool) -> Self {
+        let ranges = match parse_accept(value, strict) {
+            Some(ranges) => ranges,
+            None => return ContentType::Other(value.to_string()),
+        };
+
+        Self::accepted(&ranges, preference)
+            .into_iter()
+            .next()
+            .map(|(content_type, _)| content_type)
+            .unwrap_or_else(|| ContentType::Other(value.to_string()))
+    }
+
+    /// The formats in `preference` an `Accept` header value accepts with their quality values,
+    /// the highest quality value first. Returns `None` if `strict` and the header value contains a malformed media range.
+    pub(crate) fn accepted_formats(value: &str, preference: &[ContentType], strict: bool) -> Option<Vec<(Self, f32)>> {
+        parse_accept(value, strict).map(|ranges| Self::accepted(&ranges, preference))
+    }
+
+    /// The formats in `preference` the media ranges accept with their quality values, the highest quality value first.
+    /// Formats with the same quality value keep the order of `preference`.
+    fn accepted(ranges: &[MediaRange], preference: &[ContentType]) -> Vec<(Self, f32)> {
+        let mut accepted: Vec<(Self, f32)> = preference
+            .iter()
+            .filter_map(|content_type| content_type.quality(ranges).map(|q| (content_type.clone(), q)))
+            .filter(|(_, q)| *q > 0.0)
+            .collect();
+
+        // The sort is stable, so ties keep the order of preference
+        accepted.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        accepted
     }
 
     #[inline]
     pub fn from_request_header<S: AsRef<str>>(req: &HttpRequest, name: S) -> Self {
-        if let Some(header_value) = req.headers().get(name.as_ref()) {
-            if let Ok(hv_str) = header_value.to_str() {
-                let l = hv_str.to_lowercase();
-
-                if l.starts_with("application/json") {
-                    #[cfg(feature = "json")]
-                    return Self::Json;
-                    #[cfg(not(feature = "json"))]
-                    return Self::Other;
-                } else if l.starts_with("application/protobuf") {
-                    #[cfg(feature = "protobuf")]
-                    return Self::Protobuf;
-                    #[cfg(not(feature = "protobuf"))]
-                    return Self::Other;
-                } else if l.starts_with("application/xml") || l.starts_with("text/xml") {
-                    #[cfg(feature = "xml")]
-                    return Self::Xml;
-                    #[cfg(not(feature = "xml"))]
-                    return Self::Other;
-                } else {
-                    return Self::Other;
+        req.headers()
+            .get(name.as_ref())
+            .and_then(|header_value| header_value.to_str().ok())
+            .map(Self::from_header_value)
+            .unwrap_or(ContentType::Other(String::new()))
+    }
+
+    /// Like [ContentType::from_request_content_type], but reject requests with more than one
+    /// `Content-Type` header or a `Content-Type` header which is not valid UTF-8.
+    #[inline]
+    pub fn try_from_request_content_type(req: &HttpRequest) -> Result<Self, PayloadError> {
+        Self::try_from_request_header(req, "Content-Type")
+    }
+
+    /// Like [ContentType::from_request_header], but reject requests where the header
+    /// occurs more than once, or where the header value is not valid UTF-8.
+    pub fn try_from_request_header<S: AsRef<str>>(req: &HttpRequest, name: S) -> Result<Self, PayloadError> {
+        Self::try_from_header_map(req.headers(), name.as_ref())
+    }
+
+    /// Like [ContentType::try_from_request_header], for a header map rather than a request
+    pub(crate) fn try_from_header_map(headers: &HeaderMap, name: &str) -> Result<Self, PayloadError> {
+        let mut values = headers.get_all(name);
+        let header_value = match (values.next(), values.next()) {
+            (Some(header_value), None) => header_value,
+            (Some(_), Some(_)) => return Err(PayloadError::AmbiguousContentType),
+            (None, _) => return Ok(ContentType::Other(String::new())),
+        };
+
+        let hv_str = header_value
+            .to_str()
+            .map_err(|_| PayloadError::MalformedHeader(name.to_string()))?;
+
+        Ok(Self::from_header_value(hv_str))
+    }
+
+    /// All enabled formats, in their default order.
+    /// [ContentType::GrpcWeb] is not included, as it has to be enabled with [crate::PayloadConfig::grpc_web]
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::ContentType;
+    ///
+    ///     let media_types: Vec<_> = ContentType::enabled().iter().filter_map(ContentType::media_type).collect();
+    ///     assert_eq!(cfg!(feature = "json"), media_types.contains(&"application/json"));
+    ///     assert_eq!(cfg!(feature = "xml"), media_types.contains(&"application/xml"));
+    /// ```
+    pub fn enabled() -> &'static [Self] {
+        const ENABLED: &[ContentType] = &[
+            #[cfg(feature = "json")]
+            ContentType::Json,
+            #[cfg(feature = "protobuf")]
+            ContentType::Protobuf,
+            #[cfg(feature = "xml")]
+            ContentType::Xml,
+        ];
+
+        ENABLED
+    }
+
+    /// Whether any format is enabled. This is always the case, building without any of the `json`, `protobuf`
+    /// or `xml` features fails to compile. It allows generic code to check this without repeating the feature flags.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::ContentType;
+    ///
+    ///     assert!(ContentType::any_enabled());
+    /// ```
+    pub const fn any_enabled() -> bool {
+        cfg!(any(feature = "json", feature = "protobuf", feature = "xml"))
+    }
+
+    /// The canonical media type of this format, e.g. `application/json`.
+    /// Returns `None` for [ContentType::Other]
+    pub fn media_type(&self) -> Option<&'static str> {
+        self.media_types().first().copied()
+    }
+
+    /// The short name of this format, e.g. `json` or `grpc-web`.
+    /// Parsing the name with [FromStr] results in this format again
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "json")]
+            Self::Json => "json",
+            #[cfg(feature = "protobuf")]
+            Self::Protobuf => "protobuf",
+            #[cfg(feature = "protobuf")]
+            Self::GrpcWeb => "grpc-web",
+            #[cfg(feature = "xml")]
+            Self::Xml => "xml",
+            Self::Other(_) => "other",
+        }
+    }
+
+    /// The media types which indicate this format
+    pub(crate) fn media_types(&self) -> &'static [&'static str] {
+        match self {
+            #[cfg(feature = "json")]
+            Self::Json => &["application/json"],
+            #[cfg(feature = "protobuf")]
+            Self::Protobuf => &["application/protobuf"],
+            #[cfg(feature = "protobuf")]
+            Self::GrpcWeb => &["application/grpc-web+proto", "application/grpc-web"],
+            #[cfg(feature = "xml")]
+            Self::Xml => &["application/xml", "text/xml"],
+            Self::Other(_) => &[],
+        }
+    }
+
+    /// The quality value the media ranges assign to this format.
+    /// The most specific matching range is used, `None` is returned if no range matches.
+    fn quality(&self, ranges: &[MediaRange]) -> Option<f32> {
+        let mut best: Option<(u8, f32)> = None;
+        for media_type in self.media_types() {
+            for range in ranges {
+                let specificity = match range.specificity(media_type) {
+                    Some(specificity) => specificity,
+                    None => continue,
+                };
+
+                if best.map(|(best_specificity, _)| specificity > best_specificity).unwrap_or(true) {
+                    best = Some((specificity, range.q));
                 }
             }
         }
 
-        ContentType::Other
+        best.map(|(_, q)| q)
+    }
+
+    /// Get the format from a header value, e.g. `application/json; charset=UTF-8`
+    pub fn from_header_value<S: AsRef<str>>(value: S) -> Self {
+        let value = value.as_ref();
+        let l = value.to_lowercase();
+        let essence = l.split(';').next().unwrap_or_default().trim();
+
+        if essence.eq("application/grpc-web") || essence.eq("application/grpc-web+proto") {
+            #[cfg(feature = "protobuf")]
+            return Self::GrpcWeb;
+            #[cfg(not(feature = "protobuf"))]
+            return Self::Other(value.trim().to_string());
+        } else if essence.eq("application/json") {
+            #[cfg(feature = "json")]
+            return Self::Json;
+            #[cfg(not(feature = "json"))]
+            return Self::Other(value.trim().to_string());
+        } else if essence.eq("application/protobuf") {
+            #[cfg(feature = "protobuf")]
+            return Self::Protobuf;
+            #[cfg(not(feature = "protobuf"))]
+            return Self::Other(value.trim().to_string());
+        } else if essence.eq("application/xml") || essence.eq("text/xml") {
+            #[cfg(feature = "xml")]
+            return Self::Xml;
+            #[cfg(not(feature = "xml"))]
+            return Self::Other(value.trim().to_string());
+        } else {
+            Self::Other(value.trim().to_string())
+        }
+    }
+}
+
+impl ContentType {
+    /// The canonical media type of this format, as [Mime].
+    /// Returns `None` for [ContentType::Other]
+    pub fn to_mime(&self) -> Option<Mime> {
+        self.media_type()
+            .map(|media_type| media_type.parse().expect("Valid media type"))
+    }
+}
+
+impl From<&Mime> for ContentType {
+    fn from(mime: &Mime) -> Self {
+        Self::from_header_value(mime.essence_str())
+    }
+}
+
+impl From<Mime> for ContentType {
+    fn from(mime: Mime) -> Self {
+        Self::from(&mime)
+    }
+}
+
+impl FromStr for ContentType {
+    type Err = Infallible;
+
+    /// Parse a media type or the short name of a format, see [ContentType::as_str].
+    /// Unsupported media types result in [ContentType::Other]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const NAMED: &[ContentType] = &[
+            #[cfg(feature = "json")]
+            ContentType::Json,
+            #[cfg(feature = "protobuf")]
+            ContentType::Protobuf,
+            #[cfg(feature = "protobuf")]
+            ContentType::GrpcWeb,
+            #[cfg(feature = "xml")]
+            ContentType::Xml,
+        ];
+
+        let by_name = NAMED
+            .iter()
+            .find(|content_type| content_type.as_str().eq_ignore_ascii_case(s.trim()));
+
+        Ok(by_name.cloned().unwrap_or_else(|| Self::from_header_value(s)))
+    }
+}
+
+impl fmt::Display for ContentType {
+    /// Formats the canonical media type of the format, e.g. `application/json`.
+    /// [ContentType::Other] formats the media type as it was sent, or `none` if none was sent
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.media_type(), self) {
+            (Some(media_type), _) => f.write_str(media_type),
+            (None, Self::Other(media_type)) if !media_type.is_empty() => f.write_str(media_type),
+            (None, _) => f.write_str("none"),
+        }
+    }
+}
+
+/// A single media range in an `Accept` header
+#[derive(Debug, PartialEq, Clone)]
+struct MediaRange {
+    /// The lowercase media type, e.g. `application/json`, `application/*` or `*/*`
+    media_type: String,
+    /// The quality value, between `0.0` and `1.0`
+    q: f32,
+}
+
+impl MediaRange {
+    /// How specific this range matches the media type.
+    /// `2` for an exact match, `1` for a subtype wildcard and `0` for `*/*`
+    fn specificity(&self, media_type: &str) -> Option<u8> {
+        if self.media_type.eq(media_type) {
+            return Some(2);
+        }
+
+        let (range_type, range_subtype) = self.media_type.split_once('/')?;
+        let (main_type, _) = media_type.split_once('/')?;
+
+        match (range_type, range_subtype) {
+            ("*", "*") => Some(0),
+            (range_type, "*") if range_type.eq(main_type) => Some(1),
+            _ => None,
+        }
     }
 }
 
+/// All values of a header which may occur multiple times, joined into a single comma separated list, as per RFC 7230.
+/// Values which are not valid UTF-8 are skipped. Returns `None` if the header is not present
+pub(crate) fn merged_header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .filter_map(|header_value| header_value.to_str().ok())
+        .collect();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(", "))
+    }
+}
+
+/// Whether an `Accept` header value explicitly accepts `media_type`, without wildcards
+#[cfg(all(feature = "problem-details", feature = "json"))]
+pub(crate) fn accepts_media_type(value: &str, media_type: &str) -> bool {
+    parse_accept(value, false)
+        .map(|ranges| ranges.iter().any(|range| range.media_type.eq(media_type) && range.q > 0.0))
+        .unwrap_or(false)
+}
+
+/// Parse an `Accept` header value into its media ranges.
+/// Malformed media ranges are skipped, unless `strict`, in which case `None` is returned
+fn parse_accept(value: &str, strict: bool) -> Option<Vec<MediaRange>> {
+    let mut ranges = Vec::new();
+    for entry in value.split(',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+
+        match parse_media_range(entry) {
+            Some(range) => ranges.push(range),
+            None if strict => return None,
+            None => {}
+        }
+    }
+
+    Some(ranges)
+}
+
+/// Parse a single media range of an `Accept` header value, returning `None` if it is malformed
+fn parse_media_range(entry: &str) -> Option<MediaRange> {
+    let mut parts = entry.split(';');
+    let media_type = parts.next()?.trim().to_lowercase();
+    match media_type.split_once('/') {
+        Some((main_type, subtype)) if !main_type.is_empty() && !subtype.is_empty() => {}
+        _ => return None,
+    }
+
+    let mut q = 1.0;
+    for param in parts {
+        let (name, value) = param.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("q") {
+            q = value.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?;
+        }
+    }
+
+    Some(MediaRange { media_type, q })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -159,8 +511,211 @@ mod test {
             .to_http_request();
 
         assert_eq!(
-            ContentType::Other,
+            ContentType::Other("foo/bar".to_string()),
             ContentType::from_request_content_type(&req)
         );
     }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_try_json() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "application/json"))
+            .to_http_request();
+
+        assert_eq!(
+            ContentType::Json,
+            ContentType::try_from_request_content_type(&req).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_try_missing() {
+        let req = TestRequest::get().to_http_request();
+
+        assert_eq!(
+            ContentType::Other(String::new()),
+            ContentType::try_from_request_content_type(&req).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_try_duplicate() {
+        let req = TestRequest::get()
+            .append_header(("Content-Type", "application/json"))
+            .append_header(("Content-Type", "application/protobuf"))
+            .to_http_request();
+
+        assert!(matches!(
+            ContentType::try_from_request_content_type(&req),
+            Err(PayloadError::AmbiguousContentType)
+        ));
+    }
+
+    #[test]
+    fn test_try_non_utf8() {
+        let req = TestRequest::get()
+            .insert_header((
+                "Content-Type",
+                actix_web::http::header::HeaderValue::from_bytes(b"application/json\xff").unwrap(),
+            ))
+            .to_http_request();
+
+        assert!(matches!(
+            ContentType::try_from_request_content_type(&req),
+            Err(PayloadError::MalformedHeader(name)) if name == "Content-Type"
+        ));
+    }
+
+    #[test]
+    fn test_other_display() {
+        let content_type = ContentType::from_header_value(" text/html; charset=utf-8");
+        assert!(content_type.is_other());
+        assert_eq!("text/html; charset=utf-8", content_type.to_string());
+        assert_eq!("none", ContentType::Other(String::new()).to_string());
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_accept_quality() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json;q=0.5, application/protobuf;q=0.9"))
+            .to_http_request();
+
+        assert_eq!(ContentType::Protobuf, ContentType::from_request_accepts(&req));
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_accept_preference_tie() {
+        let preference = [ContentType::Protobuf, ContentType::Json];
+
+        assert_eq!(
+            ContentType::Protobuf,
+            ContentType::from_accept_value("application/json, application/protobuf", &preference)
+        );
+        assert_eq!(
+            ContentType::Protobuf,
+            ContentType::from_accept_value("*/*", &preference)
+        );
+        assert_eq!(
+            ContentType::Json,
+            ContentType::from_accept_value("application/*;q=0.5, application/json", &preference)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_accept_rejected() {
+        assert_eq!(
+            ContentType::Other("application/json;q=0".to_string()),
+            ContentType::from_accept_value("application/json;q=0", ContentType::enabled())
+        );
+        assert_eq!(
+            ContentType::Other("text/html".to_string()),
+            ContentType::from_accept_value("text/html", ContentType::enabled())
+        );
+    }
+
+    #[test]
+    fn test_accept_malformed() {
+        assert_eq!(
+            ContentType::Other("application/json;q=foo".to_string()),
+            ContentType::from_accept_value("application/json;q=foo", ContentType::enabled())
+        );
+        assert_eq!(
+            ContentType::Other("json".to_string()),
+            ContentType::from_accept_value("json", ContentType::enabled())
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_accept_malformed_entries() {
+        let preference = [ContentType::Json, ContentType::Protobuf];
+        assert_eq!(ContentType::Json, ContentType::from_accept_value("application/json, ;;;", &preference));
+        assert_eq!(
+            ContentType::Protobuf,
+            ContentType::from_accept_value("text/html;q=x, application/protobuf, json, application/json;q=0.5", &preference)
+        );
+
+        assert_eq!(
+            ContentType::Other("application/json, ;;;".to_string()),
+            ContentType::from_accept_value_with("application/json, ;;;", &preference, true)
+        );
+        assert_eq!(
+            ContentType::Json,
+            ContentType::from_accept_value_with("application/json, text/html", &preference, true)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_grpc_web() {
+        assert_eq!(ContentType::GrpcWeb, ContentType::from_header_value("application/grpc-web+proto"));
+        assert_eq!(ContentType::GrpcWeb, ContentType::from_header_value("application/grpc-web"));
+        assert_eq!(
+            ContentType::Other("application/grpc-web-text".to_string()),
+            ContentType::from_header_value("application/grpc-web-text")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_essence_exact() {
+        assert_eq!(ContentType::Json, ContentType::from_header_value("Application/JSON ; charset=utf-8"));
+        for media_type in ["application/json-seq", "application/jsonx", "application/json-patch+json"] {
+            assert_eq!(ContentType::Other(media_type.to_string()), ContentType::from_header_value(media_type));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_mime() {
+        assert_eq!(Some(mime::APPLICATION_JSON), ContentType::Json.to_mime());
+        assert_eq!(ContentType::Json, ContentType::from(mime::APPLICATION_JSON));
+        assert_eq!(
+            ContentType::Json,
+            ContentType::from("application/json; charset=utf-8".parse::<Mime>().unwrap())
+        );
+        assert_eq!(ContentType::Other("text/html".to_string()), ContentType::from(mime::TEXT_HTML));
+        assert_eq!(None, ContentType::Other(String::new()).to_mime());
+    }
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_str() {
+        assert_eq!(ContentType::Protobuf, "application/protobuf".parse().unwrap());
+        assert_eq!("application/protobuf", ContentType::Protobuf.to_string());
+        assert_eq!(
+            Some(ContentType::Protobuf),
+            ContentType::Protobuf.to_mime().map(ContentType::from)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_names() {
+        assert_eq!(ContentType::GrpcWeb, "grpc-web".parse().unwrap());
+        assert_eq!(ContentType::Protobuf, ContentType::Protobuf.as_str().parse().unwrap());
+        assert_eq!(ContentType::Other("html".to_string()), "html".parse().unwrap());
+        assert_eq!(Some("application/grpc-web+proto"), ContentType::GrpcWeb.media_type());
+        assert!(!ContentType::enabled().contains(&ContentType::GrpcWeb));
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_multiple_accept_headers() {
+        // As sent by a proxy appending its own Accept line
+        let req = TestRequest::get()
+            .append_header(("Accept", "application/json;q=0.5"))
+            .append_header(("Accept", "application/protobuf"))
+            .to_http_request();
+
+        assert_eq!(ContentType::Protobuf, ContentType::from_request_accepts(&req));
+        assert_eq!(
+            Some("application/json;q=0.5, application/protobuf".to_string()),
+            merged_header_value(req.headers(), "Accept")
+        );
+    }
 }
@@ -0,0 +1,96 @@
+//! Capturing idempotency keys of requests, see [crate::PayloadConfig::idempotency_key].
+
+use crate::{checksum, PayloadConfig, PayloadError};
+
+use actix_web::http::header::HeaderName;
+use actix_web::{HttpMessage, HttpRequest};
+
+/// The `Idempotency-Key` header, as proposed by the IETF HTTPAPI working group
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// The idempotency key of a request, together with the SHA-256 digest of its raw body.
+///
+/// Stored in the request extensions while the body is read, if [PayloadConfig::idempotency_key] is configured
+/// and the request has the header. Services can use it to respond to retried requests with the stored response,
+/// and to reject requests reusing a key with a different body.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{IdempotencyKey, Payload};
+///     use actix_web::{HttpMessage, HttpRequest};
+///
+///     async fn create(req: HttpRequest, payload: Payload<String>) -> Payload<String> {
+///         if let Some(key) = req.extensions().get::<IdempotencyKey>() {
+///             println!("{} {:x?}", key.key(), key.body_hash());
+///         }
+///         payload
+///     }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct IdempotencyKey {
+    key: String,
+    body_hash: [u8; 32],
+}
+
+impl IdempotencyKey {
+    /// The value of the header
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The SHA-256 digest of the raw request body, before any transformation
+    pub fn body_hash(&self) -> &[u8; 32] {
+        &self.body_hash
+    }
+}
+
+/// Store the idempotency key of the request in its extensions, if configured and present.
+/// A header which is not visible ASCII is rejected
+pub(crate) fn capture(req: &HttpRequest, body: &[u8], config: &PayloadConfig) -> Result<(), PayloadError> {
+    let Some(name) = &config.idempotency_key else {
+        return Ok(());
+    };
+    let Some(header_value) = req.headers().get(name) else {
+        return Ok(());
+    };
+
+    let key = header_value
+        .to_str()
+        .map_err(|_| PayloadError::MalformedHeader(name.to_string()))?;
+    req.extensions_mut().insert(IdempotencyKey {
+        key: key.to_string(),
+        body_hash: checksum::sha256(body),
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_capture() {
+        let config = PayloadConfig::default().idempotency_key(IDEMPOTENCY_KEY);
+        let req = TestRequest::post()
+            .insert_header(("Idempotency-Key", "abc"))
+            .to_http_request();
+
+        capture(&req, b"foo", &config).unwrap();
+        let extensions = req.extensions();
+        let key = extensions.get::<IdempotencyKey>().unwrap();
+        assert_eq!("abc", key.key());
+        assert_eq!(&checksum::sha256(b"foo"), key.body_hash());
+    }
+
+    #[test]
+    fn test_not_configured() {
+        let req = TestRequest::post()
+            .insert_header(("Idempotency-Key", "abc"))
+            .to_http_request();
+
+        capture(&req, b"foo", &PayloadConfig::default()).unwrap();
+        assert!(req.extensions().get::<IdempotencyKey>().is_none());
+    }
+}
@@ -0,0 +1,130 @@
+//! The JSON implementation used by all JSON (de)serialization of this crate.
+//!
+//! Everything depending on a particular JSON library lives behind [JsonBackend],
+//! so switching to a different implementation only touches this module.
+
+use crate::naming::{self, CamelCase};
+use crate::proto_json;
+use crate::{DeserializeError, JsonFormat, JsonNaming, SerializeError};
+
+use std::io;
+
+/// A JSON implementation
+pub(crate) trait JsonBackend {
+    /// Serialize `value` into `writer`, laid out according to `format`, with fields named according to `naming`
+    fn to_writer<W: io::Write, T: serde::Serialize + ?Sized>(
+        writer: W,
+        value: &T,
+        format: &JsonFormat,
+        naming: JsonNaming,
+    ) -> Result<(), SerializeError>;
+
+    /// Deserialize a complete JSON text, borrowing from `body` where `T` allows it.
+    /// With `deny_unknown_fields`, fields which are not part of `T` are rejected, see [crate::PayloadConfig::deny_unknown_fields].
+    /// The fields are expected to be named according to `naming`.
+    /// With `int64_strings`, 64 bit integers may be strings, see [crate::PayloadConfig::int64_as_string]
+    fn from_slice<'de, T: serde::Deserialize<'de>>(
+        body: &'de [u8],
+        deny_unknown_fields: bool,
+        naming: JsonNaming,
+        int64_strings: bool,
+    ) -> Result<T, DeserializeError>;
+}
+
+/// The JSON implementation selected through the enabled features.
+/// `serde_json` is the only implementation for now, simd-json and sonic-rs are not available to the build
+pub(crate) type Backend = SerdeJson;
+
+/// JSON through `serde_json`
+pub(crate) struct SerdeJson;
+
+impl JsonBackend for SerdeJson {
+    fn to_writer<W: io::Write, T: serde::Serialize + ?Sized>(
+        writer: W,
+        value: &T,
+        format: &JsonFormat,
+        naming: JsonNaming,
+    ) -> Result<(), SerializeError> {
+        match naming {
+            JsonNaming::Preserve => write_formatted(writer, value, format),
+            JsonNaming::CamelCase => write_formatted(writer, &CamelCase(value), format),
+        }
+    }
+
+    fn from_slice<'de, T: serde::Deserialize<'de>>(
+        body: &'de [u8],
+        deny_unknown_fields: bool,
+        naming: JsonNaming,
+        int64_strings: bool,
+    ) -> Result<T, DeserializeError> {
+        let mut deserializer = serde_json::Deserializer::from_slice(body);
+        let value = if int64_strings {
+            deserialize(proto_json::int64_strings(&mut deserializer), deny_unknown_fields, naming)
+        } else {
+            deserialize(&mut deserializer, deny_unknown_fields, naming)
+        };
+
+        match value.and_then(|value| deserializer.end().map(|_| value)) {
+            Ok(value) => Ok(value),
+            Err(e) => Err(DeserializeError::from(e).in_field(error_path::<T>(body, deny_unknown_fields, naming, int64_strings))),
+        }
+    }
+}
+
+/// The path of the value deserializing `body` fails in, by deserializing it again while keeping track of the path
+fn error_path<'de, T: serde::Deserialize<'de>>(
+    body: &'de [u8],
+    deny_unknown_fields: bool,
+    naming: JsonNaming,
+    int64_strings: bool,
+) -> Option<String> {
+    use crate::strict::error_path;
+
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    match (int64_strings, naming) {
+        (false, JsonNaming::Preserve) => error_path::<T, _>(&mut deserializer, deny_unknown_fields),
+        (false, JsonNaming::CamelCase) => error_path::<T, _>(naming::camel_case(&mut deserializer), deny_unknown_fields),
+        (true, JsonNaming::Preserve) => {
+            error_path::<T, _>(proto_json::int64_strings(&mut deserializer), deny_unknown_fields)
+        }
+        (true, JsonNaming::CamelCase) => error_path::<T, _>(
+            naming::camel_case(proto_json::int64_strings(&mut deserializer)),
+            deny_unknown_fields,
+        ),
+    }
+}
+
+/// Deserialize a value, rejecting unknown fields with `deny_unknown_fields`, with fields named according to `naming`
+fn deserialize<'de, T: serde::Deserialize<'de>, D: serde::Deserializer<'de>>(
+    deserializer: D,
+    deny_unknown_fields: bool,
+    naming: JsonNaming,
+) -> Result<T, D::Error> {
+    match (deny_unknown_fields, naming) {
+        (false, JsonNaming::Preserve) => T::deserialize(deserializer),
+        (true, JsonNaming::Preserve) => crate::strict::deserialize(deserializer),
+        (false, JsonNaming::CamelCase) => T::deserialize(naming::camel_case(deserializer)),
+        (true, JsonNaming::CamelCase) => crate::strict::deserialize(naming::camel_case(deserializer)),
+    }
+}
+
+/// Serialize `value` into `writer` through `serde_json`, laid out according to `format`
+fn write_formatted<W: io::Write, T: serde::Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+    format: &JsonFormat,
+) -> Result<(), SerializeError> {
+    use serde_json::ser::{PrettyFormatter, Serializer};
+
+    match format {
+        JsonFormat::Pretty => serde_json::to_writer_pretty(writer, value)?,
+        JsonFormat::Compact => serde_json::to_writer(writer, value)?,
+        JsonFormat::Indent(indent) => {
+            let indent = vec![b' '; *indent];
+            let mut serializer = Serializer::with_formatter(writer, PrettyFormatter::with_indent(&indent));
+            value.serialize(&mut serializer)?;
+        }
+    }
+
+    Ok(())
+}
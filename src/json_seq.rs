@@ -0,0 +1,208 @@
+//! JSON text sequences, as defined by [RFC 7464](https://www.rfc-editor.org/rfc/rfc7464),
+//! under the `application/json-seq` media type.
+
+use crate::json_backend::{Backend, JsonBackend};
+use crate::negotiated_error::negotiated_extract;
+use crate::{body, ContentType, JsonFormat, JsonNaming, PayloadConfig, PayloadError, SerializeError};
+
+use actix_web::body::{BodyStream, BoxBody};
+use actix_web::dev::Payload as DevPayload;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder};
+
+use futures_util::{Stream, StreamExt};
+
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+
+/// The media type of JSON text sequences
+const JSON_SEQ: &str = "application/json-seq";
+/// The record separator, which precedes every record
+const RS: u8 = 0x1E;
+
+/// A sequence of JSON records, extracted from and responded with `application/json-seq`.
+///
+/// Requests with another `Content-Type` are rejected with a HTTP 400 error.
+/// Each record is deserialized independently, the first record which fails to deserialize rejects the request.
+/// Responses contain one compact JSON record per item.
+///
+/// Use [JsonSeqStream] to respond with records produced incrementally.
+#[derive(Debug)]
+pub struct JsonSeq<T>(pub Vec<T>);
+
+impl<T> Deref for JsonSeq<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for JsonSeq<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Whether the `Content-Type` of the request is `application/json-seq`
+fn is_json_seq(req: &HttpRequest) -> bool {
+    req.headers()
+        .get("Content-Type")
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|hv_str| hv_str.split(';').next())
+        .map(|essence| essence.trim().eq_ignore_ascii_case(JSON_SEQ))
+        .unwrap_or(false)
+}
+
+/// Split a JSON text sequence into its records, skipping empty records
+fn split_records(body: &Bytes) -> impl Iterator<Item = Bytes> + '_ {
+    body.split(|b| *b == RS)
+        .map(|record| body.slice_ref(trim_whitespace(record)))
+        .filter(|record| !record.is_empty())
+}
+
+/// Strip leading and trailing ASCII whitespace, like `<[u8]>::trim_ascii`, which needs Rust 1.80
+fn trim_whitespace(record: &[u8]) -> &[u8] {
+    let start = record.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(record.len());
+    let end = record.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+    &record[start..end]
+}
+
+/// Serialize one record, consisting of the record separator, the compact JSON text and a line feed
+fn serialize_record<T: serde::Serialize>(value: &T, naming: JsonNaming) -> Result<Bytes, SerializeError> {
+    let mut record = vec![RS];
+    Backend::to_writer(&mut record, value, &JsonFormat::Compact, naming)?;
+    record.push(b'\n');
+    Ok(record.into())
+}
+
+impl<T: 'static + serde::de::DeserializeOwned> FromRequest for JsonSeq<T> {
+    type Error = PayloadError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut DevPayload) -> Self::Future {
+        let req = req.clone();
+        let payload = payload.take();
+
+        negotiated_extract(req.clone(), async move {
+            if !is_json_seq(&req) {
+                return Err(PayloadError::InvalidContentType(ContentType::from_request_content_type(&req)));
+            }
+
+            let config = PayloadConfig::from_req(&req);
+            let payload_bytes = body::read_body_with(&req, payload, config).await?;
+
+            let records: Result<Vec<T>, _> = split_records(&payload_bytes)
+                .map(|record| Backend::from_slice(&record, false, config.json_naming, false))
+                .collect();
+
+            records.map(Self).map_err(|error| {
+                config.report_error(PayloadError::Deserialize {
+                    error,
+                    body: payload_bytes,
+                    content_type: ContentType::Json,
+                })
+            })
+        })
+    }
+}
+
+impl<T: serde::Serialize> Responder for JsonSeq<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let naming = PayloadConfig::from_req(req).json_naming;
+        let mut body = Vec::new();
+        for value in &self.0 {
+            match serialize_record(value, naming) {
+                Ok(record) => body.extend_from_slice(&record),
+                Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+            }
+        }
+
+        HttpResponse::Ok()
+            .insert_header(("Content-Type", JSON_SEQ))
+            .body(body)
+    }
+}
+
+/// Respond with a JSON text sequence of the items of a stream, serializing every item as it is produced.
+///
+/// If an item fails to serialize, the response body is aborted.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::JsonSeqStream;
+///
+///     async fn export() -> JsonSeqStream<impl futures_util::Stream<Item = u32>> {
+///         JsonSeqStream(futures_util::stream::iter(0..1000))
+///     }
+/// ```
+pub struct JsonSeqStream<S>(pub S);
+
+impl<T, S> Responder for JsonSeqStream<S>
+where
+    T: serde::Serialize,
+    S: Stream<Item = T> + 'static,
+{
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let naming = PayloadConfig::from_req(req).json_naming;
+        let records = self.0.map(move |value| serialize_record(&value, naming));
+
+        HttpResponse::Ok()
+            .insert_header(("Content-Type", JSON_SEQ))
+            .body(BodyStream::new(records))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App};
+
+    async fn responder(payload: JsonSeq<u32>) -> JsonSeq<u32> {
+        payload
+    }
+
+    #[actix_macros::test]
+    async fn test_roundtrip() {
+        let app = init_service(App::new().route("/", web::post().to(responder))).await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json-seq"))
+            .set_payload(b"\x1e1\n\x1e 2\n\x1e\n\x1e3".to_vec())
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!("application/json-seq", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&b"\x1e1\n\x1e2\n\x1e3\n"[..], &body[..]);
+    }
+
+    #[actix_macros::test]
+    async fn test_invalid_record() {
+        let app = init_service(App::new().route("/", web::post().to(responder))).await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json-seq"))
+            .set_payload(b"\x1e1\n\x1efoo\n".to_vec())
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+
+    #[actix_macros::test]
+    async fn test_stream() {
+        let req = TestRequest::get().to_http_request();
+        let resp = JsonSeqStream(futures_util::stream::iter(["a", "b"])).respond_to(&req);
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&b"\x1e\"a\"\n\x1e\"b\"\n"[..], &body[..]);
+    }
+}
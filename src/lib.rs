@@ -6,6 +6,13 @@
 //! - Protobuf
 //!
 //! All formats can be enabled with feature flags. At least one format should be enabled to make this library useful.
+//! The `derive` feature re-exports the derive macros in the [prelude], and adds the `payload_type!` macro.
+//! The `problem-details` feature renders errors as RFC 7807 problem details, see `ProblemDetails`.
+//! The `descriptor` feature adds `DescriptorSet`, serving the protobuf descriptors of the payloads.
+//! The `http` feature converts a `Rejection` into a response of the `http` crate, for servers other than actix.
+//! The `timestamps` feature adds `Timestamp`, encoded as `google.protobuf.Timestamp` in protobuf and as RFC 3339 strings otherwise.
+//! The `jsonschema` feature validates JSON request bodies against a `JsonSchema` registered for their type before deserializing them.
+//! The `tracing` feature logs the causes of errors redacted with `ErrorVerbosity::Redacted` through `tracing` instead of `log`.
 //!
 //! ### Example
 //! ```
@@ -26,33 +33,261 @@
 //!     }
 //! ```
 
-use crate::error::PayloadError;
+pub use crate::accepted::{AcceptedFormat, AcceptedFormats};
+pub use crate::batch::BatchPayload;
+#[cfg(any(feature = "json", feature = "xml"))]
+pub use crate::borrowed::BorrowedPayload;
+pub use crate::cache::{CacheHandle, ResponseCache, ResponseCacheMiddleware};
+pub use crate::capture::BodyCapture;
+pub use crate::bridge::{FormatBridge, FormatBridgeMiddleware};
+pub use crate::config::{PayloadConfig, StatusClass};
+pub use crate::created::Created;
+#[cfg(feature = "descriptor")]
+pub use crate::descriptor::DescriptorSet;
+pub use crate::download::Downloadable;
+#[cfg(feature = "json")]
+pub use crate::config::{JsonFormat, JsonNaming};
+pub use crate::envelope::ResponseMeta;
+pub use crate::error::{ErrorLocation, PayloadError};
+pub use crate::erased::ErasedPayload;
+pub use crate::error_id::{ErrorId, ErrorVerbosity};
+pub use crate::error_mapping::DeserializeErrorKind;
+pub use crate::expect::ExpectContinue;
+pub use crate::extract::PayloadFuture;
+pub use crate::field_mask::FieldMask;
+pub use crate::formats::{formats_handler, FormatInfo, Formats};
+#[cfg(feature = "protobuf")]
+pub use crate::error::ProtobufError;
 pub use crate::headers::ContentType;
+pub use crate::idempotency::{IdempotencyKey, IDEMPOTENCY_KEY};
+#[cfg(feature = "json")]
+pub use crate::json_seq::{JsonSeq, JsonSeqStream};
+pub use crate::links::Link;
+pub use crate::mirror::{Mirrored, MirroredRequest};
+pub use crate::negotiate::{current_format, ForcedFormat, Negotiation, NegotiationRule, Negotiator, UnsupportedAccept};
+pub use crate::negotiated_error::{render_negotiated_error, ErrorBody};
+pub use crate::no_content::{Empty, MaybePayload};
+#[cfg(feature = "json")]
+pub use crate::patch::{Patch, PatchError, PatchOperation, PatchPayload, ACCEPT_PATCH};
+pub use crate::payload_file::{Encoding, MultiEncoded, PayloadFile};
+#[cfg(any(feature = "json", feature = "xml"))]
+pub use crate::proto_json::{
+    deserialize_proto_enum, deserialize_proto_optional, serialize_proto_enum, serialize_proto_optional, ProtobufEnum,
+};
+#[cfg(feature = "problem-details")]
+pub use crate::problem::ProblemDetails;
+pub use crate::rejection::{Rejection, RejectionKind};
+pub use crate::restricted::{AnyFormat, FormatSet};
+#[cfg(feature = "jsonschema")]
+pub use crate::schema::{JsonSchema, SchemaError, SchemaViolation};
+#[cfg(feature = "timestamps")]
+pub use crate::timestamp::{Timestamp, TimestampError};
+#[cfg(feature = "json")]
+pub use crate::restricted::JsonOnly;
+#[cfg(feature = "protobuf")]
+pub use crate::restricted::ProtoOnly;
+#[cfg(feature = "xml")]
+pub use crate::restricted::XmlOnly;
+pub use crate::with_meta::{FromHeaders, WithMeta};
+#[cfg(feature = "json")]
+pub use crate::wrappers::Json;
+#[cfg(feature = "protobuf")]
+pub use crate::wrappers::Proto;
+#[cfg(feature = "xml")]
+pub use crate::wrappers::Xml;
+#[cfg(feature = "xml")]
+pub use crate::xml_mapping::XmlMapping;
+#[cfg(feature = "protobuf")]
+pub use prost;
+#[cfg(any(feature = "json", feature = "xml"))]
+pub use serde;
+
+#[cfg(not(any(feature = "json", feature = "protobuf", feature = "xml")))]
+compile_error!(
+    "actix-multiresponse requires at least one format feature, enable one of the `json`, `protobuf` or `xml` features"
+);
 
-use actix_web::body::BoxBody;
-use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder};
+use actix_web::body::{BoxBody, MessageBody, SizedStream};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder, Responder};
 use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
 
+use std::convert::Infallible;
+use std::fmt::{self, Display};
 use std::future::Future;
+use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::pin::Pin;
 
-use futures_util::StreamExt;
 use thiserror::Error;
 
+mod accepted;
+mod batch;
+mod body;
+#[cfg(feature = "build-helper")]
+pub mod build;
+#[cfg(any(feature = "json", feature = "xml"))]
+mod borrowed;
+mod bridge;
+mod cache;
+mod capture;
+mod checksum;
+mod codec;
+#[cfg(feature = "testing")]
+pub mod conformance;
+mod config;
+mod created;
+#[cfg(feature = "descriptor")]
+mod descriptor;
+mod download;
+mod envelope;
+mod erased;
 mod error;
+mod error_id;
+mod error_mapping;
+mod expect;
+mod extract;
+mod field_mask;
+mod formats;
 mod headers;
+mod idempotency;
+#[cfg(feature = "json")]
+mod json_backend;
+#[cfg(feature = "json")]
+mod json_seq;
+mod links;
+mod macros;
+mod mirror;
+#[cfg(feature = "json")]
+mod naming;
+mod negotiate;
+mod negotiated_error;
+mod no_content;
+#[cfg(feature = "json")]
+mod patch;
+mod payload_file;
+mod pool;
+#[cfg(any(feature = "json", feature = "xml"))]
+mod proto_json;
+pub mod prelude;
+#[cfg(feature = "problem-details")]
+mod problem;
+mod rejection;
+mod restricted;
+#[cfg(feature = "jsonschema")]
+mod schema;
+#[cfg(any(feature = "json", feature = "xml"))]
+mod strict;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "timestamps")]
+mod timestamp;
+mod with_meta;
+mod wrappers;
+#[cfg(feature = "xml")]
+mod xml_mapping;
 
+/// Protobuf encoding and decoding of a payload.
+///
+/// This is implemented for every [prost::Message]. Types which can't derive [prost::Message],
+/// like generic envelopes, can implement it themselves, usually by delegating to the inner value:
+/// ```
+///     use actix_multiresponse::{DeserializeError, ProtobufSupport, SerializeError};
+///     use serde_derive::{Deserialize, Serialize};
+///
+///     #[derive(Deserialize, Serialize)]
+///     struct Envelope<T> {
+///         data: T,
+///     }
+///
+///     impl<T: ProtobufSupport> ProtobufSupport for Envelope<T> {
+///         fn encode_protobuf(&self) -> Result<Vec<u8>, SerializeError> {
+///             self.data.encode_protobuf()
+///         }
+///
+///         fn decode_protobuf(body: &[u8]) -> Result<Self, DeserializeError> {
+///             Ok(Self { data: T::decode_protobuf(body)? })
+///         }
+///     }
+/// ```
 #[cfg(feature = "protobuf")]
-pub trait ProtobufSupport: prost::Message {}
+pub trait ProtobufSupport {
+    /// Encode the value to protobuf
+    fn encode_protobuf(&self) -> Result<Vec<u8>, SerializeError>;
+
+    /// Encode the value to protobuf deterministically, used with [PayloadConfig::protobuf_deterministic].
+    /// By default this calls [ProtobufSupport::encode_protobuf].
+    ///
+    /// prost encodes the fields of a message in tag order, so every [prost::Message] is encoded deterministically,
+    /// as long as its map fields are `btree_map`s rather than `hash_map`s.
+    /// Types implementing this trait themselves should override this if their encoding is not deterministic.
+    fn encode_protobuf_deterministic(&self) -> Result<Vec<u8>, SerializeError> {
+        self.encode_protobuf()
+    }
+
+    /// Encode only the fields selected by `mask` to protobuf, used with [PayloadConfig::field_masks].
+    ///
+    /// prost messages have no reflection, so types which support field masks implement this themselves,
+    /// usually by clearing the fields which are not selected on a clone before encoding it.
+    /// By default this fails with [SerializeError::MaskUnsupported], so requests with a field mask are answered
+    /// with a HTTP 406 rather than with all fields.
+    fn encode_protobuf_masked(&self, _mask: &FieldMask) -> Result<Vec<u8>, SerializeError> {
+        Err(SerializeError::MaskUnsupported)
+    }
+
+    /// Decode a value from protobuf
+    fn decode_protobuf(body: &[u8]) -> Result<Self, DeserializeError>
+    where
+        Self: Sized;
+
+    /// Decode a value from protobuf in a [Bytes] buffer.
+    /// By default this calls [ProtobufSupport::decode_protobuf].
+    fn decode_protobuf_bytes(body: Bytes) -> Result<Self, DeserializeError>
+    where
+        Self: Sized,
+    {
+        Self::decode_protobuf(&body)
+    }
+
+    /// Whether the type can be represented in protobuf, true by default. Types declared with [serde_only] cannot,
+    /// [Payload] then rejects protobuf request bodies with a HTTP 415 error, and responds in another format
+    /// or with a HTTP 406 error if the client only accepts protobuf.
+    fn supports_protobuf() -> bool
+    where
+        Self: Sized,
+    {
+        true
+    }
+}
 #[cfg(not(feature = "protobuf"))]
 pub trait ProtobufSupport {}
 
 #[cfg(feature = "protobuf")]
-impl<T: prost::Message> ProtobufSupport for T {}
+impl<T: prost::Message + Default> ProtobufSupport for T {
+    fn encode_protobuf(&self) -> Result<Vec<u8>, SerializeError> {
+        codec::serialize_protobuf(self)
+    }
+
+    fn decode_protobuf(body: &[u8]) -> Result<Self, DeserializeError> {
+        codec::deserialize_protobuf(body)
+    }
+
+    fn decode_protobuf_bytes(body: Bytes) -> Result<Self, DeserializeError> {
+        codec::deserialize_protobuf(body)
+    }
+}
 #[cfg(not(feature = "protobuf"))]
 impl<T> ProtobufSupport for T {}
 
+/// Whether the type `T` can be represented in `content_type`, see [ProtobufSupport::supports_protobuf]
+#[cfg_attr(not(feature = "protobuf"), allow(unused_variables, clippy::extra_unused_type_parameters))]
+pub(crate) fn supports_format<T: ProtobufSupport>(content_type: &ContentType) -> bool {
+    match content_type {
+        #[cfg(feature = "protobuf")]
+        ContentType::Protobuf | ContentType::GrpcWeb => T::supports_protobuf(),
+        _ => true,
+    }
+}
+
 #[cfg(any(feature = "json", feature = "xml"))]
 pub trait SerdeSupportDeserialize: serde::de::DeserializeOwned {}
 #[cfg(not(any(feature = "json", feature = "xml")))]
@@ -88,13 +323,39 @@ impl<T> SerdeSupportSerialize for T {}
 /// If the `Content-Type` header, or `Accept` header is invalid when responding this will return a HTTP 400 error,
 /// however this is *not* done if both headers are missing on response.
 ///
+/// # Formats
+///
+/// All enabled formats are supported by default. The formats can be restricted at the type level with `F`,
+/// see [FormatSet]. Requests in other formats are rejected with a HTTP 415 error, and responses are only
+/// serialized in formats of `F`. Use [Payload::new] or [From] to wrap a value.
+///
 /// # Panics
 ///
 /// If during serializing no format is enabled
-#[derive(Debug)]
-pub struct Payload<T: 'static + Default + Clone>(pub T);
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Payload<T, F = AnyFormat>(pub T, pub PhantomData<F>);
+
+impl<T, F> From<T> for Payload<T, F> {
+    fn from(value: T) -> Self {
+        Self(value, PhantomData)
+    }
+}
+
+impl<T: Display, F> Display for Payload<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Serializes as the wrapped value
+#[cfg(any(feature = "json", feature = "xml"))]
+impl<T: serde::Serialize, F> serde::Serialize for Payload<T, F> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
 
-impl<T: 'static + Default + Clone> Deref for Payload<T> {
+impl<T, F> Deref for Payload<T, F> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -102,81 +363,364 @@ impl<T: 'static + Default + Clone> Deref for Payload<T> {
     }
 }
 
-impl<T: 'static + Default + Clone> DerefMut for Payload<T> {
+impl<T, F> DerefMut for Payload<T, F> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
 
-impl<T: 'static + SerdeSupportDeserialize + ProtobufSupport + Default + Clone> FromRequest
-    for Payload<T>
-{
-    type Error = PayloadError;
-    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+impl<T> Payload<T> {
+    /// Wrap a value, supporting all enabled formats
+    pub fn new(value: T) -> Self {
+        Self(value, PhantomData)
+    }
+}
 
-    fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
-        let req = req.clone();
-        #[allow(unused)]
-        let mut payload = payload.take();
+impl<T, F> Payload<T, F> {
+    /// Transform the wrapped value, keeping the wrapper
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::Payload;
+    ///
+    ///     async fn responder(payload: Payload<String>) -> Payload<usize> {
+    ///         payload.map(|name| name.len())
+    ///     }
+    /// ```
+    pub fn map<U, M: FnOnce(T) -> U>(self, f: M) -> Payload<U, F> {
+        Payload(f(self.0), PhantomData)
+    }
 
-        Box::pin(async move {
-            let mut payload_bytes = Vec::new();
-            while let Some(Ok(b)) = payload.next().await {
-                payload_bytes.append(&mut b.to_vec())
-            }
+    /// Transform the wrapped value with a fallible function, keeping the wrapper
+    pub fn try_map<U, E, M: FnOnce(T) -> Result<U, E>>(self, f: M) -> Result<Payload<U, F>, E> {
+        f(self.0).map(Payload::from)
+    }
 
-            let content_type = ContentType::from_request_content_type(&req);
-            if content_type.eq(&ContentType::Other) {
-                return Err(PayloadError::InvalidContentType)
-            }
+    /// Transform the wrapped value with a fallible async function, keeping the wrapper
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::Payload;
+    ///
+    ///     async fn lookup(id: u64) -> Result<String, actix_web::Error> {
+    ///         Ok(format!("user-{id}"))
+    ///     }
+    ///
+    ///     async fn responder(payload: Payload<u64>) -> Result<Payload<String>, actix_web::Error> {
+    ///         payload.and_then(lookup).await
+    ///     }
+    /// ```
+    pub async fn and_then<U, E, M, Fut>(self, f: M) -> Result<Payload<U, F>, E>
+    where
+        M: FnOnce(T) -> Fut,
+        Fut: Future<Output = Result<U, E>>,
+    {
+        f(self.0).await.map(Payload::from)
+    }
 
-            let this = Payload::deserialize(&payload_bytes, content_type)?;
+    /// Replace the wrapped value, returning the previous value
+    pub fn replace(&mut self, value: T) -> T {
+        std::mem::replace(&mut self.0, value)
+    }
 
-            Ok(this)
-        })
+    /// Take the wrapped value, leaving the default value in its place
+    pub fn take(&mut self) -> T
+    where
+        T: Default,
+    {
+        std::mem::take(&mut self.0)
+    }
+}
+
+impl<T, F> FromRequest for Payload<T, F>
+where
+    T: 'static + Send + SerdeSupportDeserialize + ProtobufSupport,
+    F: FormatSet,
+{
+    type Error = PayloadError;
+    type Future = PayloadFuture<T, F>;
+
+    fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
+        PayloadFuture::new(req, payload.take())
     }
 }
 
-impl<T: ProtobufSupport + SerdeSupportSerialize + Default + Clone> Responder for Payload<T> {
+impl<T: ProtobufSupport + SerdeSupportSerialize, F: FormatSet> Responder for Payload<T, F> {
     type Body = BoxBody;
 
     fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        self.to_response(req)
+    }
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize, F: FormatSet> Payload<T, F> {
+    /// Negotiate the format and serialize the payload into a response, exactly like the [Responder] implementation.
+    ///
+    /// This allows building responses outside of handlers, e.g. in middleware.
+    pub fn to_response(&self, req: &HttpRequest) -> HttpResponse {
         // Determine the response format
         // - Check if the Accepts header was set to a valid value, use that
         // - If not, check the Content-Type header, if that is valid, use that
         // - Else, default to Json
-        let content_type = ContentType::from_request_accepts(req);
-        let content_type = if content_type.eq(&ContentType::Other) {
-            let content_type_second = ContentType::from_request_content_type(req);
-            if content_type_second.eq(&ContentType::Other) {
-                ContentType::default()
+        // The negotiation is cached if the payload was extracted for this request
+        // A ForcedFormat in the request extensions overrides all of this
+        // If the Accept header only lists unsupported formats, PayloadConfig::unsupported_accept may reject the request
+        let content_type = match Self::response_format(req) {
+            Ok(content_type) => content_type,
+            Err(response) => return *response,
+        };
+
+        let config = PayloadConfig::from_req(req);
+        let mask = config.field_masks.then(|| FieldMask::from_request(req)).flatten();
+        let links = config.links_for(req);
+        let response = if let Some(threshold) = config.stream_threshold {
+            let chunks = if config.envelope {
+                let meta = req.extensions().get::<ResponseMeta>().cloned().unwrap_or_default();
+                envelope::serialize(&self.0, &meta, content_type.clone(), config).map(body::split_chunks)
+            } else if let Some(mask) = &mask {
+                serialize_masked(&self.0, content_type.clone(), config, mask).map(body::split_chunks)
             } else {
-                content_type_second
-            }
+                self.serialize_chunked(content_type.clone(), config)
+            };
+            let chunks = if links.is_empty() && !config.transforms_responses() {
+                chunks
+            } else {
+                chunks
+                    .and_then(|chunks| links::inject(chunks.concat(), &content_type, &links, config))
+                    .and_then(|serialized| config.transform_response(&content_type, serialized))
+                    .map(body::split_chunks)
+            };
+
+            build_chunked_response(StatusCode::OK, content_type.clone(), chunks, threshold, config)
+        } else if let Some(pool) = config.buffer_pool.filter(|_| {
+            !config.envelope && mask.is_none() && links.is_empty() && !config.transforms_responses()
+        }) {
+            let serialized = pool.serialize(&self.0, content_type.clone(), config);
+            build_response(StatusCode::OK, content_type.clone(), serialized, config)
         } else {
-            content_type
+            let serialized = if config.envelope {
+                let meta = req.extensions().get::<ResponseMeta>().cloned().unwrap_or_default();
+                envelope::serialize(&self.0, &meta, content_type.clone(), config)
+            } else if let Some(mask) = &mask {
+                serialize_masked(&self.0, content_type.clone(), config, mask)
+            } else {
+                self.serialize_with(content_type.clone(), config)
+            };
+            let serialized = serialized
+                .and_then(|serialized| links::inject(serialized, &content_type, &links, config))
+                .and_then(|serialized| config.transform_response(&content_type, serialized));
+
+            build_response(StatusCode::OK, content_type.clone(), serialized, config)
         };
 
-        let serialized = match self.serialize(content_type.clone()) {
-            Ok(x) => x,
-            Err(e) => {
-                return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(e.to_string());
-            }
+        Self::finish_response(req, &content_type, response)
+    }
+
+    /// Like [Payload::to_response], but the payload is serialized on the thread pool for blocking tasks with [actix_web::web::block],
+    /// so serializing a large response does not block the worker thread. Negotiation still happens on the worker thread.
+    ///
+    /// Unlike request bodies, see [PayloadConfig::blocking_threshold], the size of a response is only known after serializing it,
+    /// so handlers choose to serialize on the blocking pool themselves, e.g. for endpoints returning large collections.
+    /// The [PayloadConfig::buffer_pool] is not used, as its buffers belong to the worker thread.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::Payload;
+    ///     use actix_web::{HttpRequest, HttpResponse};
+    ///
+    ///     #[derive(serde_derive::Serialize, prost_derive::Message)]
+    ///     struct Export {
+    ///         #[prost(string, repeated, tag = "1")]
+    ///         rows: Vec<String>,
+    ///     }
+    ///
+    ///     async fn export(req: HttpRequest) -> HttpResponse {
+    ///         let rows = (0..100_000).map(|i| i.to_string()).collect();
+    ///         Payload::new(Export { rows }).into_response_blocking(&req).await
+    ///     }
+    /// ```
+    pub async fn into_response_blocking(self, req: &HttpRequest) -> HttpResponse
+    where
+        T: Send + 'static,
+    {
+        let content_type = match Self::response_format(req) {
+            Ok(content_type) => content_type,
+            Err(response) => return *response,
         };
 
-        let mut response = HttpResponse::build(StatusCode::OK);
-        match content_type {
-            #[cfg(feature = "json")]
-            ContentType::Json => response.insert_header(("Content-Type", "application/json")),
-            #[cfg(feature = "protobuf")]
-            ContentType::Protobuf => response.insert_header(("Content-Type", "application/protobuf")),
-            #[cfg(feature = "xml")]
-            ContentType::Xml => response.insert_header(("Content-Type", "application/xml")),
-            ContentType::Other => panic!("Must have ast least one format feature enabled.")
+        let config = PayloadConfig::from_req(req);
+        let meta = config
+            .envelope
+            .then(|| req.extensions().get::<ResponseMeta>().cloned().unwrap_or_default());
+        let mask = config.field_masks.then(|| FieldMask::from_request(req)).flatten();
+        let links = config.links_for(req);
+
+        let blocking_content_type = content_type.clone();
+        let blocking_config = config.clone();
+        let value = self.0;
+        let serialized = actix_web::web::block(move || {
+            let (content_type, config) = (blocking_content_type, &blocking_config);
+            let serialized = if let Some(meta) = &meta {
+                envelope::serialize(&value, meta, content_type.clone(), config)
+            } else if let Some(mask) = &mask {
+                serialize_masked(&value, content_type.clone(), config, mask)
+            } else {
+                serialize_value(&value, content_type.clone(), config)
+            };
+
+            serialized
+                .and_then(|serialized| links::inject(serialized, &content_type, &links, config))
+                .and_then(|serialized| config.transform_response(&content_type, serialized))
+        })
+        .await
+        .map_err(SerializeError::from)
+        .and_then(|serialized| serialized);
+
+        let response = match config.stream_threshold {
+            Some(threshold) => build_chunked_response(
+                StatusCode::OK,
+                content_type.clone(),
+                serialized.map(body::split_chunks),
+                threshold,
+                config,
+            ),
+            None => build_response(StatusCode::OK, content_type.clone(), serialized, config),
         };
 
-        response.body(serialized)
+        Self::finish_response(req, &content_type, response)
+    }
+
+    /// Negotiate the response format among the formats of `F` which support `T`.
+    /// If the request only accepts formats which are not supported, the response rejecting it is returned instead.
+    fn response_format(req: &HttpRequest) -> Result<ContentType, Box<HttpResponse>> {
+        if let Some(response) = negotiate::not_acceptable(req) {
+            return Err(Box::new(response));
+        }
+        let allows = |content_type: &ContentType| F::allows(content_type) && supports_format::<T>(content_type);
+        let content_type = negotiate::response_format(req);
+        if allows(&content_type) {
+            return Ok(content_type);
+        }
+
+        // Types declared with serde_only are answered in another format, if the client accepts one
+        if !supports_format::<T>(&content_type) {
+            return negotiate::response_format_among_or_reject(req, allows);
+        }
+
+        Ok(negotiate::response_format_among(req, allows).unwrap_or_default())
+    }
+
+    /// Apply the response headers of the configuration, and remember the format of successful responses
+    fn finish_response(req: &HttpRequest, content_type: &ContentType, mut response: HttpResponse) -> HttpResponse {
+        let config = PayloadConfig::from_req(req);
+        config.apply_response_headers(&mut response);
+        if response.status().is_success() {
+            negotiate::remember_format(req, content_type, &mut response);
+        }
+
+        response
+    }
+
+    /// Negotiate the format and serialize the payload into a response built with `builder`, so handlers can set
+    /// the status, cookies or other headers themselves and still leave the body to this crate.
+    ///
+    /// The status and headers of the builder are kept, the `Content-Type` and the headers of [PayloadConfig::response_header]
+    /// replace those of the builder, [PayloadConfig::vary] is appended to its `Vary` header.
+    /// If serializing fails, the HTTP 500 response of [Payload::to_response] is returned instead.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::Payload;
+    ///     use actix_web::{HttpRequest, HttpResponse};
+    ///
+    ///     async fn login(req: HttpRequest) -> HttpResponse {
+    ///         Payload::new("welcome".to_string())
+    ///             .write_into(HttpResponse::Accepted().insert_header(("Set-Cookie", "session=foo")), &req)
+    ///     }
+    /// ```
+    pub fn write_into(&self, builder: &mut HttpResponseBuilder, req: &HttpRequest) -> HttpResponse {
+        let response = self.to_response(req);
+        if !response.status().is_success() {
+            return response;
+        }
+
+        let mut inserted = Vec::new();
+        for (name, value) in response.headers() {
+            if name == actix_web::http::header::VARY || inserted.contains(name) {
+                builder.append_header((name.clone(), value.clone()));
+            } else {
+                builder.insert_header((name.clone(), value.clone()));
+                inserted.push(name.clone());
+            }
+        }
+
+        builder.body(response.into_body())
+    }
+}
+
+/// The response builder for a body in `content_type`, with the `Content-Type` header set.
+/// No `Content-Type` header is set for [ContentType::Other], which negotiation never results in.
+pub(crate) fn response_builder(status: StatusCode, content_type: ContentType) -> HttpResponseBuilder {
+    let mut response = HttpResponse::build(status);
+    if let Some(media_type) = content_type.media_type() {
+        response.insert_header(("Content-Type", media_type));
+    }
+
+    response
+}
+
+/// Build the response for a body serialized in `content_type`.
+/// If serializing failed, a HTTP 500 is returned instead, redacted according to the `config`.
+pub(crate) fn build_response<B: MessageBody + 'static>(
+    status: StatusCode,
+    content_type: ContentType,
+    serialized: Result<B, SerializeError>,
+    config: &PayloadConfig,
+) -> HttpResponse {
+    let serialized = match serialized {
+        Ok(x) => x,
+        Err(e) => return serialize_error_response(&e, config),
+    };
+
+    response_builder(status, content_type).body(serialized)
+}
+
+/// The response for a body which failed to serialize: a HTTP 406 if the requested field mask can't be applied
+/// in the negotiated format, a HTTP 500 redacted according to the `config` otherwise
+fn serialize_error_response(error: &SerializeError, config: &PayloadConfig) -> HttpResponse {
+    match error {
+        #[cfg(feature = "protobuf")]
+        SerializeError::MaskUnsupported => HttpResponse::build(StatusCode::NOT_ACCEPTABLE).body(error.to_string()),
+        _ => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(config.serialize_error_message(error)),
+    }
+}
+
+/// Build the response for a body serialized in `content_type` in chunks.
+/// Bodies larger than `threshold` bytes are streamed, smaller bodies are sent as one buffer.
+/// If serializing failed, a HTTP 500 is returned instead, redacted according to the `config`.
+fn build_chunked_response(
+    status: StatusCode,
+    content_type: ContentType,
+    serialized: Result<Vec<Bytes>, SerializeError>,
+    threshold: usize,
+    config: &PayloadConfig,
+) -> HttpResponse {
+    let mut chunks = match serialized {
+        Ok(x) => x,
+        Err(e) => return serialize_error_response(&e, config),
+    };
+
+    let len: usize = chunks.iter().map(Bytes::len).sum();
+    let mut response = response_builder(status, content_type);
+    if len > threshold {
+        let stream = futures_util::stream::iter(chunks.into_iter().map(Ok::<_, Infallible>));
+        return response.body(SizedStream::new(len as u64, stream));
+    }
+
+    match chunks.len() {
+        0 => response.finish(),
+        1 => response.body(chunks.remove(0)),
+        _ => response.body(chunks.concat()),
     }
 }
 
@@ -185,7 +729,7 @@ pub enum SerializeError {
     #[cfg(feature = "json")]
     #[error("Failed to serialize to JSON: {0}")]
     SerdeJson(#[from] serde_json::Error),
-    #[cfg(feature = "json")]
+    #[cfg(feature = "protobuf")]
     #[error("Failed to encode to protobuf: {0}")]
     Prost(String),
     #[cfg(feature = "xml")]
@@ -193,6 +737,17 @@ pub enum SerializeError {
     QuickXml(#[from] quick_xml::DeError),
     #[error("Unable to serialize")]
     Unserializable,
+    /// The [PayloadConfig::response_transform] failed
+    #[error("Failed to transform the response body: {0}")]
+    Transform(String),
+    /// Serializing on the thread pool for blocking tasks failed, see [PayloadFile::from_value_parallel]
+    #[error("Blocking error: {0}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+    /// The type does not implement [ProtobufSupport::encode_protobuf_masked], so the field mask of the request
+    /// can't be applied to the protobuf response. Answered with a HTTP 406
+    #[cfg(feature = "protobuf")]
+    #[error("Field masks are not supported for protobuf responses of this type")]
+    MaskUnsupported,
 }
 
 #[derive(Debug, Error)]
@@ -202,67 +757,202 @@ pub enum DeserializeError {
     SerdeJson(#[from] serde_json::Error),
     #[cfg(feature = "protobuf")]
     #[error("Failed to decode from protobuf: {0}")]
-    Prost(String),
+    Prost(ProtobufError),
+    #[cfg(feature = "protobuf")]
+    #[error("Invalid grpc-web body: {0}")]
+    GrpcWeb(String),
     #[cfg(feature = "xml")]
     #[error("Failed to deserialize from XML: {0}")]
     Xml(#[from] quick_xml::DeError),
+    #[error("Failed to transform the request body: {0}")]
+    Transform(String),
+    /// The JSON body does not match the schema registered with [PayloadConfig::json_schema]
+    #[cfg(feature = "jsonschema")]
+    #[error("The body does not match the JSON Schema: {}", crate::schema::violations(.0))]
+    Schema(Vec<SchemaViolation>),
     #[error("Unable to deserialize")]
-    Undeserializable
+    Undeserializable,
+    /// A JSON or XML error which occurred in the value at `path`, see [DeserializeError::path]
+    #[cfg(any(feature = "json", feature = "xml"))]
+    #[error("{source} (in `{path}`)")]
+    Field {
+        path: String,
+        source: Box<DeserializeError>,
+    },
 }
 
-impl<T: ProtobufSupport + SerdeSupportSerialize + Default + Clone> Payload<T> {
+impl DeserializeError {
+    /// The details of the error, if decoding protobuf failed
+    #[cfg(feature = "protobuf")]
+    pub fn protobuf_error(&self) -> Option<&ProtobufError> {
+        match self.detailed() {
+            Self::Prost(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The values of the body which do not match the JSON Schema, if validating it failed
+    #[cfg(feature = "jsonschema")]
+    pub fn schema_violations(&self) -> Option<&[SchemaViolation]> {
+        match self.detailed() {
+            Self::Schema(violations) => Some(violations),
+            _ => None,
+        }
+    }
+
+    /// The path of the value in the body the error occurred in, e.g. `authors[1].name`.
+    /// Known for JSON and XML errors which occurred in a field, as named by the type the body is deserialized into
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            #[cfg(any(feature = "json", feature = "xml"))]
+            Self::Field { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The error without the path it occurred at, unwrapping [DeserializeError::Field]
+    pub fn detailed(&self) -> &Self {
+        match self {
+            #[cfg(any(feature = "json", feature = "xml"))]
+            Self::Field { source, .. } => source.detailed(),
+            error => error,
+        }
+    }
+
+    /// The error wrapped in [DeserializeError::Field] if the path it occurred at is known
+    #[cfg(any(feature = "json", feature = "xml"))]
+    pub(crate) fn in_field(self, path: Option<String>) -> Self {
+        match path {
+            Some(path) => Self::Field {
+                path,
+                source: Box::new(self),
+            },
+            None => self,
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl From<prost::DecodeError> for DeserializeError {
+    fn from(e: prost::DecodeError) -> Self {
+        Self::Prost(e.into())
+    }
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize, F> Payload<T, F> {
+    /// Serialize in `content_type`, using the default configuration
     pub fn serialize(&self, content_type: ContentType) -> Result<Vec<u8>, SerializeError> {
+        serialize_value(&self.0, content_type, &config::DEFAULT_CONFIG)
+    }
+
+    /// Serialize in `content_type`, using the serializer settings of `config`
+    pub fn serialize_with(&self, content_type: ContentType, config: &PayloadConfig) -> Result<Vec<u8>, SerializeError> {
+        serialize_value(&self.0, content_type, config)
+    }
+
+    /// Serialize in chunks, see [PayloadConfig::stream_threshold]
+    fn serialize_chunked(&self, content_type: ContentType, config: &PayloadConfig) -> Result<Vec<Bytes>, SerializeError> {
         match content_type {
             #[cfg(feature = "json")]
-            ContentType::Json => {
-                let json = serde_json::to_string_pretty(&self.0)?;
-                Ok(json.into_bytes())
-            },
-            #[cfg(feature = "protobuf")]
-            ContentType::Protobuf => {
-                let mut protobuf = Vec::new();
-                self.0.encode(&mut protobuf)
-                    .map_err(|e| SerializeError::Prost(e.to_string()))?;
-                Ok(protobuf)
-            },
+            ContentType::Json => codec::serialize_json_chunked(&self.0, config),
             #[cfg(feature = "xml")]
-            ContentType::Xml => {
-                let xml = quick_xml::se::to_string(&self.0)?;
-                Ok(xml.into_bytes())
-            }
-            ContentType::Other => Err(SerializeError::Unserializable)
+            ContentType::Xml => codec::serialize_xml_chunked(&self.0, config),
+            content_type => self.serialize_with(content_type, config).map(body::split_chunks),
         }
     }
 }
 
-impl<T: ProtobufSupport + SerdeSupportDeserialize + Default + Clone> Payload<T> {
+/// Serialize a value in `content_type`
+pub(crate) fn serialize_value<T: ProtobufSupport + SerdeSupportSerialize>(
+    value: &T,
+    content_type: ContentType,
+    config: &PayloadConfig,
+) -> Result<Vec<u8>, SerializeError> {
+    match content_type {
+        #[cfg(feature = "json")]
+        ContentType::Json => codec::serialize_json(value, config),
+        #[cfg(feature = "protobuf")]
+        ContentType::Protobuf => codec::encode_protobuf(value, config),
+        #[cfg(feature = "protobuf")]
+        ContentType::GrpcWeb => codec::serialize_grpc_web(value, config),
+        #[cfg(feature = "xml")]
+        ContentType::Xml => codec::serialize_xml(value, config),
+        ContentType::Other(_) => Err(SerializeError::Unserializable)
+    }
+}
+
+/// Serialize only the fields of a value selected by `mask` in `content_type`, see [PayloadConfig::field_masks]
+fn serialize_masked<T: ProtobufSupport + SerdeSupportSerialize>(
+    value: &T,
+    content_type: ContentType,
+    config: &PayloadConfig,
+    #[cfg_attr(not(any(feature = "json", feature = "protobuf")), allow(unused_variables))] mask: &FieldMask,
+) -> Result<Vec<u8>, SerializeError> {
+    match content_type {
+        #[cfg(feature = "json")]
+        ContentType::Json => {
+            let mut json = codec::to_json_value(value, config)?;
+            mask.apply_json(&mut json);
+            codec::serialize_json_value(&json, config)
+        }
+        #[cfg(feature = "protobuf")]
+        ContentType::Protobuf => value.encode_protobuf_masked(mask),
+        #[cfg(feature = "protobuf")]
+        ContentType::GrpcWeb => Ok(codec::frame_grpc_web(&value.encode_protobuf_masked(mask)?)),
+        content_type => serialize_value(value, content_type, config),
+    }
+}
+
+impl<T: ProtobufSupport + SerdeSupportDeserialize> Payload<T> {
+    /// Deserialize from `content_type`, using the default configuration
     pub fn deserialize(body: &[u8], content_type: ContentType) -> Result<Self, DeserializeError> {
+        Self::deserialize_with(body, content_type, &config::DEFAULT_CONFIG)
+    }
+
+    /// Deserialize from `content_type`, using the deserializer settings of `config`
+    pub fn deserialize_with(
+        body: &[u8],
+        content_type: ContentType,
+        #[cfg_attr(not(any(feature = "json", feature = "xml")), allow(unused_variables))] config: &PayloadConfig,
+    ) -> Result<Self, DeserializeError> {
         match content_type {
             #[cfg(feature = "json")]
-            ContentType::Json => {
-                let payload: T = serde_json::from_slice(body)?;
-                Ok(Self(payload))
-            },
+            ContentType::Json => Ok(Self::new(codec::deserialize_json(body, config)?)),
             #[cfg(feature = "protobuf")]
-            ContentType::Protobuf => {
-                let payload = T::decode(body)
-                    .map_err(|e| DeserializeError::Prost(e.to_string()))?;
-                Ok(Self(payload))
-            },
+            ContentType::Protobuf => Ok(Self::new(T::decode_protobuf(body)?)),
+            #[cfg(feature = "protobuf")]
+            ContentType::GrpcWeb => Ok(Self::new(codec::deserialize_grpc_web(body)?)),
             #[cfg(feature = "xml")]
-            ContentType::Xml => {
-                let payload: T = quick_xml::de::from_reader(body)?;
-                Ok(Self(payload) )
-            }
-            ContentType::Other => Err(DeserializeError::Undeserializable)
+            ContentType::Xml => Ok(Self::new(codec::deserialize_xml(body, config)?)),
+            ContentType::Other(_) => Err(DeserializeError::Undeserializable)
         }
     }
 
+    /// Like [Payload::deserialize], but protobuf is decoded directly from the [Bytes] buffer.
+    /// This allows `bytes` fields to reference the body instead of copying it.
+    pub fn deserialize_bytes(body: Bytes, content_type: ContentType) -> Result<Self, DeserializeError> {
+        Self::deserialize_bytes_with(body, content_type, &config::DEFAULT_CONFIG)
+    }
+
+    /// Like [Payload::deserialize_with], but protobuf is decoded directly from the [Bytes] buffer
+    pub(crate) fn deserialize_bytes_with(
+        body: Bytes,
+        content_type: ContentType,
+        config: &PayloadConfig,
+    ) -> Result<Self, DeserializeError> {
+        match content_type {
+            #[cfg(feature = "protobuf")]
+            ContentType::Protobuf => Ok(Self::new(T::decode_protobuf_bytes(body)?)),
+            content_type => Self::deserialize_with(&body, content_type, config),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(feature = "json")]
+    use actix_web::ResponseError;
     use prost_derive::Message;
     use serde_derive::{Deserialize, Serialize};
 
@@ -287,6 +977,15 @@ mod test {
         }
     }
 
+    /// Create a request payload from a stream
+    fn stream_payload<S>(stream: S) -> actix_web::dev::Payload
+    where
+        S: futures_util::Stream<Item = Result<actix_web::web::Bytes, actix_web::error::PayloadError>> + 'static,
+    {
+        let stream: std::pin::Pin<Box<dyn futures_util::Stream<Item = _>>> = Box::pin(stream);
+        actix_web::dev::Payload::from(stream)
+    }
+
     #[allow(unused)]
     async fn responder(payload: Payload<TestPayload>) -> Payload<TestPayload> {
         payload
@@ -381,4 +1080,666 @@ mod test {
         let body = body!(resp);
         assert_eq!(TestPayload::protobuf(), body.to_vec());
     }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_duplicate_content_type() {
+        let app = setup!();
+        let req = actix_web::test::TestRequest::default()
+            .append_header(("Content-Type", "application/json"))
+            .append_header(("Content-Type", "application/json"))
+            .set_payload(TestPayload::json())
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_malformed_content_type() {
+        let app = setup!();
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((
+                "Content-Type",
+                actix_web::http::header::HeaderValue::from_bytes(b"application/json\xff").unwrap(),
+            ))
+            .set_payload(TestPayload::json())
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "protobuf")]
+    async fn test_grpc_web_disabled() {
+        let app = setup!();
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/grpc-web+proto"))
+            .set_payload(codec::serialize_grpc_web(&TestPayload::default(), &PayloadConfig::default()).unwrap())
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "protobuf")]
+    async fn test_grpc_web() {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(PayloadConfig::default().grpc_web(true))
+                .route("/", actix_web::web::get().to(responder)),
+        )
+        .await;
+        let framed = codec::serialize_grpc_web(&TestPayload::default(), &PayloadConfig::default()).unwrap();
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/grpc-web+proto"))
+            .insert_header(("Accept", "*/*"))
+            .set_payload(framed.clone())
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!("application/grpc-web+proto", resp.headers().get("Content-Type").unwrap());
+
+        let body = body!(resp);
+        assert_eq!(framed, body.to_vec());
+    }
+
+    #[actix_macros::test]
+    async fn test_invalid_content_type_body_not_read() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let polled = Rc::new(Cell::new(false));
+        let polled_stream = polled.clone();
+        let stream = futures_util::stream::poll_fn(move |_| {
+            polled_stream.set(true);
+            std::task::Poll::Ready(None)
+        });
+        let mut payload = stream_payload(stream);
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "foo/bar"))
+            .to_http_request();
+        let result = Payload::<TestPayload>::from_request(&req, &mut payload).await;
+
+        assert!(matches!(result.unwrap_err().detailed(), PayloadError::InvalidContentType(ContentType::Other(media_type)) if media_type == "foo/bar"));
+        assert!(!polled.get());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_truncated_body() {
+        let stream = futures_util::stream::iter(vec![
+            Ok(actix_web::web::Bytes::from_static(b"{\"foo\":")),
+            Err(actix_web::error::PayloadError::Incomplete(None)),
+        ]);
+        let mut payload = stream_payload(stream);
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .to_http_request();
+        let result = Payload::<TestPayload>::from_request(&req, &mut payload).await;
+
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err.detailed(),
+            PayloadError::ActixPayload(actix_web::error::PayloadError::Incomplete(_))
+        ));
+        assert_eq!(StatusCode::BAD_REQUEST, err.status_code());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_body_overflow() {
+        let stream = futures_util::stream::iter(vec![
+            Ok(actix_web::web::Bytes::from_static(b"{\"foo\":")),
+            Ok(actix_web::web::Bytes::from_static(b"\"bar\"}")),
+        ]);
+        let mut payload = stream_payload(stream);
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .app_data(PayloadConfig::default().limit(10))
+            .to_http_request();
+        let result = Payload::<TestPayload>::from_request(&req, &mut payload).await;
+
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err.detailed(),
+            PayloadError::ActixPayload(actix_web::error::PayloadError::Overflow)
+        ));
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, err.status_code());
+    }
+
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    #[derive(Deserialize, Serialize)]
+    struct Envelope<T> {
+        data: T,
+    }
+
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    impl<T: ProtobufSupport> ProtobufSupport for Envelope<T> {
+        fn encode_protobuf(&self) -> Result<Vec<u8>, SerializeError> {
+            self.data.encode_protobuf()
+        }
+
+        fn decode_protobuf(body: &[u8]) -> Result<Self, DeserializeError> {
+            Ok(Self { data: T::decode_protobuf(body)? })
+        }
+    }
+
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    async fn envelope_responder(payload: Payload<Envelope<TestPayload>>) -> Payload<Envelope<TestPayload>> {
+        payload
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    async fn test_generic_envelope() {
+        let app = actix_web::test::init_service(
+            actix_web::App::new().route("/", actix_web::web::get().to(envelope_responder)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("Accept", "application/protobuf"))
+            .set_payload(r#"{"data":{"foo":"","bar":0}}"#)
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = body!(resp);
+        assert_eq!(TestPayload::protobuf(), body.to_vec());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_envelope() {
+        async fn meta_responder(req: HttpRequest, payload: Payload<TestPayload>) -> Payload<TestPayload> {
+            let mut meta = ResponseMeta::default();
+            meta.insert("page", "1");
+            req.extensions_mut().insert(meta);
+            payload
+        }
+
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(PayloadConfig::default().envelope(true))
+                .route("/", actix_web::web::get().to(meta_responder)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(TestPayload::json())
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = body!(resp);
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            serde_json::json!({ "data": { "foo": "", "bar": 0 }, "meta": { "page": "1" } }),
+            value
+        );
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "protobuf")]
+    async fn test_protobuf_length_delimited() {
+        use prost::Message;
+
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(PayloadConfig::default().protobuf_length_delimited(true))
+                .route("/", actix_web::web::get().to(responder)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .set_payload(TestPayload { foo: "foo".to_string(), bar: 1 }.encode_length_delimited_to_vec())
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = body!(resp);
+        assert_eq!(TestPayload { foo: "foo".to_string(), bar: 1 }.encode_to_vec(), body.to_vec());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_recover_from_error() {
+        async fn fallback_responder(payload: Result<Payload<TestPayload>, PayloadError>) -> HttpResponse {
+            match payload {
+                Ok(_) => HttpResponse::Ok().finish(),
+                Err(e) => {
+                    assert_eq!(Some(&ContentType::Json), e.content_type());
+                    HttpResponse::Ok().body(e.body().cloned().unwrap_or_default())
+                }
+            }
+        }
+
+        let app = actix_web::test::init_service(
+            actix_web::App::new().route("/", actix_web::web::get().to(fallback_responder)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload("legacy")
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = body!(resp);
+        assert_eq!(b"legacy".to_vec(), body.to_vec());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_transform() {
+        let config = PayloadConfig::default().transform(|_, body| {
+            let body = std::str::from_utf8(&body).map_err(|e| e.to_string())?;
+            Ok(body.replace(",}", "}").into())
+        });
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(config)
+                .route("/", actix_web::web::get().to(responder)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#"{"foo":"foo","bar":1,}"#)
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+
+        let body = body!(resp);
+        let payload: TestPayload = serde_json::from_slice(&body).unwrap();
+        assert_eq!("foo", payload.foo);
+        assert_eq!(1, payload.bar);
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_stream_threshold() {
+        use actix_web::body::{BodySize, MessageBody};
+
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(PayloadConfig::default().stream_threshold(16))
+                .route("/", actix_web::web::get().to(responder)),
+        )
+        .await;
+
+        let foo = "a".repeat(body::CHUNK_SIZE);
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(format!(r#"{{"foo":"{foo}","bar":1}}"#))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert!(matches!(resp.response().body().size(), BodySize::Sized(len) if len as usize > body::CHUNK_SIZE));
+
+        let body = body!(resp);
+        let payload: TestPayload = serde_json::from_slice(&body).unwrap();
+        assert_eq!(foo, payload.foo);
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_blocking_threshold() {
+        /// Records the thread it was deserialized on
+        struct ThreadPayload(std::thread::ThreadId);
+
+        impl<'de> serde::Deserialize<'de> for ThreadPayload {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                serde::de::IgnoredAny::deserialize(deserializer)?;
+                Ok(Self(std::thread::current().id()))
+            }
+        }
+
+        #[cfg(feature = "protobuf")]
+        impl ProtobufSupport for ThreadPayload {
+            fn encode_protobuf(&self) -> Result<Vec<u8>, SerializeError> {
+                Err(SerializeError::Unserializable)
+            }
+
+            fn decode_protobuf(_: &[u8]) -> Result<Self, DeserializeError> {
+                Err(DeserializeError::Undeserializable)
+            }
+        }
+
+        async fn thread_responder(payload: Payload<ThreadPayload>) -> HttpResponse {
+            if payload.0 .0 == std::thread::current().id() {
+                HttpResponse::Ok().body("worker")
+            } else {
+                HttpResponse::Ok().body("blocking")
+            }
+        }
+
+        for (threshold, expected) in [(0, "blocking"), (1024, "worker")] {
+            let app = actix_web::test::init_service(
+                actix_web::App::new()
+                    .app_data(PayloadConfig::default().blocking_threshold(threshold))
+                    .route("/", actix_web::web::get().to(thread_responder)),
+            )
+            .await;
+            let req = actix_web::test::TestRequest::default()
+                .insert_header(("Content-Type", "application/json"))
+                .set_payload(r#"{"foo":"bar"}"#)
+                .to_request();
+            let resp = actix_web::test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+            assert_eq!(expected.as_bytes(), &body!(resp)[..]);
+        }
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_blocking_starvation() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        /// Takes long to deserialize, blocking the thread it is deserialized on
+        struct SlowPayload;
+
+        impl<'de> serde::Deserialize<'de> for SlowPayload {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                serde::de::IgnoredAny::deserialize(deserializer)?;
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                Ok(Self)
+            }
+        }
+
+        #[cfg(feature = "protobuf")]
+        impl ProtobufSupport for SlowPayload {
+            fn encode_protobuf(&self) -> Result<Vec<u8>, SerializeError> {
+                Err(SerializeError::Unserializable)
+            }
+
+            fn decode_protobuf(_: &[u8]) -> Result<Self, DeserializeError> {
+                Err(DeserializeError::Undeserializable)
+            }
+        }
+
+        for (threshold, expected) in [(64, ["small", "large"]), (usize::MAX, ["large", "small"])] {
+            let completed = Rc::new(RefCell::new(Vec::new()));
+            let large_completed = completed.clone();
+            let small_completed = completed.clone();
+            let app = actix_web::test::init_service(
+                actix_web::App::new()
+                    .app_data(PayloadConfig::default().blocking_threshold(threshold))
+                    .route(
+                        "/large",
+                        actix_web::web::post().to(move |_: Payload<SlowPayload>| {
+                            large_completed.borrow_mut().push("large");
+                            async { HttpResponse::Ok().finish() }
+                        }),
+                    )
+                    .route(
+                        "/small",
+                        actix_web::web::post().to(move |_: Payload<TestPayload>| {
+                            small_completed.borrow_mut().push("small");
+                            async { HttpResponse::Ok().finish() }
+                        }),
+                    ),
+            )
+            .await;
+
+            let large = actix_web::test::TestRequest::post()
+                .uri("/large")
+                .insert_header(("Content-Type", "application/json"))
+                .set_payload(format!("[{}]", vec!["0"; 1024].join(",")))
+                .to_request();
+            let small = actix_web::test::TestRequest::post()
+                .uri("/small")
+                .insert_header(("Content-Type", "application/json"))
+                .set_payload(TestPayload::json())
+                .to_request();
+            let (large, small) = futures_util::future::join(
+                actix_web::test::call_service(&app, large),
+                actix_web::test::call_service(&app, small),
+            )
+            .await;
+
+            assert!(large.status().is_success());
+            assert!(small.status().is_success());
+            assert_eq!(expected.to_vec(), *completed.borrow());
+        }
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    async fn test_into_response_blocking() {
+        for accept in ["application/json", "application/protobuf"] {
+            let req = actix_web::test::TestRequest::default()
+                .insert_header(("Accept", accept))
+                .to_http_request();
+            let payload = TestPayload {
+                foo: "bar".to_string(),
+                bar: 1,
+            };
+
+            let expected = Payload::new(payload.clone()).to_response(&req);
+            let resp = Payload::new(payload).into_response_blocking(&req).await;
+
+            assert!(resp.status().is_success());
+            assert_eq!(expected.headers().get("Content-Type"), resp.headers().get("Content-Type"));
+            assert_eq!(body!(expected), body!(resp));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_passthrough_impls() {
+        let mut set = std::collections::HashSet::new();
+        set.insert(Payload::new("foo".to_string()));
+
+        assert!(set.contains(&Payload::new("foo".to_string())));
+        assert_eq!("foo", Payload::new("foo").to_string());
+        assert_eq!(serde_json::json!({ "nested": "foo" }), serde_json::json!({ "nested": Payload::new("foo") }));
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_vary_accept() {
+        let app = setup!();
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(TestPayload::json())
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!("accept", resp.headers().get("Vary").unwrap());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_json_format() {
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(PayloadConfig::default().json_format(JsonFormat::Compact))
+                .route("/", actix_web::web::get().to(responder)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(TestPayload::json())
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!(br#"{"foo":"","bar":0}"#.to_vec(), body!(resp).to_vec());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_sort_json_keys() {
+        let req = actix_web::test::TestRequest::default()
+            .app_data(
+                PayloadConfig::default()
+                    .json_format(JsonFormat::Compact)
+                    .sort_json_keys(true)
+                    .links(|_| vec![Link::new("self", "/foo")]),
+            )
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        let resp = Payload::new(TestPayload { foo: "foo".to_string(), bar: 1 }).to_response(&req);
+
+        assert_eq!(
+            br#"{"_links":{"self":{"href":"/foo"}},"bar":1,"foo":"foo"}"#.to_vec(),
+            body!(resp).to_vec()
+        );
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_int64_as_string() {
+        let config = PayloadConfig::default()
+            .json_format(JsonFormat::Compact)
+            .int64_as_string(true);
+        let req = actix_web::test::TestRequest::default()
+            .app_data(config.clone())
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        let resp = Payload::new(TestPayload { foo: "foo".to_string(), bar: 1 }).to_response(&req);
+        assert_eq!(br#"{"foo":"foo","bar":"1"}"#.to_vec(), body!(resp).to_vec());
+
+        let payload = Payload::<TestPayload>::deserialize_with(br#"{"foo":"","bar":"9007199254740993"}"#, ContentType::Json, &config).unwrap();
+        assert_eq!(9_007_199_254_740_993, payload.bar);
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_write_into() {
+        let req = actix_web::test::TestRequest::default()
+            .app_data(PayloadConfig::default().json_format(JsonFormat::Compact))
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        let mut builder = HttpResponse::Created();
+        builder
+            .insert_header(("Set-Cookie", "session=foo"))
+            .insert_header(("Vary", "Cookie"));
+        let resp = Payload::new(TestPayload { foo: "foo".to_string(), bar: 1 }).write_into(&mut builder, &req);
+
+        assert_eq!(StatusCode::CREATED, resp.status());
+        assert_eq!("session=foo", resp.headers().get("Set-Cookie").unwrap());
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+        assert_eq!(2, resp.headers().get_all("Vary").count());
+        assert_eq!(br#"{"foo":"foo","bar":1}"#.to_vec(), body!(resp).to_vec());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_to_response() {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        let resp = Payload::new(TestPayload::default()).to_response(&req);
+
+        assert!(resp.status().is_success());
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_field_masks() {
+        let payload = Payload::new(TestPayload { foo: "foo".to_string(), bar: 1 });
+        let req = actix_web::test::TestRequest::default()
+            .app_data(PayloadConfig::default().field_masks(true).json_format(JsonFormat::Compact))
+            .insert_header(("Accept", "application/json"))
+            .insert_header(("X-Fields", "bar"))
+            .to_http_request();
+        let resp = payload.to_response(&req);
+
+        assert!(resp.status().is_success());
+        assert_eq!(br#"{"bar":1}"#.to_vec(), body!(resp).to_vec());
+
+        // Field masks are ignored unless enabled
+        let req = actix_web::test::TestRequest::default()
+            .app_data(PayloadConfig::default().json_format(JsonFormat::Compact))
+            .insert_header(("Accept", "application/json"))
+            .insert_header(("X-Fields", "bar"))
+            .to_http_request();
+        let resp = payload.to_response(&req);
+
+        assert_eq!(br#"{"foo":"foo","bar":1}"#.to_vec(), body!(resp).to_vec());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "protobuf")]
+    async fn test_protobuf_field_masks() {
+        let payload = Payload::new(TestPayload { foo: "foo".to_string(), bar: 1 });
+        let req = actix_web::test::TestRequest::default()
+            .app_data(PayloadConfig::default().field_masks(true))
+            .insert_header(("Accept", "application/protobuf"))
+            .insert_header(("X-Fields", "bar"))
+            .to_http_request();
+        let resp = payload.to_response(&req);
+
+        // The mask can't be applied to a prost message, so the response is not sent with all fields
+        assert_eq!(StatusCode::NOT_ACCEPTABLE, resp.status());
+
+        let req = actix_web::test::TestRequest::default()
+            .app_data(PayloadConfig::default().field_masks(true))
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+        let resp = payload.to_response(&req);
+
+        assert!(resp.status().is_success());
+        assert_eq!("accept, x-fields", resp.headers().get("Vary").unwrap());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_deny_unknown_fields() {
+        let (req, mut payload) = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .app_data(PayloadConfig::default().deny_unknown_fields(true))
+            .set_payload(r#"{"foo":"bar","bar":1,"baz":true}"#)
+            .to_http_parts();
+        let err = Payload::<TestPayload>::from_request(&req, &mut payload).await.unwrap_err();
+
+        assert_eq!(StatusCode::BAD_REQUEST, err.status_code());
+        assert!(err.to_string().contains("unknown field `baz`"));
+        assert_eq!(1, err.location().unwrap().line);
+    }
+
+    #[actix_macros::test]
+    async fn test_combinators() {
+        let mut payload = Payload::new("foo".to_string()).map(|foo| foo.len());
+        assert_eq!(3, payload.replace(4));
+        assert_eq!(4, payload.take());
+        assert_eq!(0, *payload);
+
+        assert_eq!(Err("negative"), Payload::new(-1i64).try_map(|value| u64::try_from(value).map_err(|_| "negative")));
+
+        let payload = Payload::new(2).and_then(|value| async move { Ok::<_, ()>(value * 2) }).await;
+        assert_eq!(Ok(Payload::new(4)), payload);
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_extract_twice() {
+        let (req, mut payload) = actix_web::test::TestRequest::default()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#"{"foo":"bar","bar":1}"#)
+            .to_http_parts();
+
+        let first = Payload::<TestPayload>::from_request(&req, &mut payload).await.unwrap();
+        let second = Payload::<TestPayload>::from_request(&req, &mut payload).await.unwrap();
+        assert_eq!(first.foo, second.foo);
+    }
 }
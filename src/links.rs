@@ -0,0 +1,374 @@
+//! Hypermedia links, injected into responses in the representation of the negotiated format,
+//! see [crate::PayloadConfig::links].
+
+#[cfg(feature = "json")]
+use crate::codec;
+use crate::{ContentType, PayloadConfig, SerializeError};
+
+use actix_web::HttpRequest;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// A hypermedia link, e.g. to the resource itself or to the next page
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Link {
+    /// The relation of the link to the resource, e.g. `self` or `next`
+    pub rel: String,
+    /// The target of the link
+    pub href: String,
+}
+
+impl Link {
+    pub fn new<R: Into<String>, H: Into<String>>(rel: R, href: H) -> Self {
+        Self {
+            rel: rel.into(),
+            href: href.into(),
+        }
+    }
+}
+
+/// A callback producing the links of a response, see [PayloadConfig::links]
+#[derive(Clone)]
+pub(crate) struct LinkProvider(Arc<LinkFn>);
+
+type LinkFn = dyn Fn(&HttpRequest) -> Vec<Link> + Send + Sync;
+
+impl LinkProvider {
+    pub(crate) fn new(provider: Arc<LinkFn>) -> Self {
+        Self(provider)
+    }
+
+    pub(crate) fn links(&self, req: &HttpRequest) -> Vec<Link> {
+        (self.0)(req)
+    }
+}
+
+impl fmt::Debug for LinkProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LinkProvider")
+    }
+}
+
+/// Links in the HAL representation, an object with a member per relation.
+/// Relations with multiple links are arrays of link objects
+#[cfg(feature = "json")]
+struct HalLinks<'a>(&'a [Link]);
+
+#[cfg(feature = "json")]
+impl serde::Serialize for HalLinks<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{SerializeMap, SerializeStruct};
+
+        struct Href<'a>(&'a str);
+
+        impl serde::Serialize for Href<'_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut link = serializer.serialize_struct("link", 1)?;
+                link.serialize_field("href", self.0)?;
+                link.end()
+            }
+        }
+
+        // Group the links by relation, in the order the relations first occur
+        let mut relations: Vec<(&str, Vec<Href>)> = Vec::new();
+        for link in self.0 {
+            match relations.iter_mut().find(|(rel, _)| rel.eq(&link.rel)) {
+                Some((_, hrefs)) => hrefs.push(Href(&link.href)),
+                None => relations.push((&link.rel, vec![Href(&link.href)])),
+            }
+        }
+
+        let mut map = serializer.serialize_map(Some(relations.len()))?;
+        for (rel, hrefs) in &relations {
+            match hrefs.as_slice() {
+                [href] => map.serialize_entry(rel, href)?,
+                hrefs => map.serialize_entry(rel, hrefs)?,
+            }
+        }
+
+        map.end()
+    }
+}
+
+/// An object containing only the `_links` member
+#[cfg(feature = "json")]
+struct HalObject<'a>(HalLinks<'a>);
+
+#[cfg(feature = "json")]
+impl serde::Serialize for HalObject<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut object = serializer.serialize_struct("links", 1)?;
+        object.serialize_field("_links", &self.0)?;
+        object.end()
+    }
+}
+
+/// Add a `_links` member to a serialized JSON object, laid out like the rest of the object.
+/// Other JSON values are returned unchanged
+#[cfg(feature = "json")]
+fn inject_json(mut serialized: Vec<u8>, links: &[Link], config: &PayloadConfig) -> Result<Vec<u8>, SerializeError> {
+    let is_object = serialized.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    if is_object && config.sort_json_keys {
+        // Splicing would leave `_links` out of order, so the object is serialized again
+        let mut object: serde_json::Value = serde_json::from_slice(&serialized)?;
+        object["_links"] = serde_json::to_value(HalLinks(links))?;
+        return codec::serialize_json_value(&object, config);
+    }
+
+    let close = match serialized.iter().rposition(|b| *b == b'}') {
+        Some(close) if is_object => close,
+        _ => return Ok(serialized),
+    };
+
+    // The links object serialized in the same format, e.g. `{\n  "_links": {..}\n}`, of which everything
+    // after the opening brace replaces the closing brace of the serialized object
+    let links = codec::serialize_json(&HalObject(HalLinks(links)), config)?;
+    let open = links.iter().position(|b| *b == b'{').unwrap_or_default();
+
+    let end = serialized[..close]
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map(|end| end + 1)
+        .unwrap_or(close);
+    let is_empty = serialized[end - 1] == b'{';
+    serialized.truncate(end);
+    if !is_empty {
+        serialized.push(b',');
+    }
+    serialized.extend_from_slice(&links[open + 1..]);
+
+    Ok(serialized)
+}
+
+/// Escape a value for an XML attribute in double quotes
+#[cfg(feature = "xml")]
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('"', "&quot;")
+}
+
+/// Add `<link rel=".." href=".."/>` elements at the end of the root element of serialized XML
+#[cfg(feature = "xml")]
+fn inject_xml(serialized: Vec<u8>, links: &[Link], config: &PayloadConfig) -> Vec<u8> {
+    let xml = match String::from_utf8(serialized) {
+        Ok(xml) => xml,
+        Err(e) => return e.into_bytes(),
+    };
+
+    let indent = config
+        .xml_indent
+        .map(|(indent_char, indent_size)| indent_char.to_string().repeat(indent_size));
+    let elements: String = links
+        .iter()
+        .map(|link| {
+            let element = format!(
+                r#"<link rel="{}" href="{}"/>"#,
+                escape_attribute(&link.rel),
+                escape_attribute(&link.href)
+            );
+            match &indent {
+                Some(indent) => format!("{indent}{element}\n"),
+                None => element,
+            }
+        })
+        .collect();
+
+    let trimmed = xml.trim_end();
+    if trimmed.ends_with("/>") && !trimmed.contains("</") {
+        // A self-closing root element, e.g. `<Empty/>`
+        let name_end = trimmed[1..]
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .map(|end| end + 1);
+        let name = &trimmed[1..name_end.unwrap_or(trimmed.len() - 2)];
+        let separator = if indent.is_some() { "\n" } else { "" };
+        return format!(
+            "{}>{separator}{elements}</{name}>",
+            trimmed[..trimmed.len() - 2].trim_end()
+        )
+        .into_bytes();
+    }
+
+    match xml.rfind("</") {
+        Some(close) if indent.is_some() && !xml[..close].ends_with('\n') => {
+            format!("{}\n{elements}{}", &xml[..close], &xml[close..]).into_bytes()
+        }
+        Some(close) => format!("{}{elements}{}", &xml[..close], &xml[close..]).into_bytes(),
+        None => xml.into_bytes(),
+    }
+}
+
+/// Append the links to a serialized protobuf message as a repeated field with number `tag` of messages
+/// equivalent to
+/// ```proto
+/// message Link {
+///     string rel = 1;
+///     string href = 2;
+/// }
+/// ```
+#[cfg(feature = "protobuf")]
+fn inject_protobuf(mut serialized: Vec<u8>, links: &[Link], tag: u32) -> Vec<u8> {
+    use prost::encoding::{encode_key, encode_varint, string, WireType};
+
+    // Fields may be appended to an encoded message, repeated fields are merged by the decoder
+    for link in links {
+        encode_key(tag, WireType::LengthDelimited, &mut serialized);
+        encode_varint(
+            (string::encoded_len(1, &link.rel) + string::encoded_len(2, &link.href)) as u64,
+            &mut serialized,
+        );
+        string::encode(1, &link.rel, &mut serialized);
+        string::encode(2, &link.href, &mut serialized);
+    }
+
+    serialized
+}
+
+/// Inject the links into a response body serialized in `content_type`.
+///
+/// JSON objects get a HAL `_links` member, XML root elements `<link>` elements, and protobuf messages
+/// the field configured with [PayloadConfig::protobuf_links_field]. Other bodies are returned unchanged.
+pub(crate) fn inject(
+    serialized: Vec<u8>,
+    content_type: &ContentType,
+    links: &[Link],
+    config: &PayloadConfig,
+) -> Result<Vec<u8>, SerializeError> {
+    if links.is_empty() {
+        return Ok(serialized);
+    }
+
+    match content_type {
+        #[cfg(feature = "json")]
+        ContentType::Json => inject_json(serialized, links, config),
+        #[cfg(feature = "protobuf")]
+        ContentType::Protobuf => Ok(match config.protobuf_links_field {
+            Some(tag) => inject_protobuf(serialized, links, tag),
+            None => serialized,
+        }),
+        #[cfg(feature = "xml")]
+        ContentType::Xml => Ok(inject_xml(serialized, links, config)),
+        _ => Ok(serialized),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn links() -> Vec<Link> {
+        vec![
+            Link::new("self", "/books/1"),
+            Link::new("author", "/authors/1"),
+            Link::new("author", "/authors/2"),
+        ]
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_json_links() {
+        let inject = |body: &str, config: &PayloadConfig| {
+            String::from_utf8(inject(body.as_bytes().to_vec(), &ContentType::Json, &links(), config).unwrap()).unwrap()
+        };
+
+        let compact = PayloadConfig::default().json_format(crate::JsonFormat::Compact);
+        assert_eq!(
+            r#"{"title":"foo","_links":{"self":{"href":"/books/1"},"author":[{"href":"/authors/1"},{"href":"/authors/2"}]}}"#,
+            inject(r#"{"title":"foo"}"#, &compact)
+        );
+        let single = inject_json(b"{}".to_vec(), &[Link::new("self", "/books/1")], &compact).unwrap();
+        assert_eq!(br#"{"_links":{"self":{"href":"/books/1"}}}"#, single.as_slice());
+        assert_eq!("[1]", inject("[1]", &compact));
+
+        let pretty = inject("{\n  \"title\": \"foo\"\n}", &PayloadConfig::default());
+        let value: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+        assert_eq!("/authors/2", value["_links"]["author"][1]["href"]);
+        assert!(pretty.contains("\"title\": \"foo\",\n  \"_links\": {\n    \"self\": {"));
+    }
+
+    #[test]
+    #[cfg(feature = "xml")]
+    fn test_xml_links() {
+        let inject = |body: &str, config: &PayloadConfig| {
+            String::from_utf8(inject(body.as_bytes().to_vec(), &ContentType::Xml, &links(), config).unwrap()).unwrap()
+        };
+
+        let links = r#"<link rel="self" href="/books/1"/><link rel="author" href="/authors/1"/><link rel="author" href="/authors/2"/>"#;
+        let config = PayloadConfig::default();
+        assert_eq!(format!("<book><title>foo</title>{links}</book>"), inject("<book><title>foo</title></book>", &config));
+        assert_eq!(format!("<Empty>{links}</Empty>"), inject("<Empty/>", &config));
+
+        let indented = inject("<book>\n  <title>foo</title>\n</book>", &PayloadConfig::default().xml_indent(' ', 2));
+        assert!(indented.contains("  <title>foo</title>\n  <link rel=\"self\" href=\"/books/1\"/>\n"));
+        assert!(indented.ends_with("<link rel=\"author\" href=\"/authors/2\"/>\n</book>"));
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_response_links() {
+        use actix_web::test::TestRequest;
+
+        let config = PayloadConfig::default().links(|req| vec![Link::new("self", req.path())]);
+        let req = TestRequest::get()
+            .uri("/books/1")
+            .insert_header(("Accept", "application/json"))
+            .app_data(config)
+            .to_http_request();
+        let resp = crate::Payload::new("foo".to_string()).to_response(&req);
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        // Only objects get links
+        assert_eq!(r#""foo""#, body);
+
+        #[derive(serde_derive::Serialize, prost_derive::Message)]
+        struct Book {
+            #[prost(string, tag = "1")]
+            title: String,
+        }
+
+        let resp = crate::Payload::new(Book {
+            title: "foo".to_string(),
+        })
+        .to_response(&req);
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            serde_json::json!({"title": "foo", "_links": {"self": {"href": "/books/1"}}}),
+            value
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_protobuf_links() {
+        #[derive(prost_derive::Message)]
+        struct TestLink {
+            #[prost(string, tag = "1")]
+            rel: String,
+            #[prost(string, tag = "2")]
+            href: String,
+        }
+
+        #[derive(prost_derive::Message)]
+        struct TestPayload {
+            #[prost(string, tag = "1")]
+            title: String,
+            #[prost(message, repeated, tag = "15")]
+            links: Vec<TestLink>,
+        }
+
+        let body = prost::Message::encode_to_vec(&TestPayload {
+            title: "foo".to_string(),
+            links: Vec::new(),
+        });
+        let config = PayloadConfig::default().protobuf_links_field(15);
+        let injected = inject(body, &ContentType::Protobuf, &links(), &config).unwrap();
+
+        let decoded: TestPayload = prost::Message::decode(injected.as_slice()).unwrap();
+        assert_eq!("foo", decoded.title);
+        assert_eq!(
+            vec!["/books/1", "/authors/1", "/authors/2"],
+            decoded.links.iter().map(|l| l.href.as_str()).collect::<Vec<_>>()
+        );
+    }
+}
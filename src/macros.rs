@@ -0,0 +1,317 @@
+/// Declare a payload type, deriving the traits required by the enabled formats.
+/// Requires the `derive` feature.
+///
+/// With the `protobuf` feature, the type derives `Clone`, `PartialEq` and [prost::Message],
+/// which also implements `Debug` and `Default`, so fields need `#[prost(..)]` attributes.
+/// With the `json` or `xml` feature, the type derives `Serialize` and `Deserialize`.
+///
+/// The `Serialize` and `Deserialize` derives refer to `serde` through the re-export of this crate,
+/// so `serde` does not need to be a dependency, as long as this crate is not renamed in `Cargo.toml`.
+/// The [prost::Message] derive always refers to `::prost`, so `prost` must still be a dependency,
+/// with the same version as used by this crate.
+///
+/// # Example
+/// ```
+///     actix_multiresponse::payload_type! {
+///         pub struct User {
+///             #[prost(string, tag = "1")]
+///             pub name: String,
+///         }
+///     }
+/// ```
+#[cfg(all(feature = "derive", feature = "protobuf", any(feature = "json", feature = "xml")))]
+#[macro_export]
+macro_rules! payload_type {
+    ($(#[$meta:meta])* $vis:vis struct $($rest:tt)*) => {
+        $(#[$meta])*
+        #[derive(
+            Clone,
+            PartialEq,
+            $crate::prelude::Message,
+            $crate::prelude::Serialize,
+            $crate::prelude::Deserialize,
+        )]
+        #[serde(crate = "actix_multiresponse::serde")]
+        $vis struct $($rest)*
+    };
+}
+
+/// Declare a payload type, deriving the traits required by the enabled formats.
+/// Requires the `derive` feature.
+///
+/// With the `protobuf` feature, the type derives `Clone`, `PartialEq` and [prost::Message],
+/// which also implements `Debug` and `Default`, so fields need `#[prost(..)]` attributes.
+#[cfg(all(feature = "derive", feature = "protobuf", not(any(feature = "json", feature = "xml"))))]
+#[macro_export]
+macro_rules! payload_type {
+    ($(#[$meta:meta])* $vis:vis struct $($rest:tt)*) => {
+        $(#[$meta])*
+        #[derive(Clone, PartialEq, $crate::prelude::Message)]
+        $vis struct $($rest)*
+    };
+}
+
+/// Declare a payload type, deriving the traits required by the enabled formats.
+/// Requires the `derive` feature.
+///
+/// With the `json` or `xml` feature, the type derives `Debug`, `Default`, `Clone`, `PartialEq`,
+/// `Serialize` and `Deserialize`.
+///
+/// The derives refer to `serde` through the re-export of this crate, so `serde` does not need to be a dependency,
+/// as long as this crate is not renamed in `Cargo.toml`.
+#[cfg(all(feature = "derive", not(feature = "protobuf"), any(feature = "json", feature = "xml")))]
+#[macro_export]
+macro_rules! payload_type {
+    ($(#[$meta:meta])* $vis:vis struct $($rest:tt)*) => {
+        $(#[$meta])*
+        #[derive(
+            Debug,
+            Default,
+            Clone,
+            PartialEq,
+            $crate::prelude::Serialize,
+            $crate::prelude::Deserialize,
+        )]
+        #[serde(crate = "actix_multiresponse::serde")]
+        $vis struct $($rest)*
+    };
+}
+
+/// Register routes for the methods of a service trait, e.g. generated with a `prost-build` service generator,
+/// with [crate::Payload] request and response bodies. This brings gRPC like services to plain HTTP,
+/// with content negotiation.
+///
+/// Every method is routed as `POST {prefix}/{Method}`. The methods of the trait take `&self` and the request message,
+/// and return a future of `Result<Response, E>`, where `E` converts into [actix_web::Error].
+/// The macro generates a function taking the implementation of the service, returning a closure for [actix_web::App::configure].
+///
+/// The generated code refers to `actix_web`, which must be a dependency.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::payload_service;
+///
+///     #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+///     pub struct GetOrder {
+///         #[prost(string, tag = "1")]
+///         pub id: String,
+///     }
+///
+///     #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+///     pub struct Order {
+///         #[prost(string, tag = "1")]
+///         pub id: String,
+///     }
+///
+///     pub trait Orders {
+///         async fn get_order(&self, request: GetOrder) -> Result<Order, actix_web::Error>;
+///     }
+///
+///     payload_service! {
+///         /// The routes of the `shop.Orders` service
+///         pub fn orders_service(Orders) at "/shop.Orders" {
+///             rpc GetOrder(GetOrder) -> Order => get_order;
+///         }
+///     }
+///
+///     struct Shop;
+///
+///     impl Orders for Shop {
+///         async fn get_order(&self, request: GetOrder) -> Result<Order, actix_web::Error> {
+///             Ok(Order { id: request.id })
+///         }
+///     }
+///
+///     // Serves `POST /shop.Orders/GetOrder`
+///     let app = actix_web::App::new().configure(orders_service(Shop));
+/// ```
+#[macro_export]
+macro_rules! payload_service {
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($service:path) at $prefix:literal {
+            $(rpc $rpc:ident($request:ty) -> $response:ty => $method:ident;)*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis fn $name<S: $service + 'static>(service: S) -> impl FnOnce(&mut ::actix_web::web::ServiceConfig) {
+            move |cfg: &mut ::actix_web::web::ServiceConfig| {
+                let service = ::std::rc::Rc::new(service);
+                $(
+                    let rpc_service = ::std::rc::Rc::clone(&service);
+                    cfg.route(
+                        concat!($prefix, "/", stringify!($rpc)),
+                        ::actix_web::web::post().to(move |payload: $crate::Payload<$request>| {
+                            let service = ::std::rc::Rc::clone(&rpc_service);
+                            async move {
+                                service
+                                    .$method(payload.0)
+                                    .await
+                                    .map($crate::Payload::<$response>::new)
+                                    .map_err(::std::convert::Into::<::actix_web::Error>::into)
+                            }
+                        }),
+                    );
+                )*
+                let _ = service;
+            }
+        }
+    };
+}
+
+/// Declare types which can only be represented with serde, so they can be used with [crate::Payload] in builds with the
+/// `protobuf` feature enabled, without implementing [prost::Message].
+///
+/// This implements [crate::ProtobufSupport] with [crate::ProtobufSupport::supports_protobuf] returning false.
+/// [crate::Payload] rejects protobuf request bodies of these types with a HTTP 415 error and responds in another format,
+/// or with a HTTP 406 error if the `Accept` header only accepts protobuf. Without the `protobuf` feature, this does nothing.
+/// Only types of the calling crate can be declared.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::Payload;
+///
+///     #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+///     struct Report {
+///         rows: Vec<std::collections::HashMap<String, String>>,
+///     }
+///
+///     actix_multiresponse::serde_only!(Report);
+///
+///     async fn handler(report: Payload<Report>) -> Payload<Report> {
+///         report
+///     }
+/// ```
+#[cfg(feature = "protobuf")]
+#[macro_export]
+macro_rules! serde_only {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl $crate::ProtobufSupport for $ty {
+                fn encode_protobuf(&self) -> ::std::result::Result<::std::vec::Vec<u8>, $crate::SerializeError> {
+                    ::std::result::Result::Err($crate::SerializeError::Unserializable)
+                }
+
+                fn decode_protobuf(_: &[u8]) -> ::std::result::Result<Self, $crate::DeserializeError> {
+                    ::std::result::Result::Err($crate::DeserializeError::Undeserializable)
+                }
+
+                fn supports_protobuf() -> bool {
+                    false
+                }
+            }
+        )+
+    };
+}
+
+/// Declare types which can only be represented with serde. Without the `protobuf` feature every type can, so this does nothing.
+#[cfg(not(feature = "protobuf"))]
+#[macro_export]
+macro_rules! serde_only {
+    ($($ty:ty),+ $(,)?) => {};
+}
+
+/// Include the code generated by `prost-build` for the protobuf package `$package`, e.g. with the attributes of
+/// [crate::build::ProstAttributes]. Requires the `build-helper` feature.
+///
+/// # Example
+/// ```ignore
+///     pub mod proto {
+///         actix_multiresponse::include_protos!("shop");
+///     }
+/// ```
+#[cfg(feature = "build-helper")]
+#[macro_export]
+macro_rules! include_protos {
+    ($package:literal) => {
+        include!(concat!(env!("OUT_DIR"), "/", $package, ".rs"));
+    };
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::App;
+
+    #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+    struct Greeting {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    trait Greeter {
+        async fn greet(&self, request: Greeting) -> Result<Greeting, actix_web::Error>;
+    }
+
+    struct Hello;
+
+    impl Greeter for Hello {
+        async fn greet(&self, request: Greeting) -> Result<Greeting, actix_web::Error> {
+            Ok(Greeting {
+                name: format!("Hello, {}", request.name),
+            })
+        }
+    }
+
+    payload_service! {
+        fn greeter_service(Greeter) at "/greeter.Greeter" {
+            rpc Greet(Greeting) -> Greeting => greet;
+        }
+    }
+
+    #[actix_macros::test]
+    async fn test_payload_service() {
+        let app = init_service(App::new().configure(greeter_service(Hello))).await;
+        let req = TestRequest::post()
+            .uri("/greeter.Greeter/Greet")
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#"{"name":"foo"}"#)
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!(200, resp.status().as_u16());
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let greeting: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("Hello, foo", greeting["name"]);
+    }
+
+    #[derive(Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+    #[cfg(feature = "protobuf")]
+    struct Report {
+        title: String,
+    }
+
+    #[cfg(feature = "protobuf")]
+    serde_only!(Report);
+
+    #[cfg(feature = "protobuf")]
+    async fn report(report: crate::Payload<Report>) -> crate::Payload<Report> {
+        report
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "protobuf")]
+    async fn test_serde_only() {
+        use actix_web::web;
+
+        let app = init_service(App::new().route("/", web::post().to(report))).await;
+        let request = |content_type: &str, accept: &str, body: &'static str| {
+            TestRequest::post()
+                .insert_header(("Content-Type", content_type))
+                .insert_header(("Accept", accept))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let accept = "application/protobuf, application/json;q=0.5";
+        let resp = call_service(&app, request("application/json", accept, r#"{"title":"foo"}"#)).await;
+        assert_eq!(200, resp.status().as_u16());
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+
+        let resp = call_service(&app, request("application/json", "application/protobuf", r#"{"title":"foo"}"#)).await;
+        assert_eq!(406, resp.status().as_u16());
+
+        let resp = call_service(&app, request("application/protobuf", "application/json", "\n\x03foo")).await;
+        assert_eq!(415, resp.status().as_u16());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    ContentType, Payload, PayloadConfig, PayloadError, ProtobufSupport, SerdeSupportDeserialize, SerdeSupportSerialize,
+};
+
+use actix_web::dev::Payload as DevPayload;
+use actix_web::http::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, PROXY_AUTHORIZATION,
+};
+use actix_web::http::Method;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest};
+
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// A request body extracted like [Payload], which is also mirrored to the sender of [PayloadConfig::mirror] after it
+/// was extracted successfully, re-serialized in the configured format. This allows dark launching a service
+/// which expects another format with real traffic.
+///
+/// Without a mirror configured, this is equivalent to [Payload].
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{Mirrored, Payload};
+///
+///     async fn handler(payload: Mirrored<String>) -> Payload<String> {
+///         Payload::new(payload.into_inner())
+///     }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Mirrored<T>(pub T);
+
+/// A copy of a request, sent to the sender of [PayloadConfig::mirror]
+#[derive(Debug, Clone)]
+pub struct MirroredRequest {
+    pub method: Method,
+    /// The path and query of the request
+    pub path: String,
+    /// The headers of the request, with the `Content-Type` of the mirrored body and without a `Content-Length`.
+    /// The credentials of the request are removed, unless [PayloadConfig::mirror_credentials] is enabled
+    pub headers: HeaderMap,
+    /// The format of the mirrored body
+    pub content_type: ContentType,
+    pub body: Bytes,
+}
+
+/// The mirror of extracted [Mirrored] payloads, see [PayloadConfig::mirror]
+#[derive(Clone)]
+pub(crate) struct Mirror {
+    format: ContentType,
+    sender: Arc<SendFn>,
+}
+
+/// The headers carrying the credentials of a request, which are not mirrored by default
+const CREDENTIALS: [HeaderName; 3] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE];
+
+type SendFn = dyn Fn(MirroredRequest) -> Pin<Box<dyn Future<Output = ()>>> + Send + Sync;
+
+impl Mirror {
+    pub(crate) fn new<F, Fut>(format: ContentType, sender: F) -> Self
+    where
+        F: Fn(MirroredRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        Self {
+            format,
+            sender: Arc::new(move |request| Box::pin(sender(request))),
+        }
+    }
+
+    /// Re-serialize `payload` and spawn sending it, without waiting for it to be sent
+    fn send<T: ProtobufSupport + SerdeSupportSerialize>(&self, req: &HttpRequest, payload: &Payload<T>, config: &PayloadConfig) {
+        let body = match payload.serialize_with(self.format.clone(), config) {
+            Ok(body) => body,
+            Err(e) => {
+                log::warn!("Failed to serialize the mirrored request body as {}: {e}", self.format);
+                return;
+            }
+        };
+
+        let mut headers = req.headers().clone();
+        headers.remove(CONTENT_LENGTH);
+        if !config.mirror_credentials {
+            for name in &CREDENTIALS {
+                headers.remove(name);
+            }
+        }
+        if let Some(media_type) = self.format.media_type() {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(media_type));
+        }
+
+        let request = MirroredRequest {
+            method: req.method().clone(),
+            path: req.uri().path_and_query().map(|pq| pq.to_string()).unwrap_or_default(),
+            headers,
+            content_type: self.format.clone(),
+            body: body.into(),
+        };
+
+        actix_rt::spawn((self.sender)(request));
+    }
+}
+
+impl fmt::Debug for Mirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mirror").field("format", &self.format).finish_non_exhaustive()
+    }
+}
+
+impl<T> Mirrored<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Mirrored<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Mirrored<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for Mirrored<T>
+where
+    T: 'static + Send + SerdeSupportDeserialize + SerdeSupportSerialize + ProtobufSupport,
+{
+    type Error = PayloadError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut DevPayload) -> Self::Future {
+        let req = req.clone();
+        let extract = Payload::<T>::from_request(&req, payload);
+
+        Box::pin(async move {
+            let payload = extract.await?;
+            let config = PayloadConfig::from_req(&req);
+            if let Some(mirror) = &config.mirror {
+                mirror.send(&req, &payload, config);
+            }
+
+            Ok(Self(payload.0))
+        })
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::sync::Mutex;
+
+    #[actix_macros::test]
+    async fn test_mirror() {
+        let mirrored = Arc::new(Mutex::new(Vec::new()));
+        let sent = mirrored.clone();
+        let config = PayloadConfig::default().mirror(ContentType::Protobuf, move |request| {
+            let sent = sent.clone();
+            async move { sent.lock().unwrap().push(request) }
+        });
+
+        let (req, mut payload) = TestRequest::post()
+            .uri("/books?draft=true")
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("X-Request-Id", "1"))
+            .insert_header(("Authorization", "Bearer secret"))
+            .insert_header(("Cookie", "session=secret"))
+            .app_data(config)
+            .set_payload(r#""foo""#)
+            .to_http_parts();
+        let extracted = Mirrored::<String>::from_request(&req, &mut payload).await.unwrap();
+        assert_eq!("foo", extracted.0);
+
+        actix_rt::task::yield_now().await;
+        let mirrored = mirrored.lock().unwrap();
+        assert_eq!(1, mirrored.len());
+        assert_eq!("/books?draft=true", mirrored[0].path);
+        assert_eq!("application/protobuf", mirrored[0].headers.get(CONTENT_TYPE).unwrap());
+        assert_eq!("1", mirrored[0].headers.get("X-Request-Id").unwrap());
+        assert!(mirrored[0].headers.get(CONTENT_LENGTH).is_none());
+        assert!(mirrored[0].headers.get(AUTHORIZATION).is_none());
+        assert!(mirrored[0].headers.get(COOKIE).is_none());
+        assert_eq!(Payload::new("foo".to_string()).serialize(ContentType::Protobuf).unwrap(), mirrored[0].body);
+    }
+
+    #[actix_macros::test]
+    async fn test_mirror_credentials() {
+        let mirrored = Arc::new(Mutex::new(Vec::new()));
+        let sent = mirrored.clone();
+        let config = PayloadConfig::default()
+            .mirror(ContentType::Protobuf, move |request| {
+                let sent = sent.clone();
+                async move { sent.lock().unwrap().push(request) }
+            })
+            .mirror_credentials(true);
+
+        let (req, mut payload) = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("Authorization", "Bearer secret"))
+            .app_data(config)
+            .set_payload(r#""foo""#)
+            .to_http_parts();
+        Mirrored::<String>::from_request(&req, &mut payload).await.unwrap();
+
+        actix_rt::task::yield_now().await;
+        assert_eq!("Bearer secret", mirrored.lock().unwrap()[0].headers.get(AUTHORIZATION).unwrap());
+    }
+
+    #[actix_macros::test]
+    async fn test_failed_extraction_not_mirrored() {
+        let mirrored = Arc::new(Mutex::new(Vec::new()));
+        let sent = mirrored.clone();
+        let config = PayloadConfig::default().mirror(ContentType::Protobuf, move |request| {
+            let sent = sent.clone();
+            async move { sent.lock().unwrap().push(request) }
+        });
+
+        let (req, mut payload) = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .app_data(config)
+            .set_payload("foo")
+            .to_http_parts();
+        assert!(Mirrored::<String>::from_request(&req, &mut payload).await.is_err());
+
+        actix_rt::task::yield_now().await;
+        assert!(mirrored.lock().unwrap().is_empty());
+    }
+}
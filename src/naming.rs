@@ -0,0 +1,663 @@
+//! Renaming the fields of JSON objects, see [crate::PayloadConfig::json_naming].
+//!
+//! The serializer and deserializer are wrapped, so only the fields of structs are renamed, while the keys of maps
+//! are kept. Fields of `#[serde(flatten)]` structs and of internally tagged or untagged enums are buffered by serde
+//! as maps, and are not renamed.
+
+use crate::strict::{forward_deserialize, forward_visit};
+use crate::JsonNaming;
+
+use serde::de::value::{BorrowedStrDeserializer, StringDeserializer};
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+/// The characters of the camelCase form of a snake_case field name
+fn camel_chars(field: &str) -> impl Iterator<Item = char> + '_ {
+    let rest = field.trim_start_matches('_');
+    let mut upper = false;
+
+    field[..field.len() - rest.len()]
+        .chars()
+        .chain(rest.chars().filter_map(move |c| {
+            if c == '_' {
+                upper = true;
+                None
+            } else if upper {
+                upper = false;
+                Some(c.to_ascii_uppercase())
+            } else {
+                Some(c)
+            }
+        }))
+}
+
+/// The camelCase names of fields, leaked once per field name, as serializers require static field names
+static CAMEL_NAMES: Mutex<BTreeMap<&'static str, &'static str>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// The names of [CAMEL_NAMES] used on this thread, so serializing a field does not lock
+    static LOCAL_CAMEL_NAMES: RefCell<BTreeMap<&'static str, &'static str>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// The camelCase form of a field name
+fn camel_name(field: &'static str) -> &'static str {
+    if !field.trim_start_matches('_').contains('_') {
+        return field;
+    }
+
+    LOCAL_CAMEL_NAMES.with(|local| {
+        *local.borrow_mut().entry(field).or_insert_with(|| {
+            let mut names = CAMEL_NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+            names
+                .entry(field)
+                .or_insert_with(|| Box::leak(camel_chars(field).collect::<String>().into_boxed_str()))
+        })
+    })
+}
+
+/// A value serialized with the field names of structs in camelCase
+pub(crate) struct CamelCase<'a, T: ?Sized>(pub(crate) &'a T);
+
+impl<T: Serialize + ?Sized> Serialize for CamelCase<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(CamelCaseSerializer(serializer))
+    }
+}
+
+/// Convert a value to a JSON value, applying the naming policy
+pub(crate) fn to_value<T: Serialize + ?Sized>(value: &T, naming: JsonNaming) -> serde_json::Result<serde_json::Value> {
+    match naming {
+        JsonNaming::Preserve => serde_json::to_value(value),
+        JsonNaming::CamelCase => serde_json::to_value(CamelCase(value)),
+    }
+}
+
+/// Wrap a deserializer of which the structs have camelCase field names
+pub(crate) fn camel_case<'de, D: Deserializer<'de>>(deserializer: D) -> impl Deserializer<'de, Error = D::Error> {
+    CamelCaseDeserializer { de: deserializer }
+}
+
+/// A serializer renaming the fields of structs, and wrapping all nested values
+struct CamelCaseSerializer<S>(S);
+
+/// A compound value of a [CamelCaseSerializer], wrapping its elements
+struct Compound<C>(C);
+
+/// Forward the `serialize_*` methods of [CamelCaseSerializer] for values without nested values
+macro_rules! forward_serialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method(self, $($arg: $ty),*) -> Result<Self::Ok, Self::Error> {
+                self.0.$method($($arg),*)
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for CamelCaseSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<S::SerializeSeq>;
+    type SerializeTuple = Compound<S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<S::SerializeTupleVariant>;
+    type SerializeMap = Compound<S::SerializeMap>;
+    type SerializeStruct = Compound<S::SerializeStruct>;
+    type SerializeStructVariant = Compound<S::SerializeStructVariant>;
+
+    forward_serialize! {
+        serialize_bool(v: bool);
+        serialize_i8(v: i8);
+        serialize_i16(v: i16);
+        serialize_i32(v: i32);
+        serialize_i64(v: i64);
+        serialize_i128(v: i128);
+        serialize_u8(v: u8);
+        serialize_u16(v: u16);
+        serialize_u32(v: u32);
+        serialize_u64(v: u64);
+        serialize_u128(v: u128);
+        serialize_f32(v: f32);
+        serialize_f64(v: f64);
+        serialize_char(v: char);
+        serialize_str(v: &str);
+        serialize_bytes(v: &[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(name: &'static str);
+        serialize_unit_variant(name: &'static str, variant_index: u32, variant: &'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        self.0.serialize_some(&CamelCase(value))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.0.serialize_newtype_struct(name, &CamelCase(value))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.0
+            .serialize_newtype_variant(name, variant_index, variant, &CamelCase(value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        self.0.serialize_seq(len).map(Compound)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.0.serialize_tuple(len).map(Compound)
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.0.serialize_tuple_struct(name, len).map(Compound)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        self.0
+            .serialize_tuple_variant(name, variant_index, variant, len)
+            .map(Compound)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        self.0.serialize_map(len).map(Compound)
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        self.0.serialize_struct(name, len).map(Compound)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        self.0
+            .serialize_struct_variant(name, variant_index, variant, len)
+            .map(Compound)
+    }
+
+    fn collect_str<T: fmt::Display + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        self.0.collect_str(value)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+/// Implement a `Serialize*` trait of elements for [Compound]
+macro_rules! compound_elements {
+    ($($serialize:ident::$method:ident;)*) => {
+        $(
+            impl<C: ser::$serialize> ser::$serialize for Compound<C> {
+                type Ok = C::Ok;
+                type Error = C::Error;
+
+                fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+                    self.0.$method(&CamelCase(value))
+                }
+
+                fn end(self) -> Result<Self::Ok, Self::Error> {
+                    self.0.end()
+                }
+            }
+        )*
+    };
+}
+
+compound_elements! {
+    SerializeSeq::serialize_element;
+    SerializeTuple::serialize_element;
+    SerializeTupleStruct::serialize_field;
+    SerializeTupleVariant::serialize_field;
+}
+
+/// Implement a `Serialize*` trait of fields for [Compound], renaming the fields
+macro_rules! compound_fields {
+    ($($serialize:ident;)*) => {
+        $(
+            impl<C: ser::$serialize> ser::$serialize for Compound<C> {
+                type Ok = C::Ok;
+                type Error = C::Error;
+
+                fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error> {
+                    self.0.serialize_field(camel_name(key), &CamelCase(value))
+                }
+
+                fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+                    self.0.skip_field(camel_name(key))
+                }
+
+                fn end(self) -> Result<Self::Ok, Self::Error> {
+                    self.0.end()
+                }
+            }
+        )*
+    };
+}
+
+compound_fields! {
+    SerializeStruct;
+    SerializeStructVariant;
+}
+
+impl<C: ser::SerializeMap> ser::SerializeMap for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.0.serialize_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.0.serialize_value(&CamelCase(value))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.0.end()
+    }
+}
+
+/// A deserializer of values of which the structs have camelCase field names
+struct CamelCaseDeserializer<D> {
+    de: D,
+}
+
+impl<D> CamelCaseDeserializer<D> {
+    fn wrap<V>(self, visitor: V) -> (D, CamelCaseVisitor<V>) {
+        (self.de, CamelCaseVisitor { visitor, fields: None })
+    }
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for CamelCaseDeserializer<D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_struct(
+            name,
+            fields,
+            CamelCaseVisitor {
+                visitor,
+                fields: Some(fields),
+            },
+        )
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_ignored_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.de.is_human_readable()
+    }
+}
+
+/// The visitor of a value, wrapping the access to nested values.
+/// The keys of maps are renamed to the `fields` if the value is a struct
+struct CamelCaseVisitor<V> {
+    visitor: V,
+    fields: Option<&'static [&'static str]>,
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for CamelCaseVisitor<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.visitor.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(v: bool);
+        visit_i8(v: i8);
+        visit_i16(v: i16);
+        visit_i32(v: i32);
+        visit_i64(v: i64);
+        visit_i128(v: i128);
+        visit_u8(v: u8);
+        visit_u16(v: u16);
+        visit_u32(v: u32);
+        visit_u64(v: u64);
+        visit_u128(v: u128);
+        visit_f32(v: f32);
+        visit_f64(v: f64);
+        visit_char(v: char);
+        visit_str(v: &str);
+        visit_borrowed_str(v: &'de str);
+        visit_string(v: String);
+        visit_bytes(v: &[u8]);
+        visit_borrowed_bytes(v: &'de [u8]);
+        visit_byte_buf(v: Vec<u8>);
+        visit_none();
+        visit_unit();
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_some(CamelCaseDeserializer { de: deserializer })
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor
+            .visit_newtype_struct(CamelCaseDeserializer { de: deserializer })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_seq(CamelCaseSeq { seq })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_map(CamelCaseMap {
+            map,
+            fields: self.fields,
+        })
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_enum(CamelCaseEnum { data })
+    }
+}
+
+/// A seed deserializing a nested value
+struct CamelCaseSeed<S> {
+    seed: S,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for CamelCaseSeed<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.seed.deserialize(CamelCaseDeserializer { de: deserializer })
+    }
+}
+
+/// The elements of a sequence
+struct CamelCaseSeq<A> {
+    seq: A,
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for CamelCaseSeq<A> {
+    type Error = A::Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error> {
+        self.seq.next_element_seed(CamelCaseSeed { seed })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.seq.size_hint()
+    }
+}
+
+/// The entries of a map, or the fields of a struct
+struct CamelCaseMap<A> {
+    map: A,
+    fields: Option<&'static [&'static str]>,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for CamelCaseMap<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        match self.fields {
+            Some(fields) => self.map.next_key_seed(FieldSeed { seed, fields }),
+            None => self.map.next_key_seed(seed),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, Self::Error> {
+        self.map.next_value_seed(CamelCaseSeed { seed })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint()
+    }
+}
+
+/// A seed deserializing the key of a struct field, replacing the camelCase name with the name of the field.
+/// Keys which do not match a field are passed on unchanged
+struct FieldSeed<S> {
+    seed: S,
+    fields: &'static [&'static str],
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for FieldSeed<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        match deserializer.deserialize_str(KeyVisitor { fields: self.fields })? {
+            Key::Field(field) => self.seed.deserialize(BorrowedStrDeserializer::new(field)),
+            Key::Borrowed(key) => self.seed.deserialize(BorrowedStrDeserializer::new(key)),
+            Key::Owned(key) => self.seed.deserialize(StringDeserializer::new(key)),
+        }
+    }
+}
+
+/// A key of a struct, which is only copied if it is not one of the fields and cannot be borrowed
+enum Key<'de> {
+    Field(&'static str),
+    Borrowed(&'de str),
+    Owned(String),
+}
+
+/// Look up the field a camelCase key is the name of
+struct KeyVisitor {
+    fields: &'static [&'static str],
+}
+
+impl KeyVisitor {
+    fn field(&self, key: &str) -> Option<&'static str> {
+        self.fields.iter().copied().find(|field| camel_chars(field).eq(key.chars()))
+    }
+}
+
+impl<'de> Visitor<'de> for KeyVisitor {
+    type Value = Key<'de>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a field name")
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, key: &'de str) -> Result<Self::Value, E> {
+        Ok(self.field(key).map_or(Key::Borrowed(key), Key::Field))
+    }
+
+    fn visit_str<E: de::Error>(self, key: &str) -> Result<Self::Value, E> {
+        Ok(self.field(key).map_or_else(|| Key::Owned(key.to_string()), Key::Field))
+    }
+
+    fn visit_string<E: de::Error>(self, key: String) -> Result<Self::Value, E> {
+        Ok(self.field(&key).map_or(Key::Owned(key), Key::Field))
+    }
+}
+
+/// The variant of an enum
+struct CamelCaseEnum<A> {
+    data: A,
+}
+
+impl<'de, A: EnumAccess<'de>> EnumAccess<'de> for CamelCaseEnum<A> {
+    type Error = A::Error;
+    type Variant = CamelCaseEnum<A::Variant>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self::Variant), Self::Error> {
+        let (value, data) = self.data.variant_seed(seed)?;
+        Ok((value, CamelCaseEnum { data }))
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for CamelCaseEnum<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.data.unit_variant()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Self::Error> {
+        self.data.newtype_variant_seed(CamelCaseSeed { seed })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.data.tuple_variant(len, CamelCaseVisitor { visitor, fields: None })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.data.struct_variant(
+            fields,
+            CamelCaseVisitor {
+                visitor,
+                fields: Some(fields),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+    struct Profile {
+        display_name: String,
+        _private_note: Option<String>,
+        nested_items: Vec<Item>,
+        labels: HashMap<String, String>,
+    }
+
+    #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+    enum Item {
+        Named { item_name: String },
+        Plain(u32),
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            display_name: "foo".to_string(),
+            _private_note: None,
+            nested_items: vec![
+                Item::Named {
+                    item_name: "bar".to_string(),
+                },
+                Item::Plain(1),
+            ],
+            labels: HashMap::from([("snake_key".to_string(), "baz".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_camel_chars() {
+        assert_eq!("displayName", camel_chars("display_name").collect::<String>());
+        assert_eq!("_links", camel_chars("_links").collect::<String>());
+        assert_eq!("__privateNote", camel_chars("__private_note").collect::<String>());
+        assert_eq!("name", camel_name("name"));
+    }
+
+    #[test]
+    fn test_camel_name_leaked_once() {
+        let name = camel_name("display_name");
+        assert_eq!("displayName", name);
+        assert!(std::ptr::eq(name, camel_name("display_name")));
+
+        let other = std::thread::spawn(|| camel_name("display_name")).join().unwrap();
+        assert!(std::ptr::eq(name, other));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let value = to_value(&profile(), JsonNaming::CamelCase).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "displayName": "foo",
+                "_privateNote": null,
+                "nestedItems": [{"Named": {"itemName": "bar"}}, {"Plain": 1}],
+                "labels": {"snake_key": "baz"},
+            }),
+            value
+        );
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let json = r#"{"displayName":"foo","nestedItems":[{"Named":{"itemName":"bar"}},{"Plain":1}],"labels":{"snake_key":"baz"}}"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let deserialized = Profile::deserialize(camel_case(&mut deserializer)).unwrap();
+
+        assert_eq!(profile(), deserialized);
+    }
+
+    #[test]
+    fn test_deny_unknown_fields() {
+        use crate::json_backend::{Backend, JsonBackend};
+
+        let json = br#"{"displayName":"foo","nestedItems":[],"labels":{},"display_name":"bar"}"#;
+        let error = Backend::from_slice::<Profile>(json, true, JsonNaming::CamelCase, false).unwrap_err();
+        assert!(error.to_string().contains("display_name"), "{error}");
+
+        let json = br#"{"displayName":"foo","nestedItems":[],"labels":{"snake_key":"baz"}}"#;
+        assert!(Backend::from_slice::<Profile>(json, true, JsonNaming::CamelCase, false).is_ok());
+    }
+}
@@ -0,0 +1,640 @@
+use crate::{ContentType, PayloadConfig, PayloadError};
+use crate::headers::merged_header_value;
+
+use actix_web::dev::Payload as DevPayload;
+use actix_web::http::header::{HeaderValue, ACCEPT, COOKIE, SET_COOKIE};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+
+/// The result of content negotiation for a request.
+///
+/// Negotiation is performed once per request and stored in the request extensions,
+/// subsequent calls to [Negotiation::from_request] return the cached result.
+/// Both the extractor and the responder of [crate::Payload] use this, so the
+/// headers are only parsed once.
+///
+/// This type can also be used as an extractor in handlers.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Negotiation {
+    /// The format indicated by the `Content-Type` header
+    pub content_type: ContentType,
+    /// The format indicated by the `Accept` header
+    pub accept: ContentType,
+}
+
+impl Negotiation {
+    /// Get the negotiation result for the request.
+    /// If the request was negotiated before, the cached result is returned.
+    ///
+    /// Unlike [Negotiation::try_from_request], invalid headers are treated
+    /// as if they indicate an unsupported format.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        match Self::try_from_request(req) {
+            Ok(negotiation) => negotiation,
+            Err(_) => Self {
+                content_type: ContentType::from_request_content_type(req),
+                accept: ContentType::from_request_accepts(req),
+            },
+        }
+    }
+
+    /// Get the negotiation result for the request.
+    /// If the request was negotiated before, the cached result is returned.
+    ///
+    /// # Errors
+    ///
+    /// If the `Content-Type` header occurs more than once, or if the `Content-Type` or `Accept`
+    /// headers are not valid UTF-8. Failed negotiations are not cached.
+    pub fn try_from_request(req: &HttpRequest) -> Result<Self, PayloadError> {
+        if let Some(negotiation) = req.extensions().get::<Self>() {
+            return Ok(negotiation.clone());
+        }
+
+        let content_type = ContentType::try_from_request_content_type(req)?;
+        if req.headers().get_all("Accept").any(|value| value.to_str().is_err()) {
+            return Err(PayloadError::MalformedHeader("Accept".to_string()));
+        }
+
+        let config = PayloadConfig::from_req(req);
+        let content_type = config.request_format(content_type, req.headers());
+
+        let preference = config.preference_order(&content_type);
+        // Multiple Accept headers are merged as one list
+        let accept = merged_header_value(req.headers(), "Accept")
+            .map(|hv_str| ContentType::from_accept_value_with(&hv_str, &preference, config.strict_accept))
+            .unwrap_or(ContentType::Other(String::new()));
+
+        let negotiation = Self {
+            content_type,
+            accept,
+        };
+
+        req.extensions_mut().insert(negotiation.clone());
+        Ok(negotiation)
+    }
+
+    /// The format the response should be serialized in.
+    ///
+    /// The `Accept` header is used if it indicates a supported format,
+    /// if not, the `Content-Type` header is used. If both are missing or invalid,
+    /// the default format is used.
+    pub fn response_format(&self) -> ContentType {
+        if !self.accept.is_other() {
+            self.accept.clone()
+        } else if !self.content_type.is_other() {
+            self.content_type.clone()
+        } else {
+            ContentType::default()
+        }
+    }
+}
+
+/// Force the response format of a request, bypassing header negotiation.
+///
+/// Insert it into the request extensions, e.g. from middleware which determines the format from the route.
+/// The responder of [crate::Payload] honors it over the `Accept` and `Content-Type` headers.
+/// Unsupported formats are ignored.
+/// ```
+/// # #[cfg(feature = "json")]
+/// # fn main() {
+///     use actix_multiresponse::{ContentType, ForcedFormat};
+///     use actix_web::{HttpMessage, HttpRequest};
+///
+///     fn force_json(req: &HttpRequest) {
+///         req.extensions_mut().insert(ForcedFormat(ContentType::Json));
+///     }
+/// # }
+/// # #[cfg(not(feature = "json"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ForcedFormat(pub ContentType);
+
+/// The format the response of [crate::Payload] to the request is serialized in,
+/// so helpers can serialize fragments of a response consistently with the response itself.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{current_format, Payload, SerializeError};
+///     use actix_web::HttpRequest;
+///
+///     fn serialize_fragment(req: &HttpRequest, fragment: String) -> Result<Vec<u8>, SerializeError> {
+///         Payload::new(fragment).serialize(current_format(req))
+///     }
+/// ```
+pub fn current_format(req: &HttpRequest) -> ContentType {
+    response_format(req)
+}
+
+/// The format the response to the request should be serialized in, picked by the [Negotiator] of the configuration
+pub(crate) fn response_format(req: &HttpRequest) -> ContentType {
+    PayloadConfig::from_req(req).negotiator.pick(req)
+}
+
+/// A source of the response format, see [Negotiator]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub enum NegotiationRule {
+    /// A [ForcedFormat] in the request extensions
+    Forced,
+    /// The format remembered in the cookie of [PayloadConfig::format_cookie],
+    /// if the `Accept` header does not explicitly indicate a supported format
+    Cookie,
+    /// The format indicated by the `Accept` header, see [Negotiation::accept]
+    Accept,
+    /// The format of the request body, see [Negotiation::content_type]
+    RequestFormat,
+    /// A fixed format
+    Format(ContentType),
+}
+
+/// Picks the format responses are serialized in, used by the responders of this crate.
+///
+/// The rules are tried in order, the first rule which results in a format supported by the
+/// [PayloadConfig] is used. If none does, the default format is used.
+/// By default, a [ForcedFormat] takes precedence over the cookie of [PayloadConfig::format_cookie], the `Accept` header
+/// and the format of the request body, in that order.
+/// Set a custom negotiator with [PayloadConfig::negotiator].
+///
+/// # Example
+/// ```
+/// # #[cfg(all(feature = "json", feature = "protobuf"))]
+/// # fn main() {
+///     use actix_multiresponse::{ContentType, NegotiationRule, Negotiator};
+///     use actix_web::test::TestRequest;
+///
+///     // Answer in the format of the request body, regardless of the `Accept` header
+///     let negotiator = Negotiator::new([NegotiationRule::Forced, NegotiationRule::RequestFormat, NegotiationRule::Accept]);
+///
+///     let req = TestRequest::post()
+///         .insert_header(("Content-Type", "application/protobuf"))
+///         .insert_header(("Accept", "application/json"))
+///         .to_http_request();
+///     assert_eq!(ContentType::Protobuf, negotiator.pick(&req));
+/// # }
+/// # #[cfg(not(all(feature = "json", feature = "protobuf")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Negotiator {
+    rules: Cow<'static, [NegotiationRule]>,
+}
+
+/// The rules of the default [Negotiator]
+const DEFAULT_RULES: &[NegotiationRule] = &[
+    NegotiationRule::Forced,
+    NegotiationRule::Cookie,
+    NegotiationRule::Accept,
+    NegotiationRule::RequestFormat,
+];
+
+impl Negotiator {
+    /// A negotiator trying `rules` in order
+    pub fn new<I: IntoIterator<Item = NegotiationRule>>(rules: I) -> Self {
+        Self {
+            rules: Cow::Owned(rules.into_iter().collect()),
+        }
+    }
+
+    /// Try `rule` after the existing rules
+    pub fn then(mut self, rule: NegotiationRule) -> Self {
+        self.rules.to_mut().push(rule);
+        self
+    }
+
+    /// The rules, in the order they are tried
+    pub fn rules(&self) -> &[NegotiationRule] {
+        &self.rules
+    }
+
+    /// The format the response to `req` should be serialized in
+    pub fn pick(&self, req: &HttpRequest) -> ContentType {
+        let config = PayloadConfig::from_req(req);
+        let negotiation = Negotiation::from_request(req);
+
+        self.rules
+            .iter()
+            .filter_map(|rule| match rule {
+                NegotiationRule::Forced => req.extensions().get::<ForcedFormat>().map(|forced| forced.0.clone()),
+                NegotiationRule::Cookie => cookie_format(req),
+                NegotiationRule::Accept => Some(negotiation.accept.clone()),
+                NegotiationRule::RequestFormat => Some(negotiation.content_type.clone()),
+                NegotiationRule::Format(content_type) => Some(content_type.clone()),
+            })
+            .find(|content_type| config.supports(content_type))
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Negotiator {
+    fn default() -> Self {
+        DEFAULT_NEGOTIATOR.clone()
+    }
+}
+
+pub(crate) const DEFAULT_NEGOTIATOR: Negotiator = Negotiator {
+    rules: Cow::Borrowed(DEFAULT_RULES),
+};
+
+/// How requests of which the `Accept` header only lists unsupported formats are answered, see [PayloadConfig::unsupported_accept]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum UnsupportedAccept {
+    /// Respond in the format picked by the [Negotiator] from the remaining rules, usually the format of the request body
+    #[default]
+    Fallback,
+    /// Respond with a HTTP 406 error, as RFC 9110 allows
+    Reject,
+}
+
+/// The HTTP 406 response to the request, if its `Accept` header only lists unsupported formats
+/// and the configuration rejects such requests, see [PayloadConfig::unsupported_accept]
+pub(crate) fn not_acceptable(req: &HttpRequest) -> Option<HttpResponse> {
+    let config = PayloadConfig::from_req(req);
+    if config.unsupported_accept != UnsupportedAccept::Reject || !req.headers().contains_key(ACCEPT) {
+        return None;
+    }
+
+    let forced = req.extensions().get::<ForcedFormat>().is_some_and(|forced| config.supports(&forced.0));
+    let remembered = cookie_format(req).is_some_and(|format| config.supports(&format));
+    if forced || remembered || !Negotiation::from_request(req).accept.is_other() {
+        return None;
+    }
+
+    Some(not_acceptable_response(config, &config.preference_order(&ContentType::default())))
+}
+
+/// The format the response to the request should be serialized in out of the formats for which `allows` is true,
+/// see [response_format_among], or a HTTP 406 response if the `Accept` header of the request accepts none of them
+pub(crate) fn response_format_among_or_reject<F: Fn(&ContentType) -> bool>(
+    req: &HttpRequest,
+    allows: F,
+) -> Result<ContentType, Box<HttpResponse>> {
+    let config = PayloadConfig::from_req(req);
+    let allowed: Vec<ContentType> = config
+        .preference_order(&ContentType::default())
+        .into_iter()
+        .filter(|content_type| allows(content_type))
+        .collect();
+
+    let accepted = merged_header_value(req.headers(), "Accept")
+        .map(|hv_str| ContentType::from_accept_value_with(&hv_str, &allowed, config.strict_accept));
+    if accepted.is_some_and(|accepted| accepted.is_other()) {
+        return Err(Box::new(not_acceptable_response(config, &allowed)));
+    }
+
+    Ok(response_format_among(req, allows).unwrap_or_default())
+}
+
+/// A HTTP 406 response listing the `supported` formats
+fn not_acceptable_response(config: &PayloadConfig, supported: &[ContentType]) -> HttpResponse {
+    let supported: Vec<&str> = supported.iter().filter_map(ContentType::media_type).collect();
+    let mut response = HttpResponse::NotAcceptable().body(format!(
+        "None of the formats in the Accept header are supported, supported are: {}",
+        supported.join(", ")
+    ));
+    config.apply_response_headers(&mut response);
+
+    response
+}
+
+/// The format the response to the request should be serialized in, out of the formats for which `allows` is true.
+///
+/// A supported and allowed [ForcedFormat] takes precedence. Otherwise, the `Accept` header is used if it indicates
+/// an allowed format, if not, the format of the request body is used. If that is not allowed either,
+/// the most preferred allowed format is used. If no enabled format is allowed, `None` is returned.
+pub(crate) fn response_format_among<F: Fn(&ContentType) -> bool>(req: &HttpRequest, allows: F) -> Option<ContentType> {
+    let config = PayloadConfig::from_req(req);
+    let forced = req.extensions().get::<ForcedFormat>().cloned();
+    if let Some(ForcedFormat(content_type)) = forced {
+        if config.supports(&content_type) && allows(&content_type) {
+            return Some(content_type);
+        }
+    }
+
+    let negotiation = Negotiation::from_request(req);
+    let preference: Vec<ContentType> = config
+        .preference_order(&negotiation.content_type)
+        .into_iter()
+        .filter(|content_type| allows(content_type))
+        .collect();
+
+    let accept = merged_header_value(req.headers(), "Accept")
+        .map(|hv_str| ContentType::from_accept_value_with(&hv_str, &preference, config.strict_accept))
+        .unwrap_or(ContentType::Other(String::new()));
+
+    if !accept.is_other() {
+        Some(accept)
+    } else if !negotiation.content_type.is_other() && allows(&negotiation.content_type) {
+        Some(negotiation.content_type)
+    } else {
+        preference.into_iter().next()
+    }
+}
+
+/// The format remembered in the cookie of [PayloadConfig::format_cookie],
+/// if the `Accept` header of the request does not explicitly indicate a supported format
+fn cookie_format(req: &HttpRequest) -> Option<ContentType> {
+    let config = PayloadConfig::from_req(req);
+    let name = config.format_cookie.as_deref()?;
+    if config.explicit_accept_format(req.headers()).is_some() {
+        return None;
+    }
+
+    cookie_value(req, name).and_then(|value| value.parse().ok())
+}
+
+/// Remember `content_type` in the cookie of [PayloadConfig::format_cookie], if the `Accept` header of the request
+/// explicitly indicates it and the cookie does not hold it already
+pub(crate) fn remember_format(req: &HttpRequest, content_type: &ContentType, response: &mut HttpResponse) {
+    let config = PayloadConfig::from_req(req);
+    let name = match &config.format_cookie {
+        Some(name) => name,
+        None => return,
+    };
+
+    if config.explicit_accept_format(req.headers()).as_ref() != Some(content_type)
+        || cookie_value(req, name).is_some_and(|value| value.eq(content_type.as_str()))
+    {
+        return;
+    }
+
+    let cookie = format!("{name}={}; Path=/; SameSite=Lax", content_type.as_str());
+    if let Ok(value) = HeaderValue::from_str(&cookie) {
+        response.headers_mut().append(SET_COOKIE, value);
+    }
+}
+
+/// The value of the cookie `name` sent with the request, if any
+fn cookie_value(req: &HttpRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get_all(COOKIE)
+        .filter_map(|header_value| header_value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| cookie_name.eq(&name))
+        .map(|(_, value)| value.trim_matches('"').to_string())
+}
+
+impl FromRequest for Negotiation {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut DevPayload) -> Self::Future {
+        ready(Ok(Negotiation::from_request(req)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_negotiator_rules() {
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        assert_eq!(ContentType::Json, Negotiator::default().pick(&req));
+
+        let negotiator = Negotiator::new([NegotiationRule::Format(ContentType::GrpcWeb)]).then(NegotiationRule::RequestFormat);
+        // grpc-web is not enabled in the configuration, so the next rule is used
+        assert_eq!(ContentType::Protobuf, negotiator.pick(&req));
+
+        let req = TestRequest::get().insert_header(("Accept", "text/html")).to_http_request();
+        assert_eq!(ContentType::default(), Negotiator::new([NegotiationRule::Accept]).pick(&req));
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_negotiation_cached() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+
+        let negotiation = Negotiation::from_request(&req);
+        assert_eq!(ContentType::Json, negotiation.content_type);
+        assert_eq!(ContentType::Protobuf, negotiation.accept);
+
+        assert_eq!(Some(&negotiation), req.extensions().get::<Negotiation>());
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_negotiation_reuses_cache() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "application/json"))
+            .to_http_request();
+
+        req.extensions_mut().insert(Negotiation {
+            content_type: ContentType::Protobuf,
+            accept: ContentType::Other(String::new()),
+        });
+
+        let negotiation = Negotiation::from_request(&req);
+        assert_eq!(ContentType::Protobuf, negotiation.content_type);
+        assert_eq!(ContentType::Protobuf, negotiation.response_format());
+    }
+
+    #[test]
+    fn test_response_format_default() {
+        let req = TestRequest::get().to_http_request();
+
+        assert_eq!(
+            ContentType::default(),
+            Negotiation::from_request(&req).response_format()
+        );
+    }
+
+    #[test]
+    fn test_failed_negotiation_not_cached() {
+        let req = TestRequest::get()
+            .append_header(("Content-Type", "application/json"))
+            .append_header(("Content-Type", "application/json"))
+            .to_http_request();
+
+        assert!(Negotiation::try_from_request(&req).is_err());
+        assert!(req.extensions().get::<Negotiation>().is_none());
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_wildcard_prefers_request_format() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .insert_header(("Accept", "*/*"))
+            .to_http_request();
+
+        assert_eq!(ContentType::Protobuf, Negotiation::from_request(&req).accept);
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_wildcard_prefers_configured_format() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("Accept", "*/*"))
+            .app_data(PayloadConfig::default().preference([ContentType::Protobuf]))
+            .to_http_request();
+
+        assert_eq!(ContentType::Protobuf, Negotiation::from_request(&req).accept);
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_forced_format() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        req.extensions_mut().insert(ForcedFormat(ContentType::Protobuf));
+
+        assert_eq!(ContentType::Protobuf, response_format(&req));
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_forced_format_unsupported() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        req.extensions_mut().insert(ForcedFormat(ContentType::GrpcWeb));
+
+        assert_eq!(ContentType::Json, response_format(&req));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_content_type_alias() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "text/plain; charset=utf-8"))
+            .app_data(PayloadConfig::default().alias("text/plain", ContentType::Json))
+            .to_http_request();
+
+        assert_eq!(ContentType::Json, Negotiation::from_request(&req).content_type);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_assume_content_type() {
+        let config = PayloadConfig::default().assume_content_type(ContentType::Json);
+
+        let req = TestRequest::get().app_data(config.clone()).to_http_request();
+        assert_eq!(ContentType::Json, Negotiation::from_request(&req).content_type);
+
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "text/plain"))
+            .app_data(config)
+            .to_http_request();
+        assert_eq!(ContentType::Other("text/plain".to_string()), Negotiation::from_request(&req).content_type);
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_current_format() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+
+        assert_eq!(ContentType::Protobuf, current_format(&req));
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_format_cookie() {
+        let config = PayloadConfig::default().format_cookie("format");
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .app_data(config.clone())
+            .to_http_request();
+        let resp = crate::Payload::new("foo".to_string()).to_response(&req);
+        assert_eq!("format=protobuf; Path=/; SameSite=Lax", resp.headers().get(SET_COOKIE).unwrap());
+        assert_eq!("accept, cookie", resp.headers().get("Vary").unwrap());
+
+        // Wildcards do not indicate a format explicitly
+        let req = TestRequest::get()
+            .insert_header(("Accept", "*/*"))
+            .insert_header((COOKIE, "session=foo; format=protobuf"))
+            .app_data(config.clone())
+            .to_http_request();
+        assert_eq!(ContentType::Protobuf, current_format(&req));
+        let resp = crate::Payload::new("foo".to_string()).to_response(&req);
+        assert!(resp.headers().get(SET_COOKIE).is_none());
+
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json"))
+            .insert_header((COOKIE, "format=protobuf"))
+            .app_data(config)
+            .to_http_request();
+        assert_eq!(ContentType::Json, current_format(&req));
+
+        let req = TestRequest::get()
+            .insert_header((COOKIE, "format=protobuf"))
+            .to_http_request();
+        assert_eq!(ContentType::Json, current_format(&req));
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_unsupported_accept() {
+        use actix_web::http::StatusCode;
+
+        let request = |config: PayloadConfig, accept: Option<&str>| {
+            let mut req = TestRequest::post().insert_header(("Content-Type", "application/protobuf"));
+            if let Some(accept) = accept {
+                req = req.insert_header(("Accept", accept));
+            }
+            req.app_data(config).to_http_request()
+        };
+
+        // Lenient by default, falling back to the format of the request body
+        let resp = crate::Payload::new("foo".to_string()).to_response(&request(PayloadConfig::default(), Some("text/html")));
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+
+        let strict = PayloadConfig::default().unsupported_accept(UnsupportedAccept::Reject);
+        let resp = crate::Payload::new("foo".to_string()).to_response(&request(strict.clone(), Some("text/html")));
+        assert_eq!(StatusCode::NOT_ACCEPTABLE, resp.status());
+        assert_eq!("accept", resp.headers().get("Vary").unwrap());
+
+        // Excluding the only accepted format leaves no acceptable format
+        let req = request(strict.clone(), Some("application/json;q=0"));
+        assert_eq!(StatusCode::NOT_ACCEPTABLE, crate::Payload::new("foo".to_string()).to_response(&req).status());
+
+        for accept in [None, Some("*/*"), Some("text/html, application/json;q=0.5")] {
+            let req = request(strict.clone(), accept);
+            assert_eq!(StatusCode::OK, crate::Payload::new("foo".to_string()).to_response(&req).status());
+        }
+
+        let req = request(strict.clone(), Some("text/html"));
+        req.extensions_mut().insert(ForcedFormat(ContentType::Json));
+        assert_eq!(StatusCode::OK, crate::Payload::new("foo".to_string()).to_response(&req).status());
+
+        let req = TestRequest::get()
+            .insert_header(("Accept", "text/html"))
+            .insert_header((COOKIE, "format=protobuf"))
+            .app_data(strict.format_cookie("format"))
+            .to_http_request();
+        assert_eq!(StatusCode::OK, crate::Payload::new("foo".to_string()).to_response(&req).status());
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    fn test_strict_accept() {
+        let request = |config: PayloadConfig| {
+            TestRequest::post()
+                .insert_header(("Content-Type", "application/protobuf"))
+                .insert_header(("Accept", "application/json, ;;;"))
+                .app_data(config)
+                .to_http_request()
+        };
+
+        assert_eq!(ContentType::Json, current_format(&request(PayloadConfig::default())));
+        assert_eq!(ContentType::Protobuf, current_format(&request(PayloadConfig::default().strict_accept(true))));
+    }
+}
@@ -0,0 +1,553 @@
+use crate::{build_response, ErrorLocation, Payload, PayloadConfig, PayloadError};
+#[cfg(not(feature = "problem-details"))]
+use crate::negotiate;
+
+#[cfg(feature = "problem-details")]
+use actix_web::http::header::{HeaderValue, CONTENT_TYPE};
+use actix_web::{HttpRequest, HttpResponse};
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// The body of an error rendered by [render_negotiated_error].
+///
+/// In JSON and XML this is a structure with the `status` and `message` fields, the `location`
+/// field with `line`, `column`, `snippet` and, if the error occurred in a field, `path`,
+/// if the location of the error in the request body is known,
+/// and the `code` field if an error code is configured with [PayloadConfig::map_error].
+/// In protobuf it is a message equivalent to
+/// ```proto
+/// message Error {
+///     uint32 status = 1;
+///     string message = 2;
+///     optional Location location = 3;
+///     optional string code = 4;
+/// }
+///
+/// message Location {
+///     uint64 line = 1;
+///     uint64 column = 2;
+///     string snippet = 3;
+///     optional string path = 4;
+/// }
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ErrorBody {
+    /// The HTTP status code
+    pub status: u16,
+    /// The error message
+    pub message: String,
+    /// The location of the error in the request body, see [PayloadError::location]
+    pub location: Option<ErrorLocation>,
+    /// The error code configured with [PayloadConfig::map_error], see [crate::PayloadError::error_code]
+    pub code: Option<String>,
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl serde::Serialize for ErrorBody {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut error = serializer.serialize_struct("error", 2 + self.location.is_some() as usize + self.code.is_some() as usize)?;
+        error.serialize_field("status", &self.status)?;
+        error.serialize_field("message", &self.message)?;
+        if let Some(location) = &self.location {
+            error.serialize_field("location", location)?;
+        }
+        if let Some(code) = &self.code {
+            error.serialize_field("code", code)?;
+        }
+        error.end()
+    }
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl serde::Serialize for ErrorLocation {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut location = serializer.serialize_struct("location", 3 + self.path.is_some() as usize)?;
+        location.serialize_field("line", &self.line)?;
+        location.serialize_field("column", &self.column)?;
+        location.serialize_field("snippet", &self.snippet)?;
+        if let Some(path) = &self.path {
+            location.serialize_field("path", path)?;
+        }
+        location.end()
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl prost::Message for ErrorBody {
+    fn encode_raw<B: prost::bytes::BufMut>(&self, buf: &mut B) {
+        if self.status != 0 {
+            prost::encoding::uint32::encode(1, &(self.status as u32), buf);
+        }
+
+        if !self.message.is_empty() {
+            prost::encoding::string::encode(2, &self.message, buf);
+        }
+
+        if let Some(location) = &self.location {
+            prost::encoding::message::encode(3, location, buf);
+        }
+
+        if let Some(code) = &self.code {
+            prost::encoding::string::encode(4, code, buf);
+        }
+    }
+
+    fn merge_field<B: prost::bytes::Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: prost::encoding::WireType,
+        buf: &mut B,
+        ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        match tag {
+            1 => {
+                let mut status = 0;
+                prost::encoding::uint32::merge(wire_type, &mut status, buf, ctx)?;
+                self.status = u16::try_from(status)
+                    .map_err(|_| prost::DecodeError::new("status is out of range"))?;
+                Ok(())
+            }
+            2 => prost::encoding::string::merge(wire_type, &mut self.message, buf, ctx),
+            3 => prost::encoding::message::merge(
+                wire_type,
+                self.location.get_or_insert_with(ErrorLocation::default),
+                buf,
+                ctx,
+            ),
+            4 => prost::encoding::string::merge(wire_type, self.code.get_or_insert_with(String::new), buf, ctx),
+            _ => prost::encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if self.status != 0 {
+            len += prost::encoding::uint32::encoded_len(1, &(self.status as u32));
+        }
+
+        if !self.message.is_empty() {
+            len += prost::encoding::string::encoded_len(2, &self.message);
+        }
+
+        if let Some(location) = &self.location {
+            len += prost::encoding::message::encoded_len(3, location);
+        }
+
+        if let Some(code) = &self.code {
+            len += prost::encoding::string::encoded_len(4, code);
+        }
+
+        len
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl prost::Message for ErrorLocation {
+    fn encode_raw<B: prost::bytes::BufMut>(&self, buf: &mut B) {
+        if self.line != 0 {
+            prost::encoding::uint64::encode(1, &(self.line as u64), buf);
+        }
+
+        if self.column != 0 {
+            prost::encoding::uint64::encode(2, &(self.column as u64), buf);
+        }
+
+        if !self.snippet.is_empty() {
+            prost::encoding::string::encode(3, &self.snippet, buf);
+        }
+
+        if let Some(path) = &self.path {
+            prost::encoding::string::encode(4, path, buf);
+        }
+    }
+
+    fn merge_field<B: prost::bytes::Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: prost::encoding::WireType,
+        buf: &mut B,
+        ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        match tag {
+            1 | 2 => {
+                let mut value = 0;
+                prost::encoding::uint64::merge(wire_type, &mut value, buf, ctx)?;
+                let value = usize::try_from(value).map_err(|_| prost::DecodeError::new("location is out of range"))?;
+                if tag == 1 {
+                    self.line = value;
+                } else {
+                    self.column = value;
+                }
+                Ok(())
+            }
+            3 => prost::encoding::string::merge(wire_type, &mut self.snippet, buf, ctx),
+            4 => prost::encoding::string::merge(wire_type, self.path.get_or_insert_with(String::new), buf, ctx),
+            _ => prost::encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if self.line != 0 {
+            len += prost::encoding::uint64::encoded_len(1, &(self.line as u64));
+        }
+
+        if self.column != 0 {
+            len += prost::encoding::uint64::encoded_len(2, &(self.column as u64));
+        }
+
+        if !self.snippet.is_empty() {
+            len += prost::encoding::string::encoded_len(3, &self.snippet);
+        }
+
+        if let Some(path) = &self.path {
+            len += prost::encoding::string::encoded_len(4, path);
+        }
+
+        len
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Render an error in the format the client prefers, using the same negotiation as [Payload],
+/// including a [crate::ForcedFormat]. A format configured with [PayloadConfig::error_format]
+/// for the status of the error takes precedence.
+///
+/// The body is an [ErrorBody], or with the `problem-details` feature, a `ProblemDetails`.
+/// With the `problem-details` feature, the errors of the extractors of this crate are rendered the same way.
+///
+/// This allows errors of other extractors, middleware or the default service to match
+/// the format of the payloads.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::render_negotiated_error;
+///     use actix_web::{HttpRequest, HttpResponse};
+///
+///     async fn not_found(req: HttpRequest) -> HttpResponse {
+///         render_negotiated_error(&req, actix_web::error::ErrorNotFound("Not found"))
+///     }
+///
+///     let app = actix_web::App::new().default_service(actix_web::web::to(not_found));
+/// ```
+pub fn render_negotiated_error<E: Into<actix_web::Error>>(req: &HttpRequest, err: E) -> HttpResponse {
+    let err = err.into();
+    render(req, err.as_response_error().status_code(), err.to_string(), err.as_error::<PayloadError>())
+}
+
+/// Render `error` for `req` up front, as the response of a [PayloadError] is rendered without access to the request,
+/// see [PayloadError::Negotiated]. A response which is not buffered is left to [PayloadError] itself
+#[cfg(feature = "problem-details")]
+pub(crate) fn negotiated(req: &HttpRequest, error: PayloadError) -> PayloadError {
+    if matches!(error, PayloadError::Negotiated { .. }) {
+        return error;
+    }
+
+    let response = render(req, actix_web::ResponseError::status_code(&error), error.to_string(), Some(&error));
+    let status = response.status();
+    let headers = response.headers().clone();
+    match actix_web::body::MessageBody::try_into_bytes(response.into_body()) {
+        Ok(body) => PayloadError::Negotiated {
+            status,
+            headers,
+            body,
+            source: Box::new(error),
+        },
+        Err(_) => error,
+    }
+}
+
+/// Without the `problem-details` feature, the response of a [PayloadError] does not depend on the request
+#[cfg(not(feature = "problem-details"))]
+pub(crate) fn negotiated(_req: &HttpRequest, error: PayloadError) -> PayloadError {
+    error
+}
+
+/// Box the future of an extractor, rendering its error for `req` with [negotiated]
+pub(crate) fn negotiated_extract<T: 'static>(
+    req: HttpRequest,
+    extract: impl Future<Output = Result<T, PayloadError>> + 'static,
+) -> Pin<Box<dyn Future<Output = Result<T, PayloadError>>>> {
+    Box::pin(async move { extract.await.map_err(|error| negotiated(&req, error)) })
+}
+
+/// Render an error with `status` and `message`, including the location and the error code if it is a [PayloadError]
+fn render(req: &HttpRequest, status: actix_web::http::StatusCode, message: String, error: Option<&PayloadError>) -> HttpResponse {
+    let config = PayloadConfig::from_req(req);
+
+    #[cfg(not(feature = "problem-details"))]
+    let mut response = {
+        let body = ErrorBody {
+            status: status.as_u16(),
+            message,
+            location: error.and_then(PayloadError::location),
+            code: error.and_then(PayloadError::error_code).map(str::to_string),
+        };
+
+        let content_type = config
+            .error_format_for(status)
+            .unwrap_or_else(|| negotiate::response_format(req));
+        let serialized = Payload::new(body).serialize_with(content_type.clone(), config);
+        build_response(status, content_type, serialized, config)
+    };
+
+    #[cfg(feature = "problem-details")]
+    let mut response = {
+        let detail = match error {
+            Some(error) => error.coded_message(),
+            None => message,
+        };
+        let mut body = crate::ProblemDetails::new(status, detail, config.problem_type_base.as_deref());
+        body.location = error.and_then(PayloadError::location);
+
+        let content_type = config
+            .error_format_for(status)
+            .unwrap_or_else(|| crate::problem::response_format(req, config));
+        let serialized = Payload::new(body).serialize_with(content_type.clone(), config);
+        let mut response = build_response(status, content_type.clone(), serialized, config);
+        if let Some(media_type) = crate::problem::media_type(&content_type).filter(|_| response.status().eq(&status)) {
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(media_type));
+        }
+        response
+    };
+
+    config.apply_response_headers(&mut response);
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", not(feature = "problem-details")))]
+    async fn test_json_error() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorNotFound("Not found"));
+
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(serde_json::json!({ "status": 404, "message": "Not found" }), value);
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "protobuf", not(feature = "problem-details")))]
+    async fn test_protobuf_error() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorUnauthorized("Unauthorized"));
+
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let error: ErrorBody = prost::Message::decode(body).unwrap();
+        assert_eq!(
+            ErrorBody {
+                status: 401,
+                message: "Unauthorized".to_string(),
+                location: None,
+                code: None,
+            },
+            error
+        );
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "xml", not(feature = "problem-details")))]
+    async fn test_xml_error() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "text/xml"))
+            .to_http_request();
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorForbidden("Forbidden"));
+
+        assert_eq!(StatusCode::FORBIDDEN, resp.status());
+        assert_eq!("application/xml", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("<error><status>403</status><message>Forbidden</message></error>", body);
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", not(feature = "problem-details")))]
+    async fn test_json_error_location() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "application/json"))
+            .to_http_request();
+        let error = PayloadError::Deserialize {
+            error: crate::DeserializeError::SerdeJson(serde_json::from_str::<String>("x").unwrap_err()),
+            body: actix_web::web::Bytes::from_static(b"x"),
+            content_type: crate::ContentType::Json,
+        };
+        let resp = render_negotiated_error(&req, error);
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(serde_json::json!({ "line": 1, "column": 1, "snippet": "x" }), value["location"]);
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", feature = "protobuf", not(feature = "problem-details")))]
+    async fn test_error_format() {
+        let config = PayloadConfig::default().error_format(crate::StatusClass::ClientError, crate::ContentType::Json);
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .app_data(config)
+            .to_http_request();
+
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorNotFound("Not found"));
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorInternalServerError("Internal error"));
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", feature = "problem-details"))]
+    async fn test_problem_json() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/problem+json"))
+            .app_data(PayloadConfig::default().problem_type_base("https://example.com/problems/"))
+            .to_http_request();
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorNotFound("No such order"));
+
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+        assert_eq!("application/problem+json", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "type": "https://example.com/problems/not-found",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "No such order",
+            }),
+            value
+        );
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "protobuf", feature = "problem-details"))]
+    async fn test_problem_protobuf() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorUnauthorized("Unauthorized"));
+
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let problem: crate::ProblemDetails = prost::Message::decode(body).unwrap();
+        assert_eq!(crate::ProblemDetails::new(StatusCode::UNAUTHORIZED, "Unauthorized", None), problem);
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "xml", feature = "problem-details"))]
+    async fn test_problem_xml() {
+        let req = TestRequest::get()
+            .insert_header(("Accept", "text/xml"))
+            .to_http_request();
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorForbidden("Forbidden"));
+
+        assert_eq!(StatusCode::FORBIDDEN, resp.status());
+        assert_eq!("application/problem+xml", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(
+            "<problem><type>about:blank</type><title>Forbidden</title><status>403</status><detail>Forbidden</detail></problem>",
+            body
+        );
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", feature = "problem-details"))]
+    async fn test_problem_location() {
+        let req = TestRequest::get()
+            .insert_header(("Content-Type", "application/json"))
+            .to_http_request();
+        let error = PayloadError::Deserialize {
+            error: crate::DeserializeError::SerdeJson(serde_json::from_str::<String>("x").unwrap_err()),
+            body: actix_web::web::Bytes::from_static(b"x"),
+            content_type: crate::ContentType::Json,
+        };
+        let resp = render_negotiated_error(&req, error);
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+        assert_eq!("application/problem+json", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(serde_json::json!({ "line": 1, "column": 1, "snippet": "x" }), value["location"]);
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", feature = "protobuf", feature = "problem-details"))]
+    async fn test_problem_error_format() {
+        let config = PayloadConfig::default().error_format(crate::StatusClass::ClientError, crate::ContentType::Json);
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .app_data(config)
+            .to_http_request();
+
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorNotFound("Not found"));
+        assert_eq!("application/problem+json", resp.headers().get("Content-Type").unwrap());
+
+        let resp = render_negotiated_error(&req, actix_web::error::ErrorInternalServerError("Internal error"));
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", feature = "xml", feature = "problem-details"))]
+    async fn test_problem_extractor() {
+        use actix_web::test::{call_service, init_service, read_body};
+        use actix_web::{web, App};
+
+        let app = init_service(
+            App::new()
+                .app_data(PayloadConfig::default().problem_type_base("https://example.com/problems/"))
+                .route("/", web::post().to(|payload: Payload<String>| async move { payload })),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("Accept", "application/xml"))
+            .set_payload("x")
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+        assert_eq!("application/problem+xml", resp.headers().get("Content-Type").unwrap());
+        assert_eq!("accept", resp.headers().get("Vary").unwrap());
+
+        let body = read_body(resp).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.starts_with("<problem><type>https://example.com/problems/bad-request</type>"), "{body}");
+        assert!(body.contains("<location><line>1</line><column>1</column><snippet>x</snippet></location>"), "{body}");
+    }
+}
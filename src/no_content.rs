@@ -0,0 +1,172 @@
+//! `204 No Content` responses, for handlers which only sometimes have a body to respond with,
+//! and the [Empty] payload of endpoints without a body.
+
+use crate::{Payload, PayloadConfig, PayloadError, ProtobufSupport, SerdeSupportSerialize};
+
+use actix_web::body::BoxBody;
+use actix_web::dev::Payload as DevPayload;
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder};
+
+use std::future::{ready, Ready};
+
+/// Either a [Payload] responded with in the negotiated format, or `204 No Content` without a body.
+///
+/// Created with [Payload::no_content], or converted from a [Payload] or an [Option].
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{MaybePayload, Payload};
+///
+///     async fn latest(found: bool) -> MaybePayload<String> {
+///         if found {
+///             Payload::new("foo".to_string()).into()
+///         } else {
+///             Payload::no_content()
+///         }
+///     }
+/// ```
+#[derive(Debug)]
+pub enum MaybePayload<T> {
+    /// Respond with the payload, exactly like [Payload]
+    Content(Payload<T>),
+    /// Respond with `204 No Content`, without a body or `Content-Type`
+    NoContent,
+}
+
+impl<T> Payload<T> {
+    /// Respond with `204 No Content`, skipping serialization. The headers of [PayloadConfig::response_header]
+    /// and [PayloadConfig::vary] are still included, as the same resource may be negotiated otherwise.
+    pub fn no_content() -> MaybePayload<T> {
+        MaybePayload::NoContent
+    }
+}
+
+impl<T> From<Payload<T>> for MaybePayload<T> {
+    fn from(payload: Payload<T>) -> Self {
+        Self::Content(payload)
+    }
+}
+
+impl<T> From<Option<T>> for MaybePayload<T> {
+    /// `None` responds with `204 No Content`
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => Self::Content(Payload::new(value)),
+            None => Self::NoContent,
+        }
+    }
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize> Responder for MaybePayload<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        match self {
+            Self::Content(payload) => payload.to_response(req),
+            Self::NoContent => no_content(req),
+        }
+    }
+}
+
+/// A `204 No Content` response, with the headers of the configuration
+fn no_content(req: &HttpRequest) -> HttpResponse {
+    let mut response = HttpResponse::NoContent().finish();
+    PayloadConfig::from_req(req).apply_response_headers(&mut response);
+    response
+}
+
+/// The payload of endpoints which take no request body, or respond without one.
+///
+/// As an extractor, the request body is not read at all, so it is neither limited nor deserialized.
+/// As a responder, it responds with `204 No Content` like [Payload::no_content], rather than serializing `()`.
+/// This keeps endpoints without a body consistent with the headers of the other endpoints.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::Empty;
+///
+///     async fn ping(_: Empty) -> Empty {
+///         Empty
+///     }
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Empty;
+
+impl FromRequest for Empty {
+    type Error = PayloadError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(_: &HttpRequest, _: &mut DevPayload) -> Self::Future {
+        ready(Ok(Self))
+    }
+}
+
+impl Responder for Empty {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        no_content(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    #[actix_macros::test]
+    async fn test_no_content() {
+        let req = TestRequest::get().to_http_request();
+        let resp = Payload::<String>::no_content().respond_to(&req);
+
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+        assert!(resp.headers().get("Content-Type").is_none());
+        assert_eq!("accept", resp.headers().get("Vary").unwrap());
+        assert!(actix_web::body::to_bytes(resp.into_body()).await.unwrap().is_empty());
+    }
+
+    // A structure rather than a string, as XML has no representation of a bare string
+    #[derive(serde_derive::Serialize, prost_derive::Message)]
+    struct Item {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    #[test]
+    fn test_from_option() {
+        let req = TestRequest::get().to_http_request();
+
+        let resp = MaybePayload::from(Some(Item { name: "foo".to_string() })).respond_to(&req);
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let resp = MaybePayload::<Item>::from(None).respond_to(&req);
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+    }
+
+    #[actix_macros::test]
+    async fn test_empty() {
+        use actix_web::test::{call_service, init_service};
+        use actix_web::{web, App};
+
+        async fn ping(_: Empty) -> Empty {
+            Empty
+        }
+
+        // The body is not read, so it is not rejected for exceeding the limit either
+        let app = init_service(
+            App::new()
+                .app_data(PayloadConfig::default().limit(1))
+                .route("/", web::post().to(ping)),
+        )
+        .await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload("{}")
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+        assert_eq!("accept", resp.headers().get("Vary").unwrap());
+    }
+}
@@ -0,0 +1,466 @@
+//! Patch documents for `PATCH` requests, as [RFC 7386](https://www.rfc-editor.org/rfc/rfc7386) JSON merge patches
+//! under `application/merge-patch+json` and [RFC 6902](https://www.rfc-editor.org/rfc/rfc6902) JSON patches
+//! under `application/json-patch+json`.
+
+use crate::json_backend::{Backend, JsonBackend};
+use crate::negotiated_error::negotiated_extract;
+use crate::{body, naming, ContentType, DeserializeError, JsonNaming, PayloadConfig, PayloadError};
+
+use actix_web::dev::Payload as DevPayload;
+use actix_web::http::StatusCode;
+use actix_web::{FromRequest, HttpRequest, ResponseError};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+/// The media type of JSON merge patches
+const MERGE_PATCH: &str = "application/merge-patch+json";
+/// The media type of JSON patches
+const JSON_PATCH: &str = "application/json-patch+json";
+
+/// The media types accepted by [PatchPayload], for the `Accept-Patch` header of RFC 5789
+pub const ACCEPT_PATCH: &str = "application/merge-patch+json, application/json-patch+json";
+
+/// A patch to a `T`, extracted from a `application/merge-patch+json` or `application/json-patch+json` body.
+///
+/// The handler loads the current value and applies the patch with [PatchPayload::apply].
+/// Requests with another `Content-Type` are rejected with a HTTP 400 error, as are bodies which are not a valid patch.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{PatchError, PatchPayload, Payload};
+///
+///     async fn update(patch: PatchPayload<Vec<String>>) -> Result<Payload<String>, PatchError> {
+///         let current = vec!["foo".to_string()];
+///         let patched = patch.apply(&current)?;
+///         Ok(Payload::new(patched.join(", ")))
+///     }
+/// ```
+#[derive(Debug)]
+pub struct PatchPayload<T> {
+    patch: Patch,
+    naming: JsonNaming,
+    _target: PhantomData<fn() -> T>,
+}
+
+/// A patch document
+#[derive(Debug, PartialEq, Clone)]
+pub enum Patch {
+    /// A JSON merge patch, the fields of which replace the fields of the value, `null` removes a field
+    Merge(Value),
+    /// A JSON patch, a sequence of operations which are applied in order
+    Json(Vec<PatchOperation>),
+}
+
+/// An operation of a JSON patch. Paths are JSON pointers, e.g. `/authors/0/name`
+#[derive(Debug, PartialEq, Clone)]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// An error applying a patch
+#[derive(Debug, Error)]
+pub enum PatchError {
+    /// The value could not be converted to JSON
+    #[error("Failed to serialize the value to patch: {0}")]
+    Serialize(serde_json::Error),
+    /// A path of an operation does not exist in the value
+    #[error("Path does not exist: {0}")]
+    InvalidPath(String),
+    /// A `test` operation did not match the value
+    #[error("Test failed at path: {0}")]
+    TestFailed(String),
+    /// The patched value is no longer a valid `T`
+    #[error("The patched value is invalid: {0}")]
+    InvalidResult(serde_json::Error),
+}
+
+impl ResponseError for PatchError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Serialize(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::TestFailed(_) => StatusCode::CONFLICT,
+            Self::InvalidPath(_) | Self::InvalidResult(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+impl<T> PatchPayload<T> {
+    /// The patch document
+    pub fn patch(&self) -> &Patch {
+        &self.patch
+    }
+
+    /// The patch document, consuming the extractor
+    pub fn into_patch(self) -> Patch {
+        self.patch
+    }
+}
+
+impl<T: serde::Serialize + serde::de::DeserializeOwned> PatchPayload<T> {
+    /// Apply the patch to `base`, returning the patched value. `base` is not modified.
+    /// JSON patches are atomic, if any operation fails, an error is returned.
+    /// The paths of the patch refer to the fields as named by [PayloadConfig::json_naming]
+    pub fn apply(&self, base: &T) -> Result<T, PatchError> {
+        let mut document = naming::to_value(base, self.naming).map_err(PatchError::Serialize)?;
+        self.patch.apply_to(&mut document)?;
+        match self.naming {
+            JsonNaming::Preserve => serde_json::from_value(document),
+            JsonNaming::CamelCase => T::deserialize(naming::camel_case(document)),
+        }
+        .map_err(PatchError::InvalidResult)
+    }
+}
+
+impl Patch {
+    /// Apply the patch to a JSON document. If an operation of a JSON patch fails,
+    /// the operations before it have been applied already
+    pub fn apply_to(&self, document: &mut Value) -> Result<(), PatchError> {
+        match self {
+            Self::Merge(patch) => {
+                merge(document, patch);
+                Ok(())
+            }
+            Self::Json(operations) => operations.iter().try_for_each(|operation| operation.apply_to(document)),
+        }
+    }
+
+    /// Parse a JSON patch, a JSON array of operations
+    fn parse_json_patch(document: Value) -> Result<Self, String> {
+        let operations = match document {
+            Value::Array(operations) => operations,
+            _ => return Err("A JSON patch must be an array of operations".to_string()),
+        };
+
+        operations
+            .into_iter()
+            .map(PatchOperation::parse)
+            .collect::<Result<_, _>>()
+            .map(Self::Json)
+    }
+}
+
+impl PatchOperation {
+    /// Parse a single operation object, e.g. `{"op": "remove", "path": "/foo"}`
+    fn parse(operation: Value) -> Result<Self, String> {
+        let mut operation = match operation {
+            Value::Object(operation) => operation,
+            _ => return Err("A JSON patch operation must be an object".to_string()),
+        };
+
+        let op = take_string(&mut operation, "op")?;
+        let path = take_string(&mut operation, "path")?;
+        let parsed = match op.as_str() {
+            "remove" => Self::Remove { path },
+            "move" => Self::Move {
+                from: take_string(&mut operation, "from")?,
+                path,
+            },
+            "copy" => Self::Copy {
+                from: take_string(&mut operation, "from")?,
+                path,
+            },
+            "add" | "replace" | "test" => {
+                let value = operation
+                    .remove("value")
+                    .ok_or_else(|| "Missing `value` member".to_string())?;
+                match op.as_str() {
+                    "add" => Self::Add { path, value },
+                    "replace" => Self::Replace { path, value },
+                    _ => Self::Test { path, value },
+                }
+            }
+            op => return Err(format!("Unknown operation `{op}`")),
+        };
+
+        Ok(parsed)
+    }
+
+    /// Apply the operation to a JSON document
+    pub fn apply_to(&self, document: &mut Value) -> Result<(), PatchError> {
+        match self {
+            Self::Add { path, value } => add(document, path, value.clone()),
+            Self::Remove { path } => remove(document, path).map(|_| ()),
+            Self::Replace { path, value } => {
+                let target = document
+                    .pointer_mut(path)
+                    .ok_or_else(|| PatchError::InvalidPath(path.clone()))?;
+                *target = value.clone();
+                Ok(())
+            }
+            Self::Move { from, path } => {
+                // A value cannot be moved into one of its own children
+                if path.starts_with(from.as_str()) && path[from.len()..].starts_with('/') {
+                    return Err(PatchError::InvalidPath(path.clone()));
+                }
+
+                let value = remove(document, from)?;
+                add(document, path, value)
+            }
+            Self::Copy { from, path } => {
+                let value = document
+                    .pointer(from)
+                    .cloned()
+                    .ok_or_else(|| PatchError::InvalidPath(from.clone()))?;
+                add(document, path, value)
+            }
+            Self::Test { path, value } => match document.pointer(path) {
+                Some(existing) if existing.eq(value) => Ok(()),
+                _ => Err(PatchError::TestFailed(path.clone())),
+            },
+        }
+    }
+}
+
+/// Remove the string member `name` of an operation object
+fn take_string(operation: &mut Map<String, Value>, name: &str) -> Result<String, String> {
+    match operation.remove(name) {
+        Some(Value::String(value)) => Ok(value),
+        _ => Err(format!("Missing or invalid `{name}` member")),
+    }
+}
+
+/// Apply a merge patch, as defined in RFC 7386
+fn merge(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        patch => {
+            *target = patch.clone();
+            return;
+        }
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+
+    if let Value::Object(target) = target {
+        for (name, value) in patch {
+            if value.is_null() {
+                target.remove(name);
+            } else {
+                merge(target.entry(name.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Split a JSON pointer into the pointer to the parent and the unescaped last reference token
+fn split_pointer(path: &str) -> Option<(&str, String)> {
+    let (parent, token) = path.rsplit_once('/')?;
+    Some((parent, token.replace("~1", "/").replace("~0", "~")))
+}
+
+/// The array index a reference token refers to, which may not have leading zeros
+fn array_index(token: &str) -> Option<usize> {
+    if token.len() > 1 && token.starts_with('0') {
+        return None;
+    }
+
+    token.parse().ok()
+}
+
+/// Add `value` at `path`, inserting into arrays and replacing existing object members
+fn add(document: &mut Value, path: &str, value: Value) -> Result<(), PatchError> {
+    if path.is_empty() {
+        *document = value;
+        return Ok(());
+    }
+
+    let invalid = || PatchError::InvalidPath(path.to_string());
+    let (parent, token) = split_pointer(path).ok_or_else(invalid)?;
+    match document.pointer_mut(parent).ok_or_else(invalid)? {
+        Value::Object(object) => {
+            object.insert(token, value);
+        }
+        Value::Array(array) if token == "-" => array.push(value),
+        Value::Array(array) => {
+            let index = array_index(&token)
+                .filter(|index| *index <= array.len())
+                .ok_or_else(invalid)?;
+            array.insert(index, value);
+        }
+        _ => return Err(invalid()),
+    }
+
+    Ok(())
+}
+
+/// Remove the value at `path`, returning it
+fn remove(document: &mut Value, path: &str) -> Result<Value, PatchError> {
+    let invalid = || PatchError::InvalidPath(path.to_string());
+    let (parent, token) = split_pointer(path).ok_or_else(invalid)?;
+    match document.pointer_mut(parent).ok_or_else(invalid)? {
+        Value::Object(object) => object.remove(&token).ok_or_else(invalid),
+        Value::Array(array) => {
+            let index = array_index(&token)
+                .filter(|index| *index < array.len())
+                .ok_or_else(invalid)?;
+            Ok(array.remove(index))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// The patch media type of the `Content-Type` of the request, if it is one
+fn patch_media_type(req: &HttpRequest) -> Option<&'static str> {
+    let essence = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|hv_str| hv_str.split(';').next())?
+        .trim();
+
+    [MERGE_PATCH, JSON_PATCH]
+        .into_iter()
+        .find(|media_type| essence.eq_ignore_ascii_case(media_type))
+}
+
+impl<T: 'static> FromRequest for PatchPayload<T> {
+    type Error = PayloadError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut DevPayload) -> Self::Future {
+        let req = req.clone();
+        let payload = payload.take();
+
+        negotiated_extract(req.clone(), async move {
+            let media_type = patch_media_type(&req)
+                .ok_or_else(|| PayloadError::InvalidContentType(ContentType::from_request_content_type(&req)))?;
+
+            let config = PayloadConfig::from_req(&req);
+            let payload_bytes = body::read_body_with(&req, payload, config).await?;
+
+            let patch = Backend::from_slice::<Value>(&payload_bytes, false, JsonNaming::Preserve, false).and_then(|document| {
+                if media_type == MERGE_PATCH {
+                    return Ok(Patch::Merge(document));
+                }
+
+                Patch::parse_json_patch(document)
+                    .map_err(|e| DeserializeError::SerdeJson(<serde_json::Error as serde::de::Error>::custom(e)))
+            });
+
+            match patch {
+                Ok(patch) => Ok(Self {
+                    patch,
+                    naming: config.json_naming,
+                    _target: PhantomData,
+                }),
+                Err(error) => Err(config.report_error(PayloadError::Deserialize {
+                    error,
+                    body: payload_bytes,
+                    content_type: ContentType::Json,
+                })),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App};
+    use serde_json::json;
+
+    fn json_patch(operations: Value) -> Patch {
+        Patch::parse_json_patch(operations).unwrap()
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut document = json!({"title": "Goodbye!", "author": {"givenName": "John", "familyName": "Doe"}, "tags": ["example", "sample"]});
+        let patch =
+            json!({"title": "Hello!", "author": {"familyName": null}, "tags": ["example"], "phoneNumber": "+01-123"});
+        Patch::Merge(patch).apply_to(&mut document).unwrap();
+
+        assert_eq!(
+            json!({"title": "Hello!", "author": {"givenName": "John"}, "tags": ["example"], "phoneNumber": "+01-123"}),
+            document
+        );
+    }
+
+    #[test]
+    fn test_json_patch() {
+        let mut document = json!({"foo": ["bar", "baz"], "a/b": 1});
+        json_patch(json!([
+            {"op": "add", "path": "/foo/1", "value": "qux"},
+            {"op": "add", "path": "/foo/-", "value": "end"},
+            {"op": "remove", "path": "/a~1b"},
+            {"op": "replace", "path": "/foo/0", "value": "first"},
+            {"op": "copy", "from": "/foo/0", "path": "/copied"},
+            {"op": "move", "from": "/foo/3", "path": "/moved"},
+            {"op": "test", "path": "/moved", "value": "end"},
+        ]))
+        .apply_to(&mut document)
+        .unwrap();
+
+        assert_eq!(
+            json!({"foo": ["first", "qux", "baz"], "copied": "first", "moved": "end"}),
+            document
+        );
+    }
+
+    #[test]
+    fn test_json_patch_errors() {
+        let mut document = json!({"foo": [1]});
+        let apply = |operations: Value, document: &mut Value| json_patch(operations).apply_to(document);
+
+        assert!(matches!(
+            apply(json!([{"op": "test", "path": "/foo/0", "value": 2}]), &mut document),
+            Err(PatchError::TestFailed(_))
+        ));
+        assert!(matches!(
+            apply(json!([{"op": "remove", "path": "/bar"}]), &mut document),
+            Err(PatchError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            apply(json!([{"op": "add", "path": "/foo/01", "value": 2}]), &mut document),
+            Err(PatchError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            apply(json!([{"op": "move", "from": "/foo", "path": "/foo/0"}]), &mut document),
+            Err(PatchError::InvalidPath(_))
+        ));
+        assert!(Patch::parse_json_patch(json!([{"op": "frobnicate", "path": "/foo"}])).is_err());
+    }
+
+    async fn handler(patch: PatchPayload<Vec<i64>>) -> Result<String, PatchError> {
+        Ok(format!("{:?}", patch.apply(&vec![1, 2])?))
+    }
+
+    #[actix_macros::test]
+    async fn test_extract() {
+        let app = init_service(App::new().route("/", web::patch().to(handler))).await;
+
+        let req = TestRequest::patch()
+            .insert_header(("Content-Type", "application/json-patch+json"))
+            .set_payload(r#"[{"op": "add", "path": "/-", "value": 3}]"#)
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!("[1, 2, 3]", actix_web::body::to_bytes(resp.into_body()).await.unwrap());
+
+        let req = TestRequest::patch()
+            .insert_header(("Content-Type", "application/merge-patch+json"))
+            .set_payload(r#"{"foo": 1}"#)
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, resp.status());
+
+        let req = TestRequest::patch()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload("[]")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+}
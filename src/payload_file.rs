@@ -0,0 +1,453 @@
+use crate::{
+    build_response, headers, negotiate, response_builder, serialize_value, ContentType, PayloadConfig, ProtobufSupport,
+    SerdeSupportSerialize, SerializeError,
+};
+
+use actix_web::body::BoxBody;
+use actix_web::http::header::{HeaderValue, CONTENT_ENCODING, VARY};
+use actix_web::http::StatusCode;
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use futures_util::future::join_all;
+
+use std::sync::Arc;
+
+/// Pre-serialized representations of the same resource, one per format.
+/// The responder picks a representation with the same negotiation as [crate::Payload], without serializing again.
+///
+/// This is meant for hot resources which rarely change. Cloning is cheap, as the representations are reference counted.
+///
+/// Representations can additionally be stored pre-compressed with [PayloadFile::encoded_variant].
+/// The encoding is then picked from the `Accept-Encoding` header, after the format has been negotiated.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "json")]
+/// # fn main() {
+///     use actix_multiresponse::{ContentType, PayloadFile};
+///     use actix_web::web;
+///
+///     async fn countries(file: web::Data<PayloadFile>) -> PayloadFile {
+///         file.as_ref().clone()
+///     }
+///
+///     let file = PayloadFile::new().variant(ContentType::Json, r#"["NL","BE"]"#);
+///     let app = actix_web::App::new()
+///         .app_data(web::Data::new(file))
+///         .route("/countries", web::get().to(countries));
+/// # }
+/// # #[cfg(not(feature = "json"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct PayloadFile {
+    variants: Vec<(ContentType, Bytes)>,
+    encoded: Vec<(ContentType, Encoding, Bytes)>,
+}
+
+/// A content coding of a pre-compressed representation, see [PayloadFile::encoded_variant]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// The `Content-Encoding` token of the coding
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Brotli => "br",
+        }
+    }
+}
+
+/// The quality value the `Accept-Encoding` header assigns to `coding`.
+/// Without a header, only `identity` is acceptable.
+fn encoding_quality(accept_encoding: Option<&str>, coding: &str) -> f32 {
+    let accept_encoding = match accept_encoding {
+        Some(accept_encoding) => accept_encoding,
+        None => return if coding == "identity" { 1.0 } else { 0.0 },
+    };
+
+    let mut wildcard = None;
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let token = parts.next().unwrap_or_default().trim();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q=").or_else(|| param.trim().strip_prefix("Q=")))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if token.eq_ignore_ascii_case(coding) {
+            return quality;
+        }
+        if token == "*" {
+            wildcard = Some(quality);
+        }
+    }
+
+    // identity is acceptable unless excluded explicitly, or through a wildcard
+    match wildcard {
+        Some(quality) => quality,
+        None if coding == "identity" => 1.0,
+        None => 0.0,
+    }
+}
+
+impl PayloadFile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serialize the value in all enabled formats, using the serializer settings of `config`
+    pub fn from_value<T: ProtobufSupport + SerdeSupportSerialize>(
+        value: &T,
+        config: &PayloadConfig,
+    ) -> Result<Self, SerializeError> {
+        let mut file = Self::new();
+        for content_type in ContentType::enabled() {
+            let serialized = serialize_value(value, content_type.clone(), config)?;
+            file = file.variant(content_type.clone(), serialized);
+        }
+
+        Ok(file)
+    }
+
+    /// Like [PayloadFile::from_value], serializing the formats in parallel on the thread pool for blocking tasks.
+    ///
+    /// This is meant for large payloads broadcast to many clients, e.g. through server-sent events,
+    /// which are serialized once when they change rather than once per request.
+    ///
+    /// # Example
+    /// ```
+    ///     use actix_multiresponse::{PayloadConfig, PayloadFile};
+    ///     use std::sync::Arc;
+    ///
+    ///     #[derive(serde_derive::Serialize, prost_derive::Message)]
+    ///     struct Announcement {
+    ///         #[prost(string, tag = "1")]
+    ///         message: String,
+    ///     }
+    ///
+    ///     # actix_rt::System::new().block_on(async {
+    ///     let announcement = Arc::new(Announcement { message: "The servers restart at midnight".to_string() });
+    ///     let file = PayloadFile::from_value_parallel(announcement, &PayloadConfig::default()).await.unwrap();
+    ///     # });
+    /// ```
+    pub async fn from_value_parallel<T: ProtobufSupport + SerdeSupportSerialize + Send + Sync + 'static>(
+        value: Arc<T>,
+        config: &PayloadConfig,
+    ) -> Result<Self, SerializeError> {
+        let config = Arc::new(config.clone());
+        let serialized = ContentType::enabled().iter().map(|content_type| {
+            let (value, config, content_type) = (value.clone(), config.clone(), content_type.clone());
+            web::block(move || serialize_value(value.as_ref(), content_type, config.as_ref()))
+        });
+
+        let mut file = Self::new();
+        for (content_type, serialized) in ContentType::enabled().iter().zip(join_all(serialized).await) {
+            file = file.variant(content_type.clone(), serialized??);
+        }
+
+        Ok(file)
+    }
+
+    /// Add the representation in `content_type`, replacing an existing representation in the same format
+    pub fn variant<B: Into<Bytes>>(mut self, content_type: ContentType, body: B) -> Self {
+        let body = body.into();
+        match self.variants.iter_mut().find(|(ct, _)| ct.eq(&content_type)) {
+            Some((_, existing)) => *existing = body,
+            None => self.variants.push((content_type, body)),
+        }
+
+        self
+    }
+
+    /// Add the representation in `content_type`, compressed with `encoding` by the caller,
+    /// replacing an existing representation in the same format and encoding.
+    ///
+    /// Encoded representations are only used alongside an uncompressed representation in the same format,
+    /// which is sent to clients not accepting any of the stored encodings.
+    pub fn encoded_variant<B: Into<Bytes>>(mut self, content_type: ContentType, encoding: Encoding, body: B) -> Self {
+        let body = body.into();
+        match self
+            .encoded
+            .iter_mut()
+            .find(|(ct, enc, _)| ct.eq(&content_type) && *enc == encoding)
+        {
+            Some((_, _, existing)) => *existing = body,
+            None => self.encoded.push((content_type, encoding, body)),
+        }
+
+        self
+    }
+
+    /// The representation in `content_type` compressed with `encoding`, if any
+    pub fn get_encoded(&self, content_type: &ContentType, encoding: Encoding) -> Option<&Bytes> {
+        self.encoded
+            .iter()
+            .find(|(ct, enc, _)| ct.eq(content_type) && *enc == encoding)
+            .map(|(_, _, body)| body)
+    }
+
+    /// The stored encoding of `content_type` the client accepts most, if it is preferred over no encoding.
+    /// Ties between encodings are broken by the order the encodings were added in.
+    fn pick_encoding(&self, req: &HttpRequest, content_type: &ContentType) -> Option<(Encoding, &Bytes)> {
+        let accept_encoding = headers::merged_header_value(req.headers(), "Accept-Encoding");
+        let accept_encoding = accept_encoding.as_deref();
+        let identity = encoding_quality(accept_encoding, "identity");
+
+        let mut best: Option<(f32, Encoding, &Bytes)> = None;
+        for (_, encoding, body) in self.encoded.iter().filter(|(ct, _, _)| ct.eq(content_type)) {
+            let quality = encoding_quality(accept_encoding, encoding.as_str());
+            if quality > 0.0 && best.map(|(best, _, _)| quality > best).unwrap_or(true) {
+                best = Some((quality, *encoding, body));
+            }
+        }
+
+        best.filter(|(quality, _, _)| *quality >= identity)
+            .map(|(_, encoding, body)| (encoding, body))
+    }
+
+    /// The representation in `content_type`, if any
+    pub fn get(&self, content_type: &ContentType) -> Option<&Bytes> {
+        self.variants
+            .iter()
+            .find(|(ct, _)| ct.eq(content_type))
+            .map(|(_, body)| body)
+    }
+}
+
+impl Responder for PayloadFile {
+    type Body = BoxBody;
+
+    /// Respond with the negotiated representation. If the client prefers a format without a representation,
+    /// the first representation is used. Without any representations, a HTTP 500 is returned.
+    ///
+    /// If encoded representations are stored, the response also varies on `Accept-Encoding`.
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let config = PayloadConfig::from_req(req);
+        let content_type = negotiate::response_format_among(req, |content_type| self.get(content_type).is_some())
+            .or_else(|| self.variants.first().map(|(content_type, _)| content_type.clone()));
+
+        let mut response = match content_type {
+            Some(content_type) => match self.pick_encoding(req, &content_type) {
+                Some((encoding, body)) => response_builder(StatusCode::OK, content_type)
+                    .insert_header((CONTENT_ENCODING, encoding.as_str()))
+                    .body(body.clone()),
+                None => {
+                    let body = self.get(&content_type).cloned().unwrap_or_default();
+                    response_builder(StatusCode::OK, content_type).body(body)
+                }
+            },
+            None => build_response::<Vec<u8>>(
+                StatusCode::OK,
+                ContentType::default(),
+                Err(SerializeError::Unserializable),
+                config,
+            ),
+        };
+
+        config.apply_response_headers(&mut response);
+        if !self.encoded.is_empty() {
+            response
+                .headers_mut()
+                .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+        }
+
+        response
+    }
+}
+
+/// A value serialized once into all enabled formats.
+/// Each request is answered by looking up the negotiated representation, see [PayloadFile].
+///
+/// The value is kept alongside its representations, so handlers can still read it.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "json")]
+/// # fn main() {
+///     use actix_multiresponse::MultiEncoded;
+///     use actix_web::web;
+///
+///     #[derive(serde_derive::Serialize, prost_derive::Message)]
+///     struct Countries {
+///         #[prost(string, repeated, tag = "1")]
+///         codes: Vec<String>,
+///     }
+///
+///     async fn countries(countries: web::Data<MultiEncoded<Countries>>) -> MultiEncoded<Countries> {
+///         countries.as_ref().clone()
+///     }
+///
+///     let encoded = MultiEncoded::new(Countries { codes: vec!["NL".to_string(), "BE".to_string()] }).unwrap();
+///     let app = actix_web::App::new()
+///         .app_data(web::Data::new(encoded))
+///         .route("/countries", web::get().to(countries));
+/// # }
+/// # #[cfg(not(feature = "json"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct MultiEncoded<T> {
+    value: Arc<T>,
+    file: PayloadFile,
+}
+
+impl<T> Clone for MultiEncoded<T> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            file: self.file.clone(),
+        }
+    }
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize> MultiEncoded<T> {
+    /// Serialize the value in all enabled formats, with the default serializer settings
+    pub fn new(value: T) -> Result<Self, SerializeError> {
+        Self::with_config(value, &PayloadConfig::default())
+    }
+
+    /// Serialize the value in all enabled formats, using the serializer settings of `config`
+    pub fn with_config(value: T, config: &PayloadConfig) -> Result<Self, SerializeError> {
+        let file = PayloadFile::from_value(&value, config)?;
+        Ok(Self {
+            value: Arc::new(value),
+            file,
+        })
+    }
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize + Send + Sync + 'static> MultiEncoded<T> {
+    /// Like [MultiEncoded::with_config], serializing the formats in parallel, see [PayloadFile::from_value_parallel]
+    pub async fn new_parallel(value: T, config: &PayloadConfig) -> Result<Self, SerializeError> {
+        let value = Arc::new(value);
+        let file = PayloadFile::from_value_parallel(value.clone(), config).await?;
+        Ok(Self { value, file })
+    }
+}
+
+impl<T> MultiEncoded<T> {
+    /// The serialized value
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// The representations of the value
+    pub fn file(&self) -> &PayloadFile {
+        &self.file
+    }
+
+    /// Take the representations of the value
+    pub fn into_file(self) -> PayloadFile {
+        self.file
+    }
+}
+
+impl<T> Responder for MultiEncoded<T> {
+    type Body = BoxBody;
+
+    /// Respond with the negotiated representation, like [PayloadFile]
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        self.file.respond_to(req)
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[derive(serde_derive::Serialize, prost_derive::Message, Clone)]
+    struct TestPayload {
+        #[prost(string, tag = "1")]
+        foo: String,
+    }
+
+    #[actix_macros::test]
+    async fn test_negotiated_variant() {
+        let file = PayloadFile::from_value(&TestPayload { foo: "bar".to_string() }, &PayloadConfig::default()).unwrap();
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+        let resp = file.clone().respond_to(&req);
+
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(file.get(&ContentType::Protobuf).unwrap(), &body);
+    }
+
+    #[actix_macros::test]
+    async fn test_from_value_parallel() {
+        let value = TestPayload { foo: "bar".to_string() };
+        let config = PayloadConfig::default();
+        let file = PayloadFile::from_value_parallel(Arc::new(value.clone()), &config).await.unwrap();
+        let expected = PayloadFile::from_value(&value, &config).unwrap();
+
+        for content_type in ContentType::enabled() {
+            assert_eq!(expected.get(content_type), file.get(content_type));
+        }
+    }
+
+    #[actix_macros::test]
+    async fn test_multi_encoded() {
+        let value = TestPayload { foo: "bar".to_string() };
+        let encoded = MultiEncoded::new_parallel(value.clone(), &PayloadConfig::default()).await.unwrap();
+        assert_eq!("bar", encoded.value().foo);
+
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/json"))
+            .to_http_request();
+        let resp = encoded.clone().respond_to(&req);
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(MultiEncoded::new(value).unwrap().file().get(&ContentType::Json).unwrap(), &body);
+    }
+
+    #[test]
+    fn test_missing_variant() {
+        let file = PayloadFile::new().variant(ContentType::Json, "{}");
+        let req = TestRequest::get()
+            .insert_header(("Accept", "application/protobuf"))
+            .to_http_request();
+        let resp = file.respond_to(&req);
+
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+    }
+
+    #[test]
+    fn test_encoded_variant() {
+        let file = PayloadFile::new()
+            .variant(ContentType::Json, "{}")
+            .encoded_variant(ContentType::Json, Encoding::Gzip, "gzip")
+            .encoded_variant(ContentType::Json, Encoding::Brotli, "br")
+            .encoded_variant(ContentType::Protobuf, Encoding::Gzip, "proto");
+
+        let encoding = |accept_encoding: Option<&str>| {
+            let mut req = TestRequest::get().insert_header(("Accept", "application/json"));
+            if let Some(accept_encoding) = accept_encoding {
+                req = req.insert_header(("Accept-Encoding", accept_encoding));
+            }
+            let resp = file.clone().respond_to(&req.to_http_request());
+
+            let vary = resp.headers().get_all("Vary").map(|v| v.to_str().unwrap()).collect::<Vec<_>>();
+            assert_eq!(vec!["accept", "Accept-Encoding"], vary);
+            resp.headers()
+                .get("Content-Encoding")
+                .map(|v| v.to_str().unwrap().to_string())
+        };
+
+        assert_eq!(None, encoding(None));
+        assert_eq!(Some("gzip".to_string()), encoding(Some("gzip, br")));
+        assert_eq!(Some("br".to_string()), encoding(Some("gzip;q=0.5, br")));
+        assert_eq!(Some("gzip".to_string()), encoding(Some("*")));
+        assert_eq!(None, encoding(Some("identity, gzip;q=0.5")));
+        assert_eq!(None, encoding(Some("deflate")));
+    }
+}
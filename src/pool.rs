@@ -0,0 +1,167 @@
+#[cfg(any(feature = "json", feature = "xml"))]
+use crate::codec;
+use crate::{serialize_value, ContentType, PayloadConfig, ProtobufSupport, SerdeSupportSerialize, SerializeError};
+
+use actix_web::web::Bytes;
+#[cfg(any(feature = "json", feature = "xml"))]
+use actix_web::web::BytesMut;
+
+#[cfg(any(feature = "json", feature = "xml"))]
+use std::cell::RefCell;
+#[cfg(feature = "json")]
+use std::io;
+
+/// The sizing of the per worker pool of response buffers, see [PayloadConfig::buffer_pool]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct BufferPool {
+    /// The maximum number of buffers kept per worker thread
+    pub(crate) buffers: usize,
+    /// The maximum size of the bodies of which the buffers are kept
+    pub(crate) max_capacity: usize,
+}
+
+/// A pooled buffer, with the length of the body last serialized into it
+#[cfg(any(feature = "json", feature = "xml"))]
+struct Pooled {
+    buf: BytesMut,
+    len: usize,
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+thread_local! {
+    static POOL: RefCell<Vec<Pooled>> = const { RefCell::new(Vec::new()) };
+}
+
+impl BufferPool {
+    /// Serialize a value in `content_type` into a pooled buffer.
+    ///
+    /// JSON and XML are written into the buffer directly. Protobuf messages are encoded into a buffer
+    /// of their exact length upfront, they are not pooled.
+    pub(crate) fn serialize<T: ProtobufSupport + SerdeSupportSerialize>(
+        &self,
+        value: &T,
+        content_type: ContentType,
+        config: &PayloadConfig,
+    ) -> Result<Bytes, SerializeError> {
+        match content_type {
+            #[cfg(feature = "json")]
+            ContentType::Json => {
+                self.write(|buf| codec::write_json(io::BufWriter::with_capacity(8192, BytesWriter(buf)), value, config))
+            }
+            #[cfg(feature = "xml")]
+            ContentType::Xml => self.write(|buf| codec::write_xml(buf, value, config)),
+            content_type => serialize_value(value, content_type, config).map(Bytes::from),
+        }
+    }
+
+    /// Write into a buffer taken from the pool, and return the buffer to the pool afterwards.
+    ///
+    /// The body shares the allocation of the buffer. Once the response is sent and the body is dropped,
+    /// the next body serialized into the buffer reuses the allocation.
+    #[cfg(any(feature = "json", feature = "xml"))]
+    fn write<F>(&self, write: F) -> Result<Bytes, SerializeError>
+    where
+        F: FnOnce(&mut BytesMut) -> Result<(), SerializeError>,
+    {
+        let mut pooled = POOL
+            .with(|pool| pool.borrow_mut().pop())
+            .unwrap_or_else(|| Pooled {
+                buf: BytesMut::new(),
+                len: 0,
+            });
+        // Reclaims the allocation if no body shares it anymore, allocates a new buffer otherwise
+        pooled.buf.reserve(pooled.len);
+
+        let result = write(&mut pooled.buf);
+        let body = pooled.buf.split().freeze();
+
+        pooled.len = body.len();
+        if result.is_ok() && pooled.len <= self.max_capacity {
+            POOL.with(|pool| {
+                let mut pool = pool.borrow_mut();
+                if pool.len() < self.buffers {
+                    pool.push(pooled);
+                }
+            });
+        }
+
+        result.map(|_| body)
+    }
+}
+
+/// Writes bytes into a [BytesMut]. Wrapped in an [io::BufWriter], as appending the many small writes
+/// of the JSON serializer to a [BytesMut] one by one is slow
+#[cfg(feature = "json")]
+struct BytesWriter<'a>(&'a mut BytesMut);
+
+#[cfg(feature = "json")]
+impl io::Write for BytesWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use crate::Payload;
+
+    #[test]
+    fn test_buffer_reused() {
+        let pool = BufferPool {
+            buffers: 1,
+            max_capacity: 1024 * 1024,
+        };
+        let value = "foo".repeat(1000);
+        let config = PayloadConfig::default();
+
+        let body = pool.serialize(&value, ContentType::Json, &config).unwrap();
+        assert_eq!(Payload::new(value.clone()).serialize(ContentType::Json).unwrap(), body);
+        let ptr = body.as_ptr();
+        drop(body);
+        // Would take the allocation of the body if it was freed
+        let other = vec![0u8; 4096];
+
+        let body = pool.serialize(&value, ContentType::Json, &config).unwrap();
+        assert_eq!(ptr, body.as_ptr());
+        assert_ne!(ptr, other.as_ptr());
+
+        // The first body is still in use, so the second one is written into a new allocation
+        let second = pool.serialize(&value, ContentType::Json, &config).unwrap();
+        assert_ne!(body.as_ptr(), second.as_ptr());
+        assert_eq!(body, second);
+    }
+
+    #[test]
+    fn test_large_buffer_not_kept() {
+        let pool = BufferPool {
+            buffers: 1,
+            max_capacity: 16,
+        };
+        let value = "foo".repeat(1000);
+        let config = PayloadConfig::default();
+
+        pool.serialize(&value, ContentType::Json, &config).unwrap();
+        assert_eq!(0, POOL.with(|pool| pool.borrow().len()));
+
+        pool.serialize(&"foo".to_string(), ContentType::Json, &config).unwrap();
+        assert_eq!(1, POOL.with(|pool| pool.borrow().len()));
+    }
+
+    #[actix_macros::test]
+    async fn test_pooled_response() {
+        let req = actix_web::test::TestRequest::get()
+            .insert_header(("Accept", "application/json"))
+            .app_data(PayloadConfig::default().buffer_pool(1, 1024))
+            .to_http_request();
+        let resp = Payload::new("foo".to_string()).to_response(&req);
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+        assert_eq!(r#""foo""#, actix_web::body::to_bytes(resp.into_body()).await.unwrap());
+        assert_eq!(1, POOL.with(|pool| pool.borrow().len()));
+    }
+}
@@ -0,0 +1,36 @@
+//! The commonly needed types, traits and, with the `derive` feature, derive macros.
+//!
+//! ```
+//!     use actix_multiresponse::prelude::*;
+//!
+//!     async fn responder(payload: Payload<String>) -> Payload<String> {
+//!         payload
+//!     }
+//! ```
+
+pub use crate::{
+    AcceptedFormats, AnyFormat, BatchPayload, BodyCapture, ContentType, Created, Downloadable, Empty, ErasedPayload,
+    ErrorVerbosity, ForcedFormat, FormatSet, FromHeaders, Link, MaybePayload, Negotiation, Payload, PayloadConfig,
+    PayloadError, PayloadFile, ProtobufSupport, ResponseMeta, SerdeSupportDeserialize, SerdeSupportSerialize,
+    StatusClass, WithMeta,
+};
+#[cfg(any(feature = "json", feature = "xml"))]
+pub use crate::BorrowedPayload;
+#[cfg(feature = "json")]
+pub use crate::{Json, JsonOnly, JsonSeq, JsonSeqStream};
+#[cfg(feature = "protobuf")]
+pub use crate::{Proto, ProtoOnly};
+#[cfg(feature = "xml")]
+pub use crate::{Xml, XmlOnly};
+
+#[cfg(feature = "problem-details")]
+pub use crate::ProblemDetails;
+#[cfg(feature = "timestamps")]
+pub use crate::Timestamp;
+
+#[cfg(feature = "derive")]
+pub use crate::payload_type;
+#[cfg(all(feature = "derive", feature = "protobuf"))]
+pub use prost_derive::Message;
+#[cfg(all(feature = "derive", any(feature = "json", feature = "xml")))]
+pub use serde_derive::{Deserialize, Serialize};
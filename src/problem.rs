@@ -0,0 +1,216 @@
+//! RFC 7807 problem details, enabled with the `problem-details` feature.
+
+use crate::{negotiate, ContentType, ErrorLocation, PayloadConfig};
+
+use actix_web::http::StatusCode;
+use actix_web::HttpRequest;
+
+/// An [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807) problem details body.
+///
+/// With the `problem-details` feature, [crate::render_negotiated_error] renders errors as problem details,
+/// with the `application/problem+json` or `application/problem+xml` media type. A client which accepts `application/problem+json`
+/// receives JSON, even when it would otherwise negotiate a different format.
+/// The errors of the extractors are rendered the same way, see [crate::PayloadError::Negotiated].
+/// If the location of the error in the request body is known, it is included in the `location` extension member,
+/// like the `location` of an [crate::ErrorBody].
+/// In protobuf it is a message equivalent to
+/// ```proto
+/// message Problem {
+///     string type = 1;
+///     string title = 2;
+///     uint32 status = 3;
+///     string detail = 4;
+///     optional Location location = 5;
+/// }
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ProblemDetails {
+    /// The URI identifying the type of problem, see [PayloadConfig::problem_type_base]
+    pub problem_type: String,
+    /// The reason phrase of the status
+    pub title: String,
+    /// The HTTP status code
+    pub status: u16,
+    /// The error message
+    pub detail: String,
+    /// The location of the error in the request body, see [crate::PayloadError::location]
+    pub location: Option<ErrorLocation>,
+}
+
+impl ProblemDetails {
+    /// Problem details for an error with `status`, with its type relative to `type_base` if given,
+    /// or `about:blank` otherwise
+    pub fn new<S: Into<String>>(status: StatusCode, detail: S, type_base: Option<&str>) -> Self {
+        let title = status.canonical_reason().unwrap_or_default();
+        let problem_type = match type_base {
+            Some(base) => format!("{base}{}", slug(title)),
+            None => "about:blank".to_string(),
+        };
+
+        Self {
+            problem_type,
+            title: title.to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            location: None,
+        }
+    }
+}
+
+/// Turn a reason phrase into the last segment of a type URI, e.g. `Not Found` into `not-found`
+fn slug(title: &str) -> String {
+    title
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(char::is_ascii_alphanumeric)
+                .map(|c| c.to_ascii_lowercase())
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The format an error is rendered in for `req`, preferring JSON if the client accepts `application/problem+json`
+#[cfg_attr(not(feature = "json"), allow(unused_variables))]
+pub(crate) fn response_format(req: &HttpRequest, config: &PayloadConfig) -> ContentType {
+    #[cfg(feature = "json")]
+    {
+        let accepts_problem_json = req
+            .headers()
+            .get_all(actix_web::http::header::ACCEPT)
+            .filter_map(|header_value| header_value.to_str().ok())
+            .any(|value| crate::headers::accepts_media_type(value, "application/problem+json"));
+        if accepts_problem_json && config.supports(&ContentType::Json) {
+            return ContentType::Json;
+        }
+    }
+
+    negotiate::response_format(req)
+}
+
+/// The problem details media type of a format, if it has one
+pub(crate) fn media_type(content_type: &ContentType) -> Option<&'static str> {
+    match content_type {
+        #[cfg(feature = "json")]
+        ContentType::Json => Some("application/problem+json"),
+        #[cfg(feature = "xml")]
+        ContentType::Xml => Some("application/problem+xml"),
+        _ => None,
+    }
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl serde::Serialize for ProblemDetails {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut problem = serializer.serialize_struct("problem", 4 + self.location.is_some() as usize)?;
+        problem.serialize_field("type", &self.problem_type)?;
+        problem.serialize_field("title", &self.title)?;
+        problem.serialize_field("status", &self.status)?;
+        problem.serialize_field("detail", &self.detail)?;
+        if let Some(location) = &self.location {
+            problem.serialize_field("location", location)?;
+        }
+        problem.end()
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl prost::Message for ProblemDetails {
+    fn encode_raw<B: prost::bytes::BufMut>(&self, buf: &mut B) {
+        if !self.problem_type.is_empty() {
+            prost::encoding::string::encode(1, &self.problem_type, buf);
+        }
+
+        if !self.title.is_empty() {
+            prost::encoding::string::encode(2, &self.title, buf);
+        }
+
+        if self.status != 0 {
+            prost::encoding::uint32::encode(3, &(self.status as u32), buf);
+        }
+
+        if !self.detail.is_empty() {
+            prost::encoding::string::encode(4, &self.detail, buf);
+        }
+
+        if let Some(location) = &self.location {
+            prost::encoding::message::encode(5, location, buf);
+        }
+    }
+
+    fn merge_field<B: prost::bytes::Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: prost::encoding::WireType,
+        buf: &mut B,
+        ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        match tag {
+            1 => prost::encoding::string::merge(wire_type, &mut self.problem_type, buf, ctx),
+            2 => prost::encoding::string::merge(wire_type, &mut self.title, buf, ctx),
+            3 => {
+                let mut status = 0;
+                prost::encoding::uint32::merge(wire_type, &mut status, buf, ctx)?;
+                self.status = u16::try_from(status).map_err(|_| prost::DecodeError::new("status is out of range"))?;
+                Ok(())
+            }
+            4 => prost::encoding::string::merge(wire_type, &mut self.detail, buf, ctx),
+            5 => prost::encoding::message::merge(
+                wire_type,
+                self.location.get_or_insert_with(ErrorLocation::default),
+                buf,
+                ctx,
+            ),
+            _ => prost::encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if !self.problem_type.is_empty() {
+            len += prost::encoding::string::encoded_len(1, &self.problem_type);
+        }
+
+        if !self.title.is_empty() {
+            len += prost::encoding::string::encoded_len(2, &self.title);
+        }
+
+        if self.status != 0 {
+            len += prost::encoding::uint32::encoded_len(3, &(self.status as u32));
+        }
+
+        if !self.detail.is_empty() {
+            len += prost::encoding::string::encoded_len(4, &self.detail);
+        }
+
+        if let Some(location) = &self.location {
+            len += prost::encoding::message::encoded_len(5, location);
+        }
+
+        len
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_problem_type() {
+        let problem = ProblemDetails::new(StatusCode::NOT_FOUND, "Not found", Some("https://example.com/problems/"));
+        assert_eq!("https://example.com/problems/not-found", problem.problem_type);
+        assert_eq!("Not Found", problem.title);
+
+        let problem = ProblemDetails::new(StatusCode::IM_A_TEAPOT, "Teapot", None);
+        assert_eq!("about:blank", problem.problem_type);
+        assert_eq!("im-a-teapot", slug(&problem.title));
+    }
+}
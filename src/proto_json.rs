@@ -0,0 +1,1079 @@
+//! Representing protobuf specific values in JSON and XML, see [crate::PayloadConfig::int64_as_string],
+//! [crate::PayloadConfig::map_entries], [crate::PayloadConfig::enum_numbers] and [crate::PayloadConfig::omit_absent_fields].
+//!
+//! The serializer is wrapped, so 64 bit integers and maps are represented according to the configuration
+//! wherever they are nested. Enums of prost are plain `i32` fields to serde, so only the fields annotated with
+//! [serialize_proto_enum] are represented by name. Likewise, only absent fields annotated with
+//! [serialize_proto_optional] are omitted.
+
+#[cfg(feature = "json")]
+use crate::strict::{forward_deserialize, forward_visit};
+
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+#[cfg(feature = "json")]
+use serde::de::{DeserializeSeed, EnumAccess, SeqAccess, VariantAccess};
+use serde::ser::{self, Serialize, Serializer};
+
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The name with which [serialize_proto_enum] serializes enums as unit variants, so the wrapped serializer can recognize them
+const ENUM_NAME: &str = "$actix_multiresponse::ProtobufEnum";
+
+/// The name with which [serialize_proto_optional] serializes absent values as unit structs, so the wrapped serializer can omit them
+const ABSENT_NAME: &str = "$actix_multiresponse::Absent";
+
+/// A protobuf enum, of which the fields annotated with [serialize_proto_enum] and [deserialize_proto_enum]
+/// are represented by the names of the variants.
+///
+/// For enums generated by `prost-build`, this is implemented with [crate::protobuf_enum].
+pub trait ProtobufEnum {
+    /// The name of the variant with `number`, e.g. `KIND_FOO`
+    fn name(number: i32) -> Option<&'static str>;
+
+    /// The number of the variant with `name`
+    fn number(name: &str) -> Option<i32>;
+}
+
+/// Implement [ProtobufEnum] for enums generated by `prost-build`, using their `from_i32`, `as_str_name` and `from_str_name` methods.
+///
+/// # Example
+/// ```
+///     mod proto {
+///         #[derive(Clone, Copy, Debug, PartialEq, Eq, prost_derive::Enumeration)]
+///         pub enum Kind {
+///             Unknown = 0,
+///             Book = 1,
+///         }
+///
+///         // Generated by prost-build
+///         impl Kind {
+///             pub fn as_str_name(&self) -> &'static str {
+///                 match self {
+///                     Kind::Unknown => "KIND_UNKNOWN",
+///                     Kind::Book => "KIND_BOOK",
+///                 }
+///             }
+///
+///             pub fn from_str_name(value: &str) -> Option<Self> {
+///                 match value {
+///                     "KIND_UNKNOWN" => Some(Self::Unknown),
+///                     "KIND_BOOK" => Some(Self::Book),
+///                     _ => None,
+///                 }
+///             }
+///         }
+///     }
+///
+///     actix_multiresponse::protobuf_enum!(proto::Kind);
+///
+///     #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+///     struct Product {
+///         #[serde(
+///             serialize_with = "actix_multiresponse::serialize_proto_enum::<proto::Kind, _>",
+///             deserialize_with = "actix_multiresponse::deserialize_proto_enum::<proto::Kind, _>"
+///         )]
+///         kind: i32,
+///     }
+/// ```
+#[macro_export]
+macro_rules! protobuf_enum {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl $crate::ProtobufEnum for $ty {
+                fn name(number: i32) -> Option<&'static str> {
+                    <$ty>::from_i32(number).map(|variant| variant.as_str_name())
+                }
+
+                fn number(name: &str) -> Option<i32> {
+                    <$ty>::from_str_name(name).map(i32::from)
+                }
+            }
+        )+
+    };
+}
+
+/// Serialize the number of a protobuf enum by the name of its variant, or by its number with [crate::PayloadConfig::enum_numbers].
+/// Numbers which are not a variant of `E` are always serialized as numbers, like the protobuf JSON mapping does
+pub fn serialize_proto_enum<E: ProtobufEnum, S: Serializer>(number: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+    match E::name(*number) {
+        Some(name) => serializer.serialize_unit_variant(ENUM_NAME, *number as u32, name),
+        None => serializer.serialize_i32(*number),
+    }
+}
+
+/// Deserialize the number of a protobuf enum from either the name of its variant or its number
+pub fn deserialize_proto_enum<'de, E: ProtobufEnum, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+    deserializer.deserialize_any(EnumVisitor::<E>(PhantomData))
+}
+
+/// Serialize a `proto3 optional` field, keeping its presence: a present value is serialized as is, even if it is the
+/// default value of its type. An absent value is serialized as `null`, or omitted with [crate::PayloadConfig::omit_absent_fields].
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{deserialize_proto_optional, serialize_proto_optional};
+///
+///     #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+///     struct Settings {
+///         #[serde(
+///             default,
+///             serialize_with = "serialize_proto_optional",
+///             deserialize_with = "deserialize_proto_optional"
+///         )]
+///         volume: Option<u32>,
+///     }
+///
+///     let json = serde_json::to_string(&Settings { volume: Some(0) }).unwrap();
+///     assert_eq!(r#"{"volume":0}"#, json);
+/// ```
+pub fn serialize_proto_optional<T: Serialize, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.serialize_some(value),
+        None => serializer.serialize_unit_struct(ABSENT_NAME),
+    }
+}
+
+/// Deserialize a `proto3 optional` field, keeping its presence: both a missing field and `null` are absent,
+/// a present value is kept even if it is the default value of its type. Combine it with `#[serde(default)]`,
+/// so missing fields are accepted.
+pub fn deserialize_proto_optional<'de, T: de::Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    <Option<T> as de::Deserialize>::deserialize(deserializer)
+}
+
+/// Whether a value is absent, i.e. serialized with [serialize_proto_optional] as [ABSENT_NAME]
+fn is_absent<T: Serialize + ?Sized>(value: &T) -> bool {
+    value.serialize(AbsentProbe).unwrap_or(false)
+}
+
+/// A serializer which only recognizes absent values, failing for all other compound values.
+/// It never serializes nested values, so probing a value is cheap
+struct AbsentProbe;
+
+/// The error of [AbsentProbe] for compound values, which are never absent
+#[derive(Debug)]
+struct NotAbsent;
+
+impl fmt::Display for NotAbsent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("not absent")
+    }
+}
+
+impl std::error::Error for NotAbsent {}
+
+impl ser::Error for NotAbsent {
+    fn custom<T: fmt::Display>(_: T) -> Self {
+        Self
+    }
+}
+
+/// Implement the `serialize_*` methods of [AbsentProbe] for values which are never absent
+macro_rules! present {
+    ($($method:ident($($ty:ty),*);)*) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<Self::Ok, Self::Error> {
+                Ok(false)
+            }
+        )*
+    };
+}
+
+impl Serializer for AbsentProbe {
+    type Ok = bool;
+    type Error = NotAbsent;
+    type SerializeSeq = ser::Impossible<bool, NotAbsent>;
+    type SerializeTuple = ser::Impossible<bool, NotAbsent>;
+    type SerializeTupleStruct = ser::Impossible<bool, NotAbsent>;
+    type SerializeTupleVariant = ser::Impossible<bool, NotAbsent>;
+    type SerializeMap = ser::Impossible<bool, NotAbsent>;
+    type SerializeStruct = ser::Impossible<bool, NotAbsent>;
+    type SerializeStructVariant = ser::Impossible<bool, NotAbsent>;
+
+    present! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(name.eq(ABSENT_NAME))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<Self::Ok, Self::Error> {
+        Ok(false)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, _: &T) -> Result<Self::Ok, Self::Error> {
+        Ok(false)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(false)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(NotAbsent)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(NotAbsent)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(NotAbsent)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(NotAbsent)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(NotAbsent)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(NotAbsent)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(NotAbsent)
+    }
+
+    fn collect_str<T: fmt::Display + ?Sized>(self, _: &T) -> Result<Self::Ok, Self::Error> {
+        Ok(false)
+    }
+}
+
+/// The visitor of [deserialize_proto_enum]
+struct EnumVisitor<E>(PhantomData<E>);
+
+impl<'de, E: ProtobufEnum> Visitor<'de> for EnumVisitor<E> {
+    type Value = i32;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the name or number of an enum variant")
+    }
+
+    fn visit_i64<Err: de::Error>(self, v: i64) -> Result<Self::Value, Err> {
+        i32::try_from(v).map_err(|_| Err::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_u64<Err: de::Error>(self, v: u64) -> Result<Self::Value, Err> {
+        i32::try_from(v).map_err(|_| Err::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<Err: de::Error>(self, v: &str) -> Result<Self::Value, Err> {
+        // Formats without typed values, e.g. XML, provide numbers as strings
+        v.parse()
+            .ok()
+            .or_else(|| E::number(v))
+            .ok_or_else(|| Err::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        // XML elements are maps, of which the text content is the only value
+        match map.next_entry::<de::IgnoredAny, EnumNumber<E>>()? {
+            Some((_, number)) => Ok(number.0),
+            None => Err(de::Error::invalid_length(0, &self)),
+        }
+    }
+}
+
+/// The number of a protobuf enum, deserialized from the text content of an XML element
+struct EnumNumber<E>(i32, PhantomData<E>);
+
+impl<'de, E: ProtobufEnum> de::Deserialize<'de> for EnumNumber<E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_proto_enum::<E, D>(deserializer).map(|number| Self(number, PhantomData))
+    }
+}
+
+/// The representation of protobuf specific values, configured in [crate::PayloadConfig]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Mapping {
+    pub(crate) int64_as_string: bool,
+    pub(crate) map_entries: bool,
+    pub(crate) enum_numbers: bool,
+    pub(crate) omit_absent_fields: bool,
+}
+
+impl Mapping {
+    pub(crate) const DEFAULT: Self = Self {
+        int64_as_string: false,
+        map_entries: false,
+        enum_numbers: false,
+        omit_absent_fields: false,
+    };
+
+    /// Whether values are serialized as they are by serde
+    #[cfg(feature = "json")]
+    pub(crate) fn is_default(&self) -> bool {
+        self.eq(&Self::DEFAULT)
+    }
+}
+
+/// A value serialized with the representation of `mapping`
+pub(crate) struct Mapped<'a, T: ?Sized> {
+    pub(crate) value: &'a T,
+    pub(crate) mapping: Mapping,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Mapped<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(MappedSerializer {
+            ser: serializer,
+            mapping: self.mapping,
+        })
+    }
+}
+
+/// Wrap a value, to be serialized with the representation of `mapping`
+pub(crate) fn mapped<T: ?Sized>(value: &T, mapping: Mapping) -> Mapped<'_, T> {
+    Mapped { value, mapping }
+}
+
+/// A serializer representing values according to `mapping`, and wrapping all nested values
+struct MappedSerializer<S> {
+    ser: S,
+    mapping: Mapping,
+}
+
+/// A compound value of a [MappedSerializer], wrapping its elements
+struct Compound<C> {
+    compound: C,
+    mapping: Mapping,
+}
+
+/// A map of a [MappedSerializer], either as a map or as a sequence of entries
+enum MapCompound<M, Q> {
+    Map(Compound<M>),
+    Entries(Compound<Q>),
+}
+
+/// An entry of a map serialized with [Mapping::map_entries]
+struct Entry<'a, K: ?Sized, V: ?Sized> {
+    key: &'a K,
+    value: &'a V,
+    mapping: Mapping,
+}
+
+impl<K: Serialize + ?Sized, V: Serialize + ?Sized> Serialize for Entry<'_, K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut entry = serializer.serialize_struct("entry", 2)?;
+        entry.serialize_field("key", &mapped(self.key, self.mapping))?;
+        entry.serialize_field("value", &mapped(self.value, self.mapping))?;
+        entry.end()
+    }
+}
+
+/// Forward the `serialize_*` methods of [MappedSerializer] for values without nested values
+macro_rules! forward_serialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method(self, $($arg: $ty),*) -> Result<Self::Ok, Self::Error> {
+                self.ser.$method($($arg),*)
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for MappedSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<S::SerializeSeq>;
+    type SerializeTuple = Compound<S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<S::SerializeTupleVariant>;
+    type SerializeMap = MapCompound<S::SerializeMap, S::SerializeSeq>;
+    type SerializeStruct = Compound<S::SerializeStruct>;
+    type SerializeStructVariant = Compound<S::SerializeStructVariant>;
+
+    forward_serialize! {
+        serialize_bool(v: bool);
+        serialize_i8(v: i8);
+        serialize_i16(v: i16);
+        serialize_i32(v: i32);
+        serialize_i128(v: i128);
+        serialize_u8(v: u8);
+        serialize_u16(v: u16);
+        serialize_u32(v: u32);
+        serialize_u128(v: u128);
+        serialize_f32(v: f32);
+        serialize_f64(v: f64);
+        serialize_char(v: char);
+        serialize_str(v: &str);
+        serialize_bytes(v: &[u8]);
+        serialize_none();
+        serialize_unit();
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        match name {
+            ABSENT_NAME => self.ser.serialize_none(),
+            _ => self.ser.serialize_unit_struct(name),
+        }
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        if self.mapping.int64_as_string {
+            self.ser.collect_str(&v)
+        } else {
+            self.ser.serialize_i64(v)
+        }
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        if self.mapping.int64_as_string {
+            self.ser.collect_str(&v)
+        } else {
+            self.ser.serialize_u64(v)
+        }
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        match name {
+            ENUM_NAME if self.mapping.enum_numbers => self.ser.serialize_i32(variant_index as i32),
+            // Formats like XML serialize unit variants as elements rather than as strings
+            ENUM_NAME => self.ser.serialize_str(variant),
+            _ => self.ser.serialize_unit_variant(name, variant_index, variant),
+        }
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        self.ser.serialize_some(&mapped(value, self.mapping))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.ser.serialize_newtype_struct(name, &mapped(value, self.mapping))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.ser
+            .serialize_newtype_variant(name, variant_index, variant, &mapped(value, self.mapping))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        let mapping = self.mapping;
+        self.ser.serialize_seq(len).map(|compound| Compound { compound, mapping })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        let mapping = self.mapping;
+        self.ser.serialize_tuple(len).map(|compound| Compound { compound, mapping })
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        let mapping = self.mapping;
+        self.ser
+            .serialize_tuple_struct(name, len)
+            .map(|compound| Compound { compound, mapping })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        let mapping = self.mapping;
+        self.ser
+            .serialize_tuple_variant(name, variant_index, variant, len)
+            .map(|compound| Compound { compound, mapping })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        let mapping = self.mapping;
+        if mapping.map_entries {
+            self.ser
+                .serialize_seq(len)
+                .map(|compound| MapCompound::Entries(Compound { compound, mapping }))
+        } else {
+            self.ser
+                .serialize_map(len)
+                .map(|compound| MapCompound::Map(Compound { compound, mapping }))
+        }
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        let mapping = self.mapping;
+        self.ser
+            .serialize_struct(name, len)
+            .map(|compound| Compound { compound, mapping })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        let mapping = self.mapping;
+        self.ser
+            .serialize_struct_variant(name, variant_index, variant, len)
+            .map(|compound| Compound { compound, mapping })
+    }
+
+    fn collect_str<T: fmt::Display + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        self.ser.collect_str(value)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.ser.is_human_readable()
+    }
+}
+
+/// Implement a `Serialize*` trait of elements for [Compound]
+macro_rules! compound_elements {
+    ($($serialize:ident::$method:ident;)*) => {
+        $(
+            impl<C: ser::$serialize> ser::$serialize for Compound<C> {
+                type Ok = C::Ok;
+                type Error = C::Error;
+
+                fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+                    self.compound.$method(&mapped(value, self.mapping))
+                }
+
+                fn end(self) -> Result<Self::Ok, Self::Error> {
+                    self.compound.end()
+                }
+            }
+        )*
+    };
+}
+
+compound_elements! {
+    SerializeSeq::serialize_element;
+    SerializeTuple::serialize_element;
+    SerializeTupleStruct::serialize_field;
+    SerializeTupleVariant::serialize_field;
+}
+
+/// Implement a `Serialize*` trait of fields for [Compound]
+macro_rules! compound_fields {
+    ($($serialize:ident;)*) => {
+        $(
+            impl<C: ser::$serialize> ser::$serialize for Compound<C> {
+                type Ok = C::Ok;
+                type Error = C::Error;
+
+                fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error> {
+                    if self.mapping.omit_absent_fields && is_absent(value) {
+                        return self.compound.skip_field(key);
+                    }
+
+                    self.compound.serialize_field(key, &mapped(value, self.mapping))
+                }
+
+                fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+                    self.compound.skip_field(key)
+                }
+
+                fn end(self) -> Result<Self::Ok, Self::Error> {
+                    self.compound.end()
+                }
+            }
+        )*
+    };
+}
+
+compound_fields! {
+    SerializeStruct;
+    SerializeStructVariant;
+}
+
+impl<M: ser::SerializeMap, Q: ser::SerializeSeq<Ok = M::Ok, Error = M::Error>> ser::SerializeMap for MapCompound<M, Q> {
+    type Ok = M::Ok;
+    type Error = M::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        match self {
+            Self::Map(map) => map.compound.serialize_key(&mapped(key, map.mapping)),
+            // Entries need the key and the value at once, which maps of the standard library and of serde_json provide
+            Self::Entries(_) => Err(ser::Error::custom("map entries must be serialized with serialize_entry")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        match self {
+            Self::Map(map) => map.compound.serialize_value(&mapped(value, map.mapping)),
+            Self::Entries(_) => Err(ser::Error::custom("map entries must be serialized with serialize_entry")),
+        }
+    }
+
+    fn serialize_entry<K: Serialize + ?Sized, V: Serialize + ?Sized>(&mut self, key: &K, value: &V) -> Result<(), Self::Error> {
+        match self {
+            Self::Map(map) => map
+                .compound
+                .serialize_entry(&mapped(key, map.mapping), &mapped(value, map.mapping)),
+            Self::Entries(entries) => entries.compound.serialize_element(&Entry {
+                key,
+                value,
+                mapping: entries.mapping,
+            }),
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        match self {
+            Self::Map(map) => map.compound.end(),
+            Self::Entries(entries) => entries.compound.end(),
+        }
+    }
+}
+
+/// Wrap a deserializer, accepting 64 bit integers as either numbers or strings, see [crate::PayloadConfig::int64_as_string]
+#[cfg(feature = "json")]
+pub(crate) fn int64_strings<'de, D: Deserializer<'de>>(deserializer: D) -> impl Deserializer<'de, Error = D::Error> {
+    Int64Deserializer { de: deserializer }
+}
+
+/// A deserializer accepting 64 bit integers as strings, wrapping all nested values
+#[cfg(feature = "json")]
+struct Int64Deserializer<D> {
+    de: D,
+}
+
+#[cfg(feature = "json")]
+impl<D> Int64Deserializer<D> {
+    fn wrap<V>(self, visitor: V) -> (D, Int64Visitor<V>) {
+        (self.de, Int64Visitor { visitor, integer: false })
+    }
+
+    /// Deserialize an integer, which the format may provide as a string
+    fn integer<V>(self, visitor: V) -> (D, Int64Visitor<V>) {
+        (self.de, Int64Visitor { visitor, integer: true })
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Int64Deserializer<D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let (de, visitor) = self.integer(visitor);
+        de.deserialize_any(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let (de, visitor) = self.integer(visitor);
+        de.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.de.is_human_readable()
+    }
+}
+
+/// The visitor of an [Int64Deserializer], parsing strings if an `integer` was requested
+#[cfg(feature = "json")]
+struct Int64Visitor<V> {
+    visitor: V,
+    integer: bool,
+}
+
+#[cfg(feature = "json")]
+impl<'de, V: Visitor<'de>> Visitor<'de> for Int64Visitor<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.visitor.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(v: bool);
+        visit_i8(v: i8);
+        visit_i16(v: i16);
+        visit_i32(v: i32);
+        visit_i64(v: i64);
+        visit_i128(v: i128);
+        visit_u8(v: u8);
+        visit_u16(v: u16);
+        visit_u32(v: u32);
+        visit_u64(v: u64);
+        visit_u128(v: u128);
+        visit_f32(v: f32);
+        visit_f64(v: f64);
+        visit_char(v: char);
+        visit_string(v: String);
+        visit_bytes(v: &[u8]);
+        visit_borrowed_bytes(v: &'de [u8]);
+        visit_byte_buf(v: Vec<u8>);
+        visit_none();
+        visit_unit();
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if !self.integer {
+            return self.visitor.visit_str(v);
+        }
+
+        if let Ok(signed) = v.parse::<i64>() {
+            self.visitor.visit_i64(signed)
+        } else if let Ok(unsigned) = v.parse::<u64>() {
+            self.visitor.visit_u64(unsigned)
+        } else {
+            Err(E::invalid_value(de::Unexpected::Str(v), &self.visitor))
+        }
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        if self.integer {
+            self.visit_str(v)
+        } else {
+            self.visitor.visit_borrowed_str(v)
+        }
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_some(Int64Deserializer { de: deserializer })
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_newtype_struct(Int64Deserializer { de: deserializer })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_seq(Int64Access(seq))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_map(Int64Access(map))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_enum(Int64Access(data))
+    }
+}
+
+/// A seed deserializing with an [Int64Deserializer]
+#[cfg(feature = "json")]
+struct Int64Seed<S>(S);
+
+#[cfg(feature = "json")]
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Int64Seed<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.0.deserialize(Int64Deserializer { de: deserializer })
+    }
+}
+
+/// The access to the nested values of a sequence, map or enum, wrapping their deserializers
+#[cfg(feature = "json")]
+struct Int64Access<A>(A);
+
+#[cfg(feature = "json")]
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for Int64Access<A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
+        self.0.next_element_seed(Int64Seed(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Int64Access<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        self.0.next_key_seed(Int64Seed(seed))
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, Self::Error> {
+        self.0.next_value_seed(Int64Seed(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'de, A: EnumAccess<'de>> EnumAccess<'de> for Int64Access<A> {
+    type Error = A::Error;
+    type Variant = Int64Access<A::Variant>;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<(T::Value, Self::Variant), Self::Error> {
+        self.0
+            .variant_seed(seed)
+            .map(|(value, variant)| (value, Int64Access(variant)))
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for Int64Access<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.0.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Self::Error> {
+        self.0.newtype_variant_seed(Int64Seed(seed))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.0.tuple_variant(len, Int64Visitor { visitor, integer: false })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        self.0.struct_variant(fields, Int64Visitor { visitor, integer: false })
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    struct Kind;
+
+    impl ProtobufEnum for Kind {
+        fn name(number: i32) -> Option<&'static str> {
+            match number {
+                0 => Some("KIND_UNKNOWN"),
+                1 => Some("KIND_BOOK"),
+                _ => None,
+            }
+        }
+
+        fn number(name: &str) -> Option<i32> {
+            match name {
+                "KIND_UNKNOWN" => Some(0),
+                "KIND_BOOK" => Some(1),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Product {
+        id: i64,
+        stock: u32,
+        #[serde(
+            serialize_with = "serialize_proto_enum::<Kind, _>",
+            deserialize_with = "deserialize_proto_enum::<Kind, _>"
+        )]
+        kind: i32,
+        prices: BTreeMap<u32, i64>,
+    }
+
+    fn product() -> Product {
+        Product {
+            id: 9_007_199_254_740_993,
+            stock: 3,
+            kind: 1,
+            prices: BTreeMap::from([(1, 100)]),
+        }
+    }
+
+    fn to_value(mapping: Mapping) -> serde_json::Value {
+        serde_json::to_value(mapped(&product(), mapping)).unwrap()
+    }
+
+    #[test]
+    fn test_default_mapping() {
+        assert_eq!(
+            serde_json::json!({ "id": 9_007_199_254_740_993_i64, "stock": 3, "kind": "KIND_BOOK", "prices": { "1": 100 } }),
+            to_value(Mapping::DEFAULT)
+        );
+    }
+
+    #[test]
+    fn test_mapping() {
+        let mapping = Mapping {
+            int64_as_string: true,
+            map_entries: true,
+            enum_numbers: true,
+            omit_absent_fields: false,
+        };
+        assert_eq!(
+            serde_json::json!({
+                "id": "9007199254740993",
+                "stock": 3,
+                "kind": 1,
+                "prices": [{ "key": 1, "value": "100" }],
+            }),
+            to_value(mapping)
+        );
+    }
+
+    #[test]
+    fn test_int64_strings() {
+        let json = r#"{ "id": "9007199254740993", "stock": 3, "kind": "KIND_BOOK", "prices": { "1": "100" } }"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let deserialized = Product::deserialize(int64_strings(&mut deserializer)).unwrap();
+        assert_eq!(product(), deserialized);
+
+        let json = r#"{ "id": 1, "stock": "3", "kind": 1, "prices": {} }"#;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        assert!(Product::deserialize(int64_strings(&mut deserializer)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "xml")]
+    fn test_xml_mapping() {
+        let mapping = Mapping {
+            map_entries: true,
+            ..Mapping::DEFAULT
+        };
+        let xml = quick_xml::se::to_string(&mapped(&product(), mapping)).unwrap();
+        assert!(xml.contains("<kind>KIND_BOOK</kind>"));
+        assert!(xml.contains("<prices><key>1</key><value>100</value></prices>"));
+
+        let xml = "<Product><id>1</id><stock>3</stock><kind>KIND_BOOK</kind><prices/></Product>";
+        assert_eq!(1, quick_xml::de::from_str::<Product>(xml).unwrap().kind);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        #[serde(
+            default,
+            serialize_with = "serialize_proto_optional",
+            deserialize_with = "deserialize_proto_optional"
+        )]
+        volume: Option<u32>,
+        #[serde(
+            default,
+            serialize_with = "serialize_proto_optional",
+            deserialize_with = "deserialize_proto_optional"
+        )]
+        name: Option<String>,
+    }
+
+    #[test]
+    fn test_proto_optional() {
+        let settings = Settings {
+            volume: Some(0),
+            name: None,
+        };
+        assert_eq!(
+            serde_json::json!({ "volume": 0, "name": null }),
+            serde_json::to_value(mapped(&settings, Mapping::DEFAULT)).unwrap()
+        );
+
+        let mapping = Mapping {
+            omit_absent_fields: true,
+            ..Mapping::DEFAULT
+        };
+        assert_eq!(serde_json::json!({ "volume": 0 }), serde_json::to_value(mapped(&settings, mapping)).unwrap());
+
+        assert_eq!(settings, serde_json::from_str(r#"{ "volume": 0 }"#).unwrap());
+        assert_eq!(settings, serde_json::from_str(r#"{ "volume": 0, "name": null }"#).unwrap());
+        assert_eq!(None, serde_json::from_str::<Settings>("{}").unwrap().volume);
+    }
+
+    #[test]
+    #[cfg(feature = "xml")]
+    fn test_xml_proto_optional() {
+        let settings = Settings {
+            volume: Some(0),
+            name: None,
+        };
+        let xml = crate::codec::serialize_xml(&settings, &crate::PayloadConfig::default()).unwrap();
+        assert_eq!("<Settings><volume>0</volume></Settings>", String::from_utf8(xml).unwrap());
+
+        let xml = "<Settings><volume>0</volume></Settings>";
+        assert_eq!(None, quick_xml::de::from_str::<Settings>(xml).unwrap().name);
+    }
+
+    #[test]
+    fn test_unknown_enum() {
+        let unknown = Product { kind: 7, ..product() };
+        assert_eq!(serde_json::json!(7), serde_json::to_value(mapped(&unknown, Mapping::DEFAULT)).unwrap()["kind"]);
+        assert!(serde_json::from_str::<Product>(r#"{ "id": 1, "stock": 3, "kind": "KIND_CD", "prices": {} }"#).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! A framework neutral representation of [PayloadError], for reusing the error handling outside of actix.
+
+#[cfg(feature = "http")]
+use crate::ContentType;
+use crate::{ErrorBody, ErrorLocation, PayloadError};
+
+use actix_web::ResponseError;
+
+/// The kind of a [Rejection]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub enum RejectionKind {
+    /// The body exceeds [crate::PayloadConfig::limit]
+    PayloadTooLarge,
+    /// The body was not received within [crate::PayloadConfig::read_timeout]
+    Timeout,
+    /// The body is not in a supported format
+    UnsupportedContentType,
+    /// A header is malformed, or occurs more than once
+    MalformedHeader,
+    /// The body could not be read or deserialized
+    InvalidBody,
+    /// An internal error, unrelated to the request
+    Internal,
+}
+
+/// A [PayloadError] without any types of actix, so other servers can render
+/// errors exactly like the extractors of this crate.
+///
+/// With the `http` feature, a rejection can be turned into an `http::Response` with `Rejection::into_http_response`.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{ContentType, PayloadError, Rejection, RejectionKind};
+///
+///     let rejection = Rejection::from(PayloadError::InvalidContentType(ContentType::Other("text/html".to_string())));
+///     assert_eq!(RejectionKind::UnsupportedContentType, rejection.kind);
+///     assert_eq!(400, rejection.status);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Rejection {
+    /// The kind of error
+    pub kind: RejectionKind,
+    /// The HTTP status code
+    pub status: u16,
+    /// The error message
+    pub message: String,
+    /// The location of the error in the request body, see [PayloadError::location]
+    pub location: Option<ErrorLocation>,
+    /// The error code configured with [crate::PayloadConfig::map_error], if any
+    pub code: Option<String>,
+}
+
+impl From<&PayloadError> for Rejection {
+    fn from(error: &PayloadError) -> Self {
+        let kind = match error.detailed() {
+            PayloadError::ActixPayload(actix_web::error::PayloadError::Overflow) => RejectionKind::PayloadTooLarge,
+            PayloadError::ActixPayload(_)
+            | PayloadError::Deserialize { .. }
+            | PayloadError::InvalidMultipart(_)
+            | PayloadError::ChecksumMismatch(_) => RejectionKind::InvalidBody,
+            PayloadError::InvalidContentType(_) | PayloadError::UnsupportedFormat(_) => RejectionKind::UnsupportedContentType,
+            PayloadError::AmbiguousContentType | PayloadError::MalformedHeader(_) => RejectionKind::MalformedHeader,
+            PayloadError::Timeout => RejectionKind::Timeout,
+            PayloadError::Blocking(_)
+            | PayloadError::Redacted { .. }
+            | PayloadError::Mapped { .. }
+            | PayloadError::Attempts { .. } => RejectionKind::Internal,
+            #[cfg(feature = "problem-details")]
+            PayloadError::Negotiated { .. } => RejectionKind::Internal,
+        };
+
+        Self {
+            kind,
+            status: error.status_code().as_u16(),
+            message: error.to_string(),
+            location: error.location(),
+            code: error.error_code().map(str::to_string),
+        }
+    }
+}
+
+impl From<PayloadError> for Rejection {
+    fn from(error: PayloadError) -> Self {
+        Self::from(&error)
+    }
+}
+
+impl From<Rejection> for ErrorBody {
+    fn from(rejection: Rejection) -> Self {
+        Self {
+            status: rejection.status,
+            message: rejection.message,
+            location: rejection.location,
+            code: rejection.code,
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl Rejection {
+    /// A response with the [ErrorBody] of the rejection, serialized in `content_type`.
+    /// If `content_type` is not supported, or serializing fails, the message is sent as plain text.
+    pub fn into_http_response(self, content_type: ContentType) -> http::Response<Vec<u8>> {
+        let status = http::StatusCode::from_u16(self.status).unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
+        let message = self.message.clone();
+        let serialized = content_type
+            .media_type()
+            .and_then(|media_type| Some((media_type, crate::Payload::new(ErrorBody::from(self)).serialize(content_type).ok()?)));
+
+        let (media_type, body) = match serialized {
+            Some(serialized) => serialized,
+            None => ("text/plain; charset=utf-8", message.into_bytes()),
+        };
+
+        let mut response = http::Response::new(body);
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(http::header::CONTENT_TYPE, http::HeaderValue::from_static(media_type));
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        let rejection = Rejection::from(PayloadError::ActixPayload(actix_web::error::PayloadError::Overflow));
+        assert_eq!(RejectionKind::PayloadTooLarge, rejection.kind);
+        assert_eq!(413, rejection.status);
+
+        assert_eq!(RejectionKind::Timeout, Rejection::from(PayloadError::Timeout).kind);
+    }
+
+    #[test]
+    #[cfg(all(feature = "http", feature = "json"))]
+    fn test_http_response() {
+        let response = Rejection::from(PayloadError::InvalidContentType(ContentType::Other("text/html".to_string()))).into_http_response(ContentType::Json);
+        assert_eq!(400, response.status().as_u16());
+        assert_eq!("application/json", response.headers()[http::header::CONTENT_TYPE]);
+
+        let response = Rejection::from(PayloadError::Timeout).into_http_response(ContentType::Other(String::new()));
+        assert_eq!(408, response.status().as_u16());
+        assert_eq!(b"Timed out reading the request body".to_vec(), response.into_body());
+    }
+}
@@ -0,0 +1,137 @@
+//! Restricting the formats of a [crate::Payload] at the type level.
+
+use crate::ContentType;
+
+/// A set of formats, restricting the formats a [crate::Payload] accepts and responds in.
+///
+/// Implemented by the format markers, and by tuples of up to three markers to allow all of their formats.
+/// The set is part of the type, so the signature of a route documents and enforces the formats it supports,
+/// without any configuration:
+/// ```
+/// # #[cfg(all(feature = "json", feature = "protobuf"))]
+/// # fn main() {
+///     use actix_multiresponse::{JsonOnly, Payload, ProtoOnly};
+///
+///     async fn responder(payload: Payload<String, (JsonOnly, ProtoOnly)>) -> Payload<String, (JsonOnly, ProtoOnly)> {
+///         payload
+///     }
+/// # }
+/// # #[cfg(not(all(feature = "json", feature = "protobuf")))]
+/// # fn main() {}
+/// ```
+///
+/// Requests in other formats are rejected with a HTTP 415 error.
+/// Responses are in the preferred format of the client out of the set.
+pub trait FormatSet {
+    /// Whether the format is part of the set
+    fn allows(content_type: &ContentType) -> bool;
+}
+
+/// All formats, the default of [crate::Payload]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnyFormat;
+
+impl FormatSet for AnyFormat {
+    fn allows(_: &ContentType) -> bool {
+        true
+    }
+}
+
+/// Only JSON
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JsonOnly;
+
+#[cfg(feature = "json")]
+impl FormatSet for JsonOnly {
+    fn allows(content_type: &ContentType) -> bool {
+        content_type.eq(&ContentType::Json)
+    }
+}
+
+/// Only protobuf, including grpc-web if it is enabled
+#[cfg(feature = "protobuf")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProtoOnly;
+
+#[cfg(feature = "protobuf")]
+impl FormatSet for ProtoOnly {
+    fn allows(content_type: &ContentType) -> bool {
+        matches!(content_type, ContentType::Protobuf | ContentType::GrpcWeb)
+    }
+}
+
+/// Only XML
+#[cfg(feature = "xml")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct XmlOnly;
+
+#[cfg(feature = "xml")]
+impl FormatSet for XmlOnly {
+    fn allows(content_type: &ContentType) -> bool {
+        content_type.eq(&ContentType::Xml)
+    }
+}
+
+impl<A: FormatSet, B: FormatSet> FormatSet for (A, B) {
+    fn allows(content_type: &ContentType) -> bool {
+        A::allows(content_type) || B::allows(content_type)
+    }
+}
+
+impl<A: FormatSet, B: FormatSet, C: FormatSet> FormatSet for (A, B, C) {
+    fn allows(content_type: &ContentType) -> bool {
+        A::allows(content_type) || B::allows(content_type) || C::allows(content_type)
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+    use crate::Payload;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App};
+
+    #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+    struct TestPayload {
+        #[prost(string, tag = "1")]
+        foo: String,
+    }
+
+    async fn json_responder(payload: Payload<TestPayload, JsonOnly>) -> Payload<TestPayload, JsonOnly> {
+        payload
+    }
+
+    #[actix_macros::test]
+    async fn test_rejects_format() {
+        let app = init_service(App::new().route("/", web::post().to(json_responder))).await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, resp.status());
+    }
+
+    #[actix_macros::test]
+    async fn test_response_format_restricted() {
+        let app = init_service(App::new().route("/", web::post().to(json_responder))).await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("Accept", "application/protobuf"))
+            .set_payload(r#"{"foo":"bar"}"#)
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+    }
+
+    #[test]
+    fn test_tuple_allows() {
+        assert!(<(JsonOnly, ProtoOnly)>::allows(&ContentType::Json));
+        assert!(<(JsonOnly, ProtoOnly)>::allows(&ContentType::Protobuf));
+        assert!(!<(JsonOnly, ProtoOnly)>::allows(&ContentType::Other(String::new())));
+    }
+}
@@ -0,0 +1,693 @@
+//! Validation of JSON request bodies against a JSON Schema, see [JsonSchema]. Requires the `jsonschema` feature.
+//!
+//! The validator implements the keywords of the draft 2020-12 validation vocabulary which are commonly used for request
+//! bodies: `type`, `enum`, `const`, `properties`, `required`, `additionalProperties`, `items`, `minItems`, `maxItems`,
+//! `uniqueItems`, `minLength`, `maxLength`, `pattern`, `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`,
+//! `multipleOf`, `minProperties`, `maxProperties`, `allOf`, `anyOf`, `oneOf`, `not` and `$ref` to definitions in the same schema.
+//! Annotations, like `title` and `format`, are ignored, see [JsonSchema::compile].
+
+use crate::{ContentType, DeserializeError, PayloadConfig};
+
+use regex_lite::Regex;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The keywords which do not affect validation, which are ignored.
+/// `$defs` and `definitions` are only compiled where they are referenced with `$ref`
+const ANNOTATIONS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$anchor",
+    "$comment",
+    "$defs",
+    "definitions",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+    "format",
+    "contentEncoding",
+    "contentMediaType",
+];
+
+/// A compiled JSON Schema, which JSON request bodies are validated against before they are deserialized,
+/// see [PayloadConfig::json_schema]. Bodies which do not match it are rejected with a HTTP 422 error,
+/// listing the [SchemaViolation]s with the JSON Pointers of the invalid values.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::JsonSchema;
+///
+///     let schema = JsonSchema::compile(&serde_json::json!({
+///         "type": "object",
+///         "properties": { "name": { "type": "string", "minLength": 1 } },
+///         "required": ["name"],
+///     }))
+///     .unwrap();
+///
+///     assert!(schema.validate(&serde_json::json!({ "name": "foo" })).is_empty());
+///     assert_eq!("/name", schema.validate(&serde_json::json!({ "name": "" }))[0].pointer());
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonSchema {
+    root: Arc<Node>,
+    /// The targets of the `$ref`s in the schema, by their reference
+    definitions: Arc<HashMap<String, Node>>,
+}
+
+/// A JSON Schema which could not be compiled
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+#[error("Invalid JSON Schema at \"{pointer}\": {message}")]
+pub struct SchemaError {
+    pointer: String,
+    message: String,
+}
+
+impl SchemaError {
+    fn new<S: Into<String>>(pointer: &str, message: S) -> Self {
+        Self {
+            pointer: pointer.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// The JSON Pointer of the invalid part of the schema
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+}
+
+/// A value of a request body which does not match the [JsonSchema]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SchemaViolation {
+    pointer: String,
+    keyword: &'static str,
+    message: String,
+}
+
+impl SchemaViolation {
+    /// The JSON Pointer of the invalid value in the body, e.g. `/items/0/name`. Empty for the body itself
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    /// The keyword of the schema the value violates, e.g. `minLength`
+    pub fn keyword(&self) -> &str {
+        self.keyword
+    }
+
+    /// The description of the violation, without the pointer
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pointer.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)
+        }
+    }
+}
+
+/// The violations separated by semicolons, for the message of [DeserializeError::Schema]
+pub(crate) fn violations(violations: &[SchemaViolation]) -> String {
+    violations.iter().map(SchemaViolation::to_string).collect::<Vec<_>>().join("; ")
+}
+
+/// A compiled schema or subschema
+#[derive(Debug)]
+enum Node {
+    Bool(bool),
+    Keywords(Vec<Keyword>),
+}
+
+#[derive(Debug)]
+enum Keyword {
+    Type(Vec<InstanceType>),
+    Enum(Vec<Value>),
+    Const(Value),
+    Properties(Vec<(String, Node)>),
+    Required(Vec<String>),
+    AdditionalProperties { known: Vec<String>, schema: Box<Node> },
+    Items(Box<Node>),
+    MinItems(usize),
+    MaxItems(usize),
+    UniqueItems,
+    MinLength(usize),
+    MaxLength(usize),
+    Pattern(Regex),
+    Minimum(f64),
+    Maximum(f64),
+    ExclusiveMinimum(f64),
+    ExclusiveMaximum(f64),
+    MultipleOf(f64),
+    MinProperties(usize),
+    MaxProperties(usize),
+    AllOf(Vec<Node>),
+    AnyOf(Vec<Node>),
+    OneOf(Vec<Node>),
+    Not(Box<Node>),
+    Ref(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum InstanceType {
+    Null,
+    Boolean,
+    Object,
+    Array,
+    Number,
+    Integer,
+    String,
+}
+
+impl InstanceType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "null" => Self::Null,
+            "boolean" => Self::Boolean,
+            "object" => Self::Object,
+            "array" => Self::Array,
+            "number" => Self::Number,
+            "integer" => Self::Integer,
+            "string" => Self::String,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Boolean => "boolean",
+            Self::Object => "object",
+            Self::Array => "array",
+            Self::Number => "number",
+            Self::Integer => "integer",
+            Self::String => "string",
+        }
+    }
+
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(_) => Self::Boolean,
+            Value::Object(_) => Self::Object,
+            Value::Array(_) => Self::Array,
+            Value::Number(n) if n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|n| n.fract() == 0.0) => Self::Integer,
+            Value::Number(_) => Self::Number,
+            Value::String(_) => Self::String,
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        let of = Self::of(value);
+        of == self || (self == Self::Number && of == Self::Integer)
+    }
+}
+
+impl JsonSchema {
+    /// Compile `schema`, including the definitions it references with `$ref`.
+    ///
+    /// The annotation keywords `$schema`, `$id`, `$anchor`, `$comment`, `$defs`, `definitions`, `title`, `description`,
+    /// `default`, `examples`, `deprecated`, `readOnly`, `writeOnly`, `format`, `contentEncoding` and `contentMediaType`
+    /// are ignored. Schemas with any other keyword which is not supported, e.g. `patternProperties` or `if`,
+    /// fail to compile, rather than accepting or rejecting bodies differently than the schema describes.
+    ///
+    /// # Errors
+    ///
+    /// If the schema contains a keyword which is not supported, a keyword has a value of the wrong type,
+    /// a `pattern` is not a valid regular expression, or a `$ref` does not point into the schema
+    pub fn compile(schema: &Value) -> Result<Self, SchemaError> {
+        let mut references = Vec::new();
+        let root = compile(schema, "", &mut references)?;
+
+        let mut definitions = HashMap::new();
+        while let Some(reference) = references.pop() {
+            if definitions.contains_key(&reference) {
+                continue;
+            }
+            let pointer = reference.strip_prefix('#').unwrap_or_default();
+            let target = schema
+                .pointer(pointer)
+                .ok_or_else(|| SchemaError::new(pointer, format!("The reference {reference} does not point into the schema")))?;
+            let node = compile(target, pointer, &mut references)?;
+            definitions.insert(reference, node);
+        }
+
+        Ok(Self {
+            root: Arc::new(root),
+            definitions: Arc::new(definitions),
+        })
+    }
+
+    /// The violations of the schema by `instance`, empty if it matches
+    pub fn validate(&self, instance: &Value) -> Vec<SchemaViolation> {
+        let mut violations = Vec::new();
+        self.validate_node(&self.root, instance, &mut String::new(), &mut violations);
+        violations
+    }
+
+    /// Whether `instance` matches the schema
+    pub fn is_valid(&self, instance: &Value) -> bool {
+        self.validate(instance).is_empty()
+    }
+
+    fn validate_node(&self, node: &Node, value: &Value, pointer: &mut String, violations: &mut Vec<SchemaViolation>) {
+        let keywords = match node {
+            Node::Bool(true) => return,
+            Node::Bool(false) => return violations.push(violation(pointer, "false", "no value is allowed")),
+            Node::Keywords(keywords) => keywords,
+        };
+
+        for keyword in keywords {
+            self.validate_keyword(keyword, value, pointer, violations);
+        }
+    }
+
+    fn validate_keyword(&self, keyword: &Keyword, value: &Value, pointer: &mut String, violations: &mut Vec<SchemaViolation>) {
+        match (keyword, value) {
+            (Keyword::Type(types), value) if !types.iter().any(|ty| ty.matches(value)) => {
+                let expected = types.iter().map(|ty| ty.name()).collect::<Vec<_>>().join(" or ");
+                let message = format!("expected {expected}, got {}", InstanceType::of(value).name());
+                violations.push(violation(pointer, "type", message));
+            }
+            (Keyword::Enum(values), value) if !values.iter().any(|allowed| json_eq(allowed, value)) => {
+                violations.push(violation(pointer, "enum", format!("{value} is not one of the allowed values")));
+            }
+            (Keyword::Const(allowed), value) if !json_eq(allowed, value) => {
+                violations.push(violation(pointer, "const", format!("expected {allowed}")));
+            }
+            (Keyword::Properties(properties), Value::Object(object)) => {
+                for (name, node) in properties {
+                    if let Some(property) = object.get(name) {
+                        with_segment(pointer, name, |pointer| self.validate_node(node, property, pointer, violations));
+                    }
+                }
+            }
+            (Keyword::Required(required), Value::Object(object)) => {
+                for name in required.iter().filter(|name| !object.contains_key(*name)) {
+                    violations.push(violation(pointer, "required", format!("missing required property \"{name}\"")));
+                }
+            }
+            (Keyword::AdditionalProperties { known, schema }, Value::Object(object)) => {
+                for (name, property) in object.iter().filter(|(name, _)| !known.contains(name)) {
+                    with_segment(pointer, name, |pointer| match schema.as_ref() {
+                        Node::Bool(false) => violations.push(violation(pointer, "additionalProperties", "unknown property")),
+                        schema => self.validate_node(schema, property, pointer, violations),
+                    });
+                }
+            }
+            (Keyword::Items(node), Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    with_segment(pointer, &i.to_string(), |pointer| self.validate_node(node, item, pointer, violations));
+                }
+            }
+            (Keyword::MinItems(min), Value::Array(items)) if items.len() < *min => {
+                violations.push(violation(pointer, "minItems", format!("expected at least {min} items, got {}", items.len())));
+            }
+            (Keyword::MaxItems(max), Value::Array(items)) if items.len() > *max => {
+                violations.push(violation(pointer, "maxItems", format!("expected at most {max} items, got {}", items.len())));
+            }
+            (Keyword::UniqueItems, Value::Array(items)) => {
+                let duplicate = (1..items.len()).find(|&i| items[..i].iter().any(|item| json_eq(item, &items[i])));
+                if let Some(i) = duplicate {
+                    violations.push(violation(pointer, "uniqueItems", format!("item {i} is a duplicate")));
+                }
+            }
+            (Keyword::MinLength(min), Value::String(s)) if s.chars().count() < *min => {
+                violations.push(violation(pointer, "minLength", format!("expected a length of at least {min}")));
+            }
+            (Keyword::MaxLength(max), Value::String(s)) if s.chars().count() > *max => {
+                violations.push(violation(pointer, "maxLength", format!("expected a length of at most {max}")));
+            }
+            (Keyword::Pattern(regex), Value::String(s)) if !regex.is_match(s) => {
+                violations.push(violation(pointer, "pattern", format!("does not match the pattern {}", regex.as_str())));
+            }
+            (Keyword::Minimum(min), Value::Number(n)) if n.as_f64().is_some_and(|n| n < *min) => {
+                violations.push(violation(pointer, "minimum", format!("expected at least {min}, got {n}")));
+            }
+            (Keyword::Maximum(max), Value::Number(n)) if n.as_f64().is_some_and(|n| n > *max) => {
+                violations.push(violation(pointer, "maximum", format!("expected at most {max}, got {n}")));
+            }
+            (Keyword::ExclusiveMinimum(min), Value::Number(n)) if n.as_f64().is_some_and(|n| n <= *min) => {
+                violations.push(violation(pointer, "exclusiveMinimum", format!("expected more than {min}, got {n}")));
+            }
+            (Keyword::ExclusiveMaximum(max), Value::Number(n)) if n.as_f64().is_some_and(|n| n >= *max) => {
+                violations.push(violation(pointer, "exclusiveMaximum", format!("expected less than {max}, got {n}")));
+            }
+            (Keyword::MultipleOf(factor), Value::Number(n)) if n.as_f64().is_some_and(|n| !is_multiple(n, *factor)) => {
+                violations.push(violation(pointer, "multipleOf", format!("expected a multiple of {factor}, got {n}")));
+            }
+            (Keyword::MinProperties(min), Value::Object(object)) if object.len() < *min => {
+                violations.push(violation(pointer, "minProperties", format!("expected at least {min} properties")));
+            }
+            (Keyword::MaxProperties(max), Value::Object(object)) if object.len() > *max => {
+                violations.push(violation(pointer, "maxProperties", format!("expected at most {max} properties")));
+            }
+            (Keyword::AllOf(nodes), value) => {
+                for node in nodes {
+                    self.validate_node(node, value, pointer, violations);
+                }
+            }
+            (Keyword::AnyOf(nodes), value) if !nodes.iter().any(|node| self.matches(node, value)) => {
+                violations.push(violation(pointer, "anyOf", "does not match any of the allowed schemas"));
+            }
+            (Keyword::OneOf(nodes), value) => {
+                let matching = nodes.iter().filter(|node| self.matches(node, value)).count();
+                if matching != 1 {
+                    let message = format!("expected to match exactly one of the allowed schemas, matches {matching}");
+                    violations.push(violation(pointer, "oneOf", message));
+                }
+            }
+            (Keyword::Not(node), value) if self.matches(node, value) => {
+                violations.push(violation(pointer, "not", "matches a disallowed schema"));
+            }
+            (Keyword::Ref(reference), value) => {
+                // Always compiled, see JsonSchema::compile
+                if let Some(node) = self.definitions.get(reference) {
+                    self.validate_node(node, value, pointer, violations);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn matches(&self, node: &Node, value: &Value) -> bool {
+        let mut violations = Vec::new();
+        self.validate_node(node, value, &mut String::new(), &mut violations);
+        violations.is_empty()
+    }
+}
+
+fn violation<S: Into<String>>(pointer: &str, keyword: &'static str, message: S) -> SchemaViolation {
+    SchemaViolation {
+        pointer: pointer.to_string(),
+        keyword,
+        message: message.into(),
+    }
+}
+
+/// Run `f` with `segment` appended to `pointer`, escaped as in RFC 6901
+fn with_segment<F: FnOnce(&mut String)>(pointer: &mut String, segment: &str, f: F) {
+    let len = pointer.len();
+    pointer.push('/');
+    pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    f(pointer);
+    pointer.truncate(len);
+}
+
+/// Whether `n` is a multiple of `factor`, allowing for the rounding errors of decimal factors like `0.1`
+fn is_multiple(n: f64, factor: f64) -> bool {
+    let quotient = n / factor;
+    (quotient - quotient.round()).abs() < 1e-9
+}
+
+/// Equality of JSON values, comparing numbers by value, so `1` equals `1.0`
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a == b || a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_eq(a, b)),
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| json_eq(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// Compile the schema at `pointer`, collecting the references it contains
+fn compile(schema: &Value, pointer: &str, references: &mut Vec<String>) -> Result<Node, SchemaError> {
+    let object = match schema {
+        Value::Bool(b) => return Ok(Node::Bool(*b)),
+        Value::Object(object) => object,
+        _ => return Err(SchemaError::new(pointer, "A schema must be an object or a boolean")),
+    };
+
+    let mut keywords = Vec::new();
+    for (name, value) in object {
+        let at = format!("{pointer}/{name}");
+        let keyword = match name.as_str() {
+            "type" => Keyword::Type(compile_types(value, &at)?),
+            "enum" => Keyword::Enum(value.as_array().cloned().ok_or_else(|| SchemaError::new(&at, "Expected an array"))?),
+            "const" => Keyword::Const(value.clone()),
+            "properties" => Keyword::Properties(
+                as_object(value, &at)?
+                    .iter()
+                    .map(|(name, schema)| Ok((name.clone(), compile(schema, &format!("{at}/{name}"), references)?)))
+                    .collect::<Result<_, SchemaError>>()?,
+            ),
+            "required" => Keyword::Required(
+                as_array(value, &at)?
+                    .iter()
+                    .map(|name| name.as_str().map(str::to_string).ok_or_else(|| SchemaError::new(&at, "Expected strings")))
+                    .collect::<Result<_, _>>()?,
+            ),
+            "additionalProperties" => Keyword::AdditionalProperties {
+                known: object
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .map(|properties| properties.keys().cloned().collect())
+                    .unwrap_or_default(),
+                schema: Box::new(compile(value, &at, references)?),
+            },
+            "items" => Keyword::Items(Box::new(compile(value, &at, references)?)),
+            "minItems" => Keyword::MinItems(as_usize(value, &at)?),
+            "maxItems" => Keyword::MaxItems(as_usize(value, &at)?),
+            "uniqueItems" => match value.as_bool() {
+                Some(true) => Keyword::UniqueItems,
+                Some(false) => continue,
+                None => return Err(SchemaError::new(&at, "Expected a boolean")),
+            },
+            "minLength" => Keyword::MinLength(as_usize(value, &at)?),
+            "maxLength" => Keyword::MaxLength(as_usize(value, &at)?),
+            "pattern" => {
+                let pattern = value.as_str().ok_or_else(|| SchemaError::new(&at, "Expected a string"))?;
+                Keyword::Pattern(Regex::new(pattern).map_err(|e| SchemaError::new(&at, format!("Invalid pattern: {e}")))?)
+            }
+            "minimum" => Keyword::Minimum(as_f64(value, &at)?),
+            "maximum" => Keyword::Maximum(as_f64(value, &at)?),
+            "exclusiveMinimum" => Keyword::ExclusiveMinimum(as_f64(value, &at)?),
+            "exclusiveMaximum" => Keyword::ExclusiveMaximum(as_f64(value, &at)?),
+            "multipleOf" => match as_f64(value, &at)? {
+                factor if factor > 0.0 => Keyword::MultipleOf(factor),
+                _ => return Err(SchemaError::new(&at, "Expected a number greater than 0")),
+            },
+            "minProperties" => Keyword::MinProperties(as_usize(value, &at)?),
+            "maxProperties" => Keyword::MaxProperties(as_usize(value, &at)?),
+            "allOf" => Keyword::AllOf(compile_all(value, &at, references)?),
+            "anyOf" => Keyword::AnyOf(compile_all(value, &at, references)?),
+            "oneOf" => Keyword::OneOf(compile_all(value, &at, references)?),
+            "not" => Keyword::Not(Box::new(compile(value, &at, references)?)),
+            "$ref" => match value.as_str() {
+                Some(reference) if reference.starts_with('#') => {
+                    references.push(reference.to_string());
+                    Keyword::Ref(reference.to_string())
+                }
+                _ => return Err(SchemaError::new(&at, "Only references into the schema itself, starting with #, are supported")),
+            },
+            name if ANNOTATIONS.contains(&name) => continue,
+            name => return Err(SchemaError::new(&at, format!("The keyword {name} is not supported"))),
+        };
+        keywords.push(keyword);
+    }
+
+    Ok(Node::Keywords(keywords))
+}
+
+fn compile_types(value: &Value, at: &str) -> Result<Vec<InstanceType>, SchemaError> {
+    let names = match value {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => return Err(SchemaError::new(at, "Expected a string or an array of strings")),
+    };
+
+    names
+        .into_iter()
+        .map(|name| InstanceType::parse(name).ok_or_else(|| SchemaError::new(at, format!("Unknown type {name}"))))
+        .collect()
+}
+
+fn compile_all(value: &Value, at: &str, references: &mut Vec<String>) -> Result<Vec<Node>, SchemaError> {
+    as_array(value, at)?
+        .iter()
+        .enumerate()
+        .map(|(i, schema)| compile(schema, &format!("{at}/{i}"), references))
+        .collect()
+}
+
+fn as_object<'a>(value: &'a Value, at: &str) -> Result<&'a Map<String, Value>, SchemaError> {
+    value.as_object().ok_or_else(|| SchemaError::new(at, "Expected an object"))
+}
+
+fn as_array<'a>(value: &'a Value, at: &str) -> Result<&'a Vec<Value>, SchemaError> {
+    value.as_array().ok_or_else(|| SchemaError::new(at, "Expected an array"))
+}
+
+fn as_usize(value: &Value, at: &str) -> Result<usize, SchemaError> {
+    value
+        .as_u64()
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or_else(|| SchemaError::new(at, "Expected a non-negative integer"))
+}
+
+fn as_f64(value: &Value, at: &str) -> Result<f64, SchemaError> {
+    value.as_f64().ok_or_else(|| SchemaError::new(at, "Expected a number"))
+}
+
+/// Validate a JSON body against the schema registered for `T` with [PayloadConfig::json_schema], if any.
+/// Bodies which are not valid JSON are left to the deserializer to report.
+///
+/// The body is parsed into a [Value] to validate it, and parsed again when deserializing it,
+/// so the deserializer reports errors at the positions in the body
+pub(crate) fn validate<T: 'static>(body: &[u8], content_type: &ContentType, config: &PayloadConfig) -> Result<(), DeserializeError> {
+    if !matches!(content_type, ContentType::Json) {
+        return Ok(());
+    }
+    let Some(schema) = config.json_schema_of(TypeId::of::<T>()) else {
+        return Ok(());
+    };
+    let Ok(instance) = serde_json::from_slice::<Value>(body) else {
+        return Ok(());
+    };
+
+    let violations = schema.validate(&instance);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(DeserializeError::Schema(violations))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Payload, PayloadError};
+    use actix_web::test::TestRequest;
+    use actix_web::{FromRequest, ResponseError};
+    use serde_json::json;
+
+    fn schema() -> JsonSchema {
+        JsonSchema::compile(&json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1, "pattern": "^[a-z]+$" },
+                "tags": { "type": "array", "items": { "$ref": "#/$defs/tag" }, "uniqueItems": true },
+                "count": { "type": "integer", "minimum": 0 },
+            },
+            "required": ["name"],
+            "additionalProperties": false,
+            "$defs": {
+                "tag": { "enum": ["a", "b"] },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = schema();
+        assert!(schema.is_valid(&json!({ "name": "foo", "tags": ["a", "b"], "count": 2.0 })));
+        assert!(JsonSchema::compile(&json!({ "multipleOf": 0.1 })).unwrap().is_valid(&json!(0.3)));
+
+        let violations = schema.validate(&json!({ "name": "Foo", "tags": ["a", "c", "a"], "count": -1, "x~/": 1 }));
+        let mut found: Vec<(&str, &str)> = violations.iter().map(|v| (v.pointer(), v.keyword())).collect();
+        found.sort();
+        assert_eq!(
+            vec![
+                ("/count", "minimum"),
+                ("/name", "pattern"),
+                ("/tags", "uniqueItems"),
+                ("/tags/1", "enum"),
+                ("/x~0~1", "additionalProperties"),
+            ],
+            found
+        );
+
+        let violations = schema.validate(&json!([]));
+        assert_eq!("expected object, got array", violations[0].to_string());
+        assert_eq!("missing required property \"name\"", schema.validate(&json!({}))[0].message());
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let error = JsonSchema::compile(&json!({ "properties": { "name": { "type": "text" } } })).unwrap_err();
+        assert_eq!("/properties/name/type", error.pointer());
+        assert!(JsonSchema::compile(&json!({ "pattern": "(" })).is_err());
+        assert!(JsonSchema::compile(&json!({ "$ref": "#/$defs/missing" })).is_err());
+        assert!(JsonSchema::compile(&json!({ "$ref": "https://example.com/schema.json" })).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_keywords() {
+        for keyword in ["patternProperties", "prefixItems", "dependentRequired", "if", "then", "else", "x-custom"] {
+            let error = JsonSchema::compile(&json!({ "properties": { "name": { keyword: {} } } })).unwrap_err();
+            assert_eq!(format!("/properties/name/{keyword}"), error.pointer());
+            assert!(error.to_string().ends_with(&format!("The keyword {keyword} is not supported")));
+        }
+
+        let schema = JsonSchema::compile(&json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Name",
+            "type": "string",
+            "format": "email",
+            "examples": ["foo"],
+            "uniqueItems": false,
+        }))
+        .unwrap();
+        assert!(schema.is_valid(&json!("foo")));
+    }
+
+    #[test]
+    fn test_recursive_reference() {
+        let schema = JsonSchema::compile(&json!({
+            "$defs": { "node": { "type": "object", "properties": { "children": { "items": { "$ref": "#/$defs/node" } } } } },
+            "$ref": "#/$defs/node",
+        }))
+        .unwrap();
+
+        assert!(schema.is_valid(&json!({ "children": [{ "children": [] }] })));
+        assert_eq!("/children/0/children/0", schema.validate(&json!({ "children": [{ "children": [1] }] }))[0].pointer());
+    }
+
+    #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+    struct Tagged {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    #[actix_macros::test]
+    async fn test_payload_schema() {
+        let extract = |body: &'static str| {
+            let (req, mut payload) = TestRequest::default()
+                .insert_header(("Content-Type", "application/json"))
+                .app_data(PayloadConfig::default().json_schema::<Tagged>(schema()))
+                .set_payload(body)
+                .to_http_parts();
+            async move { Payload::<Tagged>::from_request(&req, &mut payload).await }
+        };
+
+        assert_eq!("foo", extract(r#"{ "name": "foo" }"#).await.unwrap().0.name);
+
+        let error = extract(r#"{ "name": "", "extra": true }"#).await.unwrap_err();
+        assert_eq!(422, error.status_code().as_u16());
+        let PayloadError::Deserialize { error, .. } = error.detailed() else {
+            panic!("Expected a deserialize error");
+        };
+        assert_eq!(3, error.schema_violations().unwrap().len());
+        assert!(error.to_string().contains("/name: expected a length of at least 1;"));
+
+        // Syntax errors are reported by the deserializer
+        let error = extract("{").await.unwrap_err();
+        assert_eq!(400, error.status_code().as_u16());
+    }
+}
@@ -0,0 +1,595 @@
+//! Rejecting unknown fields while deserializing, see [crate::PayloadConfig::deny_unknown_fields],
+//! and finding the path of the value a deserialize error occurred in, see [crate::DeserializeError::path].
+//!
+//! The deserializer of the format is wrapped, keeping track of the path to the current value.
+//! Types derived with serde deserialize the value of a field they do not know as [serde::de::IgnoredAny],
+//! which is where the wrapper fails with the path of the field.
+//! The path of the innermost value which fails to deserialize is recorded on the way out.
+
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use std::cell::RefCell;
+use std::fmt;
+
+/// Deserialize a value, failing on the first field which the value does not know
+pub(crate) fn deserialize<'de, T: de::Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    tracked(deserializer, true).0
+}
+
+/// The path of the value deserializing fails in, e.g. `authors[1].name`.
+/// `None` if deserializing succeeds, or fails outside of any field.
+///
+/// Keeping track of the path costs an allocation per value, so this is meant for deserializing
+/// a body again once it is known to fail
+pub(crate) fn error_path<'de, T: de::Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+    deny_unknown_fields: bool,
+) -> Option<String> {
+    match tracked::<T, D>(deserializer, deny_unknown_fields) {
+        (Ok(_), _) => None,
+        (Err(_), path) => path,
+    }
+}
+
+/// Deserialize a value, returning the path of the value it failed in, if any
+fn tracked<'de, T: de::Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+    deny_unknown_fields: bool,
+) -> (Result<T, D::Error>, Option<String>) {
+    let context = Context {
+        deny_unknown_fields,
+        error_path: RefCell::new(None),
+    };
+    let result = T::deserialize(Strict {
+        de: deserializer,
+        path: "",
+        context: &context,
+    });
+
+    (result, context.error_path.into_inner().filter(|path| !path.is_empty()))
+}
+
+/// Shared by all values of one deserialization
+struct Context {
+    /// Fail on fields the value does not know
+    deny_unknown_fields: bool,
+    /// The path of the innermost value which failed to deserialize
+    error_path: RefCell<Option<String>>,
+}
+
+impl Context {
+    /// Record the path of a value which failed to deserialize, unless a value nested in it failed first
+    fn record(&self, path: &str) {
+        self.error_path.borrow_mut().get_or_insert_with(|| path.to_string());
+    }
+}
+
+/// The path of a field in a value, e.g. `author.name`
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// Forward the `deserialize_*` methods of a wrapping deserializer, wrapping the visitor with `Self::wrap`
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error> {
+                let (de, visitor) = self.wrap(visitor);
+                de.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+pub(crate) use forward_deserialize;
+
+/// Forward the `visit_*` methods of a wrapping visitor for values without nested values
+macro_rules! forward_visit {
+    ($($method:ident($($v:ident: $ty:ty)?);)*) => {
+        $(
+            fn $method<E: de::Error>(self, $($v: $ty)?) -> Result<Self::Value, E> {
+                self.visitor.$method($($v)?)
+            }
+        )*
+    };
+}
+pub(crate) use forward_visit;
+
+/// The deserializer of the value at `path`
+struct Strict<'a, D> {
+    de: D,
+    path: &'a str,
+    context: &'a Context,
+}
+
+impl<'a, D> Strict<'a, D> {
+    fn wrap<V>(self, visitor: V) -> (D, StrictVisitor<'a, V>) {
+        (
+            self.de,
+            StrictVisitor {
+                visitor,
+                path: self.path,
+                context: self.context,
+            },
+        )
+    }
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Strict<'_, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.context.deny_unknown_fields {
+            Err(de::Error::custom(format_args!("unknown field `{}`", self.path)))
+        } else {
+            self.de.deserialize_ignored_any(visitor)
+        }
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.de.is_human_readable()
+    }
+}
+
+/// The visitor of the value at `path`, wrapping the access to nested values
+struct StrictVisitor<'a, V> {
+    visitor: V,
+    path: &'a str,
+    context: &'a Context,
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for StrictVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.visitor.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(v: bool);
+        visit_i8(v: i8);
+        visit_i16(v: i16);
+        visit_i32(v: i32);
+        visit_i64(v: i64);
+        visit_i128(v: i128);
+        visit_u8(v: u8);
+        visit_u16(v: u16);
+        visit_u32(v: u32);
+        visit_u64(v: u64);
+        visit_u128(v: u128);
+        visit_f32(v: f32);
+        visit_f64(v: f64);
+        visit_char(v: char);
+        visit_str(v: &str);
+        visit_borrowed_str(v: &'de str);
+        visit_string(v: String);
+        visit_bytes(v: &[u8]);
+        visit_borrowed_bytes(v: &'de [u8]);
+        visit_byte_buf(v: Vec<u8>);
+        visit_none();
+        visit_unit();
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_some(Strict {
+            de: deserializer,
+            path: self.path,
+            context: self.context,
+        })
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_newtype_struct(Strict {
+            de: deserializer,
+            path: self.path,
+            context: self.context,
+        })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_seq(StrictSeq {
+            seq,
+            path: self.path,
+            context: self.context,
+            index: 0,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_map(StrictMap {
+            map,
+            path: self.path,
+            context: self.context,
+            key: None,
+        })
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_enum(StrictEnum {
+            data,
+            path: self.path,
+            context: self.context,
+        })
+    }
+}
+
+/// A seed deserializing the value at `path`, recording the path if it fails
+struct StrictSeed<'a, S> {
+    seed: S,
+    path: String,
+    context: &'a Context,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for StrictSeed<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let Self { seed, path, context } = self;
+        seed.deserialize(Strict {
+            de: deserializer,
+            path: &path,
+            context,
+        })
+        .inspect_err(|_| context.record(&path))
+    }
+}
+
+/// The elements of a sequence at `path`, where each element is at `path[index]`
+struct StrictSeq<'a, A> {
+    seq: A,
+    path: &'a str,
+    context: &'a Context,
+    index: usize,
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for StrictSeq<'_, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error> {
+        let path = format!("{}[{}]", self.path, self.index);
+        self.index += 1;
+        self.seq.next_element_seed(StrictSeed {
+            seed,
+            path,
+            context: self.context,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.seq.size_hint()
+    }
+}
+
+/// The entries of a map at `path`, where each value is at `path.key`
+struct StrictMap<'a, A> {
+    map: A,
+    path: &'a str,
+    context: &'a Context,
+    /// The last key, captured while it was deserialized
+    key: Option<String>,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for StrictMap<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        self.map.next_key_seed(CaptureKey { seed, key: &mut self.key })
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, Self::Error> {
+        let path = join(self.path, self.key.take().as_deref().unwrap_or("?"));
+        self.map.next_value_seed(StrictSeed {
+            seed,
+            path,
+            context: self.context,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint()
+    }
+}
+
+/// The variant of an enum at `path`
+struct StrictEnum<'a, A> {
+    data: A,
+    path: &'a str,
+    context: &'a Context,
+}
+
+impl<'a, 'de, A: EnumAccess<'de>> EnumAccess<'de> for StrictEnum<'a, A> {
+    type Error = A::Error;
+    type Variant = StrictEnum<'a, A::Variant>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self::Variant), Self::Error> {
+        let (value, data) = self.data.variant_seed(seed)?;
+        Ok((
+            value,
+            StrictEnum {
+                data,
+                path: self.path,
+                context: self.context,
+            },
+        ))
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for StrictEnum<'_, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.data.unit_variant()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Self::Error> {
+        self.data.newtype_variant_seed(StrictSeed {
+            seed,
+            path: self.path.to_string(),
+            context: self.context,
+        })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.data.tuple_variant(
+            len,
+            StrictVisitor {
+                visitor,
+                path: self.path,
+                context: self.context,
+            },
+        )
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.data.struct_variant(
+            fields,
+            StrictVisitor {
+                visitor,
+                path: self.path,
+                context: self.context,
+            },
+        )
+    }
+}
+
+/// A seed deserializing a map key, capturing it if it is a string or an integer
+struct CaptureKey<'a, S> {
+    seed: S,
+    key: &'a mut Option<String>,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for CaptureKey<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.seed.deserialize(CaptureKeyDeserializer { de: deserializer, key: self.key })
+    }
+}
+
+/// The deserializer of a map key, capturing it
+struct CaptureKeyDeserializer<'a, D> {
+    de: D,
+    key: &'a mut Option<String>,
+}
+
+impl<'a, D> CaptureKeyDeserializer<'a, D> {
+    fn wrap<V>(self, visitor: V) -> (D, CaptureKeyVisitor<'a, V>) {
+        (self.de, CaptureKeyVisitor { visitor, key: self.key })
+    }
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for CaptureKeyDeserializer<'_, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.de.is_human_readable()
+    }
+}
+
+/// The visitor of a map key, capturing it
+struct CaptureKeyVisitor<'a, V> {
+    visitor: V,
+    key: &'a mut Option<String>,
+}
+
+/// Forward the `visit_*` methods of [CaptureKeyVisitor], capturing the key
+macro_rules! capture_visit {
+    ($($method:ident($ty:ty);)*) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+                *self.key = Some(v.to_string());
+                self.visitor.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for CaptureKeyVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.visitor.expecting(f)
+    }
+
+    capture_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+        visit_string(String);
+    }
+
+    forward_visit! {
+        visit_f32(v: f32);
+        visit_f64(v: f64);
+        visit_bytes(v: &[u8]);
+        visit_borrowed_bytes(v: &'de [u8]);
+        visit_byte_buf(v: Vec<u8>);
+        visit_none();
+        visit_unit();
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_some(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_newtype_struct(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_seq(seq)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_map(map)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_enum(data)
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+
+    #[derive(Debug, serde_derive::Deserialize)]
+    struct Author {
+        name: String,
+    }
+
+    #[derive(Debug, serde_derive::Deserialize)]
+    struct Post {
+        title: String,
+        authors: Vec<Author>,
+        editor: Option<Author>,
+    }
+
+    fn from_str(json: &str) -> Result<Post, serde_json::Error> {
+        deserialize(&mut serde_json::Deserializer::from_str(json))
+    }
+
+    #[test]
+    fn test_known_fields() {
+        let post = from_str(r#"{"title":"foo","authors":[{"name":"bar"}],"editor":{"name":"baz"}}"#).unwrap();
+        assert_eq!("foo", post.title);
+        assert_eq!("bar", post.authors[0].name);
+        assert_eq!("baz", post.editor.unwrap().name);
+    }
+
+    #[test]
+    fn test_error_path() {
+        let error_path = |json: &str, deny_unknown_fields: bool| {
+            error_path::<Post, _>(&mut serde_json::Deserializer::from_str(json), deny_unknown_fields)
+        };
+
+        let json = r#"{"title":"foo","authors":[{"name":"bar"},{"name":1}]}"#;
+        assert_eq!(Some("authors[1].name".to_string()), error_path(json, false));
+
+        let json = r#"{"title":"foo","authors":[],"editor":{"name":"bar","email":""}}"#;
+        assert_eq!(None, error_path(json, false));
+        assert_eq!(Some("editor.email".to_string()), error_path(json, true));
+
+        // Syntax errors outside of any field have no path
+        assert_eq!(None, error_path("{", false));
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let error = from_str(r#"{"title":"foo","subtitle":"bar","authors":[]}"#).unwrap_err();
+        assert!(error.to_string().starts_with("unknown field `subtitle`"));
+    }
+
+    #[test]
+    fn test_nested_unknown_field() {
+        let error = from_str(r#"{"title":"foo","authors":[{"name":"bar"},{"name":"baz","email":""}]}"#).unwrap_err();
+        assert!(error.to_string().starts_with("unknown field `authors[1].email`"));
+
+        let error = from_str(r#"{"title":"foo","authors":[],"editor":{"name":"bar","email":""}}"#).unwrap_err();
+        assert!(error.to_string().starts_with("unknown field `editor.email`"));
+    }
+}
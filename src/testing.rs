@@ -0,0 +1,234 @@
+//! Helpers for contract tests, e.g. asserting that the JSON and protobuf responses of an endpoint represent the same data
+//! while migrating clients between formats.
+//!
+//! ```
+//! # #[cfg(all(feature = "json", feature = "protobuf"))]
+//! # fn main() {
+//!     use actix_multiresponse::testing::assert_payload_eq;
+//!     use actix_multiresponse::{ContentType, Payload};
+//!
+//!     let json = Payload::new("foo".to_string()).serialize(ContentType::Json).unwrap();
+//!     let protobuf = Payload::new("foo".to_string()).serialize(ContentType::Protobuf).unwrap();
+//!     assert_payload_eq::<String>(&json, ContentType::Json, &protobuf, ContentType::Protobuf);
+//! # }
+//! # #[cfg(not(all(feature = "json", feature = "protobuf")))]
+//! # fn main() {}
+//! ```
+
+use crate::{
+    ContentType, DeserializeError, Payload, PayloadConfig, ProtobufSupport, SerdeSupportDeserialize, SerdeSupportSerialize,
+};
+
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE};
+use actix_web::test::TestRequest;
+
+use std::fmt::Debug;
+
+/// A builder of a [TestRequest] with a body serialized in a format, keeping the `Content-Type` header and the body consistent.
+///
+/// # Example
+/// ```
+/// # #[cfg(all(feature = "json", feature = "protobuf"))]
+/// # fn main() {
+///     use actix_multiresponse::testing::PayloadTestRequest;
+///     use actix_multiresponse::ContentType;
+///
+///     let req = PayloadTestRequest::new("foo".to_string())
+///         .format(ContentType::Protobuf)
+///         .accept(ContentType::Json)
+///         .build()
+///         .uri("/echo")
+///         .to_request();
+/// # }
+/// # #[cfg(not(all(feature = "json", feature = "protobuf")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct PayloadTestRequest<T> {
+    payload: T,
+    format: ContentType,
+    accept: Option<ContentType>,
+    config: Option<PayloadConfig>,
+}
+
+impl<T: ProtobufSupport + SerdeSupportSerialize> PayloadTestRequest<T> {
+    /// A `POST` request with `payload` as body, in the default format and without an `Accept` header
+    pub fn new(payload: T) -> Self {
+        Self {
+            payload,
+            format: ContentType::default(),
+            accept: None,
+            config: None,
+        }
+    }
+
+    /// Serialize the body in `format`, and set the `Content-Type` header accordingly
+    pub fn format(mut self, format: ContentType) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the `Accept` header to the media type of `format`
+    pub fn accept(mut self, format: ContentType) -> Self {
+        self.accept = Some(format);
+        self
+    }
+
+    /// Serialize the body with the settings of `config`, e.g. with [PayloadConfig::json_naming],
+    /// and register it on the request as app data
+    pub fn config(mut self, config: PayloadConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// The request, to which e.g. the URI or other headers can be added still
+    ///
+    /// # Panics
+    ///
+    /// If the format has no media type, or the payload fails to serialize in it
+    #[track_caller]
+    pub fn build(self) -> TestRequest {
+        let media_type = match self.format.media_type() {
+            Some(media_type) => media_type,
+            None => panic!("The {} format has no media type", self.format),
+        };
+        let config = self.config.clone().unwrap_or_default();
+        let body = match Payload::new(self.payload).serialize_with(self.format.clone(), &config) {
+            Ok(body) => body,
+            Err(e) => panic!("Failed to serialize the payload as {}: {e}", self.format),
+        };
+
+        let mut req = TestRequest::post().insert_header((CONTENT_TYPE, media_type)).set_payload(body);
+        if self.config.is_some() {
+            req = req.app_data(config);
+        }
+        if let Some(media_type) = self.accept.as_ref().and_then(ContentType::media_type) {
+            req = req.insert_header((ACCEPT, media_type));
+        }
+
+        req
+    }
+}
+
+/// Deserialize `a` from `format_a` and `b` from `format_b` into `T` with `config`, and compare them
+///
+/// # Errors
+///
+/// If either side fails to deserialize
+pub fn payload_eq_with<T>(
+    a: &[u8],
+    format_a: ContentType,
+    b: &[u8],
+    format_b: ContentType,
+    config: &PayloadConfig,
+) -> Result<bool, DeserializeError>
+where
+    T: SerdeSupportDeserialize + ProtobufSupport + PartialEq,
+{
+    let a = Payload::<T>::deserialize_with(a, format_a, config)?;
+    let b = Payload::<T>::deserialize_with(b, format_b, config)?;
+    Ok(a.0 == b.0)
+}
+
+/// Assert that `a` in `format_a` and `b` in `format_b` represent the same `T`, deserializing both with the default configuration.
+///
+/// # Panics
+///
+/// If either side fails to deserialize or the deserialized values differ, showing both values
+#[track_caller]
+pub fn assert_payload_eq<T>(a: &[u8], format_a: ContentType, b: &[u8], format_b: ContentType)
+where
+    T: SerdeSupportDeserialize + ProtobufSupport + PartialEq + Debug,
+{
+    assert_payload_eq_with::<T>(a, format_a, b, format_b, &PayloadConfig::default())
+}
+
+/// Like [assert_payload_eq], deserializing both sides with `config`, e.g. with [PayloadConfig::json_naming]
+#[track_caller]
+pub fn assert_payload_eq_with<T>(a: &[u8], format_a: ContentType, b: &[u8], format_b: ContentType, config: &PayloadConfig)
+where
+    T: SerdeSupportDeserialize + ProtobufSupport + PartialEq + Debug,
+{
+    let left = match Payload::<T>::deserialize_with(a, format_a.clone(), config) {
+        Ok(left) => left.0,
+        Err(e) => panic!("Failed to deserialize the {format_a} payload: {e}"),
+    };
+    let right = match Payload::<T>::deserialize_with(b, format_b.clone(), config) {
+        Ok(right) => right.0,
+        Err(e) => panic!("Failed to deserialize the {format_b} payload: {e}"),
+    };
+
+    assert!(
+        left == right,
+        "The {format_a} and {format_b} payloads differ\n{format_a}: {left:?}\n{format_b}: {right:?}"
+    );
+}
+
+#[cfg(all(test, feature = "json", feature = "protobuf"))]
+mod test {
+    use super::*;
+
+    #[derive(Clone, PartialEq, serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+    struct Book {
+        #[prost(string, tag = "1")]
+        title: String,
+        #[prost(uint32, tag = "2")]
+        pages: u32,
+    }
+
+    fn protobuf(book: &Book) -> Vec<u8> {
+        prost::Message::encode_to_vec(book)
+    }
+
+    #[test]
+    fn test_payload_eq() {
+        let book = Book {
+            title: "Dune".to_string(),
+            pages: 412,
+        };
+        let json = br#"{ "title": "Dune", "pages": 412 }"#;
+        assert_payload_eq::<Book>(json, ContentType::Json, &protobuf(&book), ContentType::Protobuf);
+
+        let other = protobuf(&Book { pages: 411, ..book });
+        let config = PayloadConfig::default();
+        let eq = payload_eq_with::<Book>(json, ContentType::Json, &other, ContentType::Protobuf, &config);
+        assert!(!eq.unwrap());
+        assert!(payload_eq_with::<Book>(b"{", ContentType::Json, &other, ContentType::Protobuf, &config).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "The application/json and application/protobuf payloads differ")]
+    fn test_assert_payload_ne() {
+        let other = protobuf(&Book {
+            title: "Dune".to_string(),
+            pages: 411,
+        });
+        assert_payload_eq::<Book>(br#"{ "title": "Dune", "pages": 412 }"#, ContentType::Json, &other, ContentType::Protobuf);
+    }
+
+    #[actix_macros::test]
+    async fn test_payload_test_request() {
+        use actix_web::test::{call_service, init_service};
+        use actix_web::{web, App};
+
+        async fn echo(book: Payload<Book>) -> Payload<Book> {
+            book
+        }
+
+        let book = Book {
+            title: "Dune".to_string(),
+            pages: 412,
+        };
+        let app = init_service(App::new().route("/", web::post().to(echo))).await;
+        let req = PayloadTestRequest::new(book.clone())
+            .format(ContentType::Protobuf)
+            .accept(ContentType::Json)
+            .build()
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_payload_eq::<Book>(&body, ContentType::Json, &protobuf(&book), ContentType::Protobuf);
+    }
+}
@@ -0,0 +1,335 @@
+//! Timestamps rendered idiomatically in every format, enabled with the `timestamps` feature.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A point in time, encoded like `google.protobuf.Timestamp` in protobuf,
+/// and as an RFC 3339 string, e.g. `2023-01-31T12:00:00.5Z`, in JSON and XML.
+///
+/// This allows the same payload type to contain idiomatic timestamps in every format.
+/// Timestamps are formatted in UTC, with 0, 3, 6 or 9 fractional digits, following the JSON mapping of protobuf.
+/// When parsing, any offset is accepted. Like `google.protobuf.Timestamp`, only years 1 to 9999 are supported.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::Timestamp;
+///
+///     #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+///     struct Order {
+///         #[prost(message, optional, tag = "1")]
+///         created_at: Option<Timestamp>,
+///     }
+///
+///     let created_at: Timestamp = "2023-01-31T12:00:00+01:00".parse().unwrap();
+///     assert_eq!("2023-01-31T11:00:00Z", created_at.to_string());
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Timestamp {
+    /// Seconds since the Unix epoch
+    pub seconds: i64,
+    /// Non-negative fractions of a second, in nanoseconds, between 0 and 999,999,999
+    pub nanos: i32,
+}
+
+/// The seconds since the Unix epoch of `0001-01-01T00:00:00Z`
+const MIN_SECONDS: i64 = -62_135_596_800;
+/// The seconds since the Unix epoch of `9999-12-31T23:59:59Z`
+const MAX_SECONDS: i64 = 253_402_300_799;
+
+/// An error parsing a [Timestamp]
+#[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
+#[error("Invalid RFC 3339 timestamp: {0}")]
+pub struct TimestampError(String);
+
+impl Timestamp {
+    /// The timestamp of `seconds` and `nanos` since the Unix epoch.
+    /// Returns `None` if the timestamp is out of range
+    pub fn new(seconds: i64, nanos: i32) -> Option<Self> {
+        let valid = (MIN_SECONDS..=MAX_SECONDS).contains(&seconds) && (0..1_000_000_000).contains(&nanos);
+        valid.then_some(Self { seconds, nanos })
+    }
+
+    /// The current time
+    pub fn now() -> Self {
+        Self::from(SystemTime::now())
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => Self {
+                seconds: since.as_secs() as i64,
+                nanos: since.subsec_nanos() as i32,
+            },
+            Err(e) => {
+                let before = e.duration();
+                let mut seconds = -(before.as_secs() as i64);
+                let mut nanos = before.subsec_nanos() as i32;
+                if nanos > 0 {
+                    seconds -= 1;
+                    nanos = 1_000_000_000 - nanos;
+                }
+                Self { seconds, nanos }
+            }
+        }
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> Self {
+        let nanos = Duration::from_nanos(timestamp.nanos.max(0) as u64);
+        if timestamp.seconds >= 0 {
+            UNIX_EPOCH + Duration::from_secs(timestamp.seconds as u64) + nanos
+        } else {
+            UNIX_EPOCH - Duration::from_secs(timestamp.seconds.unsigned_abs()) + nanos
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    /// Formats as RFC 3339 in UTC
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = self.seconds.div_euclid(86_400);
+        let secs = self.seconds.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )?;
+
+        match self.nanos {
+            0 => {}
+            nanos if nanos % 1_000_000 == 0 => write!(f, ".{:03}", nanos / 1_000_000)?,
+            nanos if nanos % 1_000 == 0 => write!(f, ".{:06}", nanos / 1_000)?,
+            nanos => write!(f, ".{nanos:09}")?,
+        }
+
+        f.write_str("Z")
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = TimestampError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_rfc3339(s).ok_or_else(|| TimestampError(s.to_string()))
+    }
+}
+
+/// Parse an RFC 3339 timestamp, e.g. `2023-01-31T12:00:00.5+01:00`
+fn parse_rfc3339(s: &str) -> Option<Timestamp> {
+    let bytes = s.as_bytes();
+    if bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+
+    if !matches!(bytes[10], b'T' | b't' | b' ') {
+        return None;
+    }
+
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = s.get(range)?;
+        digits.bytes().all(|b| b.is_ascii_digit()).then(|| digits.parse().ok())?
+    };
+
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    // Leap seconds are not supported by `google.protobuf.Timestamp` either
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let mut rest = &s[19..];
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 || digits > 9 {
+            return None;
+        }
+
+        nanos = fraction[..digits].parse::<i32>().ok()? * 10i32.pow(9 - digits as u32);
+        rest = &fraction[digits..];
+    }
+
+    let offset = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let digit = |b: &u8| b.is_ascii_digit().then(|| (b - b'0') as i64);
+            let hours = digit(h1)? * 10 + digit(h2)?;
+            let minutes = digit(m1)? * 10 + digit(m2)?;
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    Timestamp::new(seconds, nanos)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The days since the Unix epoch of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date in the proleptic Gregorian calendar of a number of days since the Unix epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(any(feature = "json", feature = "xml"))]
+impl<'de> serde::Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TimestampVisitor;
+
+        impl serde::de::Visitor<'_> for TimestampVisitor {
+            type Value = Timestamp;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an RFC 3339 timestamp")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(TimestampVisitor)
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl prost::Message for Timestamp {
+    fn encode_raw<B: prost::bytes::BufMut>(&self, buf: &mut B) {
+        if self.seconds != 0 {
+            prost::encoding::int64::encode(1, &self.seconds, buf);
+        }
+
+        if self.nanos != 0 {
+            prost::encoding::int32::encode(2, &self.nanos, buf);
+        }
+    }
+
+    fn merge_field<B: prost::bytes::Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: prost::encoding::WireType,
+        buf: &mut B,
+        ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        match tag {
+            1 => prost::encoding::int64::merge(wire_type, &mut self.seconds, buf, ctx),
+            2 => prost::encoding::int32::merge(wire_type, &mut self.nanos, buf, ctx),
+            _ => prost::encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 0;
+        if self.seconds != 0 {
+            len += prost::encoding::int64::encoded_len(1, &self.seconds);
+        }
+
+        if self.nanos != 0 {
+            len += prost::encoding::int32::encoded_len(2, &self.nanos);
+        }
+
+        len
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!("1970-01-01T00:00:00Z", Timestamp::default().to_string());
+        assert_eq!("2023-01-31T12:00:00.500Z", Timestamp::new(1_675_166_400, 500_000_000).unwrap().to_string());
+        assert_eq!("1969-12-31T23:59:59.000001Z", Timestamp::new(-1, 1_000).unwrap().to_string());
+        assert_eq!("0001-01-01T00:00:00Z", Timestamp::new(MIN_SECONDS, 0).unwrap().to_string());
+        assert_eq!("9999-12-31T23:59:59.999999999Z", Timestamp::new(MAX_SECONDS, 999_999_999).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(Timestamp::new(1_675_166_400, 500_000_000).unwrap()), "2023-01-31T13:00:00.5+01:00".parse());
+        assert_eq!(Ok(Timestamp::new(951_782_400, 0).unwrap()), "2000-02-29T00:00:00Z".parse());
+        assert!("2001-02-29T00:00:00Z".parse::<Timestamp>().is_err());
+        assert!("2023-01-31T12:00:00".parse::<Timestamp>().is_err());
+        assert!("2023-01-31T12:00:00.Z".parse::<Timestamp>().is_err());
+        assert!("10000-01-01T00:00:00Z".parse::<Timestamp>().is_err());
+    }
+
+    #[test]
+    fn test_system_time() {
+        let time = UNIX_EPOCH - Duration::from_millis(1500);
+        let timestamp = Timestamp::from(time);
+        assert_eq!(Timestamp::new(-2, 500_000_000).unwrap(), timestamp);
+        assert_eq!(time, SystemTime::from(timestamp));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_json() {
+        let timestamp = Timestamp::new(1_675_166_400, 0).unwrap();
+        assert_eq!("\"2023-01-31T12:00:00Z\"", serde_json::to_string(&timestamp).unwrap());
+        assert_eq!(timestamp, serde_json::from_str("\"2023-01-31T12:00:00Z\"").unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "protobuf")]
+    fn test_protobuf() {
+        let timestamp = Timestamp::new(1_675_166_400, 5).unwrap();
+        let decoded: Timestamp = prost::Message::decode(&prost::Message::encode_to_vec(&timestamp)[..]).unwrap();
+        assert_eq!(timestamp, decoded);
+    }
+}
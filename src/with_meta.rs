@@ -0,0 +1,156 @@
+use crate::negotiated_error::negotiated;
+use crate::{Payload, PayloadError, ProtobufSupport, SerdeSupportDeserialize};
+
+use actix_web::dev::Payload as DevPayload;
+use actix_web::http::header::HeaderMap;
+use actix_web::{FromRequest, HttpRequest};
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// Metadata of a request parsed from its headers, see [WithMeta]
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{FromHeaders, PayloadError};
+///     use actix_web::http::header::HeaderMap;
+///
+///     struct Tenant(String);
+///
+///     impl FromHeaders for Tenant {
+///         fn from_headers(headers: &HeaderMap) -> Result<Self, PayloadError> {
+///             headers
+///                 .get("X-Tenant")
+///                 .and_then(|value| value.to_str().ok())
+///                 .map(|tenant| Self(tenant.to_string()))
+///                 .ok_or_else(|| PayloadError::MalformedHeader("X-Tenant".to_string()))
+///         }
+///     }
+/// ```
+pub trait FromHeaders: Sized {
+    /// Parse the metadata from the request headers.
+    /// An error rejects the request, usually [PayloadError::MalformedHeader] with the name of the offending header,
+    /// which results in a HTTP 400 error.
+    fn from_headers(headers: &HeaderMap) -> Result<Self, PayloadError>;
+}
+
+impl FromHeaders for () {
+    fn from_headers(_: &HeaderMap) -> Result<Self, PayloadError> {
+        Ok(())
+    }
+}
+
+impl<A: FromHeaders, B: FromHeaders> FromHeaders for (A, B) {
+    fn from_headers(headers: &HeaderMap) -> Result<Self, PayloadError> {
+        Ok((A::from_headers(headers)?, B::from_headers(headers)?))
+    }
+}
+
+/// A request body in the negotiated format together with metadata parsed from the request headers,
+/// so a handler contract of a body and headers is a single typed argument.
+///
+/// The metadata is parsed before the body is read, requests with invalid metadata are rejected without reading the body.
+///
+/// # Example
+/// ```
+///     use actix_multiresponse::{FromHeaders, Payload, PayloadError, WithMeta};
+///     use actix_web::http::header::HeaderMap;
+///
+///     struct Tenant(String);
+///
+///     impl FromHeaders for Tenant {
+///         fn from_headers(headers: &HeaderMap) -> Result<Self, PayloadError> {
+///             headers
+///                 .get("X-Tenant")
+///                 .and_then(|value| value.to_str().ok())
+///                 .map(|tenant| Self(tenant.to_string()))
+///                 .ok_or_else(|| PayloadError::MalformedHeader("X-Tenant".to_string()))
+///         }
+///     }
+///
+///     async fn handler(request: WithMeta<String, Tenant>) -> Payload<String> {
+///         Payload::new(format!("{} for tenant {}", request.body, request.meta.0))
+///     }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WithMeta<T, M> {
+    /// The request body
+    pub body: T,
+    /// The metadata parsed from the request headers
+    pub meta: M,
+}
+
+impl<T, M> WithMeta<T, M> {
+    pub fn into_inner(self) -> (T, M) {
+        (self.body, self.meta)
+    }
+}
+
+impl<T, M> FromRequest for WithMeta<T, M>
+where
+    T: 'static + Send + SerdeSupportDeserialize + ProtobufSupport,
+    M: 'static + FromHeaders,
+{
+    type Error = PayloadError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut DevPayload) -> Self::Future {
+        let meta = match M::from_headers(req.headers()) {
+            Ok(meta) => meta,
+            Err(e) => return Box::pin(std::future::ready(Err(negotiated(req, e)))),
+        };
+        let extract = Payload::<T>::from_request(req, payload);
+
+        Box::pin(async move {
+            Ok(Self {
+                body: extract.await?.0,
+                meta,
+            })
+        })
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{web, App};
+
+    struct Tenant(String);
+
+    impl FromHeaders for Tenant {
+        fn from_headers(headers: &HeaderMap) -> Result<Self, PayloadError> {
+            headers
+                .get("X-Tenant")
+                .and_then(|value| value.to_str().ok())
+                .map(|tenant| Self(tenant.to_string()))
+                .ok_or_else(|| PayloadError::MalformedHeader("X-Tenant".to_string()))
+        }
+    }
+
+    async fn handler(request: WithMeta<String, (Tenant, ())>) -> Payload<String> {
+        let (body, (tenant, ())) = request.into_inner();
+        Payload::new(format!("{body} for {}", tenant.0))
+    }
+
+    #[actix_macros::test]
+    async fn test_with_meta() {
+        let app = init_service(App::new().route("/", web::post().to(handler))).await;
+
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("X-Tenant", "acme"))
+            .set_payload(r#""order""#)
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(r#""order for acme""#, read_body(resp).await);
+
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#""order""#)
+            .to_request();
+        assert_eq!(StatusCode::BAD_REQUEST, call_service(&app, req).await.status());
+    }
+}
@@ -0,0 +1,208 @@
+//! Wrappers which only support a single format, comparable to [actix_web::web::Json].
+//! They share the configuration and errors of [crate::Payload], which eases migrating routes
+//! one by one.
+
+use crate::negotiated_error::negotiated_extract;
+use crate::{body, build_response, codec, ContentType, Negotiation, PayloadConfig, PayloadError};
+#[cfg(feature = "protobuf")]
+use crate::ProtobufSupport;
+
+use actix_web::body::BoxBody;
+use actix_web::dev::Payload as DevPayload;
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder};
+
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+
+/// Read the request body, requiring the `Content-Type` of the request to be `format`
+async fn extract(req: &HttpRequest, payload: DevPayload, format: ContentType) -> Result<Bytes, PayloadError> {
+    let negotiation = Negotiation::try_from_request(req)?;
+    if negotiation.content_type.ne(&format) {
+        return Err(PayloadError::InvalidContentType(negotiation.content_type));
+    }
+
+    body::read_body_with(req, payload, PayloadConfig::from_req(req)).await
+}
+
+macro_rules! single_format {
+    (
+        $(#[$meta:meta])*
+        $name:ident, $format:expr,
+        deserialize: |$body:ident, $config:ident| $deserialize:expr, where $($de_bound:path),+;
+        serialize: $serialize:path where $($ser_bound:path),+;
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub struct $name<T>(pub T);
+
+        impl<T> Deref for $name<T> {
+            type Target = T;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl<T> DerefMut for $name<T> {
+            fn deref_mut(&mut self) -> &mut Self::Target {
+                &mut self.0
+            }
+        }
+
+        impl<T: 'static $(+ $de_bound)+> FromRequest for $name<T> {
+            type Error = PayloadError;
+            type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+            fn from_request(req: &HttpRequest, payload: &mut DevPayload) -> Self::Future {
+                let req = req.clone();
+                let payload = payload.take();
+
+                negotiated_extract(req.clone(), async move {
+                    let raw = extract(&req, payload, $format).await?;
+                    let $config = PayloadConfig::from_req(&req);
+                    let deserialized = body::prepare_body($config, raw.clone(), &$format)
+                        .and_then(|$body| $deserialize);
+
+                    deserialized.map(Self).map_err(|error| {
+                        $config.report_error(PayloadError::Deserialize {
+                            error,
+                            body: raw,
+                            content_type: $format,
+                        })
+                    })
+                })
+            }
+        }
+
+        impl<T: $($ser_bound +)+> Responder for $name<T> {
+            type Body = BoxBody;
+
+            fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+                let config = PayloadConfig::from_req(req);
+                build_response(StatusCode::OK, $format, $serialize(&self.0, config), config)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "json")]
+single_format! {
+    /// Payload wrapper which only supports JSON.
+    ///
+    /// Requests with a `Content-Type` other than `application/json` are rejected with a HTTP 400 error.
+    /// Responses are always JSON, regardless of the `Accept` header.
+    Json, ContentType::Json,
+    deserialize: |body, config| codec::deserialize_json(&body, config), where serde::de::DeserializeOwned;
+    serialize: codec::serialize_json where serde::Serialize;
+}
+
+#[cfg(feature = "protobuf")]
+single_format! {
+    /// Payload wrapper which only supports Protobuf.
+    ///
+    /// Requests with a `Content-Type` other than `application/protobuf` are rejected with a HTTP 400 error.
+    /// Responses are always Protobuf, regardless of the `Accept` header.
+    Proto, ContentType::Protobuf,
+    deserialize: |body, _config| T::decode_protobuf_bytes(body), where ProtobufSupport;
+    serialize: codec::encode_protobuf where ProtobufSupport;
+}
+
+#[cfg(feature = "xml")]
+single_format! {
+    /// Payload wrapper which only supports XML.
+    ///
+    /// Requests with a `Content-Type` other than `application/xml` or `text/xml` are rejected with a HTTP 400 error.
+    /// Responses are always XML, regardless of the `Accept` header.
+    Xml, ContentType::Xml,
+    deserialize: |body, config| codec::deserialize_xml(&body, config), where serde::de::DeserializeOwned;
+    serialize: codec::serialize_xml where serde::Serialize;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App};
+
+    #[derive(serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+    struct TestPayload {
+        #[prost(string, tag = "1")]
+        foo: String,
+    }
+
+    #[cfg(feature = "json")]
+    async fn json_responder(payload: Json<TestPayload>) -> Json<TestPayload> {
+        payload
+    }
+
+    #[cfg(feature = "xml")]
+    async fn xml_responder(payload: Xml<TestPayload>) -> Xml<TestPayload> {
+        payload
+    }
+
+    #[cfg(feature = "protobuf")]
+    async fn proto_responder(payload: Proto<TestPayload>) -> Proto<TestPayload> {
+        payload
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "json")]
+    async fn test_json() {
+        let app = init_service(App::new().route("/", web::post().to(json_responder))).await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/json"))
+            .insert_header(("Accept", "application/protobuf"))
+            .set_payload(r#"{"foo":"bar"}"#)
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!("application/json", resp.headers().get("Content-Type").unwrap());
+    }
+
+    #[actix_macros::test]
+    #[cfg(all(feature = "json", feature = "protobuf"))]
+    async fn test_json_rejects_protobuf() {
+        let app = init_service(App::new().route("/", web::post().to(json_responder))).await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "protobuf")]
+    async fn test_proto() {
+        let app = init_service(App::new().route("/", web::post().to(proto_responder))).await;
+        let body = prost::Message::encode_to_vec(&TestPayload { foo: "bar".to_string() });
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "application/protobuf"))
+            .set_payload(body.clone())
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!("application/protobuf", resp.headers().get("Content-Type").unwrap());
+        assert_eq!(body, actix_web::body::to_bytes(resp.into_body()).await.unwrap().to_vec());
+    }
+
+    #[actix_macros::test]
+    #[cfg(feature = "xml")]
+    async fn test_xml() {
+        let app = init_service(App::new().route("/", web::post().to(xml_responder))).await;
+        let req = TestRequest::post()
+            .insert_header(("Content-Type", "text/xml"))
+            .insert_header(("Accept", "application/json"))
+            .set_payload("<TestPayload><foo>bar</foo></TestPayload>")
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!("application/xml", resp.headers().get("Content-Type").unwrap());
+    }
+}
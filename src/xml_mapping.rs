@@ -0,0 +1,1035 @@
+//! Mapping the fields of structs to XML attributes, elements and text content, see [crate::PayloadConfig::xml_mapping].
+//!
+//! quick-xml represents fields named with a leading `@` as attributes, and fields named `$text` or `$value`
+//! as the content of the element. The serializer and deserializer are wrapped, so the fields of the configured types
+//! are renamed accordingly without `#[serde(rename)]` attributes, which would change the JSON representation as well.
+//! Only structs are mapped, the fields of struct variants of enums keep their names.
+
+use crate::strict::{forward_deserialize, forward_visit};
+
+use serde::de::value::{BorrowedStrDeserializer, StringDeserializer};
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+/// The XML key of quick-xml for the text content of an element
+const TEXT_KEY: &str = "$text";
+/// The XML key of quick-xml for the content of an element, which may contain elements
+const VALUE_KEY: &str = "$value";
+
+/// How a field is represented in XML
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum XmlField {
+    /// An attribute, with the name quick-xml serializes it with
+    Attribute(&'static str),
+    Element(&'static str),
+    Text,
+    Content,
+}
+
+/// The mapping of the fields of types to XML attributes, elements and text content, see [crate::PayloadConfig::xml_mapping].
+///
+/// Types are identified by their serde name, which is the name of the Rust type unless it is renamed with `#[serde(rename)]`,
+/// and fields by their serde name as well. Fields which are not mapped are serialized as elements named after the field.
+///
+/// # Example
+/// With this mapping, `Price { currency: "EUR", amount: 9.99 }` is serialized as `<price currency="EUR">9.99</price>`,
+/// while its JSON representation is unchanged.
+/// ```
+///     use actix_multiresponse::XmlMapping;
+///
+///     let mapping = XmlMapping::new()
+///         .root("Price", "price")
+///         .attribute("Price", "currency")
+///         .text("Price", "amount");
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct XmlMapping {
+    fields: Vec<(&'static str, &'static str, XmlField)>,
+    scalar_attributes: Vec<&'static str>,
+    roots: Vec<(&'static str, &'static str)>,
+}
+
+impl XmlMapping {
+    /// A mapping which leaves all fields as elements
+    pub const fn new() -> Self {
+        Self {
+            fields: Vec::new(),
+            scalar_attributes: Vec::new(),
+            roots: Vec::new(),
+        }
+    }
+
+    /// Represent `field` of `type_name` as an attribute of the element of the type.
+    /// The value must be a scalar, like a number or a string
+    pub fn attribute(self, type_name: &'static str, field: &'static str) -> Self {
+        self.map(type_name, field, XmlField::Attribute(attribute_name(field)))
+    }
+
+    /// Represent all fields of `type_name` with scalar values, like numbers and strings, as attributes.
+    /// Fields mapped explicitly are not affected
+    pub fn scalar_attributes(mut self, type_name: &'static str) -> Self {
+        self.scalar_attributes.push(type_name);
+        self
+    }
+
+    /// Represent `field` of `type_name` as an element named `name`
+    pub fn element(self, type_name: &'static str, field: &'static str, name: &'static str) -> Self {
+        self.map(type_name, field, XmlField::Element(name))
+    }
+
+    /// Represent `field` of `type_name` as the text content of the element of the type, like `#[serde(rename = "$text")]`.
+    /// The value must be a scalar, like a number or a string
+    pub fn text(self, type_name: &'static str, field: &'static str) -> Self {
+        self.map(type_name, field, XmlField::Text)
+    }
+
+    /// Represent `field` of `type_name` as the content of the element of the type without a wrapping element,
+    /// like `#[serde(rename = "$value")]`, e.g. for a list of enums of which the variants are the names of the elements
+    pub fn content(self, type_name: &'static str, field: &'static str) -> Self {
+        self.map(type_name, field, XmlField::Content)
+    }
+
+    /// Name the root element `name` if the body is a `type_name`, rather than after the type
+    pub fn root(mut self, type_name: &'static str, name: &'static str) -> Self {
+        self.roots.retain(|(mapped, _)| *mapped != type_name);
+        self.roots.push((type_name, name));
+        self
+    }
+
+    fn map(mut self, type_name: &'static str, field: &'static str, xml_field: XmlField) -> Self {
+        self.fields.retain(|(mapped_type, mapped_field, _)| (*mapped_type, *mapped_field) != (type_name, field));
+        self.fields.push((type_name, field, xml_field));
+        self
+    }
+
+    /// Whether the mapping leaves all values unchanged
+    pub(crate) fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.scalar_attributes.is_empty() && self.roots.is_empty()
+    }
+
+    fn field(&self, type_name: &str, field: &str) -> Option<XmlField> {
+        self.fields
+            .iter()
+            .find(|(mapped_type, mapped_field, _)| *mapped_type == type_name && *mapped_field == field)
+            .map(|(_, _, xml_field)| *xml_field)
+    }
+
+    fn has_type(&self, type_name: &str) -> bool {
+        self.scalar_attributes.contains(&type_name) || self.fields.iter().any(|(mapped_type, _, _)| *mapped_type == type_name)
+    }
+
+    /// The key quick-xml serializes `field` of `type_name` with, given whether its value is a scalar
+    fn serialized_key(&self, type_name: &str, field: &'static str, scalar: impl FnOnce() -> bool) -> &'static str {
+        match self.field(type_name, field) {
+            Some(XmlField::Attribute(name)) => name,
+            Some(XmlField::Element(name)) => name,
+            Some(XmlField::Text) => TEXT_KEY,
+            Some(XmlField::Content) => VALUE_KEY,
+            None if self.scalar_attributes.contains(&type_name) && scalar() => attribute_name(field),
+            None => field,
+        }
+    }
+
+    /// The field of `type_name` among `fields` which quick-xml deserialized from `key`, if it is mapped
+    fn deserialized_field(&self, type_name: &str, fields: &[&'static str], key: &str) -> Option<&'static str> {
+        let attribute = key.strip_prefix('@');
+        fields.iter().copied().find(|field| match self.field(type_name, field) {
+            Some(XmlField::Attribute(_)) => attribute == Some(*field),
+            Some(XmlField::Element(name)) => key == name,
+            Some(XmlField::Text) => key == TEXT_KEY,
+            Some(XmlField::Content) => key == VALUE_KEY,
+            None => attribute == Some(*field) && self.scalar_attributes.contains(&type_name),
+        })
+    }
+
+    /// The fields of `type_name` as quick-xml expects them, which decides where content and elements are deserialized to
+    fn xml_fields(&self, type_name: &'static str, fields: &'static [&'static str]) -> &'static [&'static str] {
+        let xml_field = |field: &'static str| self.serialized_key(type_name, field, || false);
+        if fields.iter().all(|field| xml_field(field) == *field) {
+            return fields;
+        }
+
+        let matches =
+            |list: &[&str]| list.len() == fields.len() && list.iter().zip(fields).all(|(mapped, field)| *mapped == xml_field(field));
+        LOCAL_FIELD_LISTS.with(|local| {
+            let mut local = local.borrow_mut();
+            let lists = local.entry(type_name).or_default();
+            if let Some(list) = lists.iter().find(|list| matches(list)) {
+                return *list;
+            }
+
+            let xml_fields: Vec<&'static str> = fields.iter().map(|field| xml_field(field)).collect();
+            let list = *FIELD_LISTS
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(xml_fields)
+                .or_insert_with_key(|xml_fields| Box::leak(xml_fields.clone().into_boxed_slice()));
+            lists.push(list);
+            list
+        })
+    }
+}
+
+/// The names of attributes, leaked once per field name, as serializers require static field names
+static ATTRIBUTE_NAMES: Mutex<BTreeMap<&'static str, &'static str>> = Mutex::new(BTreeMap::new());
+
+/// The mapped field lists of structs, leaked once per list, as deserializers require static field lists
+static FIELD_LISTS: Mutex<BTreeMap<Vec<&'static str>, &'static [&'static str]>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// The names of [ATTRIBUTE_NAMES] used on this thread, so serializing a field as an attribute does not lock
+    static LOCAL_ATTRIBUTE_NAMES: RefCell<BTreeMap<&'static str, &'static str>> = const { RefCell::new(BTreeMap::new()) };
+
+    /// The lists of [FIELD_LISTS] used on this thread by the name of their type, so deserializing does not lock
+    static LOCAL_FIELD_LISTS: RefCell<BTreeMap<&'static str, Vec<&'static [&'static str]>>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+/// The name quick-xml serializes the field as an attribute with.
+/// Explicitly mapped attributes are named when they are mapped, only [XmlMapping::scalar_attributes] are named while serializing
+fn attribute_name(field: &'static str) -> &'static str {
+    LOCAL_ATTRIBUTE_NAMES.with(|local| {
+        *local.borrow_mut().entry(field).or_insert_with(|| {
+            let mut names = ATTRIBUTE_NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+            names
+                .entry(field)
+                .or_insert_with(|| Box::leak(format!("@{field}").into_boxed_str()))
+        })
+    })
+}
+
+/// A value serialized with the fields of structs mapped to XML
+pub(crate) struct XmlMapped<'a, T: ?Sized> {
+    pub(crate) value: &'a T,
+    pub(crate) mapping: &'a XmlMapping,
+}
+
+impl<T: Serialize + ?Sized> Serialize for XmlMapped<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(MappedSerializer {
+            ser: serializer,
+            mapping: self.mapping,
+        })
+    }
+}
+
+/// Wrap a deserializer of which the fields of structs are mapped to XML
+pub(crate) fn mapped<'de, 'a, D: Deserializer<'de> + 'a>(
+    deserializer: D,
+    mapping: &'a XmlMapping,
+) -> impl Deserializer<'de, Error = D::Error> + 'a {
+    MappedDeserializer { de: deserializer, mapping }
+}
+
+/// A serializer renaming the fields of the mapped structs, and wrapping all nested values
+struct MappedSerializer<'a, S> {
+    ser: S,
+    mapping: &'a XmlMapping,
+}
+
+/// A compound value of a [MappedSerializer], wrapping its elements.
+/// The fields are renamed if it is a struct of a mapped type
+struct Compound<'a, C> {
+    compound: C,
+    mapping: &'a XmlMapping,
+    type_name: Option<&'static str>,
+}
+
+/// Forward the `serialize_*` methods of [MappedSerializer] for values without nested values
+macro_rules! forward_serialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method(self, $($arg: $ty),*) -> Result<Self::Ok, Self::Error> {
+                self.ser.$method($($arg),*)
+            }
+        )*
+    };
+}
+
+impl<'a, S: Serializer> Serializer for MappedSerializer<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<'a, S::SerializeSeq>;
+    type SerializeTuple = Compound<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<'a, S::SerializeTupleVariant>;
+    type SerializeMap = Compound<'a, S::SerializeMap>;
+    type SerializeStruct = Compound<'a, S::SerializeStruct>;
+    type SerializeStructVariant = Compound<'a, S::SerializeStructVariant>;
+
+    forward_serialize! {
+        serialize_bool(v: bool);
+        serialize_i8(v: i8);
+        serialize_i16(v: i16);
+        serialize_i32(v: i32);
+        serialize_i64(v: i64);
+        serialize_i128(v: i128);
+        serialize_u8(v: u8);
+        serialize_u16(v: u16);
+        serialize_u32(v: u32);
+        serialize_u64(v: u64);
+        serialize_u128(v: u128);
+        serialize_f32(v: f32);
+        serialize_f64(v: f64);
+        serialize_char(v: char);
+        serialize_str(v: &str);
+        serialize_bytes(v: &[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(name: &'static str);
+        serialize_unit_variant(name: &'static str, variant_index: u32, variant: &'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        self.ser.serialize_some(&XmlMapped {
+            value,
+            mapping: self.mapping,
+        })
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let mapping = self.mapping;
+        self.ser.serialize_newtype_struct(name, &XmlMapped { value, mapping })
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let mapping = self.mapping;
+        self.ser
+            .serialize_newtype_variant(name, variant_index, variant, &XmlMapped { value, mapping })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        let mapping = self.mapping;
+        self.ser.serialize_seq(len).map(|compound| Compound::new(compound, mapping))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        let mapping = self.mapping;
+        self.ser.serialize_tuple(len).map(|compound| Compound::new(compound, mapping))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        let mapping = self.mapping;
+        self.ser
+            .serialize_tuple_struct(name, len)
+            .map(|compound| Compound::new(compound, mapping))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        let mapping = self.mapping;
+        self.ser
+            .serialize_tuple_variant(name, variant_index, variant, len)
+            .map(|compound| Compound::new(compound, mapping))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        let mapping = self.mapping;
+        self.ser.serialize_map(len).map(|compound| Compound::new(compound, mapping))
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        let mapping = self.mapping;
+        // Only the root element is named after the struct, nested elements are named after their fields
+        let root = mapping
+            .roots
+            .iter()
+            .find(|(type_name, _)| *type_name == name)
+            .map_or(name, |(_, root)| root);
+
+        self.ser.serialize_struct(root, len).map(|compound| Compound {
+            compound,
+            mapping,
+            type_name: Some(name).filter(|name| mapping.has_type(name)),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        let mapping = self.mapping;
+        self.ser
+            .serialize_struct_variant(name, variant_index, variant, len)
+            .map(|compound| Compound::new(compound, mapping))
+    }
+
+    fn collect_str<T: fmt::Display + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        self.ser.collect_str(value)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.ser.is_human_readable()
+    }
+}
+
+impl<'a, C> Compound<'a, C> {
+    fn new(compound: C, mapping: &'a XmlMapping) -> Self {
+        Self {
+            compound,
+            mapping,
+            type_name: None,
+        }
+    }
+
+    /// The key of the field `key` for quick-xml
+    fn key<T: Serialize + ?Sized>(&self, key: &'static str, value: &T) -> &'static str {
+        match self.type_name {
+            Some(type_name) => self.mapping.serialized_key(type_name, key, || is_scalar(value)),
+            None => key,
+        }
+    }
+}
+
+/// Implement a `Serialize*` trait of elements for [Compound]
+macro_rules! compound_elements {
+    ($($serialize:ident::$method:ident;)*) => {
+        $(
+            impl<C: ser::$serialize> ser::$serialize for Compound<'_, C> {
+                type Ok = C::Ok;
+                type Error = C::Error;
+
+                fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+                    self.compound.$method(&XmlMapped { value, mapping: self.mapping })
+                }
+
+                fn end(self) -> Result<Self::Ok, Self::Error> {
+                    self.compound.end()
+                }
+            }
+        )*
+    };
+}
+
+compound_elements! {
+    SerializeSeq::serialize_element;
+    SerializeTuple::serialize_element;
+    SerializeTupleStruct::serialize_field;
+    SerializeTupleVariant::serialize_field;
+}
+
+/// Implement a `Serialize*` trait of fields for [Compound], renaming the fields
+macro_rules! compound_fields {
+    ($($serialize:ident;)*) => {
+        $(
+            impl<C: ser::$serialize> ser::$serialize for Compound<'_, C> {
+                type Ok = C::Ok;
+                type Error = C::Error;
+
+                fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error> {
+                    let key = self.key(key, value);
+                    self.compound.serialize_field(key, &XmlMapped { value, mapping: self.mapping })
+                }
+
+                fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+                    let key = self.key(key, &());
+                    self.compound.skip_field(key)
+                }
+
+                fn end(self) -> Result<Self::Ok, Self::Error> {
+                    self.compound.end()
+                }
+            }
+        )*
+    };
+}
+
+compound_fields! {
+    SerializeStruct;
+    SerializeStructVariant;
+}
+
+impl<C: ser::SerializeMap> ser::SerializeMap for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.compound.serialize_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.compound.serialize_value(&XmlMapped {
+            value,
+            mapping: self.mapping,
+        })
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.compound.end()
+    }
+}
+
+/// Whether a value is a scalar, which can be represented as an attribute. Absent optional values are not
+fn is_scalar<T: Serialize + ?Sized>(value: &T) -> bool {
+    value.serialize(ScalarProbe).unwrap_or(false)
+}
+
+/// A serializer returning whether a value is a scalar, without serializing it
+struct ScalarProbe;
+
+/// The error of [ScalarProbe] for values which are not scalars
+#[derive(Debug)]
+struct NotScalar;
+
+impl fmt::Display for NotScalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("not a scalar")
+    }
+}
+
+impl std::error::Error for NotScalar {}
+
+impl ser::Error for NotScalar {
+    fn custom<T: fmt::Display>(_: T) -> Self {
+        Self
+    }
+}
+
+/// Implement the `serialize_*` methods of [ScalarProbe] with a constant result
+macro_rules! probe {
+    ($result:expr => $($method:ident($($ty:ty),*);)*) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<Self::Ok, Self::Error> {
+                $result
+            }
+        )*
+    };
+}
+
+impl Serializer for ScalarProbe {
+    type Ok = bool;
+    type Error = NotScalar;
+    type SerializeSeq = ser::Impossible<bool, NotScalar>;
+    type SerializeTuple = ser::Impossible<bool, NotScalar>;
+    type SerializeTupleStruct = ser::Impossible<bool, NotScalar>;
+    type SerializeTupleVariant = ser::Impossible<bool, NotScalar>;
+    type SerializeMap = ser::Impossible<bool, NotScalar>;
+    type SerializeStruct = ser::Impossible<bool, NotScalar>;
+    type SerializeStructVariant = ser::Impossible<bool, NotScalar>;
+
+    probe! {
+        Ok(true) =>
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_i128(i128);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_u128(u128);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    probe! {
+        Ok(false) =>
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(false)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(NotScalar)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(NotScalar)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(NotScalar)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(NotScalar)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(NotScalar)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(NotScalar)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(NotScalar)
+    }
+
+    fn collect_str<T: fmt::Display + ?Sized>(self, _: &T) -> Result<Self::Ok, Self::Error> {
+        Ok(true)
+    }
+}
+
+/// A deserializer of values of which the fields of structs are mapped to XML
+struct MappedDeserializer<'a, D> {
+    de: D,
+    mapping: &'a XmlMapping,
+}
+
+impl<'a, D> MappedDeserializer<'a, D> {
+    fn wrap<V>(self, visitor: V) -> (D, MappedVisitor<'a, V>) {
+        (
+            self.de,
+            MappedVisitor {
+                visitor,
+                mapping: self.mapping,
+                fields: None,
+            },
+        )
+    }
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for MappedDeserializer<'_, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if !self.mapping.has_type(name) {
+            let (de, visitor) = self.wrap(visitor);
+            return de.deserialize_struct(name, fields, visitor);
+        }
+
+        let xml_fields = self.mapping.xml_fields(name, fields);
+        self.de.deserialize_struct(
+            name,
+            xml_fields,
+            MappedVisitor {
+                visitor,
+                mapping: self.mapping,
+                fields: Some((name, fields)),
+            },
+        )
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.de.deserialize_ignored_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.de.is_human_readable()
+    }
+}
+
+/// The visitor of a value, wrapping the access to nested values.
+/// The keys of maps are renamed to the `fields` if the value is a struct of a mapped type
+struct MappedVisitor<'a, V> {
+    visitor: V,
+    mapping: &'a XmlMapping,
+    fields: Option<(&'static str, &'static [&'static str])>,
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for MappedVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.visitor.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(v: bool);
+        visit_i8(v: i8);
+        visit_i16(v: i16);
+        visit_i32(v: i32);
+        visit_i64(v: i64);
+        visit_i128(v: i128);
+        visit_u8(v: u8);
+        visit_u16(v: u16);
+        visit_u32(v: u32);
+        visit_u64(v: u64);
+        visit_u128(v: u128);
+        visit_f32(v: f32);
+        visit_f64(v: f64);
+        visit_char(v: char);
+        visit_str(v: &str);
+        visit_borrowed_str(v: &'de str);
+        visit_string(v: String);
+        visit_bytes(v: &[u8]);
+        visit_borrowed_bytes(v: &'de [u8]);
+        visit_byte_buf(v: Vec<u8>);
+        visit_none();
+        visit_unit();
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_some(mapped(deserializer, self.mapping))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.visitor.visit_newtype_struct(mapped(deserializer, self.mapping))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_seq(MappedSeq {
+            seq,
+            mapping: self.mapping,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_map(MappedMap {
+            map,
+            mapping: self.mapping,
+            fields: self.fields,
+        })
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.visitor.visit_enum(MappedEnum {
+            data,
+            mapping: self.mapping,
+        })
+    }
+}
+
+/// A seed deserializing a nested value
+struct MappedSeed<'a, S> {
+    seed: S,
+    mapping: &'a XmlMapping,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for MappedSeed<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.seed.deserialize(mapped(deserializer, self.mapping))
+    }
+}
+
+/// The elements of a sequence
+struct MappedSeq<'a, A> {
+    seq: A,
+    mapping: &'a XmlMapping,
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for MappedSeq<'_, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error> {
+        self.seq.next_element_seed(MappedSeed {
+            seed,
+            mapping: self.mapping,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.seq.size_hint()
+    }
+}
+
+/// The entries of a map, or the fields of a struct
+struct MappedMap<'a, A> {
+    map: A,
+    mapping: &'a XmlMapping,
+    fields: Option<(&'static str, &'static [&'static str])>,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for MappedMap<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        match self.fields {
+            Some((type_name, fields)) => self.map.next_key_seed(FieldSeed {
+                seed,
+                mapping: self.mapping,
+                type_name,
+                fields,
+            }),
+            None => self.map.next_key_seed(seed),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, Self::Error> {
+        self.map.next_value_seed(MappedSeed {
+            seed,
+            mapping: self.mapping,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint()
+    }
+}
+
+/// A seed deserializing the key of a struct field, replacing its XML key with the name of the field.
+/// Keys which do not match a mapped field are passed on unchanged
+struct FieldSeed<'a, S> {
+    seed: S,
+    mapping: &'a XmlMapping,
+    type_name: &'static str,
+    fields: &'static [&'static str],
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for FieldSeed<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let key = <String as de::Deserialize>::deserialize(deserializer)?;
+        match self.mapping.deserialized_field(self.type_name, self.fields, &key) {
+            Some(field) => self.seed.deserialize(BorrowedStrDeserializer::new(field)),
+            None => self.seed.deserialize(StringDeserializer::new(key)),
+        }
+    }
+}
+
+/// The variant of an enum
+struct MappedEnum<'a, A> {
+    data: A,
+    mapping: &'a XmlMapping,
+}
+
+impl<'a, 'de, A: EnumAccess<'de>> EnumAccess<'de> for MappedEnum<'a, A> {
+    type Error = A::Error;
+    type Variant = MappedEnum<'a, A::Variant>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self::Variant), Self::Error> {
+        let (value, data) = self.data.variant_seed(seed)?;
+        Ok((
+            value,
+            MappedEnum {
+                data,
+                mapping: self.mapping,
+            },
+        ))
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for MappedEnum<'_, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.data.unit_variant()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Self::Error> {
+        self.data.newtype_variant_seed(MappedSeed {
+            seed,
+            mapping: self.mapping,
+        })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        let visitor = MappedVisitor {
+            visitor,
+            mapping: self.mapping,
+            fields: None,
+        };
+        self.data.tuple_variant(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let visitor = MappedVisitor {
+            visitor,
+            mapping: self.mapping,
+            fields: None,
+        };
+        self.data.struct_variant(fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codec::{deserialize_xml, serialize_xml};
+    use crate::PayloadConfig;
+
+    #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+    struct Price {
+        currency: String,
+        amount: f64,
+    }
+
+    #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+    struct Product {
+        id: u32,
+        name: String,
+        price: Price,
+        tags: Vec<String>,
+    }
+
+    fn product() -> Product {
+        Product {
+            id: 1,
+            name: "Dune".to_string(),
+            price: Price {
+                currency: "EUR".to_string(),
+                amount: 9.5,
+            },
+            tags: vec!["book".to_string()],
+        }
+    }
+
+    fn config() -> PayloadConfig {
+        PayloadConfig::default().xml_mapping(
+            XmlMapping::new()
+                .root("Product", "product")
+                .scalar_attributes("Product")
+                .element("Product", "name", "Title")
+                .attribute("Price", "currency")
+                .text("Price", "amount"),
+        )
+    }
+
+    #[test]
+    fn test_serialize() {
+        let xml = serialize_xml(&product(), &config()).unwrap();
+        assert_eq!(
+            r#"<product id="1"><Title>Dune</Title><price currency="EUR">9.5</price><tags>book</tags></product>"#,
+            String::from_utf8(xml).unwrap()
+        );
+
+        let unmapped = serialize_xml(&product(), &PayloadConfig::default()).unwrap();
+        assert!(String::from_utf8(unmapped).unwrap().starts_with("<Product><id>1</id><name>Dune</name>"));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let config = config();
+        let xml = serialize_xml(&product(), &config).unwrap();
+        assert_eq!(product(), deserialize_xml::<Product>(&xml, &config).unwrap());
+
+        let strict = config.deny_unknown_fields(true);
+        assert_eq!(product(), deserialize_xml::<Product>(&xml, &strict).unwrap());
+    }
+
+    #[test]
+    fn test_content() {
+        #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+        enum Item {
+            Book(String),
+            Film(String),
+        }
+
+        #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+        struct Shelf {
+            items: Vec<Item>,
+        }
+
+        let shelf = Shelf {
+            items: vec![Item::Book("Dune".to_string()), Item::Film("Alien".to_string())],
+        };
+        let config = PayloadConfig::default().xml_mapping(XmlMapping::new().content("Shelf", "items"));
+        let xml = serialize_xml(&shelf, &config).unwrap();
+        assert_eq!("<Shelf><Book>Dune</Book><Film>Alien</Film></Shelf>", std::str::from_utf8(&xml).unwrap());
+        assert_eq!(shelf, deserialize_xml::<Shelf>(&xml, &config).unwrap());
+    }
+
+    #[test]
+    fn test_names_leaked_once() {
+        let name = attribute_name("currency");
+        assert_eq!("@currency", name);
+        assert!(std::ptr::eq(name, attribute_name("currency")));
+        let other = std::thread::spawn(|| attribute_name("currency")).join().unwrap();
+        assert!(std::ptr::eq(name, other));
+
+        let mapping = XmlMapping::new().attribute("Price", "currency").text("Price", "amount");
+        let fields = mapping.xml_fields("Price", &["currency", "amount"]);
+        assert_eq!(&["@currency", "$text"], fields);
+        assert!(std::ptr::eq(fields, mapping.xml_fields("Price", &["currency", "amount"])));
+
+        // Another mapping of the type has its own list
+        let element = XmlMapping::new().element("Price", "currency", "Currency");
+        assert_eq!(&["Currency", "amount"], element.xml_fields("Price", &["currency", "amount"]));
+        assert!(std::ptr::eq(fields, mapping.xml_fields("Price", &["currency", "amount"])));
+    }
+}
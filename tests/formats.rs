@@ -0,0 +1,119 @@
+//! Request and response roundtrips for every pair of enabled formats, including the error paths.
+//!
+//! Every test is only compiled when the formats it uses are enabled, so the suite covers any combination
+//! of features, e.g. `cargo test --no-default-features --features xml`. Run it for every combination
+//! to catch code which only breaks with a particular set of features.
+
+use actix_multiresponse::testing::PayloadTestRequest;
+use actix_multiresponse::{ContentType, Payload};
+use actix_web::http::StatusCode;
+use actix_web::test::{call_service, init_service, TestRequest};
+use actix_web::{web, App};
+
+#[derive(Clone, PartialEq, serde_derive::Deserialize, serde_derive::Serialize, prost_derive::Message)]
+struct TestPayload {
+    #[prost(string, tag = "1")]
+    foo: String,
+    #[prost(int64, tag = "2")]
+    bar: i64,
+}
+
+async fn echo(payload: Payload<TestPayload>) -> Payload<TestPayload> {
+    payload
+}
+
+/// Send a body in `request` to the echo handler, accepting `response`, and check the echoed payload
+async fn roundtrip(request: ContentType, response: ContentType) {
+    let payload = Payload::new(TestPayload {
+        foo: "foo".to_string(),
+        bar: 42,
+    });
+
+    let app = init_service(App::new().route("/", web::post().to(echo))).await;
+    let req = PayloadTestRequest::new(payload.0.clone())
+        .format(request)
+        .accept(response.clone())
+        .build()
+        .to_request();
+    let resp = call_service(&app, req).await;
+
+    assert_eq!(StatusCode::OK, resp.status());
+    assert_eq!(response.to_string(), resp.headers().get("Content-Type").unwrap().to_str().unwrap());
+
+    let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+    assert_eq!(payload, Payload::<TestPayload>::deserialize(&body, response).unwrap());
+}
+
+/// Send `body` with `content_type` to the echo handler, and check it is rejected with `status`
+async fn rejects(content_type: Option<&str>, body: &'static [u8], status: StatusCode) {
+    let app = init_service(App::new().route("/", web::post().to(echo))).await;
+    let mut req = TestRequest::post().set_payload(body);
+    if let Some(content_type) = content_type {
+        req = req.insert_header(("Content-Type", content_type));
+    }
+
+    let resp = call_service(&app, req.to_request()).await;
+    assert_eq!(status, resp.status());
+}
+
+/// A roundtrip test for every pair of formats, compiled if both formats are enabled
+macro_rules! roundtrips {
+    ($($name:ident: $request:ident($request_feature:literal) => $response:ident($response_feature:literal);)*) => {
+        $(
+            #[actix_macros::test]
+            #[cfg(all(feature = $request_feature, feature = $response_feature))]
+            async fn $name() {
+                roundtrip(ContentType::$request, ContentType::$response).await;
+            }
+        )*
+    };
+}
+
+/// A test rejecting a malformed body for every format, compiled if the format is enabled
+macro_rules! malformed {
+    ($($name:ident: $format:ident($feature:literal), $body:expr;)*) => {
+        $(
+            #[actix_macros::test]
+            #[cfg(feature = $feature)]
+            async fn $name() {
+                let content_type = ContentType::$format.to_string();
+                rejects(Some(&content_type), $body, StatusCode::BAD_REQUEST).await;
+            }
+        )*
+    };
+}
+
+roundtrips! {
+    json_to_json: Json("json") => Json("json");
+    json_to_protobuf: Json("json") => Protobuf("protobuf");
+    json_to_xml: Json("json") => Xml("xml");
+    protobuf_to_json: Protobuf("protobuf") => Json("json");
+    protobuf_to_protobuf: Protobuf("protobuf") => Protobuf("protobuf");
+    protobuf_to_xml: Protobuf("protobuf") => Xml("xml");
+    xml_to_json: Xml("xml") => Json("json");
+    xml_to_protobuf: Xml("xml") => Protobuf("protobuf");
+    xml_to_xml: Xml("xml") => Xml("xml");
+}
+
+malformed! {
+    malformed_json: Json("json"), b"{";
+    // Field 1 with a length beyond the end of the body
+    malformed_protobuf: Protobuf("protobuf"), &[0x0a, 0xff];
+    malformed_xml: Xml("xml"), b"<TestPayload><foo>";
+}
+
+#[actix_macros::test]
+async fn unsupported_content_type() {
+    rejects(Some("text/plain"), b"foo", StatusCode::BAD_REQUEST).await;
+}
+
+#[actix_macros::test]
+async fn missing_content_type() {
+    rejects(None, b"foo", StatusCode::BAD_REQUEST).await;
+}
+
+#[test]
+#[cfg(any(feature = "json", feature = "protobuf", feature = "xml"))]
+fn default_format_is_enabled() {
+    assert!(!ContentType::default().is_other());
+}